@@ -0,0 +1,727 @@
+//! Arbre DOM modifiable.
+//!
+//! Les nœuds sont stockés dans une arène appartenant au [`Document`] et sont
+//! désignés par un [`NodeId`]. Chaque nœud connaît son parent, son premier et
+//! son dernier enfant ainsi que ses voisins : l'arbre peut être parcouru dans
+//! tous les sens et modifié sur place, ce que ne permet pas l'arbre de
+//! `HtmlNode` produit par le parseur.
+
+mod node;
+
+pub use node::{ElementData, NodeData, NodeId};
+
+use flux_parser::html::parser::HtmlNode;
+use flux_parser::html::{HtmlDocument, Namespace};
+use node::Node;
+
+/// Erreurs des opérations de modification, nommées d'après les exceptions du DOM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DomError {
+    /// L'opération rendrait l'arbre invalide : nœud inséré dans lui-même ou
+    /// dans un de ses descendants, enfant sous un texte, second élément
+    /// racine...
+    HierarchyRequest,
+    /// Le nœud donné comme enfant ou comme référence n'est pas un enfant du
+    /// parent.
+    NotFound,
+}
+
+/// Un document et tous les nœuds créés pour lui.
+#[derive(Debug, Clone)]
+pub struct Document {
+    nodes: Vec<Node>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Document {
+    /// Crée un document vide, qui ne contient que le nœud document.
+    pub fn new() -> Self {
+        Document {
+            nodes: vec![Node::new(NodeData::Document)],
+        }
+    }
+
+    /// Le nœud document, racine de l'arbre.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// L'élément racine (`<html>`), s'il existe.
+    pub fn document_element(&self) -> Option<NodeId> {
+        self.children(self.root())
+            .find(|&id| self.element(id).is_some())
+    }
+
+    fn create_node(&mut self, data: NodeData) -> NodeId {
+        self.nodes.push(Node::new(data));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Crée un élément HTML détaché. Un `<template>` reçoit un fragment vide
+    /// pour son contenu.
+    pub fn create_element(&mut self, tag_name: &str, attributes: Vec<(String, String)>) -> NodeId {
+        self.create_element_ns(Namespace::Html, tag_name, attributes)
+    }
+
+    /// Crée un élément détaché dans l'espace de noms `namespace`, par exemple
+    /// un élément SVG.
+    pub fn create_element_ns(
+        &mut self,
+        namespace: Namespace,
+        tag_name: &str,
+        attributes: Vec<(String, String)>,
+    ) -> NodeId {
+        let element = self.create_node(NodeData::Element(ElementData {
+            namespace,
+            tag_name: tag_name.to_string(),
+            attributes,
+        }));
+        if namespace == Namespace::Html && tag_name == "template" {
+            let contents = self.create_document_fragment();
+            self.nodes[element.0].template_contents = Some(contents);
+        }
+        element
+    }
+
+    /// Crée un fragment de document vide.
+    pub fn create_document_fragment(&mut self) -> NodeId {
+        self.create_node(NodeData::DocumentFragment)
+    }
+
+    /// Crée un nœud texte détaché.
+    pub fn create_text(&mut self, text: &str) -> NodeId {
+        self.create_node(NodeData::Text(text.to_string()))
+    }
+
+    /// Crée un commentaire détaché.
+    pub fn create_comment(&mut self, text: &str) -> NodeId {
+        self.create_node(NodeData::Comment(text.to_string()))
+    }
+
+    /// Crée un nœud DOCTYPE détaché.
+    pub fn create_doctype(&mut self, name: &str, public_id: &str, system_id: &str) -> NodeId {
+        self.create_node(NodeData::Doctype {
+            name: name.to_string(),
+            public_id: public_id.to_string(),
+            system_id: system_id.to_string(),
+        })
+    }
+
+    pub fn data(&self, id: NodeId) -> &NodeData {
+        &self.nodes[id.0].data
+    }
+
+    pub fn data_mut(&mut self, id: NodeId) -> &mut NodeData {
+        &mut self.nodes[id.0].data
+    }
+
+    /// Les données de l'élément `id`, ou `None` si ce n'est pas un élément.
+    pub fn element(&self, id: NodeId) -> Option<&ElementData> {
+        match self.data(id) {
+            NodeData::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    pub fn element_mut(&mut self, id: NodeId) -> Option<&mut ElementData> {
+        match self.data_mut(id) {
+            NodeData::Element(element) => Some(element),
+            _ => None,
+        }
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].parent
+    }
+
+    pub fn first_child(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].first_child
+    }
+
+    pub fn last_child(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].last_child
+    }
+
+    pub fn previous_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].previous_sibling
+    }
+
+    pub fn next_sibling(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].next_sibling
+    }
+
+    /// Le fragment qui contient le contenu du `<template>` `id`. Ce contenu
+    /// ne fait pas partie de l'arbre : il n'apparaît ni dans les enfants de
+    /// l'élément ni dans `text_content`.
+    pub fn template_contents(&self, id: NodeId) -> Option<NodeId> {
+        self.nodes[id.0].template_contents
+    }
+
+    /// Parcourt les enfants de `id`, dans l'ordre de l'arbre.
+    pub fn children(&self, id: NodeId) -> Children<'_> {
+        Children {
+            document: self,
+            next: self.first_child(id),
+        }
+    }
+
+    /// Parcourt `id` et tous ses descendants, dans l'ordre de l'arbre.
+    pub fn descendants(&self, id: NodeId) -> Descendants<'_> {
+        Descendants {
+            document: self,
+            root: id,
+            next: Some(id),
+        }
+    }
+
+    /// Indique si `ancestor` est `id` ou l'un de ses ancêtres.
+    pub fn is_inclusive_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        std::iter::successors(Some(id), |&node| self.parent(node)).any(|node| node == ancestor)
+    }
+
+    /// Le texte de tous les nœuds texte sous `id`, mis bout à bout.
+    pub fn text_content(&self, id: NodeId) -> String {
+        self.descendants(id)
+            .filter_map(|node| match self.data(node) {
+                NodeData::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Ajoute `child` en dernier enfant de `parent`, en le déplaçant s'il
+    /// était déjà dans l'arbre.
+    pub fn append_child(&mut self, parent: NodeId, child: NodeId) -> Result<NodeId, DomError> {
+        self.insert_before(parent, child, None)
+    }
+
+    /// Insère `child` dans `parent` avant `reference`, ou à la fin sans
+    /// référence. Un enfant déjà présent dans l'arbre est déplacé. Insérer un
+    /// fragment y insère ses enfants, et le laisse vide.
+    pub fn insert_before(
+        &mut self,
+        parent: NodeId,
+        child: NodeId,
+        reference: Option<NodeId>,
+    ) -> Result<NodeId, DomError> {
+        self.check_insertion(parent, child, reference, None)?;
+        self.insert(parent, child, reference);
+        Ok(child)
+    }
+
+    /// Insère `child` sans vérifier que l'arbre reste valide. Les enfants
+    /// d'un fragment sont insérés à sa place.
+    fn insert(&mut self, parent: NodeId, child: NodeId, reference: Option<NodeId>) {
+        if matches!(self.data(child), NodeData::DocumentFragment) {
+            let children: Vec<NodeId> = self.children(child).collect();
+            for node in children {
+                self.insert(parent, node, reference);
+            }
+            return;
+        }
+        // Insérer un nœud avant lui-même le laisse à sa place
+        let reference = if reference == Some(child) {
+            self.next_sibling(child)
+        } else {
+            reference
+        };
+
+        self.detach(child);
+        let previous = match reference {
+            Some(reference) => self.previous_sibling(reference),
+            None => self.last_child(parent),
+        };
+        {
+            let node = &mut self.nodes[child.0];
+            node.parent = Some(parent);
+            node.previous_sibling = previous;
+            node.next_sibling = reference;
+        }
+        match previous {
+            Some(previous) => self.nodes[previous.0].next_sibling = Some(child),
+            None => self.nodes[parent.0].first_child = Some(child),
+        }
+        match reference {
+            Some(reference) => self.nodes[reference.0].previous_sibling = Some(child),
+            None => self.nodes[parent.0].last_child = Some(child),
+        }
+    }
+
+    /// Retire `child` de `parent`. Le nœud et son sous-arbre restent
+    /// utilisables.
+    pub fn remove_child(&mut self, parent: NodeId, child: NodeId) -> Result<NodeId, DomError> {
+        if self.parent(child) != Some(parent) {
+            return Err(DomError::NotFound);
+        }
+        self.detach(child);
+        Ok(child)
+    }
+
+    /// Met `replacement` à la place de `id`, qui est retiré. Ne fait rien si
+    /// `id` n'a pas de parent.
+    pub fn replace_with(&mut self, id: NodeId, replacement: NodeId) -> Result<(), DomError> {
+        let Some(parent) = self.parent(id) else {
+            return Ok(());
+        };
+        if replacement == id {
+            return Ok(());
+        }
+        // `id` est retiré : il ne compte pas comme second élément racine
+        self.check_insertion(parent, replacement, Some(id), Some(id))?;
+        self.insert(parent, replacement, Some(id));
+        self.detach(id);
+        Ok(())
+    }
+
+    /// Copie `id` dans un nouveau nœud détaché, avec tous ses descendants si
+    /// `deep` est vrai. La copie profonde d'un `<template>` copie aussi son
+    /// contenu.
+    pub fn clone_node(&mut self, id: NodeId, deep: bool) -> NodeId {
+        let copy = match self.data(id).clone() {
+            NodeData::Element(element) => {
+                self.create_element_ns(element.namespace, &element.tag_name, element.attributes)
+            }
+            data => self.create_node(data),
+        };
+        if deep {
+            self.clone_children(id, copy);
+            if let (Some(contents), Some(copy_contents)) =
+                (self.template_contents(id), self.template_contents(copy))
+            {
+                self.clone_children(contents, copy_contents);
+            }
+        }
+        copy
+    }
+
+    /// Ajoute à `parent` une copie profonde de chacun des enfants de `id`.
+    fn clone_children(&mut self, id: NodeId, parent: NodeId) {
+        let children: Vec<NodeId> = self.children(id).collect();
+        for child in children {
+            let child_copy = self.clone_node(child, true);
+            self.append_child(parent, child_copy)
+                .expect("la copie d'un arbre valide est valide");
+        }
+    }
+
+    /// Détache `id` de son parent et de ses voisins.
+    fn detach(&mut self, id: NodeId) {
+        let Node {
+            parent,
+            previous_sibling,
+            next_sibling,
+            ..
+        } = self.nodes[id.0].clone();
+        let Some(parent) = parent else {
+            return;
+        };
+        match previous_sibling {
+            Some(previous) => self.nodes[previous.0].next_sibling = next_sibling,
+            None => self.nodes[parent.0].first_child = next_sibling,
+        }
+        match next_sibling {
+            Some(next) => self.nodes[next.0].previous_sibling = previous_sibling,
+            None => self.nodes[parent.0].last_child = previous_sibling,
+        }
+        let node = &mut self.nodes[id.0];
+        node.parent = None;
+        node.previous_sibling = None;
+        node.next_sibling = None;
+    }
+
+    /// Version réduite de « ensure pre-insertion validity » du DOM. Les
+    /// enfants d'un fragment sont tous vérifiés avant d'en déplacer un seul.
+    /// `replaced`, qui va être retiré de `parent`, est ignoré.
+    fn check_insertion(
+        &self,
+        parent: NodeId,
+        child: NodeId,
+        reference: Option<NodeId>,
+        replaced: Option<NodeId>,
+    ) -> Result<(), DomError> {
+        if !matches!(
+            self.data(parent),
+            NodeData::Document | NodeData::DocumentFragment | NodeData::Element(_)
+        ) {
+            return Err(DomError::HierarchyRequest);
+        }
+        if self.is_inclusive_ancestor(child, parent) {
+            return Err(DomError::HierarchyRequest);
+        }
+        if reference.is_some_and(|reference| self.parent(reference) != Some(parent)) {
+            return Err(DomError::NotFound);
+        }
+        if !matches!(self.data(child), NodeData::DocumentFragment) {
+            return self.check_child(parent, child, replaced);
+        }
+        if matches!(self.data(parent), NodeData::Document)
+            && self
+                .children(child)
+                .filter(|&node| self.element(node).is_some())
+                .count()
+                > 1
+        {
+            return Err(DomError::HierarchyRequest);
+        }
+        self.children(child)
+            .try_for_each(|node| self.check_child(parent, node, replaced))
+    }
+
+    /// Vérifie que `parent` peut recevoir un enfant du type de `child`.
+    fn check_child(
+        &self,
+        parent: NodeId,
+        child: NodeId,
+        replaced: Option<NodeId>,
+    ) -> Result<(), DomError> {
+        let parent_is_document = matches!(self.data(parent), NodeData::Document);
+        match self.data(child) {
+            NodeData::Document => Err(DomError::HierarchyRequest),
+            NodeData::Text(_) if parent_is_document => Err(DomError::HierarchyRequest),
+            NodeData::Doctype { .. } if !parent_is_document => Err(DomError::HierarchyRequest),
+            NodeData::Element(_)
+                if parent_is_document
+                    && self.children(parent).any(|other| {
+                        other != child && Some(other) != replaced && self.element(other).is_some()
+                    }) =>
+            {
+                Err(DomError::HierarchyRequest)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<&HtmlDocument> for Document {
+    fn from(html: &HtmlDocument) -> Self {
+        fn convert(document: &mut Document, parent: NodeId, nodes: &[HtmlNode]) {
+            for node in nodes {
+                let id = match node {
+                    HtmlNode::Element(element) => {
+                        let id = document.create_element_ns(
+                            element.namespace,
+                            &element.tag_name,
+                            element.attributes.clone(),
+                        );
+                        convert(document, id, &element.children);
+                        if let (Some(nodes), Some(contents)) =
+                            (&element.template_contents, document.template_contents(id))
+                        {
+                            convert(document, contents, nodes);
+                        }
+                        id
+                    }
+                    HtmlNode::Text { text, .. } => document.create_text(text),
+                    HtmlNode::Comment { text, .. } => document.create_comment(text),
+                    HtmlNode::Doctype {
+                        name,
+                        public_id,
+                        system_id,
+                        ..
+                    } => document.create_doctype(
+                        name,
+                        public_id.as_deref().unwrap_or_default(),
+                        system_id.as_deref().unwrap_or_default(),
+                    ),
+                };
+                document
+                    .append_child(parent, id)
+                    .expect("le parseur ne construit que des arbres valides");
+            }
+        }
+
+        let mut document = Document::new();
+        let root = document.root();
+        convert(&mut document, root, &html.root_nodes);
+        document
+    }
+}
+
+/// Itérateur sur les enfants d'un nœud.
+pub struct Children<'a> {
+    document: &'a Document,
+    next: Option<NodeId>,
+}
+
+impl Iterator for Children<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let current = self.next?;
+        self.next = self.document.next_sibling(current);
+        Some(current)
+    }
+}
+
+/// Itérateur sur un nœud et ses descendants, dans l'ordre de l'arbre.
+pub struct Descendants<'a> {
+    document: &'a Document,
+    root: NodeId,
+    next: Option<NodeId>,
+}
+
+impl Iterator for Descendants<'_> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        let current = self.next?;
+        self.next = self.document.first_child(current).or_else(|| {
+            // Remonte jusqu'à un nœud qui a un voisin, sans sortir du sous-arbre
+            let mut node = current;
+            loop {
+                if node == self.root {
+                    return None;
+                }
+                if let Some(next) = self.document.next_sibling(node) {
+                    return Some(next);
+                }
+                node = self.document.parent(node)?;
+            }
+        });
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag_names(document: &Document, parent: NodeId) -> Vec<String> {
+        document
+            .children(parent)
+            .map(|id| match document.data(id) {
+                NodeData::Element(element) => element.tag_name.clone(),
+                NodeData::Text(text) => format!("#{}", text),
+                _ => "?".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_append_and_insert_before() {
+        let mut document = Document::new();
+        let div = document.create_element("div", Vec::new());
+        let a = document.create_element("a", Vec::new());
+        let b = document.create_element("b", Vec::new());
+        let c = document.create_element("c", Vec::new());
+        document.append_child(document.root(), div).unwrap();
+        document.append_child(div, a).unwrap();
+        document.append_child(div, c).unwrap();
+        document.insert_before(div, b, Some(c)).unwrap();
+
+        assert_eq!(tag_names(&document, div), ["a", "b", "c"]);
+        assert_eq!(document.parent(b), Some(div));
+        assert_eq!(document.previous_sibling(b), Some(a));
+        assert_eq!(document.next_sibling(b), Some(c));
+        assert_eq!(document.first_child(div), Some(a));
+        assert_eq!(document.last_child(div), Some(c));
+        assert_eq!(document.document_element(), Some(div));
+
+        // Ajouter un nœud déjà présent le déplace
+        document.append_child(div, a).unwrap();
+        assert_eq!(tag_names(&document, div), ["b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_remove_and_replace() {
+        let mut document = Document::new();
+        let div = document.create_element("div", Vec::new());
+        let a = document.create_element("a", Vec::new());
+        let b = document.create_element("b", Vec::new());
+        let text = document.create_text("x");
+        document.append_child(div, a).unwrap();
+        document.append_child(div, b).unwrap();
+
+        document.replace_with(a, text).unwrap();
+        assert_eq!(tag_names(&document, div), ["#x", "b"]);
+        assert_eq!(document.parent(a), None);
+
+        assert_eq!(document.remove_child(div, b), Ok(b));
+        assert_eq!(tag_names(&document, div), ["#x"]);
+        assert_eq!(document.remove_child(div, b), Err(DomError::NotFound));
+    }
+
+    #[test]
+    fn test_replace_document_element() {
+        let mut document = Document::new();
+        let root = document.root();
+        let html = document.create_element("html", Vec::new());
+        let svg = document.create_element("svg", Vec::new());
+        document.append_child(root, html).unwrap();
+
+        document.replace_with(html, svg).unwrap();
+        assert_eq!(document.document_element(), Some(svg));
+        assert_eq!(document.parent(html), None);
+
+        // Un fragment ne peut apporter qu'un seul élément racine
+        let fragment = document.create_document_fragment();
+        let a = document.create_element("a", Vec::new());
+        let b = document.create_element("b", Vec::new());
+        document.append_child(fragment, a).unwrap();
+        document.append_child(fragment, b).unwrap();
+        assert_eq!(
+            document.replace_with(svg, fragment),
+            Err(DomError::HierarchyRequest)
+        );
+        assert_eq!(tag_names(&document, root), ["svg"]);
+    }
+
+    #[test]
+    fn test_invalid_insertions() {
+        let mut document = Document::new();
+        let outer = document.create_element("div", Vec::new());
+        let inner = document.create_element("span", Vec::new());
+        let text = document.create_text("x");
+        let other = document.create_element("p", Vec::new());
+        document.append_child(outer, inner).unwrap();
+
+        assert_eq!(
+            document.append_child(inner, outer),
+            Err(DomError::HierarchyRequest)
+        );
+        assert_eq!(
+            document.append_child(inner, inner),
+            Err(DomError::HierarchyRequest)
+        );
+        assert_eq!(
+            document.append_child(text, other),
+            Err(DomError::HierarchyRequest)
+        );
+        assert_eq!(
+            document.insert_before(outer, other, Some(text)),
+            Err(DomError::NotFound)
+        );
+        let root = document.root();
+        assert_eq!(
+            document.append_child(root, text),
+            Err(DomError::HierarchyRequest)
+        );
+        document.append_child(root, outer).unwrap();
+        assert_eq!(
+            document.append_child(root, other),
+            Err(DomError::HierarchyRequest)
+        );
+    }
+
+    #[test]
+    fn test_clone_node() {
+        let mut document = Document::new();
+        let div = document.create_element("div", vec![("id".to_string(), "a".to_string())]);
+        let text = document.create_text("hello");
+        document.append_child(div, text).unwrap();
+
+        let shallow = document.clone_node(div, false);
+        assert_eq!(document.first_child(shallow), None);
+        assert_eq!(
+            document.element(shallow).unwrap().get_attribute("id"),
+            Some("a")
+        );
+
+        let deep = document.clone_node(div, true);
+        assert_eq!(document.text_content(deep), "hello");
+        assert_ne!(document.first_child(deep), Some(text));
+        assert_eq!(document.parent(deep), None);
+    }
+
+    #[test]
+    fn test_document_fragment() {
+        let mut document = Document::new();
+        let div = document.create_element("div", Vec::new());
+        let a = document.create_element("a", Vec::new());
+        document.append_child(div, a).unwrap();
+        let fragment = document.create_document_fragment();
+        let b = document.create_element("b", Vec::new());
+        let c = document.create_element("c", Vec::new());
+        document.append_child(fragment, b).unwrap();
+        document.append_child(fragment, c).unwrap();
+
+        document.insert_before(div, fragment, Some(a)).unwrap();
+        assert_eq!(tag_names(&document, div), ["b", "c", "a"]);
+        assert_eq!(document.first_child(fragment), None);
+
+        // Un enfant invalide et rien n'est déplacé
+        let d = document.create_element("d", Vec::new());
+        let text = document.create_text("x");
+        document.append_child(fragment, d).unwrap();
+        document.append_child(fragment, text).unwrap();
+        let root = document.root();
+        assert_eq!(
+            document.append_child(root, fragment),
+            Err(DomError::HierarchyRequest)
+        );
+        assert_eq!(tag_names(&document, fragment), ["d", "#x"]);
+        assert_eq!(document.first_child(root), None);
+    }
+
+    #[test]
+    fn test_template_contents() {
+        let html = flux_parser::html::parse_html("<body><template><p>a</p></template>").unwrap();
+        let mut document = Document::from(&html);
+        let body = document
+            .last_child(document.document_element().unwrap())
+            .unwrap();
+        let template = document.first_child(body).unwrap();
+        assert_eq!(document.first_child(template), None);
+        assert_eq!(document.text_content(body), "");
+
+        let contents = document.template_contents(template).unwrap();
+        assert_eq!(tag_names(&document, contents), ["p"]);
+
+        let copy = document.clone_node(template, true);
+        let copy_contents = document.template_contents(copy).unwrap();
+        assert_ne!(copy_contents, contents);
+        assert_eq!(document.text_content(copy_contents), "a");
+        let shallow = document.clone_node(template, false);
+        assert_eq!(
+            document.first_child(document.template_contents(shallow).unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_from_html_document() {
+        let html =
+            flux_parser::html::parse_html("<!DOCTYPE html><p>a<b>b</b></p><!--c-->").unwrap();
+        let document = Document::from(&html);
+        let root = document.root();
+        assert!(matches!(
+            document.data(document.first_child(root).unwrap()),
+            NodeData::Doctype { .. }
+        ));
+
+        let html_element = document.document_element().unwrap();
+        assert_eq!(tag_names(&document, html_element), ["head", "body"]);
+        let body = document.last_child(html_element).unwrap();
+        let p = document.first_child(body).unwrap();
+        assert_eq!(tag_names(&document, p), ["#a", "b"]);
+        assert_eq!(document.text_content(html_element), "ab");
+        assert_eq!(document.descendants(body).count(), 6);
+    }
+
+    #[test]
+    fn test_from_html_document_keeps_namespaces() {
+        let html =
+            flux_parser::html::parse_html("<svg><foreignObject><template></template>").unwrap();
+        let document = Document::from(&html);
+        let svg = document
+            .descendants(document.root())
+            .find(|&id| document.element(id).is_some_and(|e| e.tag_name == "svg"))
+            .unwrap();
+        assert_eq!(document.element(svg).unwrap().namespace, Namespace::Svg);
+        let foreign_object = document.first_child(svg).unwrap();
+        let element = document.element(foreign_object).unwrap();
+        assert_eq!(element.tag_name, "foreignObject");
+        assert_eq!(element.namespace, Namespace::Svg);
+        let template = document.first_child(foreign_object).unwrap();
+        assert_eq!(
+            document.element(template).unwrap().namespace,
+            Namespace::Html
+        );
+        assert!(document.template_contents(template).is_some());
+    }
+}
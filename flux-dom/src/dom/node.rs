@@ -0,0 +1,71 @@
+//! Nœuds de l'arbre DOM.
+
+use flux_parser::html::Namespace;
+
+/// Identifiant d'un nœud d'un [`Document`](super::Document).
+///
+/// Un identifiant reste valide aussi longtemps que le document, y compris pour
+/// un nœud retiré de l'arbre, qui peut ainsi être conservé puis réinséré.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub(crate) usize);
+
+/// Type d'un nœud et ses données propres.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeData {
+    Document,
+    /// Fragment de document hors de l'arbre, comme le contenu d'un
+    /// `<template>`.
+    DocumentFragment,
+    Doctype {
+        name: String,
+        public_id: String,
+        system_id: String,
+    },
+    Element(ElementData),
+    Text(String),
+    Comment(String),
+}
+
+/// Données d'un élément.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementData {
+    pub namespace: Namespace,
+    pub tag_name: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl ElementData {
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Nœud stocké dans l'arène, avec ses liens vers les nœuds voisins.
+#[derive(Debug, Clone)]
+pub(crate) struct Node {
+    pub(crate) data: NodeData,
+    pub(crate) parent: Option<NodeId>,
+    pub(crate) first_child: Option<NodeId>,
+    pub(crate) last_child: Option<NodeId>,
+    pub(crate) previous_sibling: Option<NodeId>,
+    pub(crate) next_sibling: Option<NodeId>,
+    /// Fragment qui contient le contenu d'un élément `<template>`.
+    pub(crate) template_contents: Option<NodeId>,
+}
+
+impl Node {
+    pub(crate) fn new(data: NodeData) -> Self {
+        Node {
+            data,
+            parent: None,
+            first_child: None,
+            last_child: None,
+            previous_sibling: None,
+            next_sibling: None,
+            template_contents: None,
+        }
+    }
+}
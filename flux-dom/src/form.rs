@@ -0,0 +1,630 @@
+//! Formulaires HTML.
+//!
+//! Un [`Form`] garde l'état des contrôles d'un `<form>` : le texte saisi dans
+//! chaque champ, les cases cochées, les options choisies. À l'envoi, il en
+//! tire la liste des données du formulaire (HTML §4.10.21.4), l'encode selon
+//! son `enctype` et construit la [`Request`] qui part vers son `action`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flux_network::{parse_url, resolve_url, Method, Request};
+use flux_parser::html::parser::{HtmlElement, HtmlNode};
+use flux_parser::html::Namespace;
+
+/// Types d'`<input>` reconnus. Les autres se comportent comme `text`.
+const INPUT_TYPES: &[&str] = &[
+    "hidden", "text", "search", "tel", "url", "email", "password", "date", "month", "week",
+    "time", "datetime-local", "number", "range", "color", "checkbox", "radio", "file",
+    "submit", "image", "reset", "button",
+];
+
+const URLENCODED: &str = "application/x-www-form-urlencoded";
+const MULTIPART: &str = "multipart/form-data";
+const TEXT_PLAIN: &str = "text/plain";
+
+/// Sorte de contrôle de formulaire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlKind {
+    /// `<input>`, avec son type en minuscules (`text` si l'attribut est
+    /// absent ou inconnu).
+    Input(String),
+    /// `<button>`, avec son type (`submit` par défaut).
+    Button(String),
+    Select { multiple: bool },
+    Textarea,
+}
+
+/// Une option d'un `<select>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SelectOption {
+    /// Le texte affiché dans la liste.
+    pub label: String,
+    pub value: String,
+    pub selected: bool,
+    pub disabled: bool,
+}
+
+/// Un contrôle de formulaire et son état courant.
+///
+/// Les champs de fichier ne sont pas encore envoyés.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Control {
+    pub kind: ControlKind,
+    pub name: String,
+    /// Le texte saisi, ou l'attribut `value` d'une case à cocher ou d'un
+    /// bouton (`on` par défaut pour une case).
+    pub value: String,
+    pub checked: bool,
+    /// Un contrôle désactivé, ou dans un `<fieldset disabled>`, n'est pas
+    /// envoyé.
+    pub disabled: bool,
+    pub options: Vec<SelectOption>,
+    /// Les attributs `formaction`, `formmethod` et `formenctype` d'un bouton,
+    /// qui l'emportent sur ceux du formulaire quand c'est lui qui l'envoie.
+    pub form_action: Option<String>,
+    pub form_method: Option<String>,
+    pub form_enctype: Option<String>,
+}
+
+impl Control {
+    /// Crée le contrôle d'un élément `<input>`, `<button>`, `<select>` ou
+    /// `<textarea>`, dans son état initial. `None` pour les autres éléments.
+    pub fn from_element(element: &HtmlElement) -> Option<Control> {
+        if element.namespace != Namespace::Html {
+            return None;
+        }
+        let type_ = element.get_attribute("type").unwrap_or("").to_ascii_lowercase();
+        let kind = match element.tag_name.as_str() {
+            "input" if INPUT_TYPES.contains(&type_.as_str()) => ControlKind::Input(type_),
+            "input" => ControlKind::Input("text".to_string()),
+            "button" if matches!(type_.as_str(), "reset" | "button") => ControlKind::Button(type_),
+            "button" => ControlKind::Button("submit".to_string()),
+            "select" => ControlKind::Select {
+                multiple: element.has_attribute("multiple"),
+            },
+            "textarea" => ControlKind::Textarea,
+            _ => return None,
+        };
+
+        let attribute = |name| element.get_attribute(name).map(str::to_string);
+        let value = match &kind {
+            ControlKind::Textarea => element.text_content(),
+            ControlKind::Input(type_) if type_ == "checkbox" || type_ == "radio" => {
+                attribute("value").unwrap_or_else(|| "on".to_string())
+            }
+            _ => attribute("value").unwrap_or_default(),
+        };
+        let mut options = Vec::new();
+        if let ControlKind::Select { multiple } = kind {
+            collect_options(&element.children, false, &mut options);
+            if !multiple {
+                reset_selectedness(&mut options);
+            }
+        }
+
+        Some(Control {
+            kind,
+            name: attribute("name").unwrap_or_default(),
+            value,
+            checked: element.has_attribute("checked"),
+            disabled: element.has_attribute("disabled"),
+            options,
+            form_action: attribute("formaction"),
+            form_method: attribute("formmethod"),
+            form_enctype: attribute("formenctype"),
+        })
+    }
+
+    /// Vrai pour les boutons qui envoient le formulaire.
+    pub fn is_submit_button(&self) -> bool {
+        match &self.kind {
+            ControlKind::Button(type_) => type_ == "submit",
+            ControlKind::Input(type_) => type_ == "submit" || type_ == "image",
+            _ => false,
+        }
+    }
+
+    /// Vrai pour les boutons radio.
+    pub fn is_radio(&self) -> bool {
+        self.kind == ControlKind::Input("radio".to_string())
+    }
+
+    /// Choisit l'option `index` d'un `<select>`. Dans une liste à choix
+    /// unique, les autres options sont désélectionnées.
+    pub fn select_option(&mut self, index: usize) {
+        let multiple = matches!(self.kind, ControlKind::Select { multiple: true });
+        for (i, option) in self.options.iter_mut().enumerate() {
+            if i == index {
+                option.selected = true;
+            } else if !multiple {
+                option.selected = false;
+            }
+        }
+    }
+}
+
+/// Ajoute les options d'un `<select>`, y compris celles de ses `<optgroup>`.
+fn collect_options(nodes: &[HtmlNode], group_disabled: bool, options: &mut Vec<SelectOption>) {
+    for node in nodes {
+        let HtmlNode::Element(element) = node else {
+            continue;
+        };
+        match element.tag_name.as_str() {
+            "option" => {
+                let text = collapse_whitespace(&element.text_content());
+                options.push(SelectOption {
+                    label: element.get_attribute("label").map_or_else(|| text.clone(), str::to_string),
+                    value: element.get_attribute("value").map_or(text, str::to_string),
+                    selected: element.has_attribute("selected"),
+                    disabled: group_disabled || element.has_attribute("disabled"),
+                });
+            }
+            "optgroup" if !group_disabled => {
+                collect_options(&element.children, element.has_attribute("disabled"), options)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Une liste à choix unique a toujours une option choisie : la dernière
+/// marquée `selected`, ou à défaut la première qui n'est pas désactivée.
+fn reset_selectedness(options: &mut [SelectOption]) {
+    match options.iter().rposition(|option| option.selected) {
+        Some(last) => {
+            for option in &mut options[..last] {
+                option.selected = false;
+            }
+        }
+        None => {
+            if let Some(option) = options.iter_mut().find(|option| !option.disabled) {
+                option.selected = true;
+            }
+        }
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Un formulaire et l'état de ses contrôles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Form {
+    /// L'attribut `action`. Vide, le formulaire est envoyé à l'adresse du
+    /// document.
+    pub action: String,
+    /// `get`, `post` ou `dialog`.
+    pub method: &'static str,
+    /// `application/x-www-form-urlencoded`, `multipart/form-data` ou
+    /// `text/plain`.
+    pub enctype: &'static str,
+    /// Les contrôles, dans l'ordre du document.
+    pub controls: Vec<Control>,
+}
+
+/// La requête qui envoie un formulaire.
+pub struct FormSubmission {
+    /// L'adresse de la page demandée, avec les données du formulaire pour un
+    /// envoi en GET.
+    pub url: String,
+    pub request: Request,
+}
+
+impl Form {
+    /// Crée un formulaire sans contrôles, d'après les attributs de `<form>`.
+    pub fn new(form: &HtmlElement) -> Form {
+        Form {
+            action: form.get_attribute("action").unwrap_or("").to_string(),
+            method: method_keyword(form.get_attribute("method")),
+            enctype: enctype_keyword(form.get_attribute("enctype")),
+            controls: Vec::new(),
+        }
+    }
+
+    /// Crée un formulaire avec les contrôles qui descendent de `<form>`.
+    pub fn from_element(form: &HtmlElement) -> Form {
+        let mut result = Form::new(form);
+        result.collect_controls(&form.children, false);
+        result
+    }
+
+    fn collect_controls(&mut self, nodes: &[HtmlNode], disabled: bool) {
+        for node in nodes {
+            let HtmlNode::Element(element) = node else {
+                continue;
+            };
+            if let Some(mut control) = Control::from_element(element) {
+                control.disabled |= disabled;
+                self.add_control(control);
+            } else {
+                let disabled = disabled || fieldset_disables(element);
+                self.collect_controls(&element.children, disabled);
+            }
+        }
+    }
+
+    /// Ajoute un contrôle à la fin du formulaire et renvoie son indice. Un
+    /// bouton radio coché décoche ceux de son groupe déjà ajoutés : quand
+    /// plusieurs sont marqués `checked`, le dernier l'emporte.
+    pub fn add_control(&mut self, control: Control) -> usize {
+        let checked_radio = control.checked && control.is_radio();
+        self.controls.push(control);
+        let index = self.controls.len() - 1;
+        if checked_radio {
+            self.check_radio(index);
+        }
+        index
+    }
+
+    /// Coche le bouton radio `index` et décoche les autres boutons du même
+    /// groupe, c'est-à-dire du formulaire et du même nom. Un bouton sans nom
+    /// forme un groupe à lui seul.
+    pub fn check_radio(&mut self, index: usize) {
+        let name = self.controls[index].name.clone();
+        for (i, control) in self.controls.iter_mut().enumerate() {
+            if i == index {
+                control.checked = true;
+            } else if !name.is_empty() && control.is_radio() && control.name == name {
+                control.checked = false;
+            }
+        }
+    }
+
+    /// Le bouton par défaut, qui envoie le formulaire quand on appuie sur
+    /// Entrée dans un champ : le premier bouton d'envoi.
+    pub fn default_button(&self) -> Option<usize> {
+        self.controls.iter().position(Control::is_submit_button)
+    }
+
+    /// Construit la liste des données envoyées, en paires nom-valeur dans
+    /// l'ordre du document. `submitter` est l'indice du bouton qui envoie le
+    /// formulaire : c'est le seul bouton dont la valeur fait partie des
+    /// données.
+    pub fn entries(&self, submitter: Option<usize>) -> Vec<(String, String)> {
+        let mut entries = Vec::new();
+        for (index, control) in self.controls.iter().enumerate() {
+            if control.disabled {
+                continue;
+            }
+            let name = &control.name;
+            let is_submitter = submitter == Some(index) && control.is_submit_button();
+            match &control.kind {
+                ControlKind::Input(type_) if type_ == "image" => {
+                    if is_submitter {
+                        // Envoyé au clavier : pas de coordonnées de clic
+                        let prefix = if name.is_empty() { String::new() } else { format!("{}.", name) };
+                        entries.push((format!("{}x", prefix), "0".to_string()));
+                        entries.push((format!("{}y", prefix), "0".to_string()));
+                    }
+                }
+                _ if name.is_empty() => {}
+                ControlKind::Button(_) => {
+                    if is_submitter {
+                        entries.push((name.clone(), control.value.clone()));
+                    }
+                }
+                ControlKind::Input(type_) => match type_.as_str() {
+                    "submit" if !is_submitter => {}
+                    "reset" | "button" | "file" => {}
+                    "checkbox" | "radio" if !control.checked => {}
+                    "hidden" if name.eq_ignore_ascii_case("_charset_") => {
+                        entries.push((name.clone(), "UTF-8".to_string()));
+                    }
+                    _ => entries.push((name.clone(), control.value.clone())),
+                },
+                ControlKind::Select { .. } => {
+                    for option in &control.options {
+                        if option.selected && !option.disabled {
+                            entries.push((name.clone(), option.value.clone()));
+                        }
+                    }
+                }
+                ControlKind::Textarea => entries.push((name.clone(), control.value.clone())),
+            }
+        }
+        entries
+    }
+
+    /// Construit la requête qui envoie le formulaire, en résolvant son
+    /// `action` par rapport à `document_url`.
+    ///
+    /// `None` si l'envoi ne mène à aucune page : méthode `dialog`, ou adresse
+    /// invalide ou d'un schéma qui n'est pas pris en charge (`mailto:`...).
+    pub fn submission(&self, submitter: Option<usize>, document_url: &str) -> Option<FormSubmission> {
+        let button = submitter.and_then(|index| self.controls.get(index));
+        let button = button.filter(|control| control.is_submit_button());
+        let action = button.and_then(|button| button.form_action.as_deref()).unwrap_or(&self.action);
+        let method = match button.and_then(|button| button.form_method.as_deref()) {
+            Some(method) => method_keyword(Some(method)),
+            None => self.method,
+        };
+        let enctype = match button.and_then(|button| button.form_enctype.as_deref()) {
+            Some(enctype) => enctype_keyword(Some(enctype)),
+            None => self.enctype,
+        };
+
+        let url = if action.is_empty() {
+            document_url.to_string()
+        } else {
+            resolve_url(document_url, action).ok()?
+        };
+        let mut parsed_url = parse_url(&url).ok()?;
+        let entries = self.entries(submitter);
+
+        match method {
+            "dialog" => None,
+            "post" => {
+                let mut request = Request::new(Method::POST, &parsed_url.request_target());
+                match enctype {
+                    MULTIPART => {
+                        let boundary = boundary_for(&entries);
+                        request.add_header("Content-Type", &format!("{}; boundary={}", MULTIPART, boundary));
+                        request.set_body(&multipart(&entries, &boundary));
+                    }
+                    TEXT_PLAIN => {
+                        request.add_header("Content-Type", "text/plain;charset=UTF-8");
+                        request.set_body(text_plain(&entries).as_bytes());
+                    }
+                    _ => {
+                        request.add_header("Content-Type", URLENCODED);
+                        request.set_body(urlencode(&entries).as_bytes());
+                    }
+                }
+                Some(FormSubmission { url, request })
+            }
+            _ => {
+                // Les données remplacent la requête de l'adresse
+                parsed_url.query = urlencode(&entries);
+                let request = Request::new(Method::GET, &parsed_url.request_target());
+                Some(FormSubmission {
+                    url: parsed_url.to_string(),
+                    request,
+                })
+            }
+        }
+    }
+}
+
+/// Vrai pour un `<fieldset disabled>`, qui désactive les contrôles qu'il
+/// contient.
+pub(crate) fn fieldset_disables(element: &HtmlElement) -> bool {
+    element.namespace == Namespace::Html && element.tag_name == "fieldset" && element.has_attribute("disabled")
+}
+
+fn method_keyword(method: Option<&str>) -> &'static str {
+    match method.map(str::to_ascii_lowercase).as_deref() {
+        Some("post") => "post",
+        Some("dialog") => "dialog",
+        _ => "get",
+    }
+}
+
+fn enctype_keyword(enctype: Option<&str>) -> &'static str {
+    match enctype.map(str::to_ascii_lowercase).as_deref() {
+        Some(MULTIPART) => MULTIPART,
+        Some(TEXT_PLAIN) => TEXT_PLAIN,
+        _ => URLENCODED,
+    }
+}
+
+/// Remplace chaque fin de ligne (CR, LF ou CR LF) par CR LF.
+fn normalize_newlines(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n").replace('\n', "\r\n")
+}
+
+/// Encode les données en `application/x-www-form-urlencoded` :
+/// `q=caf%C3%A9+cr%C3%A8me&page=2`.
+pub fn urlencode(entries: &[(String, String)]) -> String {
+    let encode = |text: &str| {
+        let mut encoded = String::new();
+        for byte in normalize_newlines(text).bytes() {
+            match byte {
+                b' ' => encoded.push('+'),
+                b'*' | b'-' | b'.' | b'_' => encoded.push(byte as char),
+                _ if byte.is_ascii_alphanumeric() => encoded.push(byte as char),
+                _ => encoded.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        encoded
+    };
+    entries
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Encode les données en `multipart/form-data`, chaque donnée dans une
+/// partie délimitée par `boundary`.
+pub fn multipart(entries: &[(String, String)], boundary: &str) -> Vec<u8> {
+    let mut body = String::new();
+    for (name, value) in entries {
+        let name = normalize_newlines(name)
+            .replace('"', "%22")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A");
+        body.push_str(&format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            boundary,
+            name,
+            normalize_newlines(value)
+        ));
+    }
+    body.push_str(&format!("--{}--\r\n", boundary));
+    body.into_bytes()
+}
+
+/// Encode les données en `text/plain`, une paire `nom=valeur` par ligne.
+pub fn text_plain(entries: &[(String, String)]) -> String {
+    entries
+        .iter()
+        .map(|(name, value)| format!("{}={}\r\n", normalize_newlines(name), normalize_newlines(value)))
+        .collect()
+}
+
+/// Choisit une frontière de `multipart/form-data` qui n'apparaît dans aucune
+/// des données.
+fn boundary_for(entries: &[(String, String)]) -> String {
+    let mut seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64);
+    loop {
+        let boundary = format!("----FluxaFormBoundary{:016x}", seed);
+        if !entries.iter().any(|(name, value)| name.contains(&boundary) || value.contains(&boundary)) {
+            return boundary;
+        }
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flux_parser::html::parse_html;
+
+    /// Analyse `html` et renvoie le formulaire de son premier `<form>`.
+    fn form_of(html: &str) -> Form {
+        fn find_form(nodes: &[HtmlNode]) -> Option<&HtmlElement> {
+            nodes.iter().find_map(|node| match node {
+                HtmlNode::Element(element) if element.tag_name == "form" => Some(element),
+                HtmlNode::Element(element) => find_form(&element.children),
+                _ => None,
+            })
+        }
+        let document = parse_html(html).unwrap();
+        Form::from_element(find_form(&document.root_nodes).expect("Pas de <form>"))
+    }
+
+    fn pairs(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+        entries.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_entries() {
+        let form = form_of(
+            "<form>\
+             <input name=q value='café crème'>\
+             <input type=hidden name=lang value=fr>\
+             <input type=checkbox name=a checked><input type=checkbox name=b value=x>\
+             <input type=radio name=r value=1><input type=radio name=r value=2 checked>\
+             <select name=s><option>Un<option value=2 selected>Deux</select>\
+             <select name=m multiple><option selected>x<option selected disabled>y<option selected>z</select>\
+             <textarea name=t>ligne 1\nligne 2</textarea>\
+             <input name=off disabled value=1><fieldset disabled><input name=off2></fieldset>\
+             <input value=sans-nom>\
+             <input type=submit name=go value=Envoyer><button name=b2 value=v>Autre</button>\
+             </form>",
+        );
+        assert_eq!(
+            form.entries(None),
+            pairs(&[
+                ("q", "café crème"),
+                ("lang", "fr"),
+                ("a", "on"),
+                ("r", "2"),
+                ("s", "2"),
+                ("m", "x"),
+                ("m", "z"),
+                ("t", "ligne 1\nligne 2"),
+            ])
+        );
+
+        // Seul le bouton qui envoie le formulaire fait partie des données
+        let submitter = form.controls.iter().position(|control| control.name == "b2");
+        assert_eq!(form.entries(submitter).last(), Some(&("b2".to_string(), "v".to_string())));
+        assert_eq!(form.default_button(), form.controls.iter().position(|control| control.name == "go"));
+    }
+
+    #[test]
+    fn test_select_and_radio_state() {
+        let mut form = form_of(
+            "<form><select name=s><option disabled>a<option>b<option>c</select>\
+             <input type=radio name=r value=1 checked><input type=radio name=r value=2></form>",
+        );
+        // Sans option marquée, la première qui n'est pas désactivée est choisie
+        assert_eq!(form.entries(None), pairs(&[("s", "b"), ("r", "1")]));
+        form.controls[0].select_option(2);
+        form.check_radio(2);
+        assert_eq!(form.entries(None), pairs(&[("s", "c"), ("r", "2")]));
+    }
+
+    #[test]
+    fn test_radio_groups() {
+        // Le dernier bouton coché d'un groupe l'emporte
+        let form = form_of(
+            "<form><input type=radio name=r value=1 checked><input type=radio name=r value=2 checked>\
+             <input type=radio name=o value=3 checked></form>",
+        );
+        assert_eq!(form.entries(None), pairs(&[("r", "2"), ("o", "3")]));
+
+        // Les boutons sans nom ne forment pas un groupe
+        let mut form = form_of("<form><input type=radio checked><input type=radio checked></form>");
+        assert!(form.controls.iter().all(|control| control.checked));
+        form.controls[0].checked = false;
+        form.check_radio(0);
+        assert!(form.controls.iter().all(|control| control.checked));
+    }
+
+    #[test]
+    fn test_encodings() {
+        let entries = pairs(&[("q", "a b&c=é"), ("t", "1\n2"), ("x\"y", "*-._~")]);
+        assert_eq!(urlencode(&entries), "q=a+b%26c%3D%C3%A9&t=1%0D%0A2&x%22y=*-._%7E");
+        assert_eq!(text_plain(&entries), "q=a b&c=é\r\nt=1\r\n2\r\nx\"y=*-._~\r\n");
+        assert_eq!(
+            String::from_utf8(multipart(&entries[1..], "XyZ")).unwrap(),
+            "--XyZ\r\nContent-Disposition: form-data; name=\"t\"\r\n\r\n1\r\n2\r\n\
+             --XyZ\r\nContent-Disposition: form-data; name=\"x%22y\"\r\n\r\n*-._~\r\n\
+             --XyZ--\r\n"
+        );
+    }
+
+    #[test]
+    fn test_get_submission() {
+        let form = form_of("<form action='../search?old=1#top'><input name=q value='a b'></form>");
+        let submission = form.submission(None, "http://example.com/docs/page.html").unwrap();
+        assert_eq!(submission.url, "http://example.com/search?q=a+b#top");
+        assert_eq!(submission.request.method, Method::GET);
+        assert_eq!(submission.request.path, "/search?q=a+b");
+        assert!(submission.request.body.is_empty());
+
+        // Sans action, le formulaire est envoyé à la page elle-même
+        let form = form_of("<form><input name=q value=x></form>");
+        let submission = form.submission(None, "http://example.com/find?q=old").unwrap();
+        assert_eq!(submission.url, "http://example.com/find?q=x");
+    }
+
+    #[test]
+    fn test_post_submission() {
+        let form = form_of(
+            "<form method=POST action=/send><input name=a value=1>\
+             <button name=go value=ok formenctype=multipart/form-data>Envoyer</button></form>",
+        );
+        let submission = form.submission(None, "http://example.com/").unwrap();
+        assert_eq!(submission.url, "http://example.com/send");
+        assert_eq!(submission.request.method, Method::POST);
+        assert_eq!(submission.request.path, "/send");
+        assert_eq!(
+            submission.request.headers,
+            [("Content-Type".to_string(), URLENCODED.to_string())]
+        );
+        assert_eq!(submission.request.body, b"a=1");
+
+        // Le bouton impose son propre encodage
+        let submission = form.submission(form.default_button(), "http://example.com/").unwrap();
+        let content_type = &submission.request.headers[0].1;
+        let boundary = content_type.strip_prefix("multipart/form-data; boundary=").unwrap();
+        let body = String::from_utf8(submission.request.body).unwrap();
+        assert!(body.starts_with(&format!("--{}\r\n", boundary)));
+        assert!(body.contains("name=\"go\"\r\n\r\nok\r\n"));
+        assert!(body.ends_with(&format!("--{}--\r\n", boundary)));
+    }
+
+    #[test]
+    fn test_submission_without_navigation() {
+        let form = form_of("<form method=dialog><input name=a></form>");
+        assert!(form.submission(None, "http://example.com/").is_none());
+        let form = form_of("<form action='mailto:a@example.com'><input name=a></form>");
+        assert!(form.submission(None, "http://example.com/").is_none());
+    }
+}
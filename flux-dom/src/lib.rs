@@ -1,20 +1,67 @@
+pub mod dom;
+pub mod form;
 mod style;
 
-use flux_parser::html::parser::HtmlNode;
-use flux_parser::html::HtmlDocument;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use flux_parser::html::parser::{HtmlElement, HtmlNode};
+use flux_parser::html::{HtmlDocument, Namespace};
+use flux_parser::xml::{XmlDocument, XmlElement, XmlNode};
+use form::{Control, ControlKind, Form, FormSubmission};
 use gtk::prelude::*;
-use gtk::{Box, Button, Image, Label, Orientation};
+use gtk::{gdk, glib};
+use gtk::{Box, Button, CheckButton, DropDown, Entry, Expander, Image, Label, Orientation, Picture, TextView};
+use style::text::WhiteSpaceCollapseValue;
+
+/// Ce que la page affichée peut demander au navigateur.
+#[derive(Clone)]
+pub struct Navigation {
+    /// L'adresse de la page, par rapport à laquelle se résolvent les
+    /// `action` de ses formulaires.
+    pub url: String,
+    /// Appelée avec la requête d'un formulaire envoyé, pour charger la page
+    /// qu'elle demande.
+    pub on_submit: Rc<dyn Fn(FormSubmission)>,
+}
+
+/// Ce qui se transmet d'un élément à ses descendants pendant l'affichage.
+#[derive(Clone)]
+struct DrawContext {
+    white_space: WhiteSpaceCollapseValue,
+    /// Le formulaire dans lequel se trouve l'élément.
+    form: Option<Rc<FormState>>,
+    /// Vrai dans un `<fieldset disabled>`.
+    disabled: bool,
+    navigation: Navigation,
+}
+
+/// Un formulaire affiché, dont les widgets tiennent l'état à jour.
+#[derive(Default)]
+struct FormState {
+    form: RefCell<Form>,
+    /// Le premier bouton radio de chaque groupe, auquel les suivants se
+    /// joignent.
+    radios: RefCell<HashMap<String, CheckButton>>,
+}
 
-pub fn draw_dom(main_container: Box, dom: HtmlDocument) {
+pub fn draw_dom(main_container: Box, dom: HtmlDocument, navigation: Navigation) {
     while let Some(c) = main_container.last_child() {
         main_container.remove(&c);
     }
 
+    let context = DrawContext {
+        white_space: WhiteSpaceCollapseValue::Collapse,
+        form: None,
+        disabled: false,
+        navigation,
+    };
     dom.root_nodes.iter().for_each(|node| {
-        draw_node(node, main_container.clone());
+        draw_node(node, main_container.clone(), &context);
     });
 }
-fn draw_node(node: &HtmlNode, parent: Box) {
+fn draw_node(node: &HtmlNode, parent: Box, context: &DrawContext) {
     match node {
         HtmlNode::Element(element) => {
             if element.tag_name == "head"
@@ -23,27 +70,114 @@ fn draw_node(node: &HtmlNode, parent: Box) {
             {
                 return; // TODO: Gérer les éléments head
             }
-            if handle_special_element(&element.tag_name, parent.clone(), element) {
+            if element.tag_name == "template" {
+                return; // Le contenu d'un template est inerte
+            }
+            if handle_special_element(&element.tag_name, parent.clone(), element, context) {
                 return;
             }
 
             let container = Box::new(Orientation::Vertical, 0);
             parent.append(&container);
 
+            let mut context = context.clone();
+            context.white_space = white_space_of(&element.tag_name, context.white_space);
+            if element.tag_name == "form" {
+                let state = FormState {
+                    form: RefCell::new(Form::new(element)),
+                    ..FormState::default()
+                };
+                context.form = Some(Rc::new(state));
+            }
+            context.disabled |= form::fieldset_disables(element);
             element.children.iter().for_each(|child| {
-                draw_node(child, container.clone());
+                draw_node(child, container.clone(), &context);
             });
         }
-        HtmlNode::Text(text) => {
-            // Supprime tous les \n et \t du texte
-            let text = text.replace("\n", "").replace("\t", "");
+        HtmlNode::Text { text, .. } => {
+            let mut text = context.white_space.apply(text);
+            if context.white_space == WhiteSpaceCollapseValue::Collapse {
+                // Chaque texte a sa propre ligne : les espaces en début et
+                // en fin de ligne disparaissent
+                text = text.trim().to_string();
+            }
             if text.is_empty() {
                 return;
             }
             let label = Label::new(Some(&text));
             parent.append(&label);
         }
-        HtmlNode::Comment(_) => {}
+        HtmlNode::Comment { .. } | HtmlNode::Doctype { .. } => {}
+    }
+}
+
+/// Affiche un document XML (XHTML, SVG, flux RSS…). Faute de feuille de
+/// style, chaque élément devient une boîte verticale et chaque texte une
+/// étiquette.
+pub fn draw_xml(main_container: Box, document: XmlDocument) {
+    while let Some(c) = main_container.last_child() {
+        main_container.remove(&c);
+    }
+
+    if let Some(root) = document.document_element() {
+        draw_xml_element(root, main_container);
+    }
+}
+
+/// Affiche une image téléchargée, centrée dans la page, d'après ses octets.
+/// `url` sert de texte de remplacement.
+pub fn draw_image(main_container: Box, bytes: &[u8], url: &str) {
+    while let Some(c) = main_container.last_child() {
+        main_container.remove(&c);
+    }
+
+    match gdk::Texture::from_bytes(&glib::Bytes::from(bytes)) {
+        Ok(texture) => {
+            let picture = Picture::for_paintable(&texture);
+            picture.set_alternative_text(Some(url));
+            picture.set_can_shrink(true);
+            picture.set_halign(gtk::Align::Center);
+            picture.set_valign(gtk::Align::Center);
+            picture.set_vexpand(true);
+            main_container.append(&picture);
+        }
+        Err(e) => {
+            main_container.append(&Label::new(Some(&format!("Image illisible : {}", e))));
+        }
+    }
+}
+
+fn draw_xml_element(element: &XmlElement, parent: Box) {
+    if element.is_in(Namespace::Html)
+        && matches!(element.local_name(), "head" | "style" | "script" | "template")
+    {
+        return;
+    }
+
+    let container = Box::new(Orientation::Vertical, 0);
+    parent.append(&container);
+    for child in &element.children {
+        match child {
+            XmlNode::Element(child) => draw_xml_element(child, container.clone()),
+            XmlNode::Text { text, .. } => {
+                let text = WhiteSpaceCollapseValue::Collapse.apply(text);
+                let text = text.trim();
+                if !text.is_empty() {
+                    container.append(&Label::new(Some(text)));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Valeur de `white-space-collapse` pour le contenu d'un élément, d'après la
+/// feuille de style par défaut des navigateurs. Les autres éléments héritent
+/// de la valeur de leur parent.
+fn white_space_of(tag_name: &str, inherited: WhiteSpaceCollapseValue) -> WhiteSpaceCollapseValue {
+    match tag_name {
+        "pre" | "listing" | "xmp" | "plaintext" | "textarea" => WhiteSpaceCollapseValue::Preserve,
+        _ => inherited,
     }
 }
 
@@ -52,18 +186,11 @@ fn draw_node(node: &HtmlNode, parent: Box) {
 fn handle_special_element(
     tag_name: &str,
     parent: Box,
-    element: &flux_parser::html::parser::HtmlElement,
+    element: &HtmlElement,
+    context: &DrawContext,
 ) -> bool {
     match tag_name {
-        "button" => {
-            let button = Button::with_label(
-                element
-                    .get_attribute("value")
-                    .unwrap_or(&"Button".to_string()),
-            );
-            parent.append(&button);
-            true
-        }
+        "button" | "input" | "select" | "textarea" => draw_control(element, &parent, context),
         "img" => {
             if let Some(src) = element.get_attribute("src") {
                 let image = Image::from_file(src);
@@ -71,6 +198,170 @@ fn handle_special_element(
             }
             true
         }
+        "details" => {
+            // Le résumé sert d'étiquette, le reste du contenu se replie
+            let summary = element.child_element("summary");
+            let label = summary.map_or("Détails".to_string(), |summary| {
+                summary.text_content().trim().to_string()
+            });
+            let expander = Expander::new(Some(&label));
+            expander.set_expanded(element.get_attribute("open").is_some());
+            let content = Box::new(Orientation::Vertical, 0);
+            let context = DrawContext {
+                white_space: WhiteSpaceCollapseValue::Collapse,
+                ..context.clone()
+            };
+            element
+                .children
+                .iter()
+                .filter(|child| !matches!(child, HtmlNode::Element(e) if e.tag_name == "summary"))
+                .for_each(|child| {
+                    draw_node(child, content.clone(), &context);
+                });
+            expander.set_child(Some(&content));
+            parent.append(&expander);
+            true
+        }
         _ => false, // Ce n'est pas un type spécial
     }
 }
+
+/// Affiche un contrôle de formulaire et l'inscrit dans son formulaire, dont
+/// les signaux du widget tiennent l'état à jour. Un contrôle hors de tout
+/// `<form>` s'affiche sans rien envoyer.
+fn draw_control(element: &HtmlElement, parent: &Box, context: &DrawContext) -> bool {
+    let Some(mut control) = Control::from_element(element) else {
+        return false;
+    };
+    control.disabled |= context.disabled;
+    let in_form = context.form.is_some();
+    let state = context.form.clone().unwrap_or_default();
+    let index = state.form.borrow_mut().add_control(control.clone());
+    let navigation = context.navigation.clone();
+
+    let widget: gtk::Widget = match &control.kind {
+        ControlKind::Input(type_) => match type_.as_str() {
+            "hidden" => return true,
+            "checkbox" | "radio" => {
+                let check = CheckButton::new();
+                let is_radio = type_ == "radio";
+                // Un bouton sans nom forme un groupe à lui seul
+                if is_radio && in_form && !control.name.is_empty() {
+                    let mut radios = state.radios.borrow_mut();
+                    match radios.get(&control.name) {
+                        Some(first) => check.set_group(Some(first)),
+                        None => {
+                            radios.insert(control.name.clone(), check.clone());
+                        }
+                    }
+                }
+                // Après le groupe, pour que le dernier bouton coché l'emporte
+                check.set_active(control.checked);
+                let state = state.clone();
+                check.connect_toggled(move |check| {
+                    let mut form = state.form.borrow_mut();
+                    if !is_radio {
+                        form.controls[index].checked = check.is_active();
+                    } else if check.is_active() {
+                        form.check_radio(index);
+                    }
+                });
+                check.upcast()
+            }
+            "submit" | "image" | "reset" | "button" | "file" => {
+                let label = match type_.as_str() {
+                    "submit" => element.get_attribute("value").unwrap_or("Envoyer"),
+                    "image" => element.get_attribute("alt").unwrap_or("Envoyer"),
+                    "reset" => element.get_attribute("value").unwrap_or("Réinitialiser"),
+                    "file" => "Parcourir…",
+                    _ => element.get_attribute("value").unwrap_or(""),
+                };
+                let button = Button::with_label(label);
+                if control.is_submit_button() && in_form {
+                    button.connect_clicked(move |_| submit(&state.form, Some(index), &navigation));
+                }
+                // Les fichiers ne sont pas encore envoyés
+                button.set_sensitive(type_ != "file");
+                button.upcast()
+            }
+            _ => {
+                let entry = Entry::new();
+                entry.set_text(&control.value);
+                entry.set_visibility(type_ != "password");
+                entry.set_placeholder_text(element.get_attribute("placeholder"));
+                let changed_state = state.clone();
+                entry.connect_changed(move |entry| {
+                    changed_state.form.borrow_mut().controls[index].value = entry.text().to_string();
+                });
+                if in_form {
+                    // Entrée envoie le formulaire, comme un clic sur son bouton par défaut
+                    entry.connect_activate(move |_| {
+                        let submitter = state.form.borrow().default_button();
+                        submit(&state.form, submitter, &navigation);
+                    });
+                }
+                entry.upcast()
+            }
+        },
+        ControlKind::Button(_) => {
+            let text = element.text_content();
+            let label = match text.trim() {
+                "" => element.get_attribute("value").unwrap_or("Button"),
+                text => text,
+            };
+            let button = Button::with_label(label);
+            if control.is_submit_button() && in_form {
+                button.connect_clicked(move |_| submit(&state.form, Some(index), &navigation));
+            }
+            button.upcast()
+        }
+        ControlKind::Select { multiple: false } => {
+            let labels: Vec<&str> = control.options.iter().map(|option| option.label.as_str()).collect();
+            let drop_down = DropDown::from_strings(&labels);
+            if let Some(selected) = control.options.iter().position(|option| option.selected) {
+                drop_down.set_selected(selected as u32);
+            }
+            drop_down.connect_selected_notify(move |drop_down| {
+                state.form.borrow_mut().controls[index].select_option(drop_down.selected() as usize);
+            });
+            drop_down.upcast()
+        }
+        ControlKind::Select { multiple: true } => {
+            // Une case par option, qui peuvent être cochées ensemble
+            let list = Box::new(Orientation::Vertical, 0);
+            for (option_index, option) in control.options.iter().enumerate() {
+                let check = CheckButton::with_label(&option.label);
+                check.set_active(option.selected);
+                check.set_sensitive(!option.disabled);
+                let state = state.clone();
+                check.connect_toggled(move |check| {
+                    state.form.borrow_mut().controls[index].options[option_index].selected = check.is_active();
+                });
+                list.append(&check);
+            }
+            list.upcast()
+        }
+        ControlKind::Textarea => {
+            let text_view = TextView::new();
+            let buffer = text_view.buffer();
+            buffer.set_text(&control.value);
+            buffer.connect_changed(move |buffer| {
+                let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+                state.form.borrow_mut().controls[index].value = text.to_string();
+            });
+            text_view.upcast()
+        }
+    };
+    widget.set_sensitive(widget.is_sensitive() && !control.disabled);
+    parent.append(&widget);
+    true
+}
+
+/// Envoie le formulaire : la page qu'il demande remplace celle affichée.
+fn submit(form: &RefCell<Form>, submitter: Option<usize>, navigation: &Navigation) {
+    // L'emprunt du formulaire prend fin avant la navigation, qui le détruit
+    let submission = form.borrow().submission(submitter, &navigation.url);
+    if let Some(submission) = submission {
+        (navigation.on_submit)(submission);
+    }
+}
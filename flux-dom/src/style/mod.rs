@@ -8,7 +8,7 @@ use crate::style::text::TextStyle;
 
 mod layout;
 mod unit;
-mod text;
+pub(crate) mod text;
 mod appearance;
 mod flex;
 mod grid;
@@ -235,9 +235,55 @@ pub enum VerticalAlignValue {
     Bottom,
 }
 
+#[derive(Clone, Copy, PartialEq)]
 pub enum WhiteSpaceCollapseValue {
     Collapse,
     Preserve,
+    PreserveBreaks,
+    PreserveSpaces,
+    BreakSpaces,
+}
+
+impl WhiteSpaceCollapseValue {
+    /// Applies the white space processing rules of this value to `text`.
+    ///
+    /// Collapsing turns every sequence of white space into a single space;
+    /// `PreserveBreaks` does the same but keeps line breaks, dropping the
+    /// spaces around them. Removing the spaces at the start and end of a
+    /// line is left to whoever lays the text out.
+    pub(crate) fn apply(&self, text: &str) -> String {
+        match self {
+            WhiteSpaceCollapseValue::Preserve | WhiteSpaceCollapseValue::BreakSpaces => {
+                text.to_string()
+            }
+            WhiteSpaceCollapseValue::PreserveSpaces => text.replace('\n', " "),
+            WhiteSpaceCollapseValue::Collapse => collapse_white_space(text, false),
+            WhiteSpaceCollapseValue::PreserveBreaks => collapse_white_space(text, true),
+        }
+    }
+}
+
+fn collapse_white_space(text: &str, keep_breaks: bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut pending_space = false;
+    for c in text.chars() {
+        if keep_breaks && c == '\n' {
+            pending_space = false;
+            result.push('\n');
+        } else if matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C') {
+            pending_space = !(keep_breaks && result.ends_with('\n'));
+        } else {
+            if pending_space {
+                result.push(' ');
+            }
+            pending_space = false;
+            result.push(c);
+        }
+    }
+    if pending_space {
+        result.push(' ');
+    }
+    result
 }
 
 pub enum WordBreakValue {
@@ -1,23 +1,143 @@
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{self, ErrorKind};
 use std::net::TcpStream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::time::Duration;
 
-use crate::{NetworkError, Request, Response};
+use futures::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use crate::{NetworkError, Request, Response, Url};
 use crate::http_parser::parse_http_response;
+use crate::pool::{keep_alive_timeout, ConnectionPool, MAX_CONNECTIONS_PER_HOST};
+use crate::reactor::{timeout, AsyncTcpStream};
+use crate::redirect::{is_redirect, redirect_request, redirect_url, DEFAULT_MAX_REDIRECTS};
+use crate::tls::TlsStream;
+use crate::x509::{default_trust_store, TrustStore};
+
+/// Size of the pieces in which a body is read when it is not chunked.
+const READ_BUFFER_SIZE: usize = 8192;
+
+/// How long a body read until the connection closes may pause.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The stream to a server: plain TCP for `http`, TLS over TCP for
+/// `https`. Its socket does not block: reads and writes wait through the
+/// reactor.
+pub(crate) enum Transport {
+    Plain(AsyncTcpStream),
+    Tls(Box<TlsStream<AsyncTcpStream>>),
+}
+
+/// A connection to a server. What was read from it but not used yet stays
+/// with it, so that nothing is lost between the responses of a persistent
+/// connection.
+pub(crate) struct Connection {
+    reader: BufReader<Transport>,
+}
+
+impl Connection {
+    pub(crate) fn new(transport: Transport) -> Self {
+        Connection {
+            reader: BufReader::new(transport),
+        }
+    }
+
+    pub(crate) fn tcp_stream(&self) -> &TcpStream {
+        match self.reader.get_ref() {
+            Transport::Plain(stream) => stream.get_ref(),
+            Transport::Tls(stream) => stream.get_ref().get_ref(),
+        }
+    }
+
+    pub(crate) fn is_tls(&self) -> bool {
+        matches!(self.reader.get_ref(), Transport::Tls(_))
+    }
+
+    /// Whether bytes were read past the end of the last response.
+    pub(crate) fn has_buffered_data(&self) -> bool {
+        !self.reader.buffer().is_empty()
+    }
+}
+
+impl AsyncRead for Transport {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Transport::Plain(stream) => Pin::new(stream).poll_read(cx, buffer),
+            Transport::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buffer),
+        }
+    }
+}
+
+impl AsyncWrite for Transport {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Transport::Plain(stream) => Pin::new(stream).poll_write(cx, data),
+            Transport::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, data),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Transport::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Transport::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Transport::Plain(stream) => Pin::new(stream).poll_close(cx),
+            Transport::Tls(stream) => Pin::new(stream.as_mut()).poll_close(cx),
+        }
+    }
+}
 
 /// A basic HTTP client that can send requests and receive responses over TCP.
 /// It manages the connection, sending HTTP requests, and reading raw HTTP responses.
+///
+/// Its methods are `async`, and wait for the network without blocking the
+/// thread that polls them.
 pub struct HttpClient {
-    // We store a `TcpStream` for the active connection.
+    // We store the active connection, plain or encrypted.
     // It is wrapped in an Option because the stream may not be initialized
     // until `connect` is called.
-    stream: Option<TcpStream>,
+    stream: Option<Connection>,
+    // The roots `https` servers are checked against, the default trust store
+    // if not set.
+    trust_store: Option<Arc<TrustStore>>,
+    // The connections `fetch` keeps open for the next requests.
+    pool: Arc<ConnectionPool>,
+    // The redirects `fetch` follows at most, none if zero.
+    max_redirects: usize,
 }
 
 impl HttpClient {
-    /// Creates a new `HttpClient` instance with default settings.
+    /// Creates a new `HttpClient` instance with default settings. Its
+    /// connections are shared with the other clients created this way.
     pub fn new() -> Self {
-        HttpClient { stream: None }
+        HttpClient {
+            stream: None,
+            trust_store: None,
+            pool: ConnectionPool::shared(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+
+    /// Creates a client that checks `https` servers against `trust_store`
+    /// instead of the default trust store. Its connections are its own.
+    pub fn with_trust_store(trust_store: Arc<TrustStore>) -> Self {
+        HttpClient {
+            stream: None,
+            trust_store: Some(trust_store),
+            pool: Arc::new(ConnectionPool::new(MAX_CONNECTIONS_PER_HOST)),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+        }
+    }
+
+    /// Sets how many redirects `fetch` follows at most, 20 by default. With
+    /// zero, redirects are returned like any other response.
+    pub fn set_max_redirects(&mut self, max_redirects: usize) {
+        self.max_redirects = max_redirects;
     }
 
     /// Opens a TCP connection to the given host and port.
@@ -28,20 +148,142 @@ impl HttpClient {
     ///
     /// # Returns
     /// A `Result` indicating success or an error.
-    pub fn connect(&mut self, host: &str, port: u16) -> Result<(), NetworkError> {
-        // Convert (host, port) to a valid socket address string, e.g. "example.com:80".
-        let socket_address = format!("{}:{}", host, port);
-
-        // Attempt to establish the TCP connection.
-        // Any I/O error is mapped to a `NetworkError::ConnectionFailed` (or whatever error variant you have).
-        let stream = TcpStream::connect(socket_address)
-            .map_err(|_| NetworkError::ConnectionError("ConnectionFailed".to_string()))?;
-
+    pub async fn connect(&mut self, host: &str, port: u16) -> Result<(), NetworkError> {
         // Store the connected stream for further use.
-        self.stream = Some(stream);
+        self.stream = Some(Connection::new(Transport::Plain(connect_tcp(host, port).await?)));
         Ok(())
     }
 
+    /// Opens a TCP connection to the given host and port, and runs a TLS
+    /// handshake over it, for `https`.
+    ///
+    /// # Arguments
+    /// * `host` - Host name or IP address (e.g., "example.com"), also sent to
+    ///   the server to pick the site.
+    /// * `port` - TCP port number (e.g., 443 for HTTPS).
+    ///
+    /// # Returns
+    /// A `Result` indicating success or an error: `NetworkError::TlsError` if
+    /// the handshake failed, or a certificate error such as
+    /// `NetworkError::UnknownIssuer` if the server is not trusted.
+    pub async fn connect_tls(&mut self, host: &str, port: u16) -> Result<(), NetworkError> {
+        self.stream = Some(self.open_tls(host, port).await?);
+        Ok(())
+    }
+
+    async fn open_tls(&self, host: &str, port: u16) -> Result<Connection, NetworkError> {
+        let trust_store = match &self.trust_store {
+            Some(trust_store) => trust_store.clone(),
+            None => default_trust_store()?,
+        };
+        let stream = TlsStream::connect_async(connect_tcp(host, port).await?, host, &trust_store).await?;
+        Ok(Connection::new(Transport::Tls(Box::new(stream))))
+    }
+
+    /// Sends `request` to the server of `url` and reads its response, on an
+    /// idle connection to the same server if there is one. The connection is
+    /// then kept for the next requests, unless the server closes it.
+    ///
+    /// Redirects (301, 302, 303, 307 and 308 with a `Location`) are followed,
+    /// up to the limit set with `set_max_redirects`; a 303 turns the request
+    /// into a GET, as do a 301 and a 302 for a POST.
+    ///
+    /// # Arguments
+    /// * `url` - The URL the request is for, `http` or `https`.
+    /// * `request` - The request to send, with its `Host` header.
+    /// * `on_body` - Called with consecutive pieces of the body of the final
+    ///   response, as in `receive_raw_response_with`.
+    ///
+    /// # Returns
+    /// A `Result<Response, NetworkError>` with the response of the server,
+    /// its `url` set to the URL it came from. `NetworkError::RedirectLoop`
+    /// or `NetworkError::TooManyRedirects` if the redirects do not end.
+    pub async fn fetch(
+        &self,
+        url: &Url,
+        request: &Request,
+        mut on_body: impl FnMut(&[u8]),
+    ) -> Result<Response, NetworkError> {
+        let following = self.max_redirects > 0;
+        let mut url = url.clone();
+        let mut request = request.clone();
+        // The method and URL of each request sent, the fragment left out
+        let mut visited = Vec::new();
+        loop {
+            visited.push((request.method, url.scheme.clone(), url.host.clone(), url.port, url.request_target()));
+            let mut response = self.fetch_once(&url, &request, &mut on_body, following).await?;
+            let location = match response.get_header("Location") {
+                Some(location) if following && is_redirect(response.status_code) => location,
+                _ => {
+                    response.url = Some(url.to_string());
+                    return Ok(response);
+                }
+            };
+            if visited.len() > self.max_redirects {
+                return Err(NetworkError::TooManyRedirects(self.max_redirects));
+            }
+            let next = redirect_url(&url, &location)?;
+            request = redirect_request(&request, response.status_code, &url, &next);
+            let key = (request.method, next.scheme.clone(), next.host.clone(), next.port, next.request_target());
+            if visited.contains(&key) {
+                return Err(NetworkError::RedirectLoop(next.to_string()));
+            }
+            url = next;
+        }
+    }
+
+    /// Sends one request and reads its response, as `fetch` does without
+    /// following redirects. With `following` set, the body of a redirect is
+    /// not handed to `on_body`.
+    async fn fetch_once(
+        &self,
+        url: &Url,
+        request: &Request,
+        on_body: &mut impl FnMut(&[u8]),
+        following: bool,
+    ) -> Result<Response, NetworkError> {
+        let key = (url.scheme.to_ascii_lowercase(), url.host.clone(), url.port);
+        let mut lease = self.pool.checkout(&key).await;
+        let (mut connection, mut reused) = match lease.take() {
+            Some(connection) => (connection, true),
+            None => (self.open(url).await?, false),
+        };
+        let (raw_response, delimited) = loop {
+            let result = match write_request(&mut connection, request).await {
+                Ok(()) => read_response(&mut connection, &mut *on_body, following).await,
+                Err(error) => Err(error),
+            };
+            match result {
+                // The server may close an idle connection at any time: the
+                // request is sent again on a new one
+                Err(error) if reused && closed_before_response(&error) => {
+                    connection = self.open(url).await?;
+                    reused = false;
+                }
+                result => break result?,
+            }
+        };
+
+        let response = parse_http_response(&raw_response)?;
+        // The end of a body read until the connection closes is the end of
+        // the connection too
+        if delimited {
+            let http_1_0 = raw_response.starts_with(b"HTTP/1.0");
+            if let Some(timeout) = keep_alive_timeout(request, http_1_0, &response) {
+                lease.release(connection, timeout);
+            }
+        }
+        Ok(response)
+    }
+
+    async fn open(&self, url: &Url) -> Result<Connection, NetworkError> {
+        if url.scheme.eq_ignore_ascii_case("https") {
+            self.open_tls(&url.host, url.port).await
+        } else {
+            Ok(Connection::new(Transport::Plain(connect_tcp(&url.host, url.port).await?)))
+        }
+    }
+
     /// Sends an HTTP request over the established connection.
     ///
     /// # Arguments
@@ -49,25 +291,14 @@ impl HttpClient {
     ///
     /// # Returns
     /// A `Result` indicating success or an error.
-    pub fn send_request(&mut self, request: &Request) -> Result<(), NetworkError> {
+    pub async fn send_request(&mut self, request: &Request) -> Result<(), NetworkError> {
         // Ensure we have a valid connection before attempting to write.
         let stream = match self.stream.as_mut() {
             Some(s) => s,
             None => return Err(NetworkError::ConnectionError("ConnectionNotEstablished".to_string())),
         };
 
-        // Convert the `Request` into its raw HTTP form (e.g., "GET /path HTTP/1.1\r\nHost: ...\r\n\r\n").
-        let http_data = request.to_http_string(); // If you have a method to build the HTTP request.
-
-        // Write the request headers/lines to the TCP stream.
-        stream
-            .write_all(http_data.as_bytes())
-            .map_err(|_| NetworkError::IoError("WriteFailed".to_string()))?;
-
-
-        // Flush ensures everything is sent out promptly.
-        stream.flush().map_err(|_| NetworkError::IoError("FlushFailed".to_string()))?;
-        Ok(())
+        write_request(stream, request).await
     }
 
     /// Receives the raw HTTP response from the server in a more robust way,
@@ -75,7 +306,24 @@ impl HttpClient {
     ///
     /// # Returns
     /// A `Result<Vec<u8>, NetworkError>` representing the raw bytes (headers + body).
-    pub fn receive_raw_response(&mut self) -> Result<Vec<u8>, NetworkError> {
+    pub async fn receive_raw_response(&mut self) -> Result<Vec<u8>, NetworkError> {
+        self.receive_raw_response_with(|_| {}).await
+    }
+
+    /// Same as `receive_raw_response`, but also hands every piece of the body
+    /// to `on_body` as soon as it is read, so that it can be parsed while the
+    /// rest is still on its way.
+    ///
+    /// # Arguments
+    /// * `on_body` - Called with consecutive pieces of the body, chunked
+    ///   transfer encoding already removed.
+    ///
+    /// # Returns
+    /// A `Result<Vec<u8>, NetworkError>` representing the raw bytes (headers + body).
+    pub async fn receive_raw_response_with(
+        &mut self,
+        on_body: impl FnMut(&[u8]),
+    ) -> Result<Vec<u8>, NetworkError> {
         // Ensure we have a valid connection before attempting to read.
         let stream = match self.stream.as_mut() {
             Some(s) => s,
@@ -86,164 +334,253 @@ impl HttpClient {
             }
         };
 
-        // We'll read the data using a buffered reader, but since we want to return
-        // the entire raw response, we also need to accumulate the bytes we read.
-        let mut buffer_reader = BufReader::new(stream);
+        read_response(stream, on_body, false).await.map(|(raw_response, _)| raw_response)
+    }
 
-        // Step 1: Read until we have the complete HTTP headers.
-        //         We'll accumulate bytes into `raw_headers`.
-        let mut raw_headers = Vec::new();
-        loop {
-            // Read byte by byte
-            let mut byte = [0u8; 1];
-            // If we fail to read, return an error
-            if buffer_reader.read_exact(&mut byte).is_err() {
-                return Err(NetworkError::IoError("ReadFailed".to_string()));
-            }
+    /// Parses the raw response bytes and returns a `Response`.
+    ///
+    /// # Arguments
+    /// * `raw_data` - The raw HTTP response data from `receive_raw_response`.
+    ///
+    /// # Returns
+    /// A `Result<Response, NetworkError>` representing a parsed HTTP response or an error.
+    pub fn parse_response(&self, raw_data: &[u8]) -> Result<Response, NetworkError> {
+        // Use the provided `parse_http_response` function to parse the raw data.
+        parse_http_response(raw_data)
+    }
+}
+
+/// The error of a connection the server closed before sending anything back.
+const CONNECTION_CLOSED: &str = "ConnectionClosed";
+
+/// Opens a TCP connection to the given host and port.
+async fn connect_tcp(host: &str, port: u16) -> Result<AsyncTcpStream, NetworkError> {
+    // Attempt to establish the TCP connection.
+    // Any I/O error is mapped to a `NetworkError::ConnectionError`: "ConnectionTimedOut"
+    // if the host or its name server did not answer in time, "ConnectionFailed" otherwise.
+    AsyncTcpStream::connect(host, port).await.map_err(|e| match e.kind() {
+        ErrorKind::TimedOut => NetworkError::ConnectionError("ConnectionTimedOut".to_string()),
+        _ => NetworkError::ConnectionError("ConnectionFailed".to_string()),
+    })
+}
 
-            raw_headers.push(byte[0]);
+/// Writes `request` to the connection.
+async fn write_request(connection: &mut Connection, request: &Request) -> Result<(), NetworkError> {
+    let stream = connection.reader.get_mut();
 
-            // Check if we've reached the end of headers: "\r\n\r\n" or "\n\n".
-            if raw_headers.ends_with(b"\r\n\r\n") || raw_headers.ends_with(b"\n\n") {
-                break;
+    // Convert the `Request` into its raw HTTP form (e.g., "GET /path HTTP/1.1\r\nHost: ...\r\n\r\n").
+    let http_data = request.to_http_string();
+
+    // Write the request headers/lines to the TCP stream.
+    stream
+        .write_all(http_data.as_bytes())
+        .await
+        .map_err(|_| NetworkError::IoError("WriteFailed".to_string()))?;
+
+    // Flush ensures everything is sent out promptly.
+    stream.flush().await.map_err(|_| NetworkError::IoError("FlushFailed".to_string()))?;
+    Ok(())
+}
+
+/// Whether a request failed because the connection was closed before any of
+/// the response arrived, so that it can be sent again on a new one.
+fn closed_before_response(error: &NetworkError) -> bool {
+    match error {
+        NetworkError::IoError(message) => message == "WriteFailed" || message == "FlushFailed",
+        NetworkError::ConnectionError(message) => message == CONNECTION_CLOSED,
+        _ => false,
+    }
+}
+
+/// Reads a response from the connection, as `receive_raw_response_with`.
+/// With `skip_redirect_body` set, the body of a redirect is read but not
+/// handed to `on_body`, as the redirect will be followed.
+///
+/// # Returns
+/// The raw bytes of the response (headers + body), and whether its end was
+/// known from its headers: otherwise it was read until the connection closed.
+async fn read_response(
+    stream: &mut Connection,
+    mut on_body: impl FnMut(&[u8]),
+    skip_redirect_body: bool,
+) -> Result<(Vec<u8>, bool), NetworkError> {
+    // We'll read the data using the connection's buffered reader, but since we
+    // want to return the entire raw response, we also need to accumulate the
+    // bytes we read.
+    let buffer_reader = &mut stream.reader;
+
+    // Step 1: Read until we have the complete HTTP headers.
+    //         We'll accumulate bytes into `raw_headers`.
+    let mut raw_headers = Vec::new();
+    loop {
+        // Read byte by byte
+        let mut byte = [0u8; 1];
+        // If we fail to read, return an error
+        if buffer_reader.read_exact(&mut byte).await.is_err() {
+            if raw_headers.is_empty() {
+                return Err(NetworkError::ConnectionError(CONNECTION_CLOSED.to_string()));
             }
+            return Err(NetworkError::IoError("ReadFailed".to_string()));
         }
 
-        // Convert the header bytes to a string for parsing.
-        let header_str =
-            String::from_utf8_lossy(&raw_headers).to_string();
+        raw_headers.push(byte[0]);
 
-        // Step 2: Parse out the headers to check for `Content-Length` or `Transfer-Encoding`.
-        //         We only demonstrate a minimal approach here.
-        let mut content_length: Option<usize> = None;
-        let mut is_chunked = false;
+        // Check if we've reached the end of headers: "\r\n\r\n" or "\n\n".
+        if raw_headers.ends_with(b"\r\n\r\n") || raw_headers.ends_with(b"\n\n") {
+            break;
+        }
+    }
 
-        for line in header_str.lines() {
-            let lower = line.to_lowercase();
-            if lower.starts_with("content-length:") {
-                if let Some(cl) = line.split(':').nth(1) {
-                    // Trim whitespace and parse the number
-                    let cl = cl.trim();
-                    if let Ok(parsed) = cl.parse::<usize>() {
-                        content_length = Some(parsed);
-                    }
+    // Convert the header bytes to a string for parsing.
+    let header_str =
+        String::from_utf8_lossy(&raw_headers).to_string();
+
+    // Step 2: Parse out the headers to check for `Content-Length` or `Transfer-Encoding`.
+    //         We only demonstrate a minimal approach here.
+    let mut content_length: Option<usize> = None;
+    let mut is_chunked = false;
+    let mut has_location = false;
+    // Responses without a body, whatever their headers say
+    let status_code = header_str.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok());
+    let has_body = !matches!(status_code, Some(204 | 304));
+
+    for line in header_str.lines() {
+        let lower = line.to_lowercase();
+        if lower.starts_with("content-length:") {
+            if let Some(cl) = line.split(':').nth(1) {
+                // Trim whitespace and parse the number
+                let cl = cl.trim();
+                if let Ok(parsed) = cl.parse::<usize>() {
+                    content_length = Some(parsed);
                 }
             }
-            if lower.starts_with("transfer-encoding:") {
-                if line.to_lowercase().contains("chunked") {
-                    is_chunked = true;
-                }
+        }
+        if lower.starts_with("transfer-encoding:") {
+            if line.to_lowercase().contains("chunked") {
+                is_chunked = true;
             }
         }
+        if lower.starts_with("location:") {
+            has_location = true;
+        }
+    }
 
-        // We'll place the raw response here (headers + body).
-        let mut full_response = raw_headers.clone();
+    // The body of a redirect about to be followed is not the document's
+    let skip_body = skip_redirect_body && has_location && status_code.is_some_and(is_redirect);
+    let mut on_body = |piece: &[u8]| {
+        if !skip_body {
+            on_body(piece);
+        }
+    };
 
-        // Step 3: Depending on content length or chunked encoding, read the body accordingly.
+    // We'll place the raw response here (headers + body).
+    let mut full_response = raw_headers.clone();
 
-        if is_chunked {
-            // Step 3a: Chunked encoding
-            let mut body = Vec::new();
+    // Step 3: Depending on content length or chunked encoding, read the body accordingly.
 
-            loop {
-                // 3a-i: Read one line to get the chunk size in hex
-                let mut size_line = String::new();
-                if buffer_reader.read_line(&mut size_line).is_err() {
-                    return Err(NetworkError::IoError("ChunkSizeReadFailed".to_string()));
-                }
+    if !has_body {
+        // Step 3a: Nothing to read
+    } else if is_chunked {
+        // Step 3b: Chunked encoding
+        let mut body = Vec::new();
 
-                // Append chunk size line to the "full_response" so we maintain the raw data
-                full_response.extend_from_slice(size_line.as_bytes());
+        loop {
+            // 3b-i: Read one line to get the chunk size in hex
+            let mut size_line = String::new();
+            if buffer_reader.read_line(&mut size_line).await.is_err() {
+                return Err(NetworkError::IoError("ChunkSizeReadFailed".to_string()));
+            }
 
-                // Convert hex size to decimal
-                let chunk_size = match usize::from_str_radix(size_line.trim(), 16) {
-                    Ok(size) => size,
-                    Err(_) => {
-                        return Err(NetworkError::ParseError(
-                            "InvalidChunkSize".to_string(),
-                        ))
+            // Append chunk size line to the "full_response" so we maintain the raw data
+            full_response.extend_from_slice(size_line.as_bytes());
+
+            // Convert hex size to decimal, leaving out any chunk extension
+            // (e.g. "1a;name=value")
+            let size = size_line.split(';').next().unwrap_or("").trim();
+            let chunk_size = match usize::from_str_radix(size, 16) {
+                Ok(size) => size,
+                Err(_) => {
+                    return Err(NetworkError::ParseError(
+                        "InvalidChunkSize".to_string(),
+                    ))
+                }
+            };
+
+            // Zero-size chunk indicates the end of the body
+            if chunk_size == 0 {
+                // Read the trailer fields, if any, up to the empty line that
+                // ends the body, so that none is left for the next response
+                loop {
+                    let mut trailer_line = String::new();
+                    match buffer_reader.read_line(&mut trailer_line).await {
+                        Ok(0) | Err(_) => {
+                            return Err(NetworkError::IoError(
+                                "ChunkTrailingCRLFReadFailed".to_string(),
+                            ))
+                        }
+                        Ok(_) => {}
                     }
-                };
-
-                // Zero-size chunk indicates the end of the body
-                if chunk_size == 0 {
-                    // Read the trailing \r\n after the zero chunk
-                    let mut trailing_crlf = vec![0u8; 2];
-                    if buffer_reader.read_exact(&mut trailing_crlf).is_err() {
-                        return Err(NetworkError::IoError(
-                            "ChunkTrailingCRLFReadFailed".to_string(),
-                        ));
+                    full_response.extend_from_slice(trailer_line.as_bytes());
+                    if trailer_line.trim_end_matches(['\r', '\n']).is_empty() {
+                        break;
                     }
-                    full_response.extend_from_slice(&trailing_crlf);
-                    break;
                 }
+                break;
+            }
 
-                // 3a-ii: Read the actual chunk
-                let mut chunk_data = vec![0u8; chunk_size];
-                if buffer_reader.read_exact(&mut chunk_data).is_err() {
-                    return Err(NetworkError::IoError("ChunkDataReadFailed".to_string()));
-                }
+            // 3b-ii: Read the actual chunk
+            let mut chunk_data = vec![0u8; chunk_size];
+            if buffer_reader.read_exact(&mut chunk_data).await.is_err() {
+                return Err(NetworkError::IoError("ChunkDataReadFailed".to_string()));
+            }
 
-                // 3a-iii: Read the trailing \r\n
-                let mut crlf = vec![0u8; 2];
-                if buffer_reader.read_exact(&mut crlf).is_err() {
-                    return Err(NetworkError::IoError("ChunkCRLFReadFailed".to_string()));
-                }
+            // 3b-iii: Read the trailing \r\n
+            let mut crlf = vec![0u8; 2];
+            if buffer_reader.read_exact(&mut crlf).await.is_err() {
+                return Err(NetworkError::IoError("ChunkCRLFReadFailed".to_string()));
+            }
 
-                // Accumulate in our body buffer
-                body.extend_from_slice(&chunk_data);
+            // Accumulate in our body buffer
+            body.extend_from_slice(&chunk_data);
+            on_body(&chunk_data);
 
-                // Also accumulate in the raw `full_response`
-                full_response.extend_from_slice(&chunk_data);
-                full_response.extend_from_slice(&crlf);
-            }
-        } else if let Some(length) = content_length {
-            // Step 3b: Read exactly `Content-Length` bytes
-            let mut body = vec![0u8; length];
-            if buffer_reader.read_exact(&mut body).is_err() {
+            // Also accumulate in the raw `full_response`
+            full_response.extend_from_slice(&chunk_data);
+            full_response.extend_from_slice(&crlf);
+        }
+    } else if let Some(length) = content_length {
+        // Step 3c: Read exactly `Content-Length` bytes, a buffer at a time
+        let mut remaining = length;
+        let mut buffer = [0u8; READ_BUFFER_SIZE];
+        while remaining > 0 {
+            let piece = &mut buffer[..remaining.min(READ_BUFFER_SIZE)];
+            if buffer_reader.read_exact(piece).await.is_err() {
                 return Err(NetworkError::IoError("ReadBodyFailed".to_string()));
             }
-            full_response.extend_from_slice(&body);
-        } else {
-            // Step 3c: No content length, not chunked -> read until closure with a 10s timeout.
-
-            {
-                // 1) Borrow just long enough to set the timeout, then drop.
-                let inner_stream = buffer_reader.get_mut();
-                inner_stream
-                    .set_read_timeout(Some(Duration::from_secs(10)))
-                    .map_err(|_| NetworkError::IoError("SetReadTimeoutFailed".to_string()))?;
-            } // `inner_stream` goes out of scope here, so the mutable borrow ends.
-
-            // Now we can safely call `buffer_reader.read_to_end(...)`.
-            let mut body = Vec::new();
-            if buffer_reader.read_to_end(&mut body).is_err() {
-                return Err(NetworkError::IoError("ReadBodyTimeoutOrFailed".to_string()));
-            }
-            full_response.extend_from_slice(&body);
-
-            {
-                // 2) (Optional) Borrow again if you want to reset the timeout.
-                let inner_stream = buffer_reader.get_mut();
-                inner_stream
-                    .set_read_timeout(None)
-                    .map_err(|_| NetworkError::IoError("ResetReadTimeoutFailed".to_string()))?;
-            }
+            full_response.extend_from_slice(piece);
+            on_body(piece);
+            remaining -= piece.len();
+        }
+    } else {
+        // Step 3d: No content length, not chunked -> read until closure, each
+        //          read given `READ_TIMEOUT` at most.
+        let mut buffer = [0u8; READ_BUFFER_SIZE];
+        loop {
+            let read = match timeout(READ_TIMEOUT, buffer_reader.read(&mut buffer)).await {
+                Some(Ok(0)) => break,
+                Some(Ok(read)) => read,
+                Some(Err(e)) if e.kind() == ErrorKind::Interrupted => continue,
+                Some(Err(_)) | None => {
+                    return Err(NetworkError::IoError("ReadBodyTimeoutOrFailed".to_string()))
+                }
+            };
+            full_response.extend_from_slice(&buffer[..read]);
+            on_body(&buffer[..read]);
         }
-
-        // Step 4: Return the full response (headers + body).
-        Ok(full_response)
     }
 
-    /// Parses the raw response bytes and returns a `Response`.
-    ///
-    /// # Arguments
-    /// * `raw_data` - The raw HTTP response data from `receive_raw_response`.
-    ///
-    /// # Returns
-    /// A `Result<Response, NetworkError>` representing a parsed HTTP response or an error.
-    pub fn parse_response(&self, raw_data: &[u8]) -> Result<Response, NetworkError> {
-        // Use the provided `parse_http_response` function to parse the raw data.
-        parse_http_response(raw_data)
-    }
+    // Step 4: Return the full response (headers + body), and whether its
+    //         end is known without the connection being closed.
+    let delimited = !has_body || is_chunked || content_length.is_some();
+    Ok((full_response, delimited))
 }
@@ -0,0 +1,310 @@
+//! Arithmetic on large natural numbers modulo an odd number, in Montgomery
+//! form, for RSA and elliptic curve signatures.
+//!
+//! Only signatures are verified with it, on public data, so no effort is
+//! made to run in constant time.
+
+use std::cmp::Ordering;
+
+/// A natural number, as little-endian 64-bit limbs without high zero limbs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct BigUint {
+    limbs: Vec<u64>,
+}
+
+impl BigUint {
+    pub(crate) fn from_u64(value: u64) -> Self {
+        BigUint { limbs: vec![value] }.normalized()
+    }
+
+    /// Reads a big-endian unsigned number.
+    pub(crate) fn from_bytes_be(bytes: &[u8]) -> Self {
+        let limbs = bytes
+            .rchunks(8)
+            .map(|chunk| chunk.iter().fold(0, |limb, &byte| (limb << 8) | byte as u64))
+            .collect();
+        BigUint { limbs }.normalized()
+    }
+
+    /// Writes the number big-endian on `len` bytes, or returns `None` if it
+    /// does not fit.
+    pub(crate) fn to_bytes_be(&self, len: usize) -> Option<Vec<u8>> {
+        if self.bits() > len * 8 {
+            return None;
+        }
+        let mut bytes = vec![0; len];
+        for (i, byte) in bytes.iter_mut().rev().enumerate() {
+            let limb = self.limbs.get(i / 8).copied().unwrap_or(0);
+            *byte = (limb >> (8 * (i % 8))) as u8;
+        }
+        Some(bytes)
+    }
+
+    fn normalized(mut self) -> Self {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        self
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    /// Number of significant bits.
+    pub(crate) fn bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(last) => self.limbs.len() * 64 - last.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    pub(crate) fn bit(&self, i: usize) -> bool {
+        self.limbs.get(i / 64).is_some_and(|limb| (limb >> (i % 64)) & 1 == 1)
+    }
+
+    /// `self - other`, which must not be negative.
+    pub(crate) fn sub(&self, other: &BigUint) -> BigUint {
+        let mut limbs = self.limbs.clone();
+        let borrow = sub_in_place(&mut limbs, &other.limbs);
+        debug_assert!(!borrow);
+        BigUint { limbs }.normalized()
+    }
+
+    /// The limbs padded with zeros to `len` limbs.
+    fn padded(&self, len: usize) -> Vec<u64> {
+        let mut limbs = self.limbs.clone();
+        limbs.resize(len, 0);
+        limbs
+    }
+}
+
+impl Ord for BigUint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for BigUint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Subtracts `b` from `a`, which may be longer, and returns the final borrow.
+fn sub_in_place(a: &mut [u64], b: &[u64]) -> bool {
+    let mut borrow = false;
+    for (i, limb) in a.iter_mut().enumerate() {
+        let (difference, borrow1) = limb.overflowing_sub(b.get(i).copied().unwrap_or(0));
+        let (difference, borrow2) = difference.overflowing_sub(borrow as u64);
+        *limb = difference;
+        borrow = borrow1 || borrow2;
+    }
+    borrow
+}
+
+/// Adds `b` to `a`, of the same length, and returns the final carry.
+fn add_in_place(a: &mut [u64], b: &[u64]) -> bool {
+    let mut carry = false;
+    for (limb, other) in a.iter_mut().zip(b) {
+        let (sum, carry1) = limb.overflowing_add(*other);
+        let (sum, carry2) = sum.overflowing_add(carry as u64);
+        *limb = sum;
+        carry = carry1 || carry2;
+    }
+    carry
+}
+
+/// `a >= b`, for limbs of the same length.
+fn greater_or_equal(a: &[u64], b: &[u64]) -> bool {
+    a.iter().rev().cmp(b.iter().rev()) != Ordering::Less
+}
+
+/// A number in Montgomery form for some modulus: `x·R mod n`, with `R` the
+/// power of 2^64 just above the modulus. Always as many limbs as the modulus.
+pub(crate) type Residue = Vec<u64>;
+
+/// An odd modulus, and what Montgomery multiplication needs to know of it.
+#[derive(Debug, Clone)]
+pub(crate) struct Modulus {
+    value: BigUint,
+    limbs: Vec<u64>,
+    /// `-n⁻¹ mod 2^64`.
+    n0_inverse: u64,
+    /// `R² mod n`, to bring numbers into Montgomery form.
+    r_squared: Residue,
+}
+
+impl Modulus {
+    /// Returns `None` unless `value` is odd and greater than 1.
+    pub(crate) fn new(value: BigUint) -> Option<Self> {
+        if !value.bit(0) || value.bits() < 2 {
+            return None;
+        }
+        let limbs = value.limbs.clone();
+
+        // Newton's iteration doubles the number of correct low bits each time,
+        // from the 3 bits an odd number is its own inverse on
+        let mut inverse = limbs[0];
+        for _ in 0..5 {
+            inverse = inverse.wrapping_mul(2u64.wrapping_sub(limbs[0].wrapping_mul(inverse)));
+        }
+
+        // R² mod n, by doubling 1 modulo n as many times as R² has bits
+        let mut r_squared = vec![0; limbs.len()];
+        r_squared[0] = 1;
+        for _ in 0..128 * limbs.len() {
+            let mut doubled = r_squared.clone();
+            let carry = add_in_place(&mut doubled, &r_squared);
+            if carry || greater_or_equal(&doubled, &limbs) {
+                sub_in_place(&mut doubled, &limbs);
+            }
+            r_squared = doubled;
+        }
+
+        Some(Modulus {
+            value,
+            limbs,
+            n0_inverse: inverse.wrapping_neg(),
+            r_squared,
+        })
+    }
+
+    pub(crate) fn value(&self) -> &BigUint {
+        &self.value
+    }
+
+    /// Montgomery multiplication: `a·b·R⁻¹ mod n`, for `a` and `b` below `n`.
+    pub(crate) fn mul(&self, a: &[u64], b: &[u64]) -> Residue {
+        let len = self.limbs.len();
+        let mut t = vec![0u64; len + 2];
+        for &a_limb in a {
+            // t += a_limb·b
+            let mut carry = 0u64;
+            for j in 0..len {
+                let sum = t[j] as u128 + a_limb as u128 * b[j] as u128 + carry as u128;
+                t[j] = sum as u64;
+                carry = (sum >> 64) as u64;
+            }
+            let sum = t[len] as u128 + carry as u128;
+            t[len] = sum as u64;
+            t[len + 1] = (sum >> 64) as u64;
+
+            // t += m·n, which clears the low limb, then t >>= 64
+            let m = t[0].wrapping_mul(self.n0_inverse);
+            let sum = t[0] as u128 + m as u128 * self.limbs[0] as u128;
+            let mut carry = (sum >> 64) as u64;
+            for j in 1..len {
+                let sum = t[j] as u128 + m as u128 * self.limbs[j] as u128 + carry as u128;
+                t[j - 1] = sum as u64;
+                carry = (sum >> 64) as u64;
+            }
+            let sum = t[len] as u128 + carry as u128;
+            t[len - 1] = sum as u64;
+            t[len] = t[len + 1] + (sum >> 64) as u64;
+            t[len + 1] = 0;
+        }
+        if t[len] != 0 || greater_or_equal(&t[..len], &self.limbs) {
+            sub_in_place(&mut t[..len + 1], &self.limbs);
+        }
+        t.truncate(len);
+        t
+    }
+
+    /// Brings `x` into Montgomery form, reducing it modulo `n` on the way.
+    /// `x` may have at most as many limbs as `n`.
+    pub(crate) fn residue(&self, x: &BigUint) -> Residue {
+        debug_assert!(x.limbs.len() <= self.limbs.len());
+        // x·R² is below n·R, which is all the multiplication needs
+        self.mul(&x.padded(self.limbs.len()), &self.r_squared)
+    }
+
+    /// Takes `x` out of Montgomery form.
+    pub(crate) fn value_of(&self, x: &[u64]) -> BigUint {
+        let mut one = vec![0; self.limbs.len()];
+        one[0] = 1;
+        BigUint { limbs: self.mul(x, &one) }.normalized()
+    }
+
+    pub(crate) fn add(&self, a: &[u64], b: &[u64]) -> Residue {
+        let mut sum = a.to_vec();
+        let carry = add_in_place(&mut sum, b);
+        if carry || greater_or_equal(&sum, &self.limbs) {
+            sub_in_place(&mut sum, &self.limbs);
+        }
+        sum
+    }
+
+    pub(crate) fn sub(&self, a: &[u64], b: &[u64]) -> Residue {
+        let mut difference = a.to_vec();
+        if sub_in_place(&mut difference, b) {
+            add_in_place(&mut difference, &self.limbs);
+        }
+        difference
+    }
+
+    /// `x^exponent`, by square-and-multiply.
+    pub(crate) fn pow(&self, x: &[u64], exponent: &BigUint) -> Residue {
+        let mut result = self.residue(&BigUint::from_u64(1));
+        for i in (0..exponent.bits()).rev() {
+            result = self.mul(&result, &result);
+            if exponent.bit(i) {
+                result = self.mul(&result, x);
+            }
+        }
+        result
+    }
+
+    /// `x⁻¹`, by Fermat's little theorem: the modulus must be prime.
+    pub(crate) fn invert(&self, x: &[u64]) -> Residue {
+        self.pow(x, &self.value.sub(&BigUint::from_u64(2)))
+    }
+
+    pub(crate) fn is_zero(x: &[u64]) -> bool {
+        x.iter().all(|&limb| limb == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    #[test]
+    fn test_bytes() {
+        let bytes = from_hex("000102030405060708090a0b");
+        let number = BigUint::from_bytes_be(&bytes);
+        assert_eq!(number.bits(), 81);
+        assert_eq!(number.to_bytes_be(12).unwrap(), bytes);
+        assert_eq!(number.to_bytes_be(11).unwrap(), bytes[1..]);
+        assert!(number.to_bytes_be(10).is_none());
+        assert!(BigUint::from_bytes_be(&[0, 0]).is_zero());
+    }
+
+    #[test]
+    fn test_modular_arithmetic() {
+        // The prime 2^127 - 1, over two limbs
+        let modulus = Modulus::new(BigUint::from_bytes_be(&from_hex("7fffffffffffffffffffffffffffffff"))).unwrap();
+        let x = modulus.residue(&BigUint::from_bytes_be(&from_hex("0123456789abcdef0123456789abcdef")));
+        let y = modulus.residue(&BigUint::from_u64(3));
+
+        // 3^p = 3 (mod p)
+        let pow = modulus.pow(&y, modulus.value());
+        assert_eq!(modulus.value_of(&pow), BigUint::from_u64(3));
+
+        let product = modulus.mul(&x, &modulus.invert(&x));
+        assert_eq!(modulus.value_of(&product), BigUint::from_u64(1));
+        assert_eq!(modulus.sub(&modulus.add(&x, &y), &y), x);
+        let negated = modulus.sub(&y, &modulus.add(&y, &y));
+        assert_eq!(
+            modulus.value_of(&negated).to_bytes_be(16).unwrap(),
+            from_hex("7ffffffffffffffffffffffffffffffc")
+        );
+
+        assert!(Modulus::new(BigUint::from_u64(10)).is_none());
+        assert!(Modulus::new(BigUint::from_u64(1)).is_none());
+    }
+}
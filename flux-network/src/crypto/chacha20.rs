@@ -0,0 +1,269 @@
+//! ChaCha20 and Poly1305, combined into an authenticated cipher (RFC 8439).
+
+use super::{constant_time_eq, TAG_LEN};
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+fn le32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// One 64-byte block of keystream.
+fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut initial = [0u32; 16];
+    initial[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for (word, chunk) in initial[4..12].iter_mut().zip(key.chunks_exact(4)) {
+        *word = le32(chunk);
+    }
+    initial[12] = counter;
+    for (word, chunk) in initial[13..].iter_mut().zip(nonce.chunks_exact(4)) {
+        *word = le32(chunk);
+    }
+
+    let mut state = initial;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut block = [0u8; 64];
+    for ((chunk, word), initial) in block.chunks_exact_mut(4).zip(state).zip(initial) {
+        chunk.copy_from_slice(&word.wrapping_add(initial).to_le_bytes());
+    }
+    block
+}
+
+/// Encrypts or decrypts `data` with the keystream starting at `counter`.
+fn chacha20_xor(key: &[u8; 32], counter: u32, nonce: &[u8; 12], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let keystream = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (byte, key) in chunk.iter_mut().zip(keystream) {
+            *byte ^= key;
+        }
+    }
+}
+
+/// Poly1305 one-time authenticator, with the accumulator in five limbs of
+/// 26 bits.
+struct Poly1305 {
+    r: [u64; 5],
+    pad: [u32; 4],
+    accumulator: [u64; 5],
+}
+
+const MASK_26: u64 = (1 << 26) - 1;
+
+impl Poly1305 {
+    fn new(key: &[u8; 32]) -> Self {
+        // r is clamped: some bits of it are always zero
+        let r = [
+            le32(&key[0..]) as u64 & 0x3ffffff,
+            (le32(&key[3..]) as u64 >> 2) & 0x3ffff03,
+            (le32(&key[6..]) as u64 >> 4) & 0x3ffc0ff,
+            (le32(&key[9..]) as u64 >> 6) & 0x3f03fff,
+            (le32(&key[12..]) as u64 >> 8) & 0x00fffff,
+        ];
+        let pad = [le32(&key[16..]), le32(&key[20..]), le32(&key[24..]), le32(&key[28..])];
+        Poly1305 {
+            r,
+            pad,
+            accumulator: [0; 5],
+        }
+    }
+
+    /// Adds a 16-byte block, with `high_bit` set for full blocks: a shorter
+    /// last block is padded with a one byte instead.
+    fn add_block(&mut self, block: &[u8; 16], high_bit: u64) {
+        let [r0, r1, r2, r3, r4] = self.r;
+        let [s1, s2, s3, s4] = [r1 * 5, r2 * 5, r3 * 5, r4 * 5];
+        let h = &mut self.accumulator;
+        h[0] += le32(&block[0..]) as u64 & MASK_26;
+        h[1] += (le32(&block[3..]) as u64 >> 2) & MASK_26;
+        h[2] += (le32(&block[6..]) as u64 >> 4) & MASK_26;
+        h[3] += (le32(&block[9..]) as u64 >> 6) & MASK_26;
+        h[4] += (le32(&block[12..]) as u64 >> 8) | (high_bit << 24);
+
+        let mut d = [
+            h[0] * r0 + h[1] * s4 + h[2] * s3 + h[3] * s2 + h[4] * s1,
+            h[0] * r1 + h[1] * r0 + h[2] * s4 + h[3] * s3 + h[4] * s2,
+            h[0] * r2 + h[1] * r1 + h[2] * r0 + h[3] * s4 + h[4] * s3,
+            h[0] * r3 + h[1] * r2 + h[2] * r1 + h[3] * r0 + h[4] * s4,
+            h[0] * r4 + h[1] * r3 + h[2] * r2 + h[3] * r1 + h[4] * r0,
+        ];
+        for i in 0..4 {
+            d[i + 1] += d[i] >> 26;
+            d[i] &= MASK_26;
+        }
+        d[0] += (d[4] >> 26) * 5;
+        d[4] &= MASK_26;
+        d[1] += d[0] >> 26;
+        d[0] &= MASK_26;
+        *h = d;
+    }
+
+    /// Authenticates `data`, which must be the whole message: only its last
+    /// block may be shorter than 16 bytes.
+    fn update(&mut self, data: &[u8]) {
+        for chunk in data.chunks(16) {
+            let mut block = [0u8; 16];
+            block[..chunk.len()].copy_from_slice(chunk);
+            if chunk.len() == 16 {
+                self.add_block(&block, 1);
+            } else {
+                block[chunk.len()] = 1;
+                self.add_block(&block, 0);
+            }
+        }
+    }
+
+    fn finish(self) -> [u8; TAG_LEN] {
+        let mut h = self.accumulator;
+        for i in 0..4 {
+            h[i + 1] += h[i] >> 26;
+            h[i] &= MASK_26;
+        }
+        h[0] += (h[4] >> 26) * 5;
+        h[4] &= MASK_26;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK_26;
+
+        // Compute h - p = h + 5 - 2^130 and keep it if it is not negative
+        let mut g = [0u64; 5];
+        let mut carry = 5;
+        for i in 0..5 {
+            g[i] = h[i] + carry;
+            carry = g[i] >> 26;
+            g[i] &= MASK_26;
+        }
+        let keep_g = carry.wrapping_neg();
+        for i in 0..5 {
+            h[i] = (h[i] & !keep_g) | (g[i] & keep_g);
+        }
+
+        let words = [
+            h[0] | (h[1] << 26),
+            (h[1] >> 6) | (h[2] << 20),
+            (h[2] >> 12) | (h[3] << 14),
+            (h[3] >> 18) | (h[4] << 8),
+        ];
+        let mut tag = [0u8; TAG_LEN];
+        let mut carry = 0u64;
+        for ((chunk, word), pad) in tag.chunks_exact_mut(4).zip(words).zip(self.pad) {
+            let sum = (word & 0xffffffff) + pad as u64 + carry;
+            chunk.copy_from_slice(&(sum as u32).to_le_bytes());
+            carry = sum >> 32;
+        }
+        tag
+    }
+}
+
+/// The ChaCha20-Poly1305 authenticated cipher.
+pub(crate) struct ChaCha20Poly1305 {
+    key: [u8; 32],
+}
+
+impl ChaCha20Poly1305 {
+    pub(crate) fn new(key: &[u8; 32]) -> Self {
+        ChaCha20Poly1305 { key: *key }
+    }
+
+    pub(crate) fn seal(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut output = plaintext.to_vec();
+        chacha20_xor(&self.key, 1, nonce, &mut output);
+        let tag = self.tag(nonce, aad, &output);
+        output.extend_from_slice(&tag);
+        output
+    }
+
+    pub(crate) fn open(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len().checked_sub(TAG_LEN)?);
+        if !constant_time_eq(&self.tag(nonce, aad, ciphertext), tag) {
+            return None;
+        }
+        let mut output = ciphertext.to_vec();
+        chacha20_xor(&self.key, 1, nonce, &mut output);
+        Some(output)
+    }
+
+    fn tag(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+        // The one-time key is the start of the keystream block 0
+        let block = chacha20_block(&self.key, 0, nonce);
+        let mut one_time_key = [0u8; 32];
+        one_time_key.copy_from_slice(&block[..32]);
+
+        // Both parts are padded to whole blocks, then followed by their lengths
+        let mut mac_data = aad.to_vec();
+        mac_data.resize(aad.len().div_ceil(16) * 16, 0);
+        mac_data.extend_from_slice(ciphertext);
+        mac_data.resize(mac_data.len().div_ceil(16) * 16, 0);
+        mac_data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
+        mac_data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+
+        let mut poly = Poly1305::new(&one_time_key);
+        poly.update(&mac_data);
+        poly.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    #[test]
+    fn test_chacha20_block() {
+        // RFC 8439, section 2.3.2
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8);
+        let nonce = from_hex("000000090000004a00000000").try_into().unwrap();
+        assert_eq!(
+            chacha20_block(&key, 1, &nonce).to_vec(),
+            from_hex(
+                "10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e\
+                 d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e"
+            )
+        );
+    }
+
+    #[test]
+    fn test_poly1305() {
+        // RFC 8439, section 2.5.2
+        let key = from_hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+        let mut poly = Poly1305::new(&key.try_into().unwrap());
+        poly.update(b"Cryptographic Forum Research Group");
+        assert_eq!(poly.finish().to_vec(), from_hex("a8061dc1305136c6c22b8baf0c0127a9"));
+    }
+
+    #[test]
+    fn test_chacha20_poly1305() {
+        // RFC 8439, section 2.8.2
+        let key: [u8; 32] = std::array::from_fn(|i| 0x80 + i as u8);
+        let cipher = ChaCha20Poly1305::new(&key);
+        let nonce = from_hex("070000004041424344454647").try_into().unwrap();
+        let aad = from_hex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for \
+                          the future, sunscreen would be it.";
+        let sealed = cipher.seal(&nonce, &aad, plaintext);
+        assert_eq!(sealed[..16].to_vec(), from_hex("d31a8d34648e60db7b86afbc53ef7ec2"));
+        assert_eq!(sealed[plaintext.len()..].to_vec(), from_hex("1ae10b594f09e26a7e902ecbd0600691"));
+        assert_eq!(cipher.open(&nonce, &aad, &sealed).as_deref(), Some(&plaintext[..]));
+
+        let mut tampered = sealed;
+        tampered[3] ^= 0x40;
+        assert_eq!(cipher.open(&nonce, &aad, &tampered), None);
+    }
+}
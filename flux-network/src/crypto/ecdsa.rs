@@ -0,0 +1,391 @@
+//! Verification of ECDSA signatures (FIPS 186-4) on the NIST P-256 and
+//! P-384 curves, with points in Jacobian coordinates.
+
+use super::bigint::{BigUint, Modulus, Residue};
+
+/// The curves signatures can be checked on. Both have `a = -3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Curve {
+    P256,
+    P384,
+}
+
+/// The parameters of a curve, big-endian.
+struct Parameters {
+    /// The prime of the field.
+    p: &'static [u8],
+    /// The order of the generator.
+    n: &'static [u8],
+    b: &'static [u8],
+    generator_x: &'static [u8],
+    generator_y: &'static [u8],
+}
+
+const P256: Parameters = Parameters {
+    p: &[
+        0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    ],
+    n: &[
+        0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xbc, 0xe6, 0xfa, 0xad, 0xa7, 0x17, 0x9e, 0x84, 0xf3, 0xb9, 0xca, 0xc2, 0xfc, 0x63, 0x25, 0x51,
+    ],
+    b: &[
+        0x5a, 0xc6, 0x35, 0xd8, 0xaa, 0x3a, 0x93, 0xe7, 0xb3, 0xeb, 0xbd, 0x55, 0x76, 0x98, 0x86, 0xbc,
+        0x65, 0x1d, 0x06, 0xb0, 0xcc, 0x53, 0xb0, 0xf6, 0x3b, 0xce, 0x3c, 0x3e, 0x27, 0xd2, 0x60, 0x4b,
+    ],
+    generator_x: &[
+        0x6b, 0x17, 0xd1, 0xf2, 0xe1, 0x2c, 0x42, 0x47, 0xf8, 0xbc, 0xe6, 0xe5, 0x63, 0xa4, 0x40, 0xf2,
+        0x77, 0x03, 0x7d, 0x81, 0x2d, 0xeb, 0x33, 0xa0, 0xf4, 0xa1, 0x39, 0x45, 0xd8, 0x98, 0xc2, 0x96,
+    ],
+    generator_y: &[
+        0x4f, 0xe3, 0x42, 0xe2, 0xfe, 0x1a, 0x7f, 0x9b, 0x8e, 0xe7, 0xeb, 0x4a, 0x7c, 0x0f, 0x9e, 0x16,
+        0x2b, 0xce, 0x33, 0x57, 0x6b, 0x31, 0x5e, 0xce, 0xcb, 0xb6, 0x40, 0x68, 0x37, 0xbf, 0x51, 0xf5,
+    ],
+};
+
+const P384: Parameters = Parameters {
+    p: &[
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+        0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+    ],
+    n: &[
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xc7, 0x63, 0x4d, 0x81, 0xf4, 0x37, 0x2d, 0xdf,
+        0x58, 0x1a, 0x0d, 0xb2, 0x48, 0xb0, 0xa7, 0x7a, 0xec, 0xec, 0x19, 0x6a, 0xcc, 0xc5, 0x29, 0x73,
+    ],
+    b: &[
+        0xb3, 0x31, 0x2f, 0xa7, 0xe2, 0x3e, 0xe7, 0xe4, 0x98, 0x8e, 0x05, 0x6b, 0xe3, 0xf8, 0x2d, 0x19,
+        0x18, 0x1d, 0x9c, 0x6e, 0xfe, 0x81, 0x41, 0x12, 0x03, 0x14, 0x08, 0x8f, 0x50, 0x13, 0x87, 0x5a,
+        0xc6, 0x56, 0x39, 0x8d, 0x8a, 0x2e, 0xd1, 0x9d, 0x2a, 0x85, 0xc8, 0xed, 0xd3, 0xec, 0x2a, 0xef,
+    ],
+    generator_x: &[
+        0xaa, 0x87, 0xca, 0x22, 0xbe, 0x8b, 0x05, 0x37, 0x8e, 0xb1, 0xc7, 0x1e, 0xf3, 0x20, 0xad, 0x74,
+        0x6e, 0x1d, 0x3b, 0x62, 0x8b, 0xa7, 0x9b, 0x98, 0x59, 0xf7, 0x41, 0xe0, 0x82, 0x54, 0x2a, 0x38,
+        0x55, 0x02, 0xf2, 0x5d, 0xbf, 0x55, 0x29, 0x6c, 0x3a, 0x54, 0x5e, 0x38, 0x72, 0x76, 0x0a, 0xb7,
+    ],
+    generator_y: &[
+        0x36, 0x17, 0xde, 0x4a, 0x96, 0x26, 0x2c, 0x6f, 0x5d, 0x9e, 0x98, 0xbf, 0x92, 0x92, 0xdc, 0x29,
+        0xf8, 0xf4, 0x1d, 0xbd, 0x28, 0x9a, 0x14, 0x7c, 0xe9, 0xda, 0x31, 0x13, 0xb5, 0xf0, 0xb8, 0xc0,
+        0x0a, 0x60, 0xb1, 0xce, 0x1d, 0x7e, 0x81, 0x9d, 0x7a, 0x43, 0x1d, 0x7c, 0x90, 0xea, 0x0e, 0x5f,
+    ],
+};
+
+impl Curve {
+    /// Size of a coordinate or a scalar, in bytes.
+    pub(crate) fn len(self) -> usize {
+        match self {
+            Curve::P256 => 32,
+            Curve::P384 => 48,
+        }
+    }
+
+    fn arithmetic(self) -> Arithmetic {
+        let parameters = match self {
+            Curve::P256 => &P256,
+            Curve::P384 => &P384,
+        };
+        let p = Modulus::new(BigUint::from_bytes_be(parameters.p)).unwrap();
+        let n = Modulus::new(BigUint::from_bytes_be(parameters.n)).unwrap();
+        let b = p.residue(&BigUint::from_bytes_be(parameters.b));
+        let one = p.residue(&BigUint::from_u64(1));
+        let generator = Point {
+            x: p.residue(&BigUint::from_bytes_be(parameters.generator_x)),
+            y: p.residue(&BigUint::from_bytes_be(parameters.generator_y)),
+            z: one.clone(),
+        };
+        Arithmetic {
+            len: self.len(),
+            p,
+            n,
+            b,
+            one,
+            generator,
+        }
+    }
+}
+
+/// A point in Jacobian coordinates, `(X/Z², Y/Z³)`, in Montgomery form. The
+/// point at infinity has `Z = 0`.
+#[derive(Clone)]
+struct Point {
+    x: Residue,
+    y: Residue,
+    z: Residue,
+}
+
+/// The moduli and constants of a curve, ready for computing.
+struct Arithmetic {
+    len: usize,
+    p: Modulus,
+    n: Modulus,
+    b: Residue,
+    one: Residue,
+    generator: Point,
+}
+
+impl Arithmetic {
+    fn infinity(&self) -> Point {
+        let zero = vec![0; self.one.len()];
+        Point {
+            x: self.one.clone(),
+            y: self.one.clone(),
+            z: zero,
+        }
+    }
+
+    /// Decodes an uncompressed point, `04 || X || Y`, checking that it lies
+    /// on the curve.
+    fn decode(&self, encoded: &[u8]) -> Option<Point> {
+        if encoded.len() != 1 + 2 * self.len || encoded[0] != 4 {
+            return None;
+        }
+        let x = BigUint::from_bytes_be(&encoded[1..1 + self.len]);
+        let y = BigUint::from_bytes_be(&encoded[1 + self.len..]);
+        if x >= *self.p.value() || y >= *self.p.value() {
+            return None;
+        }
+        let (x, y) = (self.p.residue(&x), self.p.residue(&y));
+
+        // y² = x³ - 3x + b
+        let p = &self.p;
+        let y_squared = p.mul(&y, &y);
+        let x_cubed = p.mul(&p.mul(&x, &x), &x);
+        let three_x = p.add(&p.add(&x, &x), &x);
+        let right = p.add(&p.sub(&x_cubed, &three_x), &self.b);
+        (y_squared == right).then(|| Point { x, y, z: self.one.clone() })
+    }
+
+    fn double(&self, point: &Point) -> Point {
+        let p = &self.p;
+        let delta = p.mul(&point.z, &point.z);
+        let gamma = p.mul(&point.y, &point.y);
+        let beta = p.mul(&point.x, &gamma);
+        // alpha = 3·(X - delta)·(X + delta), which is where a = -3 is used
+        let product = p.mul(&p.sub(&point.x, &delta), &p.add(&point.x, &delta));
+        let alpha = p.add(&p.add(&product, &product), &product);
+
+        let four_beta = p.add(&p.add(&beta, &beta), &p.add(&beta, &beta));
+        let x = p.sub(&p.mul(&alpha, &alpha), &p.add(&four_beta, &four_beta));
+        let y_plus_z = p.add(&point.y, &point.z);
+        let z = p.sub(&p.sub(&p.mul(&y_plus_z, &y_plus_z), &gamma), &delta);
+        let gamma_squared = p.mul(&gamma, &gamma);
+        let eight_gamma_squared = [0; 3].iter().fold(gamma_squared, |sum, _| p.add(&sum, &sum));
+        let y = p.sub(&p.mul(&alpha, &p.sub(&four_beta, &x)), &eight_gamma_squared);
+        Point { x, y, z }
+    }
+
+    fn add(&self, a: &Point, b: &Point) -> Point {
+        if Modulus::is_zero(&a.z) {
+            return b.clone();
+        }
+        if Modulus::is_zero(&b.z) {
+            return a.clone();
+        }
+        let p = &self.p;
+        let z1_squared = p.mul(&a.z, &a.z);
+        let z2_squared = p.mul(&b.z, &b.z);
+        let u1 = p.mul(&a.x, &z2_squared);
+        let u2 = p.mul(&b.x, &z1_squared);
+        let s1 = p.mul(&a.y, &p.mul(&b.z, &z2_squared));
+        let s2 = p.mul(&b.y, &p.mul(&a.z, &z1_squared));
+        let h = p.sub(&u2, &u1);
+        let r = p.sub(&s2, &s1);
+        if Modulus::is_zero(&h) {
+            return if Modulus::is_zero(&r) {
+                self.double(a)
+            } else {
+                self.infinity()
+            };
+        }
+
+        let h_squared = p.mul(&h, &h);
+        let h_cubed = p.mul(&h, &h_squared);
+        let v = p.mul(&u1, &h_squared);
+        let x = p.sub(&p.sub(&p.mul(&r, &r), &h_cubed), &p.add(&v, &v));
+        let y = p.sub(&p.mul(&r, &p.sub(&v, &x)), &p.mul(&s1, &h_cubed));
+        let z = p.mul(&p.mul(&a.z, &b.z), &h);
+        Point { x, y, z }
+    }
+
+    /// `first·a + second·b`, with one pass of doublings over both scalars.
+    fn multiply_two(&self, first: &BigUint, a: &Point, second: &BigUint, b: &Point) -> Point {
+        let sum = self.add(a, b);
+        let mut result = self.infinity();
+        for i in (0..first.bits().max(second.bits())).rev() {
+            result = self.double(&result);
+            match (first.bit(i), second.bit(i)) {
+                (true, true) => result = self.add(&result, &sum),
+                (true, false) => result = self.add(&result, a),
+                (false, true) => result = self.add(&result, b),
+                (false, false) => {}
+            }
+        }
+        result
+    }
+
+    /// The affine coordinates of a point other than infinity.
+    fn affine(&self, point: &Point) -> (BigUint, BigUint) {
+        let p = &self.p;
+        let z_inverse = p.invert(&point.z);
+        let z_inverse_squared = p.mul(&z_inverse, &z_inverse);
+        let x = p.mul(&point.x, &z_inverse_squared);
+        let y = p.mul(&point.y, &p.mul(&z_inverse_squared, &z_inverse));
+        (p.value_of(&x), p.value_of(&y))
+    }
+
+    /// The leftmost bits of a digest, as many as the order has, as a number.
+    fn digest_number(&self, digest: &[u8]) -> BigUint {
+        BigUint::from_bytes_be(&digest[..digest.len().min(self.len)])
+    }
+}
+
+/// A public key: a point of the curve.
+#[derive(Debug, Clone)]
+pub(crate) struct EcdsaPublicKey {
+    curve: Curve,
+    point: Vec<u8>,
+}
+
+impl EcdsaPublicKey {
+    /// Reads an uncompressed point, or returns `None` if it is not on the
+    /// curve. Compressed points are not supported.
+    pub(crate) fn new(curve: Curve, point: &[u8]) -> Option<Self> {
+        curve.arithmetic().decode(point)?;
+        Some(EcdsaPublicKey {
+            curve,
+            point: point.to_vec(),
+        })
+    }
+
+    pub(crate) fn curve(&self) -> Curve {
+        self.curve
+    }
+
+    /// Checks the signature `(r, s)`, big-endian numbers, of a message whose
+    /// digest is `digest`.
+    pub(crate) fn verify(&self, digest: &[u8], r: &[u8], s: &[u8]) -> bool {
+        let curve = self.curve.arithmetic();
+        let Some(public) = curve.decode(&self.point) else {
+            return false;
+        };
+        let n = &curve.n;
+        let (r, s) = (BigUint::from_bytes_be(r), BigUint::from_bytes_be(s));
+        if r.is_zero() || s.is_zero() || r >= *n.value() || s >= *n.value() {
+            return false;
+        }
+
+        let w = n.invert(&n.residue(&s));
+        let u1 = n.value_of(&n.mul(&n.residue(&curve.digest_number(digest)), &w));
+        let u2 = n.value_of(&n.mul(&n.residue(&r), &w));
+        let point = curve.multiply_two(&u1, &curve.generator, &u2, &public);
+        if Modulus::is_zero(&point.z) {
+            return false;
+        }
+        let (x, _) = curve.affine(&point);
+        n.value_of(&n.residue(&x)) == r
+    }
+}
+
+/// The public key of `private_key`, uncompressed, for the tests' servers.
+#[cfg(test)]
+pub(crate) fn public_key(curve: Curve, private_key: &[u8]) -> Vec<u8> {
+    let arithmetic = curve.arithmetic();
+    let zero = BigUint::from_bytes_be(&[]);
+    let point = arithmetic.multiply_two(
+        &BigUint::from_bytes_be(private_key),
+        &arithmetic.generator,
+        &zero,
+        &arithmetic.generator,
+    );
+    let (x, y) = arithmetic.affine(&point);
+    [vec![4], x.to_bytes_be(curve.len()).unwrap(), y.to_bytes_be(curve.len()).unwrap()].concat()
+}
+
+/// Signs `digest` with `private_key` and the given nonce, for the tests'
+/// servers. Returns `(r, s)`.
+#[cfg(test)]
+pub(crate) fn sign(curve: Curve, private_key: &[u8], digest: &[u8], nonce: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let arithmetic = curve.arithmetic();
+    let n = &arithmetic.n;
+    let (x, _) = arithmetic.affine(&arithmetic.multiply_two(
+        &BigUint::from_bytes_be(nonce),
+        &arithmetic.generator,
+        &BigUint::from_bytes_be(&[]),
+        &arithmetic.generator,
+    ));
+    let r = n.residue(&x);
+    let e = n.residue(&arithmetic.digest_number(digest));
+    let d = n.residue(&BigUint::from_bytes_be(private_key));
+    let k_inverse = n.invert(&n.residue(&BigUint::from_bytes_be(nonce)));
+    let s = n.mul(&k_inverse, &n.add(&e, &n.mul(&r, &d)));
+    (
+        n.value_of(&r).to_bytes_be(curve.len()).unwrap(),
+        n.value_of(&s).to_bytes_be(curve.len()).unwrap(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+    use crate::crypto::HashAlgorithm;
+
+    const MESSAGE: &[u8] = b"flux-network signature test";
+
+    /// Keys and signatures of MESSAGE made by another implementation.
+    const VECTORS: [(Curve, HashAlgorithm, &str, &str, &str, &str); 2] = [
+        (
+            Curve::P256,
+            HashAlgorithm::Sha256,
+            "1cdd99835f5aa0cf8df0ef26da17b88cc9a6f89cc6d37c24d85d80a0a06f8588",
+            "047fbd6e5f9ba89ede959a2cd50850db1e8fde202ce9a7ed4e85e4fcfe0c419f01
+               05b1e8e70ece98648e7b289028ca452e4ba4a95f675f5ed1d1e2ab54b4f790ad",
+            "40598257c8dd556ccf6aa901ee9e29ee4ee5a9d0a7a5e1d78e30cdb3fbddf264",
+            "bd158c124f14ad2f442bda5fe68c42e26bfcb00898c463904c67665a06dec0fd",
+        ),
+        (
+            Curve::P384,
+            HashAlgorithm::Sha384,
+            "4c01da649a57b00bf4f3add3fca9dd045fd5fe77595b2a68c69312da3cf195d34ee1e67552afeca5216dc25fbe39cae6",
+            "0435a249d86c9a2f3212ccf1a65abb91a1aef9be48a03df3f47385371813aadb0da636269cbe66ed6b3bf94ee28c892855
+               77f07b7afc2845608a7f11c46c573cb4228c07fdd56ff8f24703de9ed6fa4594537b715533883d745a354633a0f93d50",
+            "1a29f08c40dea2e8967701c11d0dabc9a10b7fe7031f031bf2673b9e891384805ed75e72a4b9b3a4656c90665eada212",
+            "95c3249ab5fc7b9a59f1dda9365305df619e2626adbde02c058571c38f12e476fae1ea3f255432d41ad9ab08bca1c30c",
+        ),
+    ];
+
+    #[test]
+    fn test_verify() {
+        for (curve, hash, _, point, r, s) in VECTORS {
+            let key = EcdsaPublicKey::new(curve, &from_hex(point)).unwrap();
+            let digest = hash.hash(MESSAGE);
+            let (r, s) = (from_hex(r), from_hex(s));
+            assert!(key.verify(&digest, &r, &s));
+            assert!(!key.verify(&hash.hash(b"another message"), &r, &s));
+            assert!(!key.verify(&digest, &s, &r));
+            assert!(!key.verify(&digest, &r, &[0]));
+        }
+    }
+
+    #[test]
+    fn test_invalid_points() {
+        let mut point = from_hex(VECTORS[0].3);
+        assert!(EcdsaPublicKey::new(Curve::P384, &point).is_none());
+        point[40] ^= 1;
+        assert!(EcdsaPublicKey::new(Curve::P256, &point).is_none());
+        point[0] = 2;
+        assert!(EcdsaPublicKey::new(Curve::P256, &point[..33]).is_none());
+    }
+
+    #[test]
+    fn test_sign() {
+        for (curve, hash, private_key, point, _, _) in VECTORS {
+            assert_eq!(public_key(curve, &from_hex(private_key)), from_hex(point));
+            let digest = hash.hash(MESSAGE);
+            let (r, s) = sign(curve, &from_hex(private_key), &digest, &[0x5a; 20]);
+            assert!(EcdsaPublicKey::new(curve, &from_hex(point)).unwrap().verify(&digest, &r, &s));
+        }
+    }
+}
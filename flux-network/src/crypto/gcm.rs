@@ -0,0 +1,240 @@
+//! AES-128 (FIPS 197) in Galois/Counter Mode (NIST SP 800-38D).
+
+use super::{constant_time_eq, TAG_LEN};
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const ROUNDS: usize = 10;
+
+/// The AES-128 block cipher, encryption only: GCM never decrypts blocks.
+struct Aes128 {
+    round_keys: [[u8; 16]; ROUNDS + 1],
+}
+
+impl Aes128 {
+    fn new(key: &[u8; 16]) -> Self {
+        let mut words = [[0u8; 4]; 4 * (ROUNDS + 1)];
+        for (word, chunk) in words.iter_mut().zip(key.chunks_exact(4)) {
+            word.copy_from_slice(chunk);
+        }
+        let mut round_constant = 1u8;
+        for i in 4..words.len() {
+            let mut word = words[i - 1];
+            if i % 4 == 0 {
+                word.rotate_left(1);
+                word = word.map(|byte| SBOX[byte as usize]);
+                word[0] ^= round_constant;
+                round_constant = xtime(round_constant);
+            }
+            for (byte, previous) in word.iter_mut().zip(words[i - 4]) {
+                *byte ^= previous;
+            }
+            words[i] = word;
+        }
+
+        let mut round_keys = [[0u8; 16]; ROUNDS + 1];
+        for (round_key, round_words) in round_keys.iter_mut().zip(words.chunks_exact(4)) {
+            for (chunk, word) in round_key.chunks_exact_mut(4).zip(round_words) {
+                chunk.copy_from_slice(word);
+            }
+        }
+        Aes128 { round_keys }
+    }
+
+    fn encrypt_block(&self, block: &[u8; 16]) -> [u8; 16] {
+        let mut state = *block;
+        add_round_key(&mut state, &self.round_keys[0]);
+        for round in 1..=ROUNDS {
+            for byte in state.iter_mut() {
+                *byte = SBOX[*byte as usize];
+            }
+            shift_rows(&mut state);
+            if round != ROUNDS {
+                mix_columns(&mut state);
+            }
+            add_round_key(&mut state, &self.round_keys[round]);
+        }
+        state
+    }
+}
+
+/// Multiplies by x in GF(2^8).
+fn xtime(byte: u8) -> u8 {
+    (byte << 1) ^ (((byte >> 7) & 1) * 0x1b)
+}
+
+fn add_round_key(state: &mut [u8; 16], round_key: &[u8; 16]) {
+    for (byte, key) in state.iter_mut().zip(round_key) {
+        *byte ^= key;
+    }
+}
+
+/// The state is stored column by column: byte `4 * column + row`.
+fn shift_rows(state: &mut [u8; 16]) {
+    let previous = *state;
+    for column in 0..4 {
+        for row in 1..4 {
+            state[4 * column + row] = previous[4 * ((column + row) % 4) + row];
+        }
+    }
+}
+
+fn mix_columns(state: &mut [u8; 16]) {
+    for column in state.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        column[0] ^= all ^ xtime(a0 ^ a1);
+        column[1] ^= all ^ xtime(a1 ^ a2);
+        column[2] ^= all ^ xtime(a2 ^ a3);
+        column[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
+
+/// Multiplies two elements of GF(2^128) with GCM's bit order.
+fn gf_mul(x: u128, y: u128) -> u128 {
+    const R: u128 = 0xe1 << 120;
+    let mut product = 0;
+    let mut v = y;
+    for i in (0..128).rev() {
+        product ^= v & ((x >> i) & 1).wrapping_neg();
+        v = (v >> 1) ^ (R & (v & 1).wrapping_neg());
+    }
+    product
+}
+
+/// AES-128-GCM with a 96-bit nonce and a 128-bit tag.
+pub(crate) struct Aes128Gcm {
+    cipher: Aes128,
+    /// The hash key, the encryption of the zero block.
+    hash_key: u128,
+}
+
+impl Aes128Gcm {
+    pub(crate) fn new(key: &[u8; 16]) -> Self {
+        let cipher = Aes128::new(key);
+        let hash_key = u128::from_be_bytes(cipher.encrypt_block(&[0; 16]));
+        Aes128Gcm { cipher, hash_key }
+    }
+
+    pub(crate) fn seal(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let mut output = plaintext.to_vec();
+        self.apply_keystream(nonce, &mut output);
+        let tag = self.tag(nonce, aad, &output);
+        output.extend_from_slice(&tag);
+        output
+    }
+
+    pub(crate) fn open(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len().checked_sub(TAG_LEN)?);
+        if !constant_time_eq(&self.tag(nonce, aad, ciphertext), tag) {
+            return None;
+        }
+        let mut output = ciphertext.to_vec();
+        self.apply_keystream(nonce, &mut output);
+        Some(output)
+    }
+
+    /// The counter block `nonce || counter`.
+    fn counter_block(nonce: &[u8; 12], counter: u32) -> [u8; 16] {
+        let mut block = [0u8; 16];
+        block[..12].copy_from_slice(nonce);
+        block[12..].copy_from_slice(&counter.to_be_bytes());
+        block
+    }
+
+    /// Encrypts or decrypts `data` in counter mode, from counter 2: the
+    /// first block is kept for the tag.
+    fn apply_keystream(&self, nonce: &[u8; 12], data: &mut [u8]) {
+        for (i, chunk) in data.chunks_mut(16).enumerate() {
+            let counter = (i as u32).wrapping_add(2);
+            let keystream = self.cipher.encrypt_block(&Self::counter_block(nonce, counter));
+            for (byte, key) in chunk.iter_mut().zip(keystream) {
+                *byte ^= key;
+            }
+        }
+    }
+
+    fn tag(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; TAG_LEN] {
+        let mut hash = 0u128;
+        for data in [aad, ciphertext] {
+            for chunk in data.chunks(16) {
+                let mut block = [0u8; 16];
+                block[..chunk.len()].copy_from_slice(chunk);
+                hash = gf_mul(hash ^ u128::from_be_bytes(block), self.hash_key);
+            }
+        }
+        let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+        hash = gf_mul(hash ^ lengths, self.hash_key);
+
+        let mask = u128::from_be_bytes(self.cipher.encrypt_block(&Self::counter_block(nonce, 1)));
+        (hash ^ mask).to_be_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    #[test]
+    fn test_aes128() {
+        // FIPS 197, appendix C.1
+        let cipher = Aes128::new(&from_hex("000102030405060708090a0b0c0d0e0f").try_into().unwrap());
+        let block = from_hex("00112233445566778899aabbccddeeff").try_into().unwrap();
+        assert_eq!(
+            cipher.encrypt_block(&block).to_vec(),
+            from_hex("69c4e0d86a7b0430d8cdb78070b4c55a")
+        );
+    }
+
+    #[test]
+    fn test_aes128_gcm() {
+        // Test cases 1 and 2 of the GCM specification
+        let cipher = Aes128Gcm::new(&[0; 16]);
+        assert_eq!(cipher.seal(&[0; 12], b"", b""), from_hex("58e2fccefa7e3061367f1d57a4e7455a"));
+        assert_eq!(
+            cipher.seal(&[0; 12], b"", &[0; 16]),
+            from_hex("0388dace60b6a392f328c2b971b2fe78ab6e47d42cec13bdf53a67b21257bddf")
+        );
+
+        // Test case 4: additional data, and a last block that is not full
+        let cipher = Aes128Gcm::new(&from_hex("feffe9928665731c6d6a8f9467308308").try_into().unwrap());
+        let nonce = from_hex("cafebabefacedbaddecaf888").try_into().unwrap();
+        let aad = from_hex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let plaintext = from_hex(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+             1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+        );
+        let sealed = cipher.seal(&nonce, &aad, &plaintext);
+        assert_eq!(
+            sealed,
+            from_hex(
+                "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e\
+                 21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091\
+                 5bc94fbc3221a5db94fae95ae7121a47"
+            )
+        );
+        assert_eq!(cipher.open(&nonce, &aad, &sealed), Some(plaintext));
+
+        let mut tampered = sealed;
+        tampered[0] ^= 1;
+        assert_eq!(cipher.open(&nonce, &aad, &tampered), None);
+    }
+}
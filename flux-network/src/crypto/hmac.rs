@@ -0,0 +1,103 @@
+//! HMAC (RFC 2104) and HKDF (RFC 5869), both over SHA-256.
+
+use super::sha256::{sha256, Sha256, BLOCK_LEN, HASH_LEN};
+
+/// An HMAC-SHA256 computation in progress.
+pub(crate) struct HmacSha256 {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl HmacSha256 {
+    pub(crate) fn new(key: &[u8]) -> Self {
+        // Keys longer than a block are hashed first, shorter ones padded
+        let mut block_key = [0u8; BLOCK_LEN];
+        if key.len() > BLOCK_LEN {
+            block_key[..HASH_LEN].copy_from_slice(&sha256(key));
+        } else {
+            block_key[..key.len()].copy_from_slice(key);
+        }
+
+        let mut inner = Sha256::new();
+        inner.update(&block_key.map(|byte| byte ^ 0x36));
+        let mut outer = Sha256::new();
+        outer.update(&block_key.map(|byte| byte ^ 0x5c));
+        HmacSha256 { inner, outer }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    pub(crate) fn finish(self) -> [u8; HASH_LEN] {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finish());
+        outer.finish()
+    }
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; HASH_LEN] {
+    let mut hmac = HmacSha256::new(key);
+    hmac.update(data);
+    hmac.finish()
+}
+
+/// HKDF-Extract: concentrates the entropy of `ikm` into a pseudorandom key.
+pub(crate) fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; HASH_LEN] {
+    hmac_sha256(salt, ikm)
+}
+
+/// HKDF-Expand: derives `length` bytes bound to `info` from the
+/// pseudorandom key `prk`.
+pub(crate) fn hkdf_expand(prk: &[u8], info: &[u8], length: usize) -> Vec<u8> {
+    assert!(length <= 255 * HASH_LEN, "HKDF output too long");
+    let mut output = Vec::with_capacity(length);
+    let mut previous: Vec<u8> = Vec::new();
+    let mut counter = 1u8;
+    while output.len() < length {
+        let mut hmac = HmacSha256::new(prk);
+        hmac.update(&previous);
+        hmac.update(info);
+        hmac.update(&[counter]);
+        previous = hmac.finish().to_vec();
+        let taken = previous.len().min(length - output.len());
+        output.extend_from_slice(&previous[..taken]);
+        counter = counter.wrapping_add(1);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test cases 2 and 6
+        assert_eq!(
+            hmac_sha256(b"Jefe", b"what do ya want for nothing?").to_vec(),
+            from_hex("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843")
+        );
+        assert_eq!(
+            hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First").to_vec(),
+            from_hex("60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54")
+        );
+    }
+
+    #[test]
+    fn test_hkdf() {
+        // RFC 5869, test case 1
+        let prk = hkdf_extract(&from_hex("000102030405060708090a0b0c"), &[0x0b; 22]);
+        assert_eq!(
+            prk.to_vec(),
+            from_hex("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+        );
+        assert_eq!(
+            hkdf_expand(&prk, &from_hex("f0f1f2f3f4f5f6f7f8f9"), 42),
+            from_hex(
+                "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+            )
+        );
+    }
+}
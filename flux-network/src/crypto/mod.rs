@@ -0,0 +1,97 @@
+//! Cryptographic primitives used by the TLS client, implemented in-tree so
+//! that the crate keeps depending on nothing but `futures`.
+//!
+//! Only what TLS 1.3 needs is here: SHA-256 with HMAC and HKDF, the X25519
+//! key exchange, and the AES-128-GCM and ChaCha20-Poly1305 authenticated
+//! ciphers; then SHA-384 and SHA-512, RSA and ECDSA for checking the
+//! signatures of certificates.
+
+use std::fs::File;
+use std::io::{self, Read};
+
+pub(crate) mod bigint;
+pub(crate) mod chacha20;
+pub(crate) mod ecdsa;
+pub(crate) mod gcm;
+pub(crate) mod hmac;
+pub(crate) mod rsa;
+pub(crate) mod sha256;
+pub(crate) mod sha512;
+pub(crate) mod x25519;
+
+/// An authenticated cipher with its key, as used by the TLS record layer.
+pub(crate) enum Aead {
+    Aes128Gcm(gcm::Aes128Gcm),
+    ChaCha20Poly1305(chacha20::ChaCha20Poly1305),
+}
+
+/// Length of the authentication tag appended by both ciphers.
+pub(crate) const TAG_LEN: usize = 16;
+
+impl Aead {
+    /// Encrypts `plaintext` and returns the ciphertext followed by the tag,
+    /// which also authenticates `aad`.
+    pub(crate) fn seal(&self, nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        match self {
+            Aead::Aes128Gcm(cipher) => cipher.seal(nonce, aad, plaintext),
+            Aead::ChaCha20Poly1305(cipher) => cipher.seal(nonce, aad, plaintext),
+        }
+    }
+
+    /// Checks the tag at the end of `ciphertext` and decrypts the rest.
+    /// Returns `None` if the data or `aad` were tampered with.
+    pub(crate) fn open(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Aead::Aes128Gcm(cipher) => cipher.open(nonce, aad, ciphertext),
+            Aead::ChaCha20Poly1305(cipher) => cipher.open(nonce, aad, ciphertext),
+        }
+    }
+}
+
+/// A hash function a signature can be made with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HashAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    pub(crate) fn hash(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => sha256::sha256(data).to_vec(),
+            HashAlgorithm::Sha384 => sha512::sha384(data).to_vec(),
+            HashAlgorithm::Sha512 => sha512::sha512(data).to_vec(),
+        }
+    }
+
+    /// Size of a digest, in bytes.
+    pub(crate) fn output_len(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 => 32,
+            HashAlgorithm::Sha384 => 48,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+}
+
+/// Fills `buffer` with random bytes from the operating system.
+pub(crate) fn random_bytes(buffer: &mut [u8]) -> io::Result<()> {
+    File::open("/dev/urandom")?.read_exact(buffer)
+}
+
+/// Compares two byte strings in a time that does not depend on where they
+/// differ, for checking MACs.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (x, y)| difference | (x ^ y)) == 0
+}
+
+/// Decodes a hexadecimal string, for test vectors.
+#[cfg(test)]
+pub(crate) fn from_hex(hex: &str) -> Vec<u8> {
+    let digits: Vec<u8> = hex.bytes().filter(|byte| !byte.is_ascii_whitespace()).collect();
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
@@ -0,0 +1,221 @@
+//! Verification of RSA signatures (RFC 8017), with the PKCS #1 v1.5 and the
+//! PSS encodings.
+
+use super::bigint::{BigUint, Modulus};
+use super::{constant_time_eq, HashAlgorithm};
+
+/// Smallest modulus accepted, in bits.
+const MIN_MODULUS_BITS: usize = 2048;
+/// Largest modulus accepted, in bits, which bounds the work a signature costs.
+const MAX_MODULUS_BITS: usize = 8192;
+
+/// Start of the DER `DigestInfo` structure that precedes the hash in a
+/// PKCS #1 v1.5 signature, for each hash function.
+fn digest_info_prefix(hash: HashAlgorithm) -> &'static [u8] {
+    match hash {
+        HashAlgorithm::Sha256 => &[
+            0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04,
+            0x20,
+        ],
+        HashAlgorithm::Sha384 => &[
+            0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0x04,
+            0x30,
+        ],
+        HashAlgorithm::Sha512 => &[
+            0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03, 0x05, 0x00, 0x04,
+            0x40,
+        ],
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RsaPublicKey {
+    modulus: Modulus,
+    exponent: BigUint,
+}
+
+impl RsaPublicKey {
+    /// Builds a key from its big-endian modulus and public exponent, or
+    /// returns `None` if the key is too small, too large, or not a valid key.
+    pub(crate) fn new(modulus: &[u8], exponent: &[u8]) -> Option<Self> {
+        let modulus = BigUint::from_bytes_be(modulus);
+        let exponent = BigUint::from_bytes_be(exponent);
+        if !(MIN_MODULUS_BITS..=MAX_MODULUS_BITS).contains(&modulus.bits())
+            || exponent.bits() < 2
+            || exponent.bits() > 64
+        {
+            return None;
+        }
+        Some(RsaPublicKey {
+            modulus: Modulus::new(modulus)?,
+            exponent,
+        })
+    }
+
+    /// Size of the modulus, in bits.
+    pub(crate) fn bits(&self) -> usize {
+        self.modulus.value().bits()
+    }
+
+    /// Size of a signature, in bytes.
+    fn len(&self) -> usize {
+        self.bits().div_ceil(8)
+    }
+
+    /// Raises the signature to the public exponent, which gives back the
+    /// encoded message on `len` bytes.
+    fn encoded_message(&self, signature: &[u8], len: usize) -> Option<Vec<u8>> {
+        let signature = BigUint::from_bytes_be(signature);
+        if signature >= *self.modulus.value() {
+            return None;
+        }
+        let residue = self.modulus.residue(&signature);
+        let message = self.modulus.pow(&residue, &self.exponent);
+        self.modulus.value_of(&message).to_bytes_be(len)
+    }
+
+    /// Checks an RSASSA-PKCS1-v1_5 signature of `message`.
+    pub(crate) fn verify_pkcs1(&self, hash: HashAlgorithm, message: &[u8], signature: &[u8]) -> bool {
+        if signature.len() != self.len() {
+            return false;
+        }
+        let Some(encoded) = self.encoded_message(signature, self.len()) else {
+            return false;
+        };
+
+        // 00 01 FF...FF 00, then the DigestInfo of the hash
+        let prefix = digest_info_prefix(hash);
+        let padding_len = self.len() - 3 - prefix.len() - hash.output_len();
+        let mut expected = vec![0, 1];
+        expected.resize(2 + padding_len, 0xff);
+        expected.push(0);
+        expected.extend_from_slice(prefix);
+        expected.extend_from_slice(&hash.hash(message));
+        constant_time_eq(&encoded, &expected)
+    }
+
+    /// Checks an RSASSA-PSS signature of `message`, with MGF1 over the same
+    /// hash function and a salt of `salt_len` bytes.
+    pub(crate) fn verify_pss(&self, hash: HashAlgorithm, salt_len: usize, message: &[u8], signature: &[u8]) -> bool {
+        if signature.len() != self.len() {
+            return false;
+        }
+        let hash_len = hash.output_len();
+        let encoded_bits = self.bits() - 1;
+        let encoded_len = encoded_bits.div_ceil(8);
+        if encoded_len < hash_len + salt_len + 2 {
+            return false;
+        }
+        let Some(encoded) = self.encoded_message(signature, encoded_len) else {
+            return false;
+        };
+        if encoded[encoded_len - 1] != 0xbc {
+            return false;
+        }
+
+        let (masked_block, rest) = encoded.split_at(encoded_len - hash_len - 1);
+        let digest = &rest[..hash_len];
+        // The bits above the encoded message's size must be clear
+        let unused_bits = 8 * encoded_len - encoded_bits;
+        let top_mask = 0xffu8 >> unused_bits;
+        if masked_block[0] & !top_mask != 0 {
+            return false;
+        }
+        let mut block: Vec<u8> = masked_block
+            .iter()
+            .zip(mgf1(hash, digest, masked_block.len()))
+            .map(|(byte, mask)| byte ^ mask)
+            .collect();
+        block[0] &= top_mask;
+
+        // Zeros, a one, then the salt
+        let separator = block.len() - salt_len - 1;
+        if block[..separator].iter().any(|&byte| byte != 0) || block[separator] != 1 {
+            return false;
+        }
+        let salt = &block[separator + 1..];
+        let mut salted = vec![0; 8];
+        salted.extend_from_slice(&hash.hash(message));
+        salted.extend_from_slice(salt);
+        constant_time_eq(&hash.hash(&salted), digest)
+    }
+}
+
+/// The MGF1 mask generation function: `len` bytes from hashing `seed` with
+/// a counter.
+fn mgf1(hash: HashAlgorithm, seed: &[u8], len: usize) -> Vec<u8> {
+    let mut mask = Vec::with_capacity(len + hash.output_len());
+    let mut counter = 0u32;
+    while mask.len() < len {
+        let mut input = seed.to_vec();
+        input.extend_from_slice(&counter.to_be_bytes());
+        mask.extend(hash.hash(&input));
+        counter += 1;
+    }
+    mask.truncate(len);
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    // A 2048-bit key, and signatures of MESSAGE made with it by OpenSSL
+    const MODULUS: &str = "8d6d71c53e9960bbc2f1fc41cdca729f9dfda782b937954eda41dd6f330ab4b6
+     ac56e45bfb8932d428cc5f3cc13aaabdef769e25ef4862f206e0d8eafb7c4386
+     a59c9cbb5bb6461891ca834a1fd2754e78344641788afb00966eb14f94abe4b9
+     546babdb7fc9d04d6d2cfaa703bbc5a788022358b8272407c84062c64255b893
+     7e54178480166ea3ad079e4f6c845f5111d11c6b94f3687eb131b5fb7b282b82
+     24dbf32b3ac290917137bccc92c557595fe8d4c3d06f529d4c5d916a02592931
+     0e2211191cc5d09f57832e484c79a6fad5e8f7b5c2e9e35463af2207d22bd731
+     a268a4e56898a4766f61b96f8e5f540da83b089cd3f560ebcbc610d8498b9b6b";
+    const MESSAGE: &[u8] = b"flux-network signature test";
+    const PKCS1_SHA256: &str = "6f6ef86730696131dde4207ca00419a330ddd40468fcac1fa8d55271d0237dde
+     dea0e4db8b7e194e91b9b29957ed8ecef4372cb9b8aa79398b7372083a56329b
+     df5ecf01e949dabcd1d0aa2e8ba197a6d4a4a9175cd3cb9f7a6b0ad20414b091
+     8db9d0cfdf41e60575860911c1ff9b3a379df030970f60cd358dde63b19151a3
+     9e605c3574b2d8e44138d627940c08f4e1c7f0c4f91ac482a55b8673ff849473
+     009f0c1eeeba314e0b65129561be1805f01435d43ffac1dfe069e15eecad8f2a
+     25bf41d6a9f68d950eb4aaca703b7faba254aa7ce51c1711497be364715aa134
+     85cf56128e7f5e5e600c0d92775780e855325eba890bbc69ea3e7c9d3c960953";
+    const PSS_SHA384: &str = "53b9b630c4da94a6931f22d1b3b4cfd73d5ffba6dcb8785b416ec640af4b94a5
+     7c1790927f54265c8d7e250815abc35f9ea6c696680f667bba2c7f76c52ec444
+     b47a573e12e290bd2ae4bfebeb525f381d3fd7031a1457d1b5d7854d287764b3
+     7b2d1db142d03409fd554420db011b07cc0feb04862ace964c4616bb3a8a791c
+     01b9553175a55d8f96dba322b181c567c20c5b065d93e2e900b7c78100e129ff
+     5026bd4e0db24ade0b59e0844e82edb1749f2ccf079d9925048e8ed2d0b24209
+     17485a5e1405dcb7356b897b53f570d8722e2156e30a114297261975b351ae10
+     42d67060d83f1ffdb1368db8e92cf87835246ace19e6c57a91c4448006faf718";
+
+    #[test]
+    fn test_verify_pkcs1() {
+        let key = RsaPublicKey::new(&from_hex(MODULUS), &[1, 0, 1]).unwrap();
+        assert_eq!(key.bits(), 2048);
+        let signature = from_hex(PKCS1_SHA256);
+        assert!(key.verify_pkcs1(HashAlgorithm::Sha256, MESSAGE, &signature));
+        assert!(!key.verify_pkcs1(HashAlgorithm::Sha256, b"another message", &signature));
+        assert!(!key.verify_pkcs1(HashAlgorithm::Sha384, MESSAGE, &signature));
+        let mut tampered = signature.clone();
+        tampered[100] ^= 1;
+        assert!(!key.verify_pkcs1(HashAlgorithm::Sha256, MESSAGE, &tampered));
+        assert!(!key.verify_pkcs1(HashAlgorithm::Sha256, MESSAGE, &signature[1..]));
+    }
+
+    #[test]
+    fn test_verify_pss() {
+        let key = RsaPublicKey::new(&from_hex(MODULUS), &[1, 0, 1]).unwrap();
+        let signature = from_hex(PSS_SHA384);
+        assert!(key.verify_pss(HashAlgorithm::Sha384, 48, MESSAGE, &signature));
+        assert!(!key.verify_pss(HashAlgorithm::Sha384, 32, MESSAGE, &signature));
+        assert!(!key.verify_pss(HashAlgorithm::Sha384, 48, b"another message", &signature));
+        // A PKCS #1 v1.5 signature is not a PSS one
+        assert!(!key.verify_pss(HashAlgorithm::Sha256, 32, MESSAGE, &from_hex(PKCS1_SHA256)));
+    }
+
+    #[test]
+    fn test_key_limits() {
+        assert!(RsaPublicKey::new(&from_hex(&MODULUS[..256]), &[1, 0, 1]).is_none());
+        assert!(RsaPublicKey::new(&from_hex(MODULUS), &[1]).is_none());
+    }
+}
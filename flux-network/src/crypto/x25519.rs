@@ -0,0 +1,246 @@
+//! The X25519 Diffie-Hellman function (RFC 7748).
+//!
+//! Field elements modulo 2^255 - 19 are held in five limbs of 51 bits, and
+//! the Montgomery ladder swaps its points without branching on the secret
+//! scalar.
+
+/// A field element: the value is `sum(limbs[i] << (51 * i))`. Limbs may go a
+/// little over 51 bits between reductions.
+#[derive(Clone, Copy)]
+struct FieldElement([u64; 5]);
+
+const LIMB_MASK: u64 = (1 << 51) - 1;
+
+impl FieldElement {
+    const ZERO: FieldElement = FieldElement([0; 5]);
+    const ONE: FieldElement = FieldElement([1, 0, 0, 0, 0]);
+
+    /// Reads a little-endian u-coordinate, ignoring its top bit.
+    fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let load = |offset: usize| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(&bytes[offset..offset + 8]);
+            u64::from_le_bytes(word)
+        };
+        FieldElement([
+            load(0) & LIMB_MASK,
+            (load(6) >> 3) & LIMB_MASK,
+            (load(12) >> 6) & LIMB_MASK,
+            (load(19) >> 1) & LIMB_MASK,
+            (load(24) >> 12) & LIMB_MASK,
+        ])
+    }
+
+    /// Writes the fully reduced value in little-endian order.
+    fn to_bytes(self) -> [u8; 32] {
+        let mut limbs = self.carry().0;
+        // Subtract p if the value is at least p, i.e. if adding 19 carries
+        // past bit 255
+        let mut quotient = (limbs[0] + 19) >> 51;
+        for limb in &limbs[1..] {
+            quotient = (limb + quotient) >> 51;
+        }
+        limbs[0] += 19 * quotient;
+        for i in 0..4 {
+            limbs[i + 1] += limbs[i] >> 51;
+            limbs[i] &= LIMB_MASK;
+        }
+        limbs[4] &= LIMB_MASK;
+
+        let mut bytes = [0u8; 32];
+        let mut accumulator: u128 = 0;
+        let mut bits = 0;
+        let mut position = 0;
+        for limb in limbs {
+            accumulator |= (limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 {
+                bytes[position] = accumulator as u8;
+                accumulator >>= 8;
+                bits -= 8;
+                position += 1;
+            }
+        }
+        bytes[position] = accumulator as u8;
+        bytes
+    }
+
+    /// Brings every limb back to 51 bits, folding the overflow of the last
+    /// one into the first (2^255 = 19 modulo p).
+    fn carry(self) -> Self {
+        let mut limbs = self.0;
+        for i in 0..4 {
+            limbs[i + 1] += limbs[i] >> 51;
+            limbs[i] &= LIMB_MASK;
+        }
+        limbs[0] += 19 * (limbs[4] >> 51);
+        limbs[4] &= LIMB_MASK;
+        limbs[1] += limbs[0] >> 51;
+        limbs[0] &= LIMB_MASK;
+        FieldElement(limbs)
+    }
+
+    fn add(self, other: Self) -> Self {
+        let mut limbs = self.0;
+        for (limb, other) in limbs.iter_mut().zip(other.0) {
+            *limb += other;
+        }
+        FieldElement(limbs).carry()
+    }
+
+    fn sub(self, other: Self) -> Self {
+        // Adding 4p first keeps every limb positive
+        const FOUR_P: [u64; 5] = [
+            4 * ((1 << 51) - 19),
+            4 * LIMB_MASK,
+            4 * LIMB_MASK,
+            4 * LIMB_MASK,
+            4 * LIMB_MASK,
+        ];
+        let mut limbs = self.0;
+        for i in 0..5 {
+            limbs[i] = limbs[i] + FOUR_P[i] - other.0[i];
+        }
+        FieldElement(limbs).carry()
+    }
+
+    fn mul(self, other: Self) -> Self {
+        let [a0, a1, a2, a3, a4] = self.0.map(u128::from);
+        let [b0, b1, b2, b3, b4] = other.0.map(u128::from);
+        let (b1_19, b2_19, b3_19, b4_19) = (b1 * 19, b2 * 19, b3 * 19, b4 * 19);
+
+        let products = [
+            a0 * b0 + a1 * b4_19 + a2 * b3_19 + a3 * b2_19 + a4 * b1_19,
+            a0 * b1 + a1 * b0 + a2 * b4_19 + a3 * b3_19 + a4 * b2_19,
+            a0 * b2 + a1 * b1 + a2 * b0 + a3 * b4_19 + a4 * b3_19,
+            a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0 + a4 * b4_19,
+            a0 * b4 + a1 * b3 + a2 * b2 + a3 * b1 + a4 * b0,
+        ];
+        Self::reduce_wide(products)
+    }
+
+    fn square(self) -> Self {
+        self.mul(self)
+    }
+
+    fn mul_small(self, factor: u64) -> Self {
+        Self::reduce_wide(self.0.map(|limb| limb as u128 * factor as u128))
+    }
+
+    fn reduce_wide(mut wide: [u128; 5]) -> Self {
+        for i in 0..4 {
+            wide[i + 1] += wide[i] >> 51;
+            wide[i] &= LIMB_MASK as u128;
+        }
+        wide[0] += 19 * (wide[4] >> 51);
+        wide[4] &= LIMB_MASK as u128;
+        FieldElement(wide.map(|limb| limb as u64)).carry()
+    }
+
+    /// The inverse, as self^(p - 2) by Fermat's little theorem.
+    fn invert(self) -> Self {
+        // p - 2 = 2^255 - 21: all 255 bits set but bits 2 and 4
+        let mut result = FieldElement::ONE;
+        for bit in (0..255).rev() {
+            result = result.square();
+            if bit != 2 && bit != 4 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    /// Swaps `a` and `b` when `swap` is 1, leaves them when it is 0, with
+    /// the same operations either way.
+    fn conditional_swap(a: &mut Self, b: &mut Self, swap: u64) {
+        let mask = swap.wrapping_neg();
+        for (x, y) in a.0.iter_mut().zip(b.0.iter_mut()) {
+            let difference = mask & (*x ^ *y);
+            *x ^= difference;
+            *y ^= difference;
+        }
+    }
+}
+
+/// Multiplies the point of u-coordinate `u` by `scalar`.
+pub(crate) fn x25519(scalar: &[u8; 32], u: &[u8; 32]) -> [u8; 32] {
+    let mut k = *scalar;
+    k[0] &= 248;
+    k[31] &= 127;
+    k[31] |= 64;
+
+    let x1 = FieldElement::from_bytes(u);
+    let (mut x2, mut z2) = (FieldElement::ONE, FieldElement::ZERO);
+    let (mut x3, mut z3) = (x1, FieldElement::ONE);
+    let mut swap = 0;
+    for bit in (0..255).rev() {
+        let k_bit = ((k[bit / 8] >> (bit % 8)) & 1) as u64;
+        swap ^= k_bit;
+        FieldElement::conditional_swap(&mut x2, &mut x3, swap);
+        FieldElement::conditional_swap(&mut z2, &mut z3, swap);
+        swap = k_bit;
+
+        let a = x2.add(z2);
+        let aa = a.square();
+        let b = x2.sub(z2);
+        let bb = b.square();
+        let e = aa.sub(bb);
+        let c = x3.add(z3);
+        let d = x3.sub(z3);
+        let da = d.mul(a);
+        let cb = c.mul(b);
+        x3 = da.add(cb).square();
+        z3 = x1.mul(da.sub(cb).square());
+        x2 = aa.mul(bb);
+        z2 = e.mul(aa.add(e.mul_small(121665)));
+    }
+    FieldElement::conditional_swap(&mut x2, &mut x3, swap);
+    FieldElement::conditional_swap(&mut z2, &mut z3, swap);
+
+    x2.mul(z2.invert()).to_bytes()
+}
+
+/// The public key of the private key `scalar`: its product with the base
+/// point, of u-coordinate 9.
+pub(crate) fn x25519_base(scalar: &[u8; 32]) -> [u8; 32] {
+    let mut base = [0u8; 32];
+    base[0] = 9;
+    x25519(scalar, &base)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    fn key(hex: &str) -> [u8; 32] {
+        from_hex(hex).try_into().unwrap()
+    }
+
+    #[test]
+    fn test_x25519() {
+        // RFC 7748, section 5.2
+        assert_eq!(
+            x25519(
+                &key("a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4"),
+                &key("e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c")
+            ),
+            key("c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552")
+        );
+    }
+
+    #[test]
+    fn test_diffie_hellman() {
+        // RFC 7748, section 6.1
+        let alice = key("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = key("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let alice_public = x25519_base(&alice);
+        let bob_public = x25519_base(&bob);
+        assert_eq!(alice_public, key("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"));
+        assert_eq!(bob_public, key("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f"));
+
+        let shared = key("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+        assert_eq!(x25519(&alice, &bob_public), shared);
+        assert_eq!(x25519(&bob, &alice_public), shared);
+    }
+}
@@ -0,0 +1,360 @@
+//! Character encodings of fetched documents.
+//!
+//! This module implements the decoders of the WHATWG Encoding Standard
+//! (<https://encoding.spec.whatwg.org/>) together with the encoding sniffing
+//! algorithm of the HTML specification, so that a `Response` body can be
+//! turned into the text the parser expects.
+
+mod index;
+mod multi_byte;
+mod single_byte;
+mod sniff;
+
+pub use sniff::{decode_response, sniff_encoding};
+
+/// The character U+FFFD, emitted in place of every malformed sequence.
+pub(crate) const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+/// An encoding of the Encoding Standard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Utf8,
+    Ibm866,
+    Iso8859_2,
+    Iso8859_3,
+    Iso8859_4,
+    Iso8859_5,
+    Iso8859_6,
+    Iso8859_7,
+    Iso8859_8,
+    Iso8859_8I,
+    Iso8859_10,
+    Iso8859_13,
+    Iso8859_14,
+    Iso8859_15,
+    Iso8859_16,
+    Koi8R,
+    Koi8U,
+    Macintosh,
+    Windows874,
+    Windows1250,
+    Windows1251,
+    Windows1252,
+    Windows1253,
+    Windows1254,
+    Windows1255,
+    Windows1256,
+    Windows1257,
+    Windows1258,
+    XMacCyrillic,
+    Gbk,
+    Gb18030,
+    Big5,
+    EucJp,
+    Iso2022Jp,
+    ShiftJis,
+    EucKr,
+    /// Stands in for encodings that are unsafe to decode (ISO-2022-KR,
+    /// HZ-GB-2312, ...): any non-empty input decodes to a single U+FFFD.
+    Replacement,
+    Utf16Be,
+    Utf16Le,
+    XUserDefined,
+}
+
+impl Encoding {
+    /// Gets an encoding from a label such as `"latin1"` or `" Shift_JIS "`.
+    ///
+    /// Labels are matched case-insensitively after trimming ASCII whitespace,
+    /// as the Encoding Standard's "get an encoding" does.
+    ///
+    /// # Returns
+    /// `None` if the label is not known.
+    pub fn for_label(label: &str) -> Option<Encoding> {
+        use Encoding::*;
+
+        let label = label
+            .trim_matches(|c| matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' '))
+            .to_ascii_lowercase();
+        let encoding =
+            match label.as_str() {
+                "unicode-1-1-utf-8" | "unicode11utf8" | "unicode20utf8" | "utf-8" | "utf8"
+                | "x-unicode20utf8" => Utf8,
+                "866" | "cp866" | "csibm866" | "ibm866" => Ibm866,
+                "csisolatin2" | "iso-8859-2" | "iso-ir-101" | "iso8859-2" | "iso88592"
+                | "iso_8859-2" | "iso_8859-2:1987" | "l2" | "latin2" => Iso8859_2,
+                "csisolatin3" | "iso-8859-3" | "iso-ir-109" | "iso8859-3" | "iso88593"
+                | "iso_8859-3" | "iso_8859-3:1988" | "l3" | "latin3" => Iso8859_3,
+                "csisolatin4" | "iso-8859-4" | "iso-ir-110" | "iso8859-4" | "iso88594"
+                | "iso_8859-4" | "iso_8859-4:1988" | "l4" | "latin4" => Iso8859_4,
+                "csisolatincyrillic" | "cyrillic" | "iso-8859-5" | "iso-ir-144" | "iso8859-5"
+                | "iso88595" | "iso_8859-5" | "iso_8859-5:1988" => Iso8859_5,
+                "arabic" | "asmo-708" | "csiso88596e" | "csiso88596i" | "csisolatinarabic"
+                | "ecma-114" | "iso-8859-6" | "iso-8859-6-e" | "iso-8859-6-i" | "iso-ir-127"
+                | "iso8859-6" | "iso88596" | "iso_8859-6" | "iso_8859-6:1987" => Iso8859_6,
+                "csisolatingreek" | "ecma-118" | "elot_928" | "greek" | "greek8" | "iso-8859-7"
+                | "iso-ir-126" | "iso8859-7" | "iso88597" | "iso_8859-7" | "iso_8859-7:1987"
+                | "sun_eu_greek" => Iso8859_7,
+                "csiso88598e" | "csisolatinhebrew" | "hebrew" | "iso-8859-8" | "iso-8859-8-e"
+                | "iso-ir-138" | "iso8859-8" | "iso88598" | "iso_8859-8" | "iso_8859-8:1988"
+                | "visual" => Iso8859_8,
+                "csiso88598i" | "iso-8859-8-i" | "logical" => Iso8859_8I,
+                "csisolatin6" | "iso-8859-10" | "iso-ir-157" | "iso8859-10" | "iso885910"
+                | "l6" | "latin6" => Iso8859_10,
+                "iso-8859-13" | "iso8859-13" | "iso885913" => Iso8859_13,
+                "iso-8859-14" | "iso8859-14" | "iso885914" => Iso8859_14,
+                "csisolatin9" | "iso-8859-15" | "iso8859-15" | "iso885915" | "iso_8859-15"
+                | "l9" => Iso8859_15,
+                "iso-8859-16" => Iso8859_16,
+                "cskoi8r" | "koi" | "koi8" | "koi8-r" | "koi8_r" => Koi8R,
+                "koi8-ru" | "koi8-u" => Koi8U,
+                "csmacintosh" | "mac" | "macintosh" | "x-mac-roman" => Macintosh,
+                "dos-874" | "iso-8859-11" | "iso8859-11" | "iso885911" | "tis-620"
+                | "windows-874" => Windows874,
+                "cp1250" | "windows-1250" | "x-cp1250" => Windows1250,
+                "cp1251" | "windows-1251" | "x-cp1251" => Windows1251,
+                "ansi_x3.4-1968" | "ascii" | "cp1252" | "cp819" | "csisolatin1" | "ibm819"
+                | "iso-8859-1" | "iso-ir-100" | "iso8859-1" | "iso88591" | "iso_8859-1"
+                | "iso_8859-1:1987" | "l1" | "latin1" | "us-ascii" | "windows-1252"
+                | "x-cp1252" => Windows1252,
+                "cp1253" | "windows-1253" | "x-cp1253" => Windows1253,
+                "cp1254" | "csisolatin5" | "iso-8859-9" | "iso-ir-148" | "iso8859-9"
+                | "iso88599" | "iso_8859-9" | "iso_8859-9:1989" | "l5" | "latin5"
+                | "windows-1254" | "x-cp1254" => Windows1254,
+                "cp1255" | "windows-1255" | "x-cp1255" => Windows1255,
+                "cp1256" | "windows-1256" | "x-cp1256" => Windows1256,
+                "cp1257" | "windows-1257" | "x-cp1257" => Windows1257,
+                "cp1258" | "windows-1258" | "x-cp1258" => Windows1258,
+                "x-mac-cyrillic" | "x-mac-ukrainian" => XMacCyrillic,
+                "chinese" | "csgb2312" | "csiso58gb231280" | "gb2312" | "gb_2312"
+                | "gb_2312-80" | "gbk" | "iso-ir-58" | "x-gbk" => Gbk,
+                "gb18030" => Gb18030,
+                "big5" | "big5-hkscs" | "cn-big5" | "csbig5" | "x-x-big5" => Big5,
+                "cseucpkdfmtjapanese" | "euc-jp" | "x-euc-jp" => EucJp,
+                "csiso2022jp" | "iso-2022-jp" => Iso2022Jp,
+                "csshiftjis" | "ms932" | "ms_kanji" | "shift-jis" | "shift_jis" | "sjis"
+                | "windows-31j" | "x-sjis" => ShiftJis,
+                "cseuckr" | "csksc56011987" | "euc-kr" | "iso-ir-149" | "korean"
+                | "ks_c_5601-1987" | "ks_c_5601-1989" | "ksc5601" | "ksc_5601" | "windows-949" => {
+                    EucKr
+                }
+                "csiso2022kr" | "hz-gb-2312" | "iso-2022-cn" | "iso-2022-cn-ext"
+                | "iso-2022-kr" | "replacement" => Replacement,
+                "unicodefffe" | "utf-16be" => Utf16Be,
+                "csunicode" | "iso-10646-ucs-2" | "ucs-2" | "unicode" | "unicodefeff"
+                | "utf-16" | "utf-16le" => Utf16Le,
+                "x-user-defined" => XUserDefined,
+                _ => return None,
+            };
+        Some(encoding)
+    }
+
+    /// The canonical name of the encoding, e.g. `"windows-1252"`.
+    pub fn name(&self) -> &'static str {
+        use Encoding::*;
+
+        match self {
+            Utf8 => "UTF-8",
+            Ibm866 => "IBM866",
+            Iso8859_2 => "ISO-8859-2",
+            Iso8859_3 => "ISO-8859-3",
+            Iso8859_4 => "ISO-8859-4",
+            Iso8859_5 => "ISO-8859-5",
+            Iso8859_6 => "ISO-8859-6",
+            Iso8859_7 => "ISO-8859-7",
+            Iso8859_8 => "ISO-8859-8",
+            Iso8859_8I => "ISO-8859-8-I",
+            Iso8859_10 => "ISO-8859-10",
+            Iso8859_13 => "ISO-8859-13",
+            Iso8859_14 => "ISO-8859-14",
+            Iso8859_15 => "ISO-8859-15",
+            Iso8859_16 => "ISO-8859-16",
+            Koi8R => "KOI8-R",
+            Koi8U => "KOI8-U",
+            Macintosh => "macintosh",
+            Windows874 => "windows-874",
+            Windows1250 => "windows-1250",
+            Windows1251 => "windows-1251",
+            Windows1252 => "windows-1252",
+            Windows1253 => "windows-1253",
+            Windows1254 => "windows-1254",
+            Windows1255 => "windows-1255",
+            Windows1256 => "windows-1256",
+            Windows1257 => "windows-1257",
+            Windows1258 => "windows-1258",
+            XMacCyrillic => "x-mac-cyrillic",
+            Gbk => "GBK",
+            Gb18030 => "gb18030",
+            Big5 => "Big5",
+            EucJp => "EUC-JP",
+            Iso2022Jp => "ISO-2022-JP",
+            ShiftJis => "Shift_JIS",
+            EucKr => "EUC-KR",
+            Replacement => "replacement",
+            Utf16Be => "UTF-16BE",
+            Utf16Le => "UTF-16LE",
+            XUserDefined => "x-user-defined",
+        }
+    }
+
+    /// Decodes `bytes`, replacing malformed sequences with U+FFFD.
+    ///
+    /// A byte order mark is not treated specially here; `decode_response`
+    /// takes care of it before picking the encoding.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        use Encoding::*;
+
+        let mut output = String::with_capacity(bytes.len());
+        match self {
+            Utf8 => output.push_str(&String::from_utf8_lossy(bytes)),
+            Ibm866 => single_byte::decode(&single_byte::IBM866, bytes, &mut output),
+            Iso8859_2 => single_byte::decode(&single_byte::ISO_8859_2, bytes, &mut output),
+            Iso8859_3 => single_byte::decode(&single_byte::ISO_8859_3, bytes, &mut output),
+            Iso8859_4 => single_byte::decode(&single_byte::ISO_8859_4, bytes, &mut output),
+            Iso8859_5 => single_byte::decode(&single_byte::ISO_8859_5, bytes, &mut output),
+            Iso8859_6 => single_byte::decode(&single_byte::ISO_8859_6, bytes, &mut output),
+            Iso8859_7 => single_byte::decode(&single_byte::ISO_8859_7, bytes, &mut output),
+            // ISO-8859-8-I only differs from ISO-8859-8 in its bidi handling.
+            Iso8859_8 | Iso8859_8I => {
+                single_byte::decode(&single_byte::ISO_8859_8, bytes, &mut output)
+            }
+            Iso8859_10 => single_byte::decode(&single_byte::ISO_8859_10, bytes, &mut output),
+            Iso8859_13 => single_byte::decode(&single_byte::ISO_8859_13, bytes, &mut output),
+            Iso8859_14 => single_byte::decode(&single_byte::ISO_8859_14, bytes, &mut output),
+            Iso8859_15 => single_byte::decode(&single_byte::ISO_8859_15, bytes, &mut output),
+            Iso8859_16 => single_byte::decode(&single_byte::ISO_8859_16, bytes, &mut output),
+            Koi8R => single_byte::decode(&single_byte::KOI8_R, bytes, &mut output),
+            Koi8U => single_byte::decode(&single_byte::KOI8_U, bytes, &mut output),
+            Macintosh => single_byte::decode(&single_byte::MACINTOSH, bytes, &mut output),
+            Windows874 => single_byte::decode(&single_byte::WINDOWS_874, bytes, &mut output),
+            Windows1250 => single_byte::decode(&single_byte::WINDOWS_1250, bytes, &mut output),
+            Windows1251 => single_byte::decode(&single_byte::WINDOWS_1251, bytes, &mut output),
+            Windows1252 => single_byte::decode(&single_byte::WINDOWS_1252, bytes, &mut output),
+            Windows1253 => single_byte::decode(&single_byte::WINDOWS_1253, bytes, &mut output),
+            Windows1254 => single_byte::decode(&single_byte::WINDOWS_1254, bytes, &mut output),
+            Windows1255 => single_byte::decode(&single_byte::WINDOWS_1255, bytes, &mut output),
+            Windows1256 => single_byte::decode(&single_byte::WINDOWS_1256, bytes, &mut output),
+            Windows1257 => single_byte::decode(&single_byte::WINDOWS_1257, bytes, &mut output),
+            Windows1258 => single_byte::decode(&single_byte::WINDOWS_1258, bytes, &mut output),
+            XMacCyrillic => single_byte::decode(&single_byte::X_MAC_CYRILLIC, bytes, &mut output),
+            Gbk | Gb18030 => multi_byte::decode_gb18030(bytes, &mut output),
+            Big5 => multi_byte::decode_big5(bytes, &mut output),
+            EucJp => multi_byte::decode_euc_jp(bytes, &mut output),
+            Iso2022Jp => multi_byte::decode_iso_2022_jp(bytes, &mut output),
+            ShiftJis => multi_byte::decode_shift_jis(bytes, &mut output),
+            EucKr => multi_byte::decode_euc_kr(bytes, &mut output),
+            Replacement => {
+                if !bytes.is_empty() {
+                    output.push(REPLACEMENT_CHARACTER);
+                }
+            }
+            Utf16Be => decode_utf16(bytes, u16::from_be_bytes, &mut output),
+            Utf16Le => decode_utf16(bytes, u16::from_le_bytes, &mut output),
+            XUserDefined => output.extend(bytes.iter().map(|&byte| {
+                if byte < 0x80 {
+                    char::from(byte)
+                } else {
+                    char::from_u32(0xF780 + u32::from(byte - 0x80)).unwrap_or(REPLACEMENT_CHARACTER)
+                }
+            })),
+        }
+        output
+    }
+}
+
+/// Decodes UTF-16 code units read with `read_unit`; unpaired surrogates and a
+/// trailing odd byte each become U+FFFD.
+fn decode_utf16(bytes: &[u8], read_unit: fn([u8; 2]) -> u16, output: &mut String) {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| read_unit([pair[0], pair[1]]));
+    output.extend(char::decode_utf16(units).map(|c| c.unwrap_or(REPLACEMENT_CHARACTER)));
+    if bytes.len() % 2 == 1 {
+        output.push(REPLACEMENT_CHARACTER);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_label() {
+        assert_eq!(Encoding::for_label("utf-8"), Some(Encoding::Utf8));
+        assert_eq!(
+            Encoding::for_label(" Latin1\n"),
+            Some(Encoding::Windows1252)
+        );
+        assert_eq!(
+            Encoding::for_label("ISO-8859-1"),
+            Some(Encoding::Windows1252)
+        );
+        assert_eq!(Encoding::for_label("x-sjis"), Some(Encoding::ShiftJis));
+        assert_eq!(Encoding::for_label("utf-16"), Some(Encoding::Utf16Le));
+        assert_eq!(
+            Encoding::for_label("iso-2022-kr"),
+            Some(Encoding::Replacement)
+        );
+        assert_eq!(Encoding::for_label("utf-7"), None);
+    }
+
+    #[test]
+    fn test_decode_single_byte() {
+        assert_eq!(
+            Encoding::Windows1252.decode(b"caf\xE9 \x80\x81"),
+            "café €\u{81}"
+        );
+        assert_eq!(Encoding::Iso8859_2.decode(b"\xB1"), "ą");
+        assert_eq!(
+            Encoding::Koi8R.decode(b"\xF0\xD2\xC9\xD7\xC5\xD4"),
+            "Привет"
+        );
+        assert_eq!(Encoding::Windows1253.decode(b"\xAA"), "\u{FFFD}");
+        assert_eq!(Encoding::XUserDefined.decode(b"a\x80"), "a\u{F780}");
+    }
+
+    #[test]
+    fn test_decode_utf8_and_utf16() {
+        assert_eq!(Encoding::Utf8.decode(b"h\xC3\xA9\xFFo"), "hé\u{FFFD}o");
+        assert_eq!(Encoding::Utf16Le.decode(b"h\x00\xE9\x00"), "hé");
+        assert_eq!(Encoding::Utf16Be.decode(b"\xD8\x3D\xDE\x00"), "😀");
+        assert_eq!(
+            Encoding::Utf16Be.decode(b"\xD8\x3D\x00"),
+            "\u{FFFD}\u{FFFD}"
+        );
+    }
+
+    #[test]
+    fn test_decode_japanese() {
+        assert_eq!(Encoding::ShiftJis.decode(b"\x93\xFA\x96\x7B\xB1"), "日本ｱ");
+        assert_eq!(Encoding::EucJp.decode(b"\xC6\xFC\xCB\xDC\x8E\xB1"), "日本ｱ");
+        assert_eq!(Encoding::EucJp.decode(b"\x8F\xB0\xA1"), "丂");
+        assert_eq!(
+            Encoding::Iso2022Jp.decode(b"a\x1B$B\x46\x7C\x4B\x5C\x1B(Bb"),
+            "a日本b"
+        );
+        // A lead byte followed by ASCII keeps the ASCII byte.
+        assert_eq!(Encoding::ShiftJis.decode(b"\x93 a"), "\u{FFFD} a");
+    }
+
+    #[test]
+    fn test_decode_chinese_and_korean() {
+        assert_eq!(Encoding::Gbk.decode(b"\xC4\xE3\xBA\xC3"), "你好");
+        assert_eq!(
+            Encoding::Gb18030.decode(b"\x81\x30\x81\x30\x94\x39\xFC\x36"),
+            "\u{80}😀"
+        );
+        assert_eq!(
+            Encoding::Big5.decode(b"\xA4\xA4\xA4\xE5\x88\x62"),
+            "中文\u{CA}\u{304}"
+        );
+        assert_eq!(Encoding::EucKr.decode(b"\xC7\xD1\xB1\xB9"), "한국");
+        assert_eq!(Encoding::EucKr.decode(b"\xC7"), "\u{FFFD}");
+    }
+
+    #[test]
+    fn test_decode_replacement() {
+        assert_eq!(Encoding::Replacement.decode(b"anything"), "\u{FFFD}");
+        assert_eq!(Encoding::Replacement.decode(b""), "");
+    }
+}
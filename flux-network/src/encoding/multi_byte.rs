@@ -0,0 +1,437 @@
+//! Decoders for the legacy multi-byte encodings of the Encoding Standard.
+//!
+//! Each decoder follows the algorithm of its section in
+//! <https://encoding.spec.whatwg.org/> with the "replacement" error mode:
+//! every error appends one U+FFFD to the output.
+
+use super::index;
+use super::REPLACEMENT_CHARACTER;
+
+/// The input of a decoder.
+///
+/// Bytes that the spec "restores" to the queue are always the last ones read,
+/// so restoring is a matter of moving the read position back.
+struct ByteQueue<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteQueue<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        ByteQueue { bytes, position: 0 }
+    }
+
+    /// Reads the next byte, or `None` at the end of the queue.
+    fn read(&mut self) -> Option<u8> {
+        let byte = self.bytes.get(self.position).copied()?;
+        self.position += 1;
+        Some(byte)
+    }
+
+    /// Puts the last `count` bytes read back at the front of the queue.
+    fn restore(&mut self, count: usize) {
+        self.position -= count;
+    }
+}
+
+/// Looks `pointer` up in a two-byte index.
+fn lookup(index: &[u16], pointer: usize) -> Option<char> {
+    match index.get(pointer).copied() {
+        None | Some(0) => None,
+        Some(code_point) => char::from_u32(u32::from(code_point)),
+    }
+}
+
+/// The `index gb18030 ranges code point` for a four-byte sequence.
+fn gb18030_ranges_code_point(pointer: u32) -> Option<char> {
+    if (39420..189000).contains(&pointer) || pointer > 1237575 {
+        return None;
+    }
+    if pointer == 7457 {
+        return Some('\u{E7C7}');
+    }
+    if pointer >= 189000 {
+        return char::from_u32(0x10000 + pointer - 189000);
+    }
+    let range = index::GB18030_RANGES.partition_point(|&(offset, _)| offset <= pointer) - 1;
+    let (offset, code_point) = index::GB18030_RANGES[range];
+    char::from_u32(code_point + pointer - offset)
+}
+
+/// Decodes gb18030, which is also the decoder of GBK.
+pub(crate) fn decode_gb18030(bytes: &[u8], output: &mut String) {
+    let mut queue = ByteQueue::new(bytes);
+    let (mut first, mut second, mut third) = (0u8, 0u8, 0u8);
+    loop {
+        let Some(byte) = queue.read() else {
+            if first != 0 || second != 0 || third != 0 {
+                output.push(REPLACEMENT_CHARACTER);
+            }
+            return;
+        };
+        if third != 0 {
+            if !(0x30..=0x39).contains(&byte) {
+                queue.restore(3);
+                (first, second, third) = (0, 0, 0);
+                output.push(REPLACEMENT_CHARACTER);
+                continue;
+            }
+            let pointer = ((u32::from(first - 0x81) * 10 + u32::from(second - 0x30)) * 126
+                + u32::from(third - 0x81))
+                * 10
+                + u32::from(byte - 0x30);
+            (first, second, third) = (0, 0, 0);
+            output.push(gb18030_ranges_code_point(pointer).unwrap_or(REPLACEMENT_CHARACTER));
+            continue;
+        }
+        if second != 0 {
+            if (0x81..=0xFE).contains(&byte) {
+                third = byte;
+                continue;
+            }
+            queue.restore(2);
+            (first, second) = (0, 0);
+            output.push(REPLACEMENT_CHARACTER);
+            continue;
+        }
+        if first != 0 {
+            if (0x30..=0x39).contains(&byte) {
+                second = byte;
+                continue;
+            }
+            let lead = first;
+            first = 0;
+            let offset = if byte < 0x7F { 0x40 } else { 0x41 };
+            let code_point = match byte {
+                0x40..=0x7E | 0x80..=0xFE => lookup(
+                    index::GB18030,
+                    usize::from(lead - 0x81) * 190 + usize::from(byte - offset),
+                ),
+                _ => None,
+            };
+            match code_point {
+                Some(c) => output.push(c),
+                None => {
+                    if byte.is_ascii() {
+                        queue.restore(1);
+                    }
+                    output.push(REPLACEMENT_CHARACTER);
+                }
+            }
+            continue;
+        }
+        match byte {
+            0x00..=0x7F => output.push(char::from(byte)),
+            0x80 => output.push('\u{20AC}'),
+            0x81..=0xFE => first = byte,
+            _ => output.push(REPLACEMENT_CHARACTER),
+        }
+    }
+}
+
+/// Decodes Big5, HKSCS extensions included.
+pub(crate) fn decode_big5(bytes: &[u8], output: &mut String) {
+    let mut queue = ByteQueue::new(bytes);
+    let mut lead = 0u8;
+    loop {
+        let Some(byte) = queue.read() else {
+            if lead != 0 {
+                output.push(REPLACEMENT_CHARACTER);
+            }
+            return;
+        };
+        if lead != 0 {
+            let offset = if byte < 0x7F { 0x40 } else { 0x62 };
+            let pointer = match byte {
+                0x40..=0x7E | 0xA1..=0xFE => {
+                    Some(usize::from(lead - 0x81) * 157 + usize::from(byte - offset))
+                }
+                _ => None,
+            };
+            lead = 0;
+            // Four pointers decode to a base letter followed by a combining mark.
+            match pointer {
+                Some(1133) => output.push_str("\u{CA}\u{304}"),
+                Some(1135) => output.push_str("\u{CA}\u{30C}"),
+                Some(1164) => output.push_str("\u{EA}\u{304}"),
+                Some(1166) => output.push_str("\u{EA}\u{30C}"),
+                _ => {
+                    let code_point = pointer
+                        .and_then(|pointer| index::BIG5.get(pointer).copied())
+                        .filter(|&code_point| code_point != 0)
+                        .and_then(char::from_u32);
+                    match code_point {
+                        Some(c) => output.push(c),
+                        None => {
+                            if byte.is_ascii() {
+                                queue.restore(1);
+                            }
+                            output.push(REPLACEMENT_CHARACTER);
+                        }
+                    }
+                }
+            }
+            continue;
+        }
+        match byte {
+            0x00..=0x7F => output.push(char::from(byte)),
+            0x81..=0xFE => lead = byte,
+            _ => output.push(REPLACEMENT_CHARACTER),
+        }
+    }
+}
+
+/// Decodes EUC-JP.
+pub(crate) fn decode_euc_jp(bytes: &[u8], output: &mut String) {
+    let mut queue = ByteQueue::new(bytes);
+    let mut lead = 0u8;
+    let mut jis0212 = false;
+    loop {
+        let Some(byte) = queue.read() else {
+            if lead != 0 {
+                output.push(REPLACEMENT_CHARACTER);
+            }
+            return;
+        };
+        if lead == 0x8E && (0xA1..=0xDF).contains(&byte) {
+            lead = 0;
+            output.push(half_width_katakana(byte - 0xA1));
+            continue;
+        }
+        if lead == 0x8F && (0xA1..=0xFE).contains(&byte) {
+            jis0212 = true;
+            lead = byte;
+            continue;
+        }
+        if lead != 0 {
+            let code_point = if (0xA1..=0xFE).contains(&lead) && (0xA1..=0xFE).contains(&byte) {
+                let index: &[u16] = if jis0212 {
+                    index::JIS0212
+                } else {
+                    index::JIS0208
+                };
+                lookup(
+                    index,
+                    usize::from(lead - 0xA1) * 94 + usize::from(byte - 0xA1),
+                )
+            } else {
+                None
+            };
+            lead = 0;
+            jis0212 = false;
+            match code_point {
+                Some(c) => output.push(c),
+                None => {
+                    if byte.is_ascii() {
+                        queue.restore(1);
+                    }
+                    output.push(REPLACEMENT_CHARACTER);
+                }
+            }
+            continue;
+        }
+        match byte {
+            0x00..=0x7F => output.push(char::from(byte)),
+            0x8E | 0x8F | 0xA1..=0xFE => lead = byte,
+            _ => output.push(REPLACEMENT_CHARACTER),
+        }
+    }
+}
+
+/// The states of the ISO-2022-JP decoder.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Iso2022JpState {
+    Ascii,
+    Roman,
+    Katakana,
+    LeadByte,
+    TrailByte,
+    EscapeStart,
+    Escape,
+}
+
+/// Decodes ISO-2022-JP.
+pub(crate) fn decode_iso_2022_jp(bytes: &[u8], output: &mut String) {
+    use Iso2022JpState::*;
+
+    let mut queue = ByteQueue::new(bytes);
+    let mut state = Ascii;
+    let mut output_state = Ascii;
+    let mut lead = 0u8;
+    // Set after an escape sequence, so that two escapes in a row, which
+    // produce nothing in between, are reported as an error.
+    let mut output_flag = false;
+    loop {
+        let byte = queue.read();
+        match state {
+            Ascii | Roman | Katakana | LeadByte => match byte {
+                None => return,
+                Some(0x1B) => state = EscapeStart,
+                Some(byte) => {
+                    output_flag = false;
+                    match (state, byte) {
+                        (Ascii, 0x0E | 0x0F) | (Roman, 0x0E | 0x0F) => {
+                            output.push(REPLACEMENT_CHARACTER)
+                        }
+                        (Ascii, 0x00..=0x7F) => output.push(char::from(byte)),
+                        (Roman, 0x5C) => output.push('\u{A5}'),
+                        (Roman, 0x7E) => output.push('\u{203E}'),
+                        (Roman, 0x00..=0x7F) => output.push(char::from(byte)),
+                        (Katakana, 0x21..=0x5F) => output.push(half_width_katakana(byte - 0x21)),
+                        (LeadByte, 0x21..=0x7E) => {
+                            lead = byte;
+                            state = TrailByte;
+                        }
+                        _ => output.push(REPLACEMENT_CHARACTER),
+                    }
+                }
+            },
+            TrailByte => match byte {
+                Some(0x1B) => {
+                    state = EscapeStart;
+                    output.push(REPLACEMENT_CHARACTER);
+                }
+                Some(byte @ 0x21..=0x7E) => {
+                    state = LeadByte;
+                    let pointer = usize::from(lead - 0x21) * 94 + usize::from(byte - 0x21);
+                    output.push(lookup(index::JIS0208, pointer).unwrap_or(REPLACEMENT_CHARACTER));
+                }
+                _ => {
+                    state = LeadByte;
+                    output.push(REPLACEMENT_CHARACTER);
+                }
+            },
+            EscapeStart => match byte {
+                Some(byte @ (0x24 | 0x28)) => {
+                    lead = byte;
+                    state = Escape;
+                }
+                _ => {
+                    if byte.is_some() {
+                        queue.restore(1);
+                    }
+                    output_flag = false;
+                    state = output_state;
+                    output.push(REPLACEMENT_CHARACTER);
+                }
+            },
+            Escape => {
+                let next_state = match (lead, byte) {
+                    (0x28, Some(0x42)) => Some(Ascii),
+                    (0x28, Some(0x4A)) => Some(Roman),
+                    (0x28, Some(0x49)) => Some(Katakana),
+                    (0x24, Some(0x40 | 0x42)) => Some(LeadByte),
+                    _ => None,
+                };
+                lead = 0;
+                match next_state {
+                    Some(next_state) => {
+                        state = next_state;
+                        output_state = next_state;
+                        if output_flag {
+                            output.push(REPLACEMENT_CHARACTER);
+                        }
+                        output_flag = true;
+                    }
+                    None => {
+                        queue.restore(if byte.is_some() { 2 } else { 1 });
+                        output_flag = false;
+                        state = output_state;
+                        output.push(REPLACEMENT_CHARACTER);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Decodes Shift_JIS.
+pub(crate) fn decode_shift_jis(bytes: &[u8], output: &mut String) {
+    let mut queue = ByteQueue::new(bytes);
+    let mut lead = 0u8;
+    loop {
+        let Some(byte) = queue.read() else {
+            if lead != 0 {
+                output.push(REPLACEMENT_CHARACTER);
+            }
+            return;
+        };
+        if lead != 0 {
+            let offset = if byte < 0x7F { 0x40 } else { 0x41 };
+            let lead_offset = if lead < 0xA0 { 0x81 } else { 0xC1 };
+            let pointer = match byte {
+                0x40..=0x7E | 0x80..=0xFC => {
+                    Some(usize::from(lead - lead_offset) * 188 + usize::from(byte - offset))
+                }
+                _ => None,
+            };
+            lead = 0;
+            let code_point = match pointer {
+                // The end-user-defined area maps onto the Private Use Area.
+                Some(pointer @ 8836..=10715) => char::from_u32(0xE000 - 8836 + pointer as u32),
+                Some(pointer) => lookup(index::JIS0208, pointer),
+                None => None,
+            };
+            match code_point {
+                Some(c) => output.push(c),
+                None => {
+                    if byte.is_ascii() {
+                        queue.restore(1);
+                    }
+                    output.push(REPLACEMENT_CHARACTER);
+                }
+            }
+            continue;
+        }
+        match byte {
+            0x00..=0x80 => output.push(char::from(byte)),
+            0xA1..=0xDF => output.push(half_width_katakana(byte - 0xA1)),
+            0x81..=0x9F | 0xE0..=0xFC => lead = byte,
+            _ => output.push(REPLACEMENT_CHARACTER),
+        }
+    }
+}
+
+/// Decodes EUC-KR, which the web uses for the whole of Windows code page 949.
+pub(crate) fn decode_euc_kr(bytes: &[u8], output: &mut String) {
+    let mut queue = ByteQueue::new(bytes);
+    let mut lead = 0u8;
+    loop {
+        let Some(byte) = queue.read() else {
+            if lead != 0 {
+                output.push(REPLACEMENT_CHARACTER);
+            }
+            return;
+        };
+        if lead != 0 {
+            let code_point = match byte {
+                0x41..=0xFE => lookup(
+                    index::EUC_KR,
+                    usize::from(lead - 0x81) * 190 + usize::from(byte - 0x41),
+                ),
+                _ => None,
+            };
+            lead = 0;
+            match code_point {
+                Some(c) => output.push(c),
+                None => {
+                    if byte.is_ascii() {
+                        queue.restore(1);
+                    }
+                    output.push(REPLACEMENT_CHARACTER);
+                }
+            }
+            continue;
+        }
+        match byte {
+            0x00..=0x7F => output.push(char::from(byte)),
+            0x81..=0xFE => lead = byte,
+            _ => output.push(REPLACEMENT_CHARACTER),
+        }
+    }
+}
+
+/// The half-width katakana block starting at U+FF61.
+fn half_width_katakana(offset: u8) -> char {
+    char::from_u32(0xFF61 + u32::from(offset)).unwrap_or(REPLACEMENT_CHARACTER)
+}
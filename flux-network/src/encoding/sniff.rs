@@ -0,0 +1,405 @@
+//! The encoding sniffing algorithm of the HTML specification.
+//!
+//! See <https://html.spec.whatwg.org/multipage/parsing.html#encoding-sniffing-algorithm>.
+//! The encoding is taken, in order of precedence, from a byte order mark, the
+//! `charset` parameter of the `Content-Type` header, a `<meta>` declaration
+//! found by prescanning the start of the document, and finally a default.
+
+use super::Encoding;
+use crate::Response;
+
+/// Number of bytes the `<meta>` prescan looks at.
+const PRESCAN_LENGTH: usize = 1024;
+
+/// Decodes the body of `response` into a document string.
+///
+/// # Returns
+/// The decoded text along with the encoding that was used, picked by
+/// `sniff_encoding` from the body and the `Content-Type` header. A byte order
+/// mark is stripped from the text.
+///
+/// # Example
+/// ```rust
+/// use flux_network::{decode_response, Encoding, Response};
+///
+/// let response = Response {
+///     status_code: 200,
+///     reason_phrase: "OK".to_string(),
+///     headers: vec![("Content-Type".to_string(), "text/html; charset=latin1".to_string())],
+///     body: b"caf\xE9".to_vec(),
+///     url: None,
+/// };
+/// assert_eq!(decode_response(&response), ("café".to_string(), Encoding::Windows1252));
+/// ```
+pub fn decode_response(response: &Response) -> (String, Encoding) {
+    let content_type = response.get_header("Content-Type");
+    let encoding = sniff_encoding(&response.body, content_type.as_deref());
+    let bom_length = bom_sniff(&response.body).map_or(0, |(_, length)| length);
+    (encoding.decode(&response.body[bom_length..]), encoding)
+}
+
+/// Determines the encoding of a document.
+///
+/// # Arguments
+/// * `bytes` - The start of the document, or all of it.
+/// * `content_type` - The value of the `Content-Type` header, if any.
+pub fn sniff_encoding(bytes: &[u8], content_type: Option<&str>) -> Encoding {
+    if let Some((encoding, _)) = bom_sniff(bytes) {
+        return encoding;
+    }
+    if let Some(encoding) = content_type.and_then(charset_from_content_type) {
+        return encoding;
+    }
+    if let Some(encoding) = prescan(bytes) {
+        return encoding;
+    }
+    // Nothing declared the encoding. A document that is valid UTF-8 and not
+    // plain ASCII is almost certainly UTF-8; anything else gets the legacy
+    // default of the web.
+    if !bytes.is_ascii() && std::str::from_utf8(bytes).is_ok() {
+        return Encoding::Utf8;
+    }
+    Encoding::Windows1252
+}
+
+/// Looks for a byte order mark, returning its encoding and length.
+fn bom_sniff(bytes: &[u8]) -> Option<(Encoding, usize)> {
+    if bytes.starts_with(b"\xEF\xBB\xBF") {
+        Some((Encoding::Utf8, 3))
+    } else if bytes.starts_with(b"\xFE\xFF") {
+        Some((Encoding::Utf16Be, 2))
+    } else if bytes.starts_with(b"\xFF\xFE") {
+        Some((Encoding::Utf16Le, 2))
+    } else {
+        None
+    }
+}
+
+/// Extracts the encoding from the `charset` parameter of a MIME type such as
+/// `text/html; charset="utf-8"`.
+fn charset_from_content_type(content_type: &str) -> Option<Encoding> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case("charset") {
+            return None;
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        Encoding::for_label(value)
+    })
+}
+
+/// Whether `byte` is one of the whitespace bytes of the prescan algorithm.
+fn is_space(byte: u8) -> bool {
+    matches!(byte, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+/// Finds the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Prescans the first bytes of a document for a `<meta charset>` or
+/// `<meta http-equiv="content-type" content="...">` declaration.
+fn prescan(bytes: &[u8]) -> Option<Encoding> {
+    let mut prescan = Prescan {
+        bytes: &bytes[..bytes.len().min(PRESCAN_LENGTH)],
+        position: 0,
+    };
+    prescan.run()
+}
+
+/// State of the prescan: the bytes to look at and the current position.
+struct Prescan<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Prescan<'_> {
+    fn byte(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn rest(&self) -> &[u8] {
+        &self.bytes[self.position.min(self.bytes.len())..]
+    }
+
+    fn run(&mut self) -> Option<Encoding> {
+        while self.position < self.bytes.len() {
+            let rest = self.rest();
+            if rest.starts_with(b"<!--") {
+                // The closing "-->" may share its dashes with the opening, as in "<!-->".
+                self.position += 2 + find(&rest[2..], b"-->")? + 2;
+            } else if rest.len() > 5
+                && rest[..5].eq_ignore_ascii_case(b"<meta")
+                && (is_space(rest[5]) || rest[5] == b'/')
+            {
+                self.position += 6;
+                if let Some(encoding) = self.meta()? {
+                    return Some(encoding);
+                }
+            } else if rest.len() > 1
+                && rest[0] == b'<'
+                && (rest[1].is_ascii_alphabetic()
+                    || (rest[1] == b'/' && rest.get(2).is_some_and(u8::is_ascii_alphabetic)))
+            {
+                self.position += rest.iter().position(|&b| is_space(b) || b == b'>')?;
+                while self.attribute().is_some() {}
+                if self.position >= self.bytes.len() {
+                    return None;
+                }
+            } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?")
+            {
+                self.position += rest.iter().position(|&b| b == b'>')?;
+            }
+            self.position += 1;
+        }
+        None
+    }
+
+    /// Processes the attributes of a `<meta>` tag.
+    ///
+    /// # Returns
+    /// `None` if the input ran out, `Some(None)` if the tag declares no usable
+    /// encoding, and `Some(Some(encoding))` otherwise.
+    fn meta(&mut self) -> Option<Option<Encoding>> {
+        let mut names: Vec<Vec<u8>> = Vec::new();
+        let mut got_pragma = false;
+        let mut need_pragma = None;
+        let mut charset = None;
+
+        while let Some((name, value)) = self.attribute() {
+            if names.contains(&name) {
+                continue;
+            }
+            match name.as_slice() {
+                b"http-equiv" => got_pragma |= value == b"content-type",
+                b"content" if charset.is_none() => {
+                    if let Some(encoding) = charset_from_meta_content(&value) {
+                        charset = Some(encoding);
+                        need_pragma = Some(true);
+                    }
+                }
+                b"charset" => {
+                    charset = Encoding::for_label(&String::from_utf8_lossy(&value));
+                    need_pragma = Some(false);
+                }
+                _ => {}
+            }
+            names.push(name);
+        }
+        if self.position >= self.bytes.len() {
+            return None;
+        }
+
+        let declared = match need_pragma {
+            None => None,
+            Some(true) if !got_pragma => None,
+            Some(_) => charset,
+        };
+        // A document that can be prescanned is not UTF-16, whatever it says.
+        Some(declared.map(|encoding| match encoding {
+            Encoding::Utf16Be | Encoding::Utf16Le => Encoding::Utf8,
+            Encoding::XUserDefined => Encoding::Windows1252,
+            encoding => encoding,
+        }))
+    }
+
+    /// The "get an attribute" algorithm, returning a lowercased name and value.
+    fn attribute(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+        while self.byte().is_some_and(|b| is_space(b) || b == b'/') {
+            self.position += 1;
+        }
+        if self.byte()? == b'>' {
+            return None;
+        }
+
+        let mut name = Vec::new();
+        loop {
+            match self.byte()? {
+                b'=' if !name.is_empty() => {
+                    self.position += 1;
+                    return self.attribute_value(name);
+                }
+                b if is_space(b) => break,
+                b'/' | b'>' => return Some((name, Vec::new())),
+                b => name.push(b.to_ascii_lowercase()),
+            }
+            self.position += 1;
+        }
+
+        while self.byte().is_some_and(is_space) {
+            self.position += 1;
+        }
+        if self.byte()? != b'=' {
+            return Some((name, Vec::new()));
+        }
+        self.position += 1;
+        self.attribute_value(name)
+    }
+
+    /// The value part of "get an attribute", entered after the `=`.
+    fn attribute_value(&mut self, name: Vec<u8>) -> Option<(Vec<u8>, Vec<u8>)> {
+        let mut value = Vec::new();
+        while self.byte().is_some_and(is_space) {
+            self.position += 1;
+        }
+        match self.byte()? {
+            quote @ (b'"' | b'\'') => loop {
+                self.position += 1;
+                match self.byte()? {
+                    b if b == quote => {
+                        self.position += 1;
+                        return Some((name, value));
+                    }
+                    b => value.push(b.to_ascii_lowercase()),
+                }
+            },
+            b'>' => return Some((name, value)),
+            _ => {}
+        }
+        loop {
+            match self.byte()? {
+                b if is_space(b) || b == b'>' => return Some((name, value)),
+                b => value.push(b.to_ascii_lowercase()),
+            }
+            self.position += 1;
+        }
+    }
+}
+
+/// The algorithm for extracting a character encoding from the `content`
+/// attribute of a `<meta>` element, e.g. `text/html; charset=utf-8`.
+fn charset_from_meta_content(content: &[u8]) -> Option<Encoding> {
+    let mut position = 0;
+    loop {
+        position += find(&content[position..], b"charset")? + b"charset".len();
+        while content.get(position).copied().is_some_and(is_space) {
+            position += 1;
+        }
+        if content.get(position) == Some(&b'=') {
+            break;
+        }
+    }
+    position += 1;
+    while content.get(position).copied().is_some_and(is_space) {
+        position += 1;
+    }
+    let rest = &content[position..];
+    let value = match rest.first()? {
+        &quote @ (b'"' | b'\'') => {
+            let end = rest[1..].iter().position(|&b| b == quote)?;
+            &rest[1..1 + end]
+        }
+        _ => {
+            let end = rest
+                .iter()
+                .position(|&b| is_space(b) || b == b';')
+                .unwrap_or(rest.len());
+            &rest[..end]
+        }
+    };
+    Encoding::for_label(&String::from_utf8_lossy(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(content_type: Option<&str>, body: &[u8]) -> Response {
+        Response {
+            status_code: 200,
+            reason_phrase: "OK".to_string(),
+            headers: content_type
+                .map(|value| vec![("Content-Type".to_string(), value.to_string())])
+                .unwrap_or_default(),
+            body: body.to_vec(),
+            url: None,
+        }
+    }
+
+    #[test]
+    fn test_bom_wins_over_everything() {
+        let body = b"\xFF\xFEh\x00i\x00";
+        let (text, encoding) = decode_response(&response(Some("text/html; charset=utf-8"), body));
+        assert_eq!(encoding, Encoding::Utf16Le);
+        assert_eq!(text, "hi");
+
+        let (text, encoding) = decode_response(&response(None, b"\xEF\xBB\xBFcaf\xC3\xA9"));
+        assert_eq!(encoding, Encoding::Utf8);
+        assert_eq!(text, "café");
+    }
+
+    #[test]
+    fn test_content_type_charset() {
+        let body = b"<meta charset=utf-8>\x93\xFA\x96\x7B";
+        let (text, encoding) =
+            decode_response(&response(Some("text/html; Charset=\"Shift_JIS\""), body));
+        assert_eq!(encoding, Encoding::ShiftJis);
+        assert!(text.ends_with("日本"));
+    }
+
+    #[test]
+    fn test_meta_charset() {
+        assert_eq!(
+            prescan(b"<!DOCTYPE html><html><head><meta charset=\"koi8-r\">"),
+            Some(Encoding::Koi8R)
+        );
+        assert_eq!(
+            prescan(b"<META CHARSET=ISO-8859-2>"),
+            Some(Encoding::Iso8859_2)
+        );
+        assert_eq!(prescan(b"<meta/charset='big5'>"), Some(Encoding::Big5));
+        // UTF-16 declarations are not believed.
+        assert_eq!(prescan(b"<meta charset=utf-16>"), Some(Encoding::Utf8));
+    }
+
+    #[test]
+    fn test_meta_http_equiv() {
+        let html = b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=euc-jp\">";
+        assert_eq!(prescan(html), Some(Encoding::EucJp));
+        let html = b"<meta content='text/html; charset=gbk' http-equiv=content-type>";
+        assert_eq!(prescan(html), Some(Encoding::Gbk));
+        // Without the pragma the content attribute does not count.
+        assert_eq!(prescan(b"<meta content=\"text/html; charset=gbk\">"), None);
+    }
+
+    #[test]
+    fn test_prescan_skips_comments_and_other_tags() {
+        assert_eq!(
+            prescan(b"<!-- <meta charset=gbk> --><meta charset=euc-kr>"),
+            Some(Encoding::EucKr)
+        );
+        assert_eq!(
+            prescan(b"<!--><meta charset=euc-kr>"),
+            Some(Encoding::EucKr)
+        );
+        assert_eq!(
+            prescan(b"<div title='<meta charset=gbk>'><meta charset=big5>"),
+            Some(Encoding::Big5)
+        );
+        assert_eq!(prescan(b"<meta name=viewport><meta charset=unknown>"), None);
+    }
+
+    #[test]
+    fn test_prescan_limit() {
+        let mut html = vec![b' '; PRESCAN_LENGTH];
+        html.extend_from_slice(b"<meta charset=gbk>");
+        assert_eq!(prescan(&html), None);
+    }
+
+    #[test]
+    fn test_default_encoding() {
+        assert_eq!(sniff_encoding(b"caf\xE9", None), Encoding::Windows1252);
+        assert_eq!(sniff_encoding(b"caf\xC3\xA9", None), Encoding::Utf8);
+        assert_eq!(
+            sniff_encoding(b"plain", Some("text/html")),
+            Encoding::Windows1252
+        );
+    }
+}
@@ -1,6 +1,8 @@
 //! This module defines custom errors for the flux-network crate.
 
+use crate::tls::TlsError;
 use crate::url::ParseUrlError;
+use crate::x509::CertificateError;
 
 /// Represents all possible errors that can occur during network requests.
 #[derive(Debug)]
@@ -13,6 +15,31 @@ pub enum NetworkError {
     ParseError(String),
     /// An error occurred due to an invalid URL.
     ParseUrlError(ParseUrlError),
+    /// The TLS connection of an `https` URL failed.
+    TlsError(TlsError),
+    /// A certificate of the server cannot be read, or uses an algorithm or a
+    /// critical extension that is not supported.
+    InvalidCertificate(String),
+    /// A certificate of the server's chain has expired.
+    CertificateExpired,
+    /// A certificate of the server's chain is not valid yet.
+    CertificateNotYetValid,
+    /// The server's chain does not lead to a root of the trust store.
+    UnknownIssuer,
+    /// A signature of the server's chain does not verify.
+    InvalidCertificateSignature,
+    /// A certificate of the server's chain is used for what it may not do,
+    /// like signing other certificates without being an authority.
+    CertificateNotAllowed(String),
+    /// The server's certificate is not valid for this host.
+    HostnameMismatch(String),
+    /// The trust store, such as the system's certificate bundle, cannot be
+    /// loaded.
+    TrustStoreUnavailable(String),
+    /// A redirect led back to a URL already fetched with the same method.
+    RedirectLoop(String),
+    /// More redirects than allowed followed one another; the limit is given.
+    TooManyRedirects(usize),
 
     /// An error occurred due to invalid data in the response.
     InvalidData(String),
@@ -30,3 +57,30 @@ impl From<ParseUrlError> for NetworkError {
         NetworkError::ParseUrlError(e)
     }
 }
+
+impl From<CertificateError> for NetworkError {
+    fn from(e: CertificateError) -> Self {
+        match e {
+            CertificateError::Malformed(_)
+            | CertificateError::UnsupportedAlgorithm
+            | CertificateError::UnsupportedCriticalExtension => NetworkError::InvalidCertificate(e.to_string()),
+            CertificateError::Expired => NetworkError::CertificateExpired,
+            CertificateError::NotYetValid => NetworkError::CertificateNotYetValid,
+            CertificateError::UnknownIssuer => NetworkError::UnknownIssuer,
+            CertificateError::BadSignature => NetworkError::InvalidCertificateSignature,
+            CertificateError::NotACertificateAuthority | CertificateError::NotForServerAuth => {
+                NetworkError::CertificateNotAllowed(e.to_string())
+            }
+            CertificateError::HostnameMismatch(host) => NetworkError::HostnameMismatch(host),
+        }
+    }
+}
+
+impl From<TlsError> for NetworkError {
+    fn from(e: TlsError) -> Self {
+        match e {
+            TlsError::Certificate(e) => e.into(),
+            e => NetworkError::TlsError(e),
+        }
+    }
+}
@@ -32,6 +32,7 @@ pub fn parse_http_response(raw_data: &[u8]) -> Result<Response, NetworkError> {
         reason_phrase,
         headers,
         body: body_data.to_vec(),
+        url: None,
     })
 }
 
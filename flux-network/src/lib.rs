@@ -1,19 +1,31 @@
 //! This is the main entry file for the flux-network crate.
 //! It re-exports the public API for external usage.
 
+mod encoding;
 mod errors;
 mod client;
+mod crypto;
 mod request;
 mod response;
 mod http_parser;
+mod mime;
 mod url;
 mod settings;
+mod pool;
+mod redirect;
+mod reactor;
+mod tls;
+mod x509;
 
-use crate::request::Method;
 pub use client::HttpClient;
+pub use encoding::{decode_response, sniff_encoding, Encoding};
 pub use errors::NetworkError;
-pub use request::Request;
+pub use mime::{sniff_mime_type, sniff_response, MimeType};
+pub use request::{Method, Request};
 pub use response::Response;
+pub use tls::{Alert, CipherSuite, TlsError, TlsStream};
+pub use url::{parse_url, resolve_url, ParseUrlError, Url};
+pub use x509::{set_default_trust_store, CertificateError, TrustStore};
 
 /// Public method to fetch data from a given URL.
 ///
@@ -37,38 +49,135 @@ pub use response::Response;
 ///
 /// ```
 pub async fn fetch(url: &str) -> Result<Response, NetworkError> {
+    fetch_streaming(url, |_| {}).await
+}
+
+/// Fetches a URL like [`fetch`], handing the body to `on_body` piece by piece
+/// as it is received, e.g. to feed an incremental parser.
+///
+/// # Arguments
+/// * `url` - The URL to fetch.
+/// * `on_body` - Called with consecutive pieces of the body. Redirects are
+///   followed, and only the body of the final response is handed over.
+///
+/// # Returns
+/// The complete response, its body included, once it has been received.
+///
+/// # Example
+/// ```rust,no_run
+/// use futures::executor::block_on;
+/// let mut received = 0;
+/// let response = block_on(flux_network::fetch_streaming("http://example.com", |piece| {
+///     received += piece.len();
+/// }));
+/// ```
+pub async fn fetch_streaming(
+    url: &str,
+    on_body: impl FnMut(&[u8]),
+) -> Result<Response, NetworkError> {
     // Parse l’URL
     let parsed_url = url::parse_url(url)?;
 
-    if parsed_url.scheme == "fluxa" {
-        return settings::fetch_settings(parsed_url);
-    }
-
-    let mut client = HttpClient::new();
+    // Prépare la requête HTTP
+    let request = Request::new(Method::GET, &parsed_url.request_target());
 
-    // Connexion au serveur
-    client.connect(&parsed_url.host, parsed_url.port)?;
+    send(parsed_url, request, on_body).await
+}
 
-    // Prépare la requête HTTP
-    let mut request = Request::new(Method::GET, &parsed_url.path);
-    request.add_header("Host", &parsed_url.host);
-    request.add_header("User-Agent", "FluxNetwork/0.1");
+/// Sends a request built by the caller, such as the submission of a form,
+/// to the server of `url`.
+///
+/// # Arguments
+/// * `url` - The URL the request is for. Its path and query should be the
+///   ones of `request`.
+/// * `request` - The request to send. `Host` and `User-Agent` headers are
+///   added if it does not have them.
+///
+/// # Returns
+/// A `Result<Response, NetworkError>` with the response of the server.
+pub async fn fetch_request(url: &str, request: Request) -> Result<Response, NetworkError> {
+    fetch_request_streaming(url, request, |_| {}).await
+}
 
-    // Envoie la requête
-    client.send_request(&request)?;
+/// Sends a request like [`fetch_request`], handing the body of the response
+/// to `on_body` piece by piece as it is received, like [`fetch_streaming`].
+pub async fn fetch_request_streaming(
+    url: &str,
+    request: Request,
+    on_body: impl FnMut(&[u8]),
+) -> Result<Response, NetworkError> {
+    let parsed_url = url::parse_url(url)?;
+    send(parsed_url, request, on_body).await
+}
 
-    // Récupère la réponse brute
-    let raw_response_data = client.receive_raw_response()?;
+/// Sends `request` to the server of `url` and reads the response.
+async fn send(
+    url: Url,
+    mut request: Request,
+    mut on_body: impl FnMut(&[u8]),
+) -> Result<Response, NetworkError> {
+    if url.scheme.eq_ignore_ascii_case("fluxa") {
+        let response = settings::fetch_settings(url)?;
+        on_body(&response.body);
+        return Ok(response);
+    }
 
-    // Parse la réponse dans une structure `Response`
-    let response = client.parse_response(&raw_response_data)?;
+    let has_header = |request: &Request, name: &str| {
+        request.headers.iter().any(|(key, _)| key.eq_ignore_ascii_case(name))
+    };
+    if !has_header(&request, "Host") {
+        request.add_header("Host", &url.host_header());
+    }
+    if !has_header(&request, "User-Agent") {
+        request.add_header("User-Agent", "FluxNetwork/0.1");
+    }
 
-    Ok(response)
+    // Envoie la requête, sur une connexion déjà ouverte vers ce serveur si possible
+    HttpClient::new().fetch(&url, &request, on_body).await
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpListener};
+    use std::thread::{self, JoinHandle};
+
+    /// A plain HTTP server sending `responses` in order, one per request,
+    /// on as many connections as the client opens; an empty response closes
+    /// the connection instead. Returns the number of requests answered on
+    /// each connection.
+    fn serve(responses: &'static [&'static str]) -> (SocketAddr, JoinHandle<Vec<usize>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut connections = Vec::new();
+            let mut responses = responses.iter();
+            while responses.len() > 0 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut answered = 0;
+                for response in responses.by_ref() {
+                    let mut line = String::new();
+                    while line != "\r\n" {
+                        line.clear();
+                        assert_ne!(reader.read_line(&mut line).unwrap(), 0, "the client closed the connection");
+                    }
+                    if response.is_empty() {
+                        break;
+                    }
+                    stream.write_all(response.as_bytes()).unwrap();
+                    answered += 1;
+                    if response.contains("Connection: close") {
+                        break;
+                    }
+                }
+                connections.push(answered);
+            }
+            connections
+        });
+        (address, server)
+    }
 
     #[test]
     fn test_fetch() {
@@ -78,4 +187,256 @@ mod tests {
         let response = futures::executor::block_on(future).unwrap();
         assert_eq!(response.status_code, 200);
     }
+
+    #[test]
+    fn test_fetch_https() {
+        use crate::tls::fixture::{FixtureServer, Scenario};
+        use crate::x509::testdata::ROOT;
+
+        let mut trust_store = TrustStore::new();
+        trust_store.add_pem(ROOT);
+        set_default_trust_store(trust_store);
+        let server = FixtureServer::start(
+            CipherSuite::ChaCha20Poly1305Sha256,
+            Scenario::Normal,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+        );
+        let url = format!("https://{}/page?q=1", server.address());
+        let response = futures::executor::block_on(fetch(&url)).unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, b"hello");
+
+        let received = server.finish().unwrap();
+        // No server name is sent for an IP address
+        assert_eq!(received.server_name, None);
+        assert!(received.request.starts_with(b"GET /page?q=1 HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn test_fetch_reuses_connections() {
+        const KEEP_ALIVE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        const NO_CONTENT: &str = "HTTP/1.1 204 No Content\r\n\r\n";
+        const CLOSE: &str = "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok";
+        let (address, server) = serve(&[KEEP_ALIVE, NO_CONTENT, CLOSE, KEEP_ALIVE]);
+
+        let url = format!("http://{}/", address);
+        let status_codes: Vec<u16> = (0..4)
+            .map(|_| futures::executor::block_on(fetch(&url)).unwrap().status_code)
+            .collect();
+        assert_eq!(status_codes, [200, 204, 200, 200]);
+        // A new connection only once the server closed the first one
+        assert_eq!(server.join().unwrap(), [3, 1]);
+
+        // Closed by the server as it was reused: the request is sent again
+        let (address, server) = serve(&[KEEP_ALIVE, "", KEEP_ALIVE]);
+        let url = format!("http://{}/", address);
+        for _ in 0..2 {
+            assert_eq!(futures::executor::block_on(fetch(&url)).unwrap().body, b"ok");
+        }
+        assert_eq!(server.join().unwrap(), [1, 1]);
+    }
+
+    #[test]
+    fn test_reuse_after_chunked_trailer() {
+        // A chunk extension, and a trailer field after the last chunk
+        const CHUNKED: &str = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            2;name=value\r\nok\r\n0\r\nX-Checksum: 1234\r\n\r\n";
+        const KEEP_ALIVE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nnext";
+        let (address, server) = serve(&[CHUNKED, KEEP_ALIVE]);
+
+        let url = format!("http://{}/", address);
+        let mut received = Vec::new();
+        let first = futures::executor::block_on(fetch_streaming(&url, |piece| received.extend_from_slice(piece)));
+        assert_eq!(first.unwrap().status_code, 200);
+        assert_eq!(received, b"ok");
+        // The trailer is not taken for the start of the next response
+        let second = futures::executor::block_on(fetch(&url)).unwrap();
+        assert_eq!((second.status_code, second.body), (200, b"next".to_vec()));
+        assert_eq!(server.join().unwrap(), [2]);
+    }
+
+    #[test]
+    fn test_no_reuse_with_unread_bytes() {
+        use std::time::Duration;
+
+        // Sends a byte past the end of the first response: the connection
+        // cannot be trusted for a second one
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut connections = Vec::new();
+            let responses = [
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nokX",
+                "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nnext",
+            ];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while line != "\r\n" {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+                // Kept open, as a server keeping the connection alive would
+                connections.push(stream);
+            }
+            connections
+        });
+
+        let url = format!("http://{}/", address);
+        let first = futures::executor::block_on(fetch(&url)).unwrap();
+        assert_eq!(first.body, b"ok");
+        let second = futures::executor::block_on(reactor::timeout(Duration::from_secs(5), fetch(&url)));
+        assert_eq!(second.expect("the stale connection was reused").unwrap().body, b"next");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_fetch_follows_redirects() {
+        const MOVED: &str = "HTTP/1.1 301 Moved Permanently\r\nLocation: next?page=2\r\nContent-Length: 5\r\n\r\nmoved";
+        const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let (address, server) = serve(&[MOVED, OK]);
+        let mut received = Vec::new();
+        let url = format!("http://{}/dir/start#top", address);
+        let response = futures::executor::block_on(fetch_streaming(&url, |piece| received.extend_from_slice(piece)));
+        let response = response.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.url, Some(format!("http://{}/dir/next?page=2#top", address)));
+        // Only the body of the final response
+        assert_eq!(received, b"ok");
+        assert_eq!(server.join().unwrap(), [2]);
+
+        // Back to the start
+        const LOOP: &str = "HTTP/1.1 302 Found\r\nLocation: /a\r\nContent-Length: 0\r\n\r\n";
+        let (address, server) = serve(&[LOOP]);
+        let result = futures::executor::block_on(fetch(&format!("http://{}/a", address)));
+        assert!(matches!(result, Err(NetworkError::RedirectLoop(url)) if url.ends_with("/a")));
+        server.join().unwrap();
+
+        // Over the limit
+        const TO_B: &str = "HTTP/1.1 307 Temporary Redirect\r\nLocation: /b\r\nContent-Length: 0\r\n\r\n";
+        const TO_C: &str = "HTTP/1.1 308 Permanent Redirect\r\nLocation: /c\r\nContent-Length: 0\r\n\r\n";
+        let (address, server) = serve(&[TO_B, TO_C]);
+        let url = parse_url(&format!("http://{}/a", address)).unwrap();
+        let mut client = HttpClient::new();
+        client.set_max_redirects(1);
+        let result = futures::executor::block_on(client.fetch(&url, &Request::new(Method::GET, "/a"), |_| {}));
+        assert!(matches!(result, Err(NetworkError::TooManyRedirects(1))));
+        server.join().unwrap();
+
+        // Not followed at all
+        let (address, server) = serve(&[TO_B]);
+        let url = parse_url(&format!("http://{}/a", address)).unwrap();
+        client.set_max_redirects(0);
+        let response = futures::executor::block_on(client.fetch(&url, &Request::new(Method::GET, "/a"), |_| {}));
+        assert_eq!(response.unwrap().status_code, 307);
+        server.join().unwrap();
+    }
+
+    /// A plain HTTP server answering one request with `response`. Returns
+    /// the head of the request.
+    fn capture(response: String) -> (SocketAddr, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut head = String::new();
+            while !head.ends_with("\r\n\r\n") {
+                assert_ne!(reader.read_line(&mut head).unwrap(), 0, "the client closed the connection");
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            head
+        });
+        (address, server)
+    }
+
+    #[test]
+    fn test_redirect_to_other_port() {
+        let (target, target_server) =
+            capture("HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok".to_string());
+        let (address, server) = capture(format!(
+            "HTTP/1.1 307 Temporary Redirect\r\nLocation: http://{}/next\r\nContent-Length: 0\r\n\r\n",
+            target
+        ));
+        let response = futures::executor::block_on(fetch(&format!("http://{}/", address))).unwrap();
+        assert_eq!(response.body, b"ok");
+        // The port is part of the Host header, of the first request too
+        assert!(server.join().unwrap().contains(&format!("\r\nHost: {}\r\n", address)));
+        assert!(target_server.join().unwrap().contains(&format!("\r\nHost: {}\r\n", target)));
+    }
+
+    #[test]
+    fn test_redirect_to_uppercase_https() {
+        use crate::tls::fixture::{FixtureServer, Scenario};
+        use crate::x509::testdata::ROOT;
+        use std::sync::Arc;
+
+        let secure = FixtureServer::start(
+            CipherSuite::Aes128GcmSha256,
+            Scenario::Normal,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecret",
+        );
+        // The scheme is case-insensitive: still a TLS connection
+        let moved = format!(
+            "HTTP/1.1 302 Found\r\nLocation: HTTPS://{}/secret\r\nContent-Length: 0\r\n\r\n",
+            secure.address()
+        );
+        let (address, server) = serve(Vec::leak(vec![&*String::leak(moved)]));
+
+        let mut trust_store = TrustStore::new();
+        trust_store.add_pem(ROOT);
+        let client = HttpClient::with_trust_store(Arc::new(trust_store));
+        let url = parse_url(&format!("http://{}/", address)).unwrap();
+        let response = futures::executor::block_on(client.fetch(&url, &Request::new(Method::GET, "/"), |_| {}));
+        let response = response.unwrap();
+        assert_eq!(response.body, b"secret");
+        assert_eq!(response.url, Some(format!("https://{}/secret", secure.address())));
+        server.join().unwrap();
+        assert!(secure.finish().unwrap().request.starts_with(b"GET /secret HTTP/1.1\r\n"));
+    }
+
+    #[test]
+    fn test_concurrent_fetches() {
+        use std::time::{Duration, Instant};
+
+        // Answers only once both requests have arrived, which they do only
+        // if the first fetch yields while it waits for its response
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let server = thread::spawn(move || {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut streams = Vec::new();
+            while streams.len() < 2 && Instant::now() < deadline {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        stream.set_nonblocking(false).unwrap();
+                        let mut line = String::new();
+                        let mut reader = BufReader::new(stream.try_clone().unwrap());
+                        while line != "\r\n" {
+                            line.clear();
+                            reader.read_line(&mut line).unwrap();
+                        }
+                        streams.push(stream);
+                    }
+                    Err(_) => thread::sleep(Duration::from_millis(5)),
+                }
+            }
+            let both_arrived = streams.len() == 2;
+            for mut stream in streams {
+                let response = "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok";
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            both_arrived
+        });
+
+        let first = format!("http://{}/first", address);
+        let second = format!("http://{}/second", address);
+        let (first, second) = futures::executor::block_on(futures::future::join(fetch(&first), fetch(&second)));
+        assert!(server.join().unwrap());
+        assert_eq!(first.unwrap().body, b"ok");
+        assert_eq!(second.unwrap().body, b"ok");
+    }
 }
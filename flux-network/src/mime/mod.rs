@@ -0,0 +1,308 @@
+//! MIME types of fetched resources.
+//!
+//! This module implements the MIME types of the WHATWG MIME Sniffing
+//! Standard (<https://mimesniff.spec.whatwg.org/>): parsing and serializing
+//! `Content-Type` values, and the sniffing algorithm that works out the type
+//! of a response whose header is missing or wrong.
+
+mod sniff;
+
+use std::fmt;
+
+pub use sniff::{sniff_mime_type, sniff_response};
+
+/// A parsed MIME type, such as `text/html; charset=utf-8`.
+///
+/// The type, the subtype and the parameter names are lowercase. Parameter
+/// values keep their case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MimeType {
+    type_: String,
+    subtype: String,
+    parameters: Vec<(String, String)>,
+}
+
+impl MimeType {
+    /// Creates a MIME type without parameters. The names are lowercased.
+    ///
+    /// # Panics
+    /// If `type_` or `subtype` is not a valid token.
+    pub fn new(type_: &str, subtype: &str) -> Self {
+        assert!(
+            is_token(type_) && is_token(subtype),
+            "invalid MIME type {}/{}",
+            type_,
+            subtype
+        );
+        MimeType {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Parses a MIME type, as found in a `Content-Type` header.
+    ///
+    /// # Returns
+    /// `None` if the type or the subtype is missing or invalid. Invalid
+    /// parameters are dropped, and only the first of several parameters with
+    /// the same name is kept.
+    ///
+    /// # Example
+    /// ```rust
+    /// use flux_network::MimeType;
+    ///
+    /// let mime_type = MimeType::parse("Text/HTML; Charset=\"utf-8\"").unwrap();
+    /// assert_eq!(mime_type.essence(), "text/html");
+    /// assert_eq!(mime_type.parameter("charset"), Some("utf-8"));
+    /// ```
+    pub fn parse(input: &str) -> Option<Self> {
+        let input = input.trim_matches(is_http_whitespace);
+        let (type_, rest) = input.split_once('/')?;
+        if !is_token(type_) {
+            return None;
+        }
+        let (subtype, mut rest) = rest.split_once(';').unwrap_or((rest, ""));
+        let subtype = subtype.trim_end_matches(is_http_whitespace);
+        if !is_token(subtype) {
+            return None;
+        }
+        let mut mime_type = MimeType {
+            type_: type_.to_ascii_lowercase(),
+            subtype: subtype.to_ascii_lowercase(),
+            parameters: Vec::new(),
+        };
+
+        while !rest.is_empty() {
+            rest = rest.trim_start_matches(is_http_whitespace);
+            let name_end = rest.find([';', '=']).unwrap_or(rest.len());
+            let name = rest[..name_end].to_ascii_lowercase();
+            rest = &rest[name_end..];
+            if let Some(after) = rest.strip_prefix(';') {
+                // A parameter without a value
+                rest = after;
+                continue;
+            }
+            rest = rest.strip_prefix('=').unwrap_or(rest);
+
+            let value = if rest.starts_with('"') {
+                let (value, after) = quoted_string(rest);
+                // Anything between the closing quote and the next ';' is ignored
+                rest = after.find(';').map_or("", |index| &after[index + 1..]);
+                value
+            } else {
+                let (value, after) = rest.split_once(';').unwrap_or((rest, ""));
+                rest = after;
+                let value = value.trim_end_matches(is_http_whitespace);
+                if value.is_empty() {
+                    continue;
+                }
+                value.to_string()
+            };
+
+            let valid = is_token(&name) && value.chars().all(is_quoted_string_char);
+            if valid && mime_type.parameter(&name).is_none() {
+                mime_type.parameters.push((name, value));
+            }
+        }
+        Some(mime_type)
+    }
+
+    /// The type, before the slash (`text`).
+    pub fn type_(&self) -> &str {
+        &self.type_
+    }
+
+    /// The subtype, after the slash (`html`).
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// The type and subtype without the parameters (`text/html`).
+    pub fn essence(&self) -> String {
+        format!("{}/{}", self.type_, self.subtype)
+    }
+
+    /// The parameters, in the order they were given.
+    pub fn parameters(&self) -> &[(String, String)] {
+        &self.parameters
+    }
+
+    /// Returns the value of a parameter. The name is case-insensitive.
+    pub fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Sets a parameter, replacing its value if it is already there.
+    pub fn set_parameter(&mut self, name: &str, value: &str) {
+        let name = name.to_ascii_lowercase();
+        match self.parameters.iter_mut().find(|(key, _)| *key == name) {
+            Some((_, old_value)) => *old_value = value.to_string(),
+            None => self.parameters.push((name, value.to_string())),
+        }
+    }
+
+    /// Whether the type is `text/html`.
+    pub fn is_html(&self) -> bool {
+        self.type_ == "text" && self.subtype == "html"
+    }
+
+    /// Whether the type is an XML type: `text/xml`, `application/xml` or
+    /// any subtype ending in `+xml`.
+    pub fn is_xml(&self) -> bool {
+        self.subtype.ends_with("+xml")
+            || ((self.type_ == "text" || self.type_ == "application") && self.subtype == "xml")
+    }
+
+    /// Whether the type is a JSON type: `application/json`, `text/json` or
+    /// any subtype ending in `+json`.
+    pub fn is_json(&self) -> bool {
+        self.subtype.ends_with("+json")
+            || ((self.type_ == "text" || self.type_ == "application") && self.subtype == "json")
+    }
+
+    /// Whether the type is an image type.
+    pub fn is_image(&self) -> bool {
+        self.type_ == "image"
+    }
+
+    /// Whether the type is an audio or video type, `application/ogg`
+    /// included.
+    pub fn is_audio_or_video(&self) -> bool {
+        self.type_ == "audio"
+            || self.type_ == "video"
+            || (self.type_ == "application" && self.subtype == "ogg")
+    }
+
+    /// Whether the type stands for "unknown": `unknown/unknown`,
+    /// `application/unknown` or `*/*`.
+    pub fn is_unknown(&self) -> bool {
+        matches!(
+            (self.type_.as_str(), self.subtype.as_str()),
+            ("unknown", "unknown") | ("application", "unknown") | ("*", "*")
+        )
+    }
+}
+
+impl fmt::Display for MimeType {
+    /// Serializes the MIME type, quoting the parameter values that need it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)?;
+        for (name, value) in &self.parameters {
+            write!(f, ";{}=", name)?;
+            if !value.is_empty() && is_token(value) {
+                write!(f, "{}", value)?;
+            } else {
+                write!(f, "\"")?;
+                for c in value.chars() {
+                    if c == '"' || c == '\\' {
+                        write!(f, "\\")?;
+                    }
+                    write!(f, "{}", c)?;
+                }
+                write!(f, "\"")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a quoted string at the start of `input`, returning its value and
+/// what follows the closing quote. An unterminated string runs to the end.
+fn quoted_string(input: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = input.char_indices().skip(1);
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return (value, &input[index + 1..]),
+            '\\' => match chars.next() {
+                Some((_, escaped)) => value.push(escaped),
+                None => value.push('\\'),
+            },
+            _ => value.push(c),
+        }
+    }
+    (value, "")
+}
+
+fn is_http_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+/// Whether `s` is a non-empty HTTP token.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+}
+
+fn is_quoted_string_char(c: char) -> bool {
+    matches!(c, '\t' | ' '..='~' | '\u{80}'..='\u{FF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let mime_type =
+            MimeType::parse(" text/HTML ;charset=UTF-8; Foo=\"b\\\"ar\" x ; empty=").unwrap();
+        assert_eq!(mime_type.type_(), "text");
+        assert_eq!(mime_type.subtype(), "html");
+        assert_eq!(mime_type.essence(), "text/html");
+        assert_eq!(
+            mime_type.parameters(),
+            [
+                ("charset".to_string(), "UTF-8".to_string()),
+                ("foo".to_string(), "b\"ar".to_string())
+            ]
+        );
+        assert_eq!(mime_type.parameter("Charset"), Some("UTF-8"));
+
+        let mime_type = MimeType::parse("text/plain;charset=a;charset=b;bad name=c").unwrap();
+        assert_eq!(
+            mime_type.parameters(),
+            [("charset".to_string(), "a".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for input in [
+            "",
+            "text",
+            "text/",
+            "/html",
+            "te xt/html",
+            "text/ht(ml",
+            "text/html/x",
+        ] {
+            assert_eq!(MimeType::parse(input), None, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut mime_type = MimeType::parse("Image/SVG+XML; A=b;c=\"d e\"").unwrap();
+        assert_eq!(mime_type.to_string(), "image/svg+xml;a=b;c=\"d e\"");
+        mime_type.set_parameter("A", "");
+        assert_eq!(mime_type.to_string(), "image/svg+xml;a=\"\";c=\"d e\"");
+    }
+
+    #[test]
+    fn test_groups() {
+        let mime_type = |input| MimeType::parse(input).unwrap();
+        assert!(mime_type("text/html").is_html());
+        assert!(mime_type("image/svg+xml").is_xml());
+        assert!(mime_type("application/xml").is_xml());
+        assert!(!mime_type("application/xml-dtd").is_xml());
+        assert!(mime_type("application/ld+json").is_json());
+        assert!(mime_type("image/png").is_image());
+        assert!(mime_type("application/ogg").is_audio_or_video());
+        assert!(mime_type("*/*").is_unknown());
+    }
+}
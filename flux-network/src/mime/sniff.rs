@@ -0,0 +1,436 @@
+//! The MIME type sniffing algorithm of the MIME Sniffing Standard.
+//!
+//! See <https://mimesniff.spec.whatwg.org/#determining-the-computed-mime-type-of-a-resource>.
+//! The type sent by the server is trusted, except when it is missing or
+//! "unknown", when it is one of the `text/plain` values that old Apache
+//! servers sent for everything, and when an HTML page is actually a feed. In
+//! these cases the first bytes of the resource decide.
+//!
+//! The WebM and ID3-less MP3 signatures, which need a parser of their own,
+//! are not recognized.
+
+use super::MimeType;
+use crate::Response;
+
+/// Number of bytes of the resource the patterns are matched against.
+const RESOURCE_HEADER_LENGTH: usize = 1445;
+
+/// `Content-Type` values sent by Apache for any file it did not know, which
+/// therefore say nothing about the resource.
+const APACHE_BUG_TYPES: &[&str] = &[
+    "text/plain",
+    "text/plain; charset=ISO-8859-1",
+    "text/plain; charset=iso-8859-1",
+    "text/plain; charset=UTF-8",
+];
+
+/// A byte pattern: the input matches when every byte, and-ed with the mask,
+/// equals the pattern.
+struct Pattern {
+    bytes: &'static [u8],
+    mask: &'static [u8],
+    mime_type: &'static str,
+}
+
+impl Pattern {
+    fn matches(&self, input: &[u8]) -> bool {
+        input.len() >= self.bytes.len()
+            && self
+                .bytes
+                .iter()
+                .zip(self.mask)
+                .zip(input)
+                .all(|((byte, mask), input)| input & mask == *byte)
+    }
+}
+
+const fn pattern(bytes: &'static [u8], mask: &'static [u8], mime_type: &'static str) -> Pattern {
+    Pattern {
+        bytes,
+        mask,
+        mime_type,
+    }
+}
+
+/// Tags that make a resource of unknown type HTML, when followed by a space
+/// or `>`. They are compared case-insensitively.
+const HTML_TAGS: &[&[u8]] = &[
+    b"<!DOCTYPE HTML",
+    b"<HTML",
+    b"<HEAD",
+    b"<SCRIPT",
+    b"<IFRAME",
+    b"<H1",
+    b"<DIV",
+    b"<FONT",
+    b"<TABLE",
+    b"<A",
+    b"<STYLE",
+    b"<TITLE",
+    b"<B",
+    b"<BODY",
+    b"<BR",
+    b"<P",
+    b"<!--",
+];
+
+/// Patterns of the other types of the "identify an unknown MIME type"
+/// algorithm, before the image, audio, video and archive ones.
+const OTHER_PATTERNS: &[Pattern] = &[
+    pattern(b"%PDF-", &[0xFF; 5], "application/pdf"),
+    pattern(b"%!PS-Adobe-", &[0xFF; 11], "application/postscript"),
+    pattern(b"\xFE\xFF\0\0", &[0xFF, 0xFF, 0, 0], "text/plain"),
+    pattern(b"\xFF\xFE\0\0", &[0xFF, 0xFF, 0, 0], "text/plain"),
+    pattern(b"\xEF\xBB\xBF\0", &[0xFF, 0xFF, 0xFF, 0], "text/plain"),
+];
+
+const IMAGE_PATTERNS: &[Pattern] = &[
+    pattern(b"\0\0\x01\0", &[0xFF; 4], "image/x-icon"),
+    pattern(b"\0\0\x02\0", &[0xFF; 4], "image/x-icon"),
+    pattern(b"BM", &[0xFF; 2], "image/bmp"),
+    pattern(b"GIF87a", &[0xFF; 6], "image/gif"),
+    pattern(b"GIF89a", &[0xFF; 6], "image/gif"),
+    pattern(
+        b"RIFF\0\0\0\0WEBPVP",
+        &[
+            0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ],
+        "image/webp",
+    ),
+    pattern(b"\x89PNG\r\n\x1A\n", &[0xFF; 8], "image/png"),
+    pattern(b"\xFF\xD8\xFF", &[0xFF; 3], "image/jpeg"),
+];
+
+const AUDIO_VIDEO_PATTERNS: &[Pattern] = &[
+    pattern(
+        b"FORM\0\0\0\0AIFF",
+        &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+        "audio/aiff",
+    ),
+    pattern(b"ID3", &[0xFF; 3], "audio/mpeg"),
+    pattern(b"OggS\0", &[0xFF; 5], "application/ogg"),
+    pattern(b"MThd\0\0\0\x06", &[0xFF; 8], "audio/midi"),
+    pattern(
+        b"RIFF\0\0\0\0AVI ",
+        &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+        "video/avi",
+    ),
+    pattern(
+        b"RIFF\0\0\0\0WAVE",
+        &[0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF],
+        "audio/wave",
+    ),
+];
+
+const ARCHIVE_PATTERNS: &[Pattern] = &[
+    pattern(b"\x1F\x8B\x08", &[0xFF; 3], "application/x-gzip"),
+    pattern(b"PK\x03\x04", &[0xFF; 4], "application/zip"),
+    pattern(
+        b"Rar \x1A\x07\0",
+        &[0xFF; 7],
+        "application/x-rar-compressed",
+    ),
+];
+
+/// Works out the MIME type of `response` from its `Content-Type` and
+/// `X-Content-Type-Options` headers and its body.
+pub fn sniff_response(response: &Response) -> MimeType {
+    let content_type = response.get_header("Content-Type");
+    // Only the first value of the header counts
+    let no_sniff = response
+        .get_header("X-Content-Type-Options")
+        .and_then(|value| {
+            let first = value.split(',').next()?;
+            Some(first.trim().eq_ignore_ascii_case("nosniff"))
+        })
+        .unwrap_or(false);
+    sniff_mime_type(&response.body, content_type.as_deref(), no_sniff)
+}
+
+/// Determines the computed MIME type of a resource.
+///
+/// # Arguments
+/// * `bytes` - The start of the resource, or all of it.
+/// * `content_type` - The value of the `Content-Type` header, if any.
+/// * `no_sniff` - Whether the server sent `X-Content-Type-Options: nosniff`.
+///   The supplied type is then trusted, and a resource of unknown type is
+///   never taken for HTML.
+///
+/// # Example
+/// ```rust
+/// use flux_network::sniff_mime_type;
+///
+/// let mime_type = sniff_mime_type(b"\x89PNG\r\n\x1A\n...", None, false);
+/// assert_eq!(mime_type.essence(), "image/png");
+/// let mime_type = sniff_mime_type(b"<rss version=\"2.0\">", Some("text/html"), false);
+/// assert_eq!(mime_type.essence(), "application/rss+xml");
+/// ```
+pub fn sniff_mime_type(bytes: &[u8], content_type: Option<&str>, no_sniff: bool) -> MimeType {
+    let header = &bytes[..bytes.len().min(RESOURCE_HEADER_LENGTH)];
+    let supplied = match content_type.and_then(MimeType::parse) {
+        Some(supplied) if !supplied.is_unknown() => supplied,
+        _ => return identify_unknown(header, !no_sniff),
+    };
+    if no_sniff {
+        return supplied;
+    }
+    if content_type.is_some_and(|content_type| APACHE_BUG_TYPES.contains(&content_type)) {
+        return text_or_binary(header);
+    }
+    if supplied.is_xml() {
+        return supplied;
+    }
+    if supplied.is_html() {
+        return feed_or_html(header).map_or(supplied, essence);
+    }
+    if supplied.is_image() {
+        return match_patterns(IMAGE_PATTERNS, header).map_or(supplied, essence);
+    }
+    if supplied.is_audio_or_video() {
+        return match_audio_or_video(header).map_or(supplied, essence);
+    }
+    supplied
+}
+
+/// "Identify an unknown MIME type". Scriptable types (HTML, XML) are only
+/// sniffed when `sniff_scriptable` is set.
+fn identify_unknown(header: &[u8], sniff_scriptable: bool) -> MimeType {
+    if sniff_scriptable {
+        let start = header
+            .iter()
+            .position(|&byte| !is_whitespace(byte))
+            .unwrap_or(header.len());
+        let rest = &header[start..];
+        if HTML_TAGS.iter().any(|tag| matches_tag(rest, tag)) {
+            return essence("text/html");
+        }
+        if rest.starts_with(b"<?xml") {
+            return essence("text/xml");
+        }
+    }
+    let sniffed = match_patterns(OTHER_PATTERNS, header)
+        .or_else(|| match_patterns(IMAGE_PATTERNS, header))
+        .or_else(|| match_audio_or_video(header))
+        .or_else(|| match_patterns(ARCHIVE_PATTERNS, header));
+    if let Some(sniffed) = sniffed {
+        return essence(sniffed);
+    }
+    if header.iter().any(|&byte| is_binary_data_byte(byte)) {
+        essence("application/octet-stream")
+    } else {
+        essence("text/plain")
+    }
+}
+
+/// Whether `input` starts with `tag`, ignoring case, followed by a
+/// tag-terminating byte.
+fn matches_tag(input: &[u8], tag: &[u8]) -> bool {
+    input.len() > tag.len()
+        && input[..tag.len()].eq_ignore_ascii_case(tag)
+        && matches!(input[tag.len()], b' ' | b'>')
+}
+
+/// "Distinguish if a resource is text or binary", for the `text/plain`
+/// types that Apache sent by default.
+fn text_or_binary(header: &[u8]) -> MimeType {
+    let bom = header.starts_with(b"\xFE\xFF")
+        || header.starts_with(b"\xFF\xFE")
+        || header.starts_with(b"\xEF\xBB\xBF");
+    if bom || !header.iter().any(|&byte| is_binary_data_byte(byte)) {
+        return essence("text/plain");
+    }
+    identify_unknown(header, false)
+}
+
+/// "Sniff a mislabeled feed": tells RSS and Atom feeds served as
+/// `text/html` from actual HTML pages.
+fn feed_or_html(header: &[u8]) -> Option<&'static str> {
+    let mut position = if header.starts_with(b"\xEF\xBB\xBF") {
+        3
+    } else {
+        0
+    };
+    loop {
+        while header
+            .get(position)
+            .is_some_and(|&byte| is_whitespace(byte))
+        {
+            position += 1;
+        }
+        if header.get(position) != Some(&b'<') {
+            return None;
+        }
+        position += 1;
+        let rest = &header[position..];
+        // Comments, DOCTYPEs and processing instructions come before the
+        // root element
+        let skipped = if rest.starts_with(b"!--") {
+            find(rest, b"-->").map(|index| index + 3)
+        } else if rest.starts_with(b"!") {
+            find(rest, b">").map(|index| index + 1)
+        } else if rest.starts_with(b"?") {
+            find(rest, b"?>").map(|index| index + 2)
+        } else if rest.starts_with(b"rss") {
+            return Some("application/rss+xml");
+        } else if rest.starts_with(b"feed") {
+            return Some("application/atom+xml");
+        } else if rest.starts_with(b"rdf:RDF") {
+            let rss = find(rest, b"http://purl.org/rss/1.0/").is_some()
+                && find(rest, b"http://www.w3.org/1999/02/22-rdf-syntax-ns#").is_some();
+            return rss.then_some("application/rss+xml");
+        } else {
+            return None;
+        };
+        position += skipped?;
+    }
+}
+
+fn match_patterns(patterns: &[Pattern], header: &[u8]) -> Option<&'static str> {
+    patterns
+        .iter()
+        .find(|pattern| pattern.matches(header))
+        .map(|pattern| pattern.mime_type)
+}
+
+fn match_audio_or_video(header: &[u8]) -> Option<&'static str> {
+    match_patterns(AUDIO_VIDEO_PATTERNS, header).or_else(|| is_mp4(header).then_some("video/mp4"))
+}
+
+/// "Matching a MP4 signature": an `ftyp` box with an `mp4` brand.
+fn is_mp4(header: &[u8]) -> bool {
+    if header.len() < 12 {
+        return false;
+    }
+    let box_size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    if header.len() < box_size || !box_size.is_multiple_of(4) || &header[4..8] != b"ftyp" {
+        return false;
+    }
+    if &header[8..11] == b"mp4" {
+        return true;
+    }
+    // The compatible brands follow the minor version
+    (16..box_size)
+        .step_by(4)
+        .any(|offset| header.get(offset..offset + 3) == Some(b"mp4"))
+}
+
+fn essence(essence: &str) -> MimeType {
+    MimeType::parse(essence).expect("valid MIME type")
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+fn is_binary_data_byte(byte: u8) -> bool {
+    matches!(byte, 0x00..=0x08 | 0x0B | 0x0E..=0x1A | 0x1C..=0x1F)
+}
+
+/// Finds the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sniff(bytes: &[u8], content_type: Option<&str>) -> String {
+        sniff_mime_type(bytes, content_type, false).essence()
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        assert_eq!(sniff(b"  \n<!doctype html>", None), "text/html");
+        assert_eq!(sniff(b"<p>Hello", Some("unknown/unknown")), "text/html");
+        assert_eq!(sniff(b"<pre>x", None), "text/plain");
+        assert_eq!(sniff(b"<?xml version=\"1.0\"?>", Some("*/*")), "text/xml");
+        assert_eq!(sniff(b"%PDF-1.7", None), "application/pdf");
+        assert_eq!(sniff(b"GIF89a\x01\0", None), "image/gif");
+        assert_eq!(sniff(b"PK\x03\x04", None), "application/zip");
+        assert_eq!(sniff(b"Hello world", None), "text/plain");
+        assert_eq!(sniff(b"\x01\x02\x03", None), "application/octet-stream");
+        assert_eq!(
+            sniff(b"\0\0\0\x18ftypmp42\0\0\0\0mp42isom", None),
+            "video/mp4"
+        );
+    }
+
+    #[test]
+    fn test_no_sniff() {
+        assert_eq!(
+            sniff_mime_type(b"<html>", None, true).essence(),
+            "text/plain"
+        );
+        assert_eq!(
+            sniff_mime_type(b"\x89PNG\r\n\x1A\n", Some("text/plain"), true).essence(),
+            "text/plain"
+        );
+    }
+
+    #[test]
+    fn test_apache_bug() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1A\n", Some("text/plain")), "image/png");
+        assert_eq!(
+            sniff(b"<html>", Some("text/plain; charset=UTF-8")),
+            "text/plain"
+        );
+        assert_eq!(
+            sniff(b"\x89PNG\r\n\x1A\n", Some("text/plain; charset=utf-8")),
+            "text/plain"
+        );
+    }
+
+    #[test]
+    fn test_feeds_served_as_html() {
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<!-- x -->\n<feed xmlns=\"http://www.w3.org/2005/Atom\">", Some("text/html")),
+            "application/atom+xml"
+        );
+        assert_eq!(
+            sniff(
+                b"<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\" xmlns=\"http://purl.org/rss/1.0/\">",
+                Some("text/html")
+            ),
+            "application/rss+xml"
+        );
+        assert_eq!(
+            sniff(b"<!DOCTYPE html><html>", Some("text/html")),
+            "text/html"
+        );
+    }
+
+    #[test]
+    fn test_supplied_type_is_kept() {
+        assert_eq!(
+            sniff(b"<html>", Some("application/json")),
+            "application/json"
+        );
+        assert_eq!(sniff(b"GIF89a", Some("image/png")), "image/gif");
+        assert_eq!(sniff(b"not an image", Some("image/png")), "image/png");
+        assert_eq!(
+            sniff_mime_type(b"<svg/>", Some("image/svg+xml; charset=utf-8"), false).to_string(),
+            "image/svg+xml;charset=utf-8"
+        );
+    }
+
+    #[test]
+    fn test_sniff_response() {
+        let response = Response {
+            status_code: 200,
+            reason_phrase: "OK".to_string(),
+            headers: vec![
+                ("Content-Type".to_string(), "text/plain".to_string()),
+                (
+                    "X-Content-Type-Options".to_string(),
+                    "NoSniff, other".to_string(),
+                ),
+            ],
+            body: b"\x89PNG\r\n\x1A\n".to_vec(),
+            url: None,
+        };
+        assert_eq!(sniff_response(&response).essence(), "text/plain");
+    }
+}
@@ -0,0 +1,339 @@
+//! A pool of the connections opened by [`HttpClient`](crate::HttpClient),
+//! so that the requests to a server reuse the same few connections (HTTP/1.1
+//! persistent connections) instead of each paying for a new TCP connection
+//! and TLS handshake.
+
+use std::collections::HashMap;
+use std::future::poll_fn;
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::client::Connection;
+use crate::{Request, Response};
+
+/// Connections open at once to the same server at most, like browsers.
+pub(crate) const MAX_CONNECTIONS_PER_HOST: usize = 6;
+
+/// How long an idle connection is kept when the server does not say how
+/// long it keeps it.
+pub(crate) const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The server a connection is to: scheme, host and port.
+pub(crate) type PoolKey = (String, String, u16);
+
+struct IdleConnection {
+    connection: Connection,
+    /// When the server may have closed it.
+    expires: Instant,
+}
+
+/// The connections to one server.
+#[derive(Default)]
+struct Host {
+    /// Most recently used last.
+    idle: Vec<IdleConnection>,
+    /// Connections open, idle or in use.
+    open: usize,
+}
+
+#[derive(Default)]
+struct Hosts {
+    hosts: HashMap<PoolKey, Host>,
+    /// The checkouts waiting for a connection to go back to the pool or be
+    /// closed.
+    waiting: Vec<Waker>,
+}
+
+impl Hosts {
+    fn wake_waiting(&mut self) {
+        self.waiting.drain(..).for_each(Waker::wake);
+    }
+}
+
+pub(crate) struct ConnectionPool {
+    hosts: Mutex<Hosts>,
+    max_per_host: usize,
+}
+
+impl ConnectionPool {
+    pub(crate) fn new(max_per_host: usize) -> Self {
+        ConnectionPool {
+            hosts: Mutex::new(Hosts::default()),
+            max_per_host,
+        }
+    }
+
+    /// The pool of the clients that check servers against the default trust
+    /// store.
+    pub(crate) fn shared() -> Arc<ConnectionPool> {
+        static SHARED: OnceLock<Arc<ConnectionPool>> = OnceLock::new();
+        SHARED
+            .get_or_init(|| Arc::new(ConnectionPool::new(MAX_CONNECTIONS_PER_HOST)))
+            .clone()
+    }
+
+    /// Takes an idle connection to `key`, or the right to open a new one,
+    /// waiting while `max_per_host` connections to it are in use. Idle
+    /// connections that expired or that the server closed are dropped.
+    pub(crate) async fn checkout(&self, key: &PoolKey) -> Lease<'_> {
+        poll_fn(|cx| {
+            let mut hosts = self.hosts.lock().unwrap();
+            let now = Instant::now();
+            let host = hosts.hosts.entry(key.clone()).or_default();
+            // The most recently used is the least likely to have been closed
+            while let Some(idle) = host.idle.pop() {
+                if idle.expires > now && is_open(&idle.connection) {
+                    return Poll::Ready(self.lease(key, Some(idle.connection)));
+                }
+                host.open -= 1;
+            }
+            if host.open < self.max_per_host {
+                host.open += 1;
+                return Poll::Ready(self.lease(key, None));
+            }
+            hosts.waiting.push(cx.waker().clone());
+            Poll::Pending
+        })
+        .await
+    }
+
+    fn lease(&self, key: &PoolKey, connection: Option<Connection>) -> Lease<'_> {
+        Lease {
+            pool: self,
+            key: key.clone(),
+            connection,
+            kept: false,
+        }
+    }
+
+    /// Number of idle connections to `key`.
+    #[cfg(test)]
+    pub(crate) fn idle_count(&self, key: &PoolKey) -> usize {
+        self.hosts.lock().unwrap().hosts.get(key).map_or(0, |host| host.idle.len())
+    }
+}
+
+/// Whether an idle connection can still be used: the server has not closed
+/// it, nor sent anything without being asked. TLS servers may send session
+/// tickets at any time, which the next read skips.
+fn is_open(connection: &Connection) -> bool {
+    // Bytes read past the last response belong to no request
+    if connection.has_buffered_data() {
+        return false;
+    }
+    // The socket does not block: with nothing to read, the peek fails
+    let mut byte = [0u8; 1];
+    match connection.tcp_stream().peek(&mut byte) {
+        Err(e) => e.kind() == ErrorKind::WouldBlock,
+        Ok(0) => false,
+        Ok(_) => connection.is_tls(),
+    }
+}
+
+/// A connection taken from the pool, or the right to open one. Dropping the
+/// lease closes the connection and frees its place, unless it was given
+/// back with [`Lease::release`].
+pub(crate) struct Lease<'a> {
+    pool: &'a ConnectionPool,
+    key: PoolKey,
+    connection: Option<Connection>,
+    kept: bool,
+}
+
+impl Lease<'_> {
+    /// The idle connection the lease came with, if any.
+    pub(crate) fn take(&mut self) -> Option<Connection> {
+        self.connection.take()
+    }
+
+    /// Gives `connection` back to the pool, to be reused for `timeout` at
+    /// most. Expired connections to every server are closed on the way.
+    pub(crate) fn release(mut self, connection: Connection, timeout: Duration) {
+        let now = Instant::now();
+        let mut hosts = self.pool.hosts.lock().unwrap();
+        for host in hosts.hosts.values_mut() {
+            let before = host.idle.len();
+            host.idle.retain(|idle| idle.expires > now);
+            host.open -= before - host.idle.len();
+        }
+        hosts.hosts.entry(self.key.clone()).or_default().idle.push(IdleConnection {
+            connection,
+            expires: now + timeout,
+        });
+        hosts.hosts.retain(|_, host| host.open > 0);
+        hosts.wake_waiting();
+        self.kept = true;
+    }
+}
+
+impl Drop for Lease<'_> {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+        let mut hosts = self.pool.hosts.lock().unwrap();
+        if let Some(host) = hosts.hosts.get_mut(&self.key) {
+            host.open -= 1;
+            if host.open == 0 {
+                hosts.hosts.remove(&self.key);
+            }
+        }
+        hosts.wake_waiting();
+    }
+}
+
+/// Whether the connection `response` came on can be reused, and for how
+/// long: `None` if the request or the response asks to close it, or if an
+/// HTTP/1.0 server did not offer to keep it.
+///
+/// # Arguments
+/// * `request` - The request the response answers.
+/// * `http_1_0` - Whether the response is an HTTP/1.0 one.
+/// * `response` - The response, whose `Keep-Alive: timeout=` header tells
+///   how long the server keeps the connection idle.
+pub(crate) fn keep_alive_timeout(request: &Request, http_1_0: bool, response: &Response) -> Option<Duration> {
+    let has_token = |headers: &[(String, String)], token: &str| {
+        headers
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case("Connection"))
+            .flat_map(|(_, value)| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    if has_token(&request.headers, "close") || has_token(&response.headers, "close") {
+        return None;
+    }
+    if http_1_0 && !has_token(&response.headers, "keep-alive") {
+        return None;
+    }
+    let server_timeout = response.get_header("Keep-Alive").and_then(|value| {
+        value.split(',').find_map(|parameter| {
+            let (name, seconds) = parameter.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("timeout").then_some(())?;
+            seconds.trim().parse::<u64>().ok()
+        })
+    });
+    match server_timeout {
+        // A second early, so that the server is not closing the connection
+        // as it is reused
+        Some(seconds) => Some(Duration::from_secs(seconds.saturating_sub(1)).min(DEFAULT_IDLE_TIMEOUT))
+            .filter(|timeout| !timeout.is_zero()),
+        None => Some(DEFAULT_IDLE_TIMEOUT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Transport;
+    use crate::reactor::AsyncTcpStream;
+    use crate::Method;
+    use futures::executor::block_on;
+    use futures::FutureExt;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    fn response(headers: &[(&str, &str)]) -> Response {
+        Response {
+            status_code: 200,
+            reason_phrase: "OK".to_string(),
+            headers: headers.iter().map(|&(k, v)| (k.to_string(), v.to_string())).collect(),
+            body: Vec::new(),
+            url: None,
+        }
+    }
+
+    #[test]
+    fn test_keep_alive_timeout() {
+        let request = Request::new(Method::GET, "/");
+        assert_eq!(keep_alive_timeout(&request, false, &response(&[])), Some(DEFAULT_IDLE_TIMEOUT));
+        assert_eq!(
+            keep_alive_timeout(&request, false, &response(&[("Keep-Alive", "timeout=5, max=100")])),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(
+            keep_alive_timeout(&request, false, &response(&[("keep-alive", "max=100, Timeout = 600")])),
+            Some(DEFAULT_IDLE_TIMEOUT)
+        );
+        assert_eq!(keep_alive_timeout(&request, false, &response(&[("Keep-Alive", "timeout=1")])), None);
+        assert_eq!(keep_alive_timeout(&request, false, &response(&[("Connection", "Upgrade, Close")])), None);
+        assert_eq!(keep_alive_timeout(&request, true, &response(&[])), None);
+        assert_eq!(
+            keep_alive_timeout(&request, true, &response(&[("Connection", "keep-alive")])),
+            Some(DEFAULT_IDLE_TIMEOUT)
+        );
+
+        let mut closing = Request::new(Method::GET, "/");
+        closing.add_header("Connection", "close");
+        assert_eq!(keep_alive_timeout(&closing, false, &response(&[])), None);
+    }
+
+    /// A connection to a local listener, and the server's end of it.
+    fn connection(listener: &TcpListener) -> (Connection, TcpStream) {
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let transport = Transport::Plain(AsyncTcpStream::new(client).unwrap());
+        (Connection::new(transport), server)
+    }
+
+    #[test]
+    fn test_checkout_and_release() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let pool = ConnectionPool::new(2);
+        let key: PoolKey = ("http".to_string(), "localhost".to_string(), 80);
+
+        let mut lease = pool.checkout(&key).now_or_never().unwrap();
+        assert!(lease.take().is_none());
+        let (first, _first_server) = connection(&listener);
+        lease.release(first, DEFAULT_IDLE_TIMEOUT);
+        assert_eq!(pool.idle_count(&key), 1);
+
+        // The idle connection is reused, and a second one may be opened
+        let mut reused = pool.checkout(&key).now_or_never().unwrap();
+        assert!(reused.take().is_some());
+        let mut second = pool.checkout(&key).now_or_never().unwrap();
+        assert!(second.take().is_none());
+
+        // At the limit, a third request waits for a connection to be freed
+        assert!(pool.checkout(&key).now_or_never().is_none());
+        let waiter = thread::scope(|scope| {
+            let waiter = scope.spawn(|| block_on(pool.checkout(&key)).take().is_none());
+            thread::sleep(Duration::from_millis(50));
+            assert!(!waiter.is_finished());
+            drop(second);
+            waiter.join().unwrap()
+        });
+        assert!(waiter);
+        drop(reused);
+        let hosts = pool.hosts.lock().unwrap();
+        assert!(hosts.hosts.is_empty());
+        assert!(hosts.waiting.is_empty());
+    }
+
+    #[test]
+    fn test_evict_stale_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let pool = ConnectionPool::new(MAX_CONNECTIONS_PER_HOST);
+        let key: PoolKey = ("http".to_string(), "localhost".to_string(), 80);
+        let other: PoolKey = ("http".to_string(), "example.com".to_string(), 80);
+
+        // Closed by the server while idle
+        let (closed, server) = connection(&listener);
+        block_on(pool.checkout(&key)).release(closed, DEFAULT_IDLE_TIMEOUT);
+        drop(server);
+        thread::sleep(Duration::from_millis(50));
+        assert!(block_on(pool.checkout(&key)).take().is_none());
+
+        // Expired: dropped when next looked for, or when any connection is
+        // given back
+        let (expired, _server) = connection(&listener);
+        block_on(pool.checkout(&key)).release(expired, Duration::ZERO);
+        assert_eq!(pool.idle_count(&key), 1);
+        let (live, _other_server) = connection(&listener);
+        block_on(pool.checkout(&other)).release(live, DEFAULT_IDLE_TIMEOUT);
+        assert_eq!(pool.idle_count(&key), 0);
+        assert_eq!(pool.idle_count(&other), 1);
+        assert_eq!(pool.hosts.lock().unwrap().hosts.len(), 1);
+    }
+}
@@ -0,0 +1,227 @@
+//! A small reactor, so that `fetch` never blocks the thread polling it: a
+//! background thread waits with epoll until sockets are ready or timers
+//! expire, and wakes the futures waiting for them. The futures themselves
+//! are polled by whichever executor runs them, such as the GTK main context,
+//! which stays free to repaint in the meantime.
+//!
+//! Sockets are watched in one-shot mode: a future that finds a socket not
+//! ready arms it for the direction it needs and waits to be woken, then
+//! tries again. Arming a socket that is already ready wakes it at once, so
+//! no readiness is missed between the attempt and the arming.
+//!
+//! Linux only, as epoll is.
+
+mod net;
+mod sys;
+mod timer;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::sync::{Mutex, OnceLock};
+use std::task::Waker;
+use std::thread;
+use std::time::Instant;
+
+pub(crate) use net::AsyncTcpStream;
+pub(crate) use timer::timeout;
+
+use sys::{EpollEvent, EPOLLERR, EPOLLHUP, EPOLLIN, EPOLLONESHOT, EPOLLOUT, EPOLLRDHUP};
+
+/// The token of the eventfd that interrupts the reactor's wait.
+const NOTIFY_TOKEN: u64 = u64::MAX;
+
+/// Events read at most per wait.
+const MAX_EVENTS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Read,
+    Write,
+}
+
+/// A registered socket, and the futures waiting for it.
+struct Source {
+    fd: RawFd,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+impl Source {
+    /// The events to watch: those the futures wait for.
+    fn interest(&self) -> u32 {
+        let mut events = 0;
+        if self.reader.is_some() {
+            events |= EPOLLIN | EPOLLRDHUP;
+        }
+        if self.writer.is_some() {
+            events |= EPOLLOUT;
+        }
+        events
+    }
+}
+
+#[derive(Default)]
+struct State {
+    sources: HashMap<u64, Source>,
+    /// The timers, by deadline, and the futures waiting for them.
+    timers: BTreeMap<(Instant, u64), Waker>,
+    /// The next token or timer id.
+    next_id: u64,
+}
+
+pub(crate) struct Reactor {
+    epoll: OwnedFd,
+    /// An eventfd written to when a timer is added, so that the reactor
+    /// waits no longer than its deadline.
+    notify: File,
+    state: Mutex<State>,
+}
+
+impl Reactor {
+    /// The reactor, started on first use.
+    pub(crate) fn get() -> io::Result<&'static Reactor> {
+        static REACTOR: OnceLock<Reactor> = OnceLock::new();
+        if let Some(reactor) = REACTOR.get() {
+            return Ok(reactor);
+        }
+        let epoll = sys::epoll_create()?;
+        let notify = sys::event_fd()?;
+        sys::epoll_add(epoll.as_raw_fd(), notify.as_raw_fd(), EPOLLIN, NOTIFY_TOKEN)?;
+        let reactor = Reactor {
+            epoll,
+            notify: File::from(notify),
+            state: Mutex::new(State::default()),
+        };
+        // Another thread may have been first: its reactor is kept
+        if REACTOR.set(reactor).is_ok() {
+            let reactor = REACTOR.get().unwrap();
+            thread::Builder::new()
+                .name("flux-reactor".to_string())
+                .spawn(move || reactor.run())?;
+        }
+        Ok(REACTOR.get().unwrap())
+    }
+
+    /// Watches `fd`, a non-blocking socket, until `deregister` is called
+    /// with the token returned.
+    pub(crate) fn register(&self, fd: RawFd) -> io::Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        let token = state.next_id;
+        state.next_id += 1;
+        // Disarmed until a future waits for it
+        sys::epoll_add(self.epoll.as_raw_fd(), fd, EPOLLONESHOT, token)?;
+        state.sources.insert(
+            token,
+            Source {
+                fd,
+                reader: None,
+                writer: None,
+            },
+        );
+        Ok(token)
+    }
+
+    pub(crate) fn deregister(&self, token: u64) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(source) = state.sources.remove(&token) {
+            let _ = sys::epoll_delete(self.epoll.as_raw_fd(), source.fd);
+        }
+    }
+
+    /// Wakes `waker` once the socket of `token` is ready in `direction`.
+    pub(crate) fn wait_for(&self, token: u64, direction: Direction, waker: &Waker) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let source = state
+            .sources
+            .get_mut(&token)
+            .ok_or_else(|| io::Error::other("socket not registered"))?;
+        let slot = match direction {
+            Direction::Read => &mut source.reader,
+            Direction::Write => &mut source.writer,
+        };
+        match slot {
+            Some(current) if current.will_wake(waker) => {}
+            _ => *slot = Some(waker.clone()),
+        }
+        sys::epoll_modify(self.epoll.as_raw_fd(), source.fd, source.interest() | EPOLLONESHOT, token)
+    }
+
+    /// A new id for a timer.
+    pub(crate) fn timer_id(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_id += 1;
+        state.next_id - 1
+    }
+
+    /// Wakes `waker` at `deadline`, in place of the waker the timer `id` had.
+    pub(crate) fn set_timer(&self, deadline: Instant, id: u64, waker: &Waker) {
+        let mut state = self.state.lock().unwrap();
+        let earliest = state.timers.keys().next().map(|&(earliest, _)| earliest);
+        state.timers.insert((deadline, id), waker.clone());
+        // The reactor may be waiting for a later deadline, or none
+        if earliest.is_none_or(|earliest| deadline < earliest) {
+            let _ = (&self.notify).write_all(&1u64.to_ne_bytes());
+        }
+    }
+
+    pub(crate) fn remove_timer(&self, deadline: Instant, id: u64) {
+        self.state.lock().unwrap().timers.remove(&(deadline, id));
+    }
+
+    /// The loop of the reactor's thread.
+    fn run(&self) {
+        let mut events = [EpollEvent { events: 0, data: 0 }; MAX_EVENTS];
+        loop {
+            let next_deadline = self.state.lock().unwrap().timers.keys().next().map(|&(deadline, _)| deadline);
+            // Rounded up, not to wake before the deadline
+            let timeout = next_deadline.map_or(-1, |deadline| {
+                let nanos = deadline.saturating_duration_since(Instant::now()).as_nanos();
+                nanos.div_ceil(1_000_000).min(i32::MAX as u128) as i32
+            });
+            let count = match sys::epoll_wait_events(self.epoll.as_raw_fd(), &mut events, timeout) {
+                Ok(count) => count,
+                Err(e) => {
+                    eprintln!("flux-network reactor stopped: {}", e);
+                    return;
+                }
+            };
+
+            let mut wakers = Vec::new();
+            let mut state = self.state.lock().unwrap();
+            for event in &events[..count] {
+                let (flags, token) = (event.events, event.data);
+                if token == NOTIFY_TOKEN {
+                    let _ = (&self.notify).read(&mut [0; 8]);
+                    continue;
+                }
+                let Some(source) = state.sources.get_mut(&token) else {
+                    continue;
+                };
+                // Errors and hang-ups wake both sides, which see them when
+                // they try again
+                if flags & (EPOLLIN | EPOLLRDHUP | EPOLLERR | EPOLLHUP) != 0 {
+                    wakers.extend(source.reader.take());
+                }
+                if flags & (EPOLLOUT | EPOLLERR | EPOLLHUP) != 0 {
+                    wakers.extend(source.writer.take());
+                }
+                // Still waited for in the other direction
+                let interest = source.interest();
+                if interest != 0 {
+                    let _ = sys::epoll_modify(self.epoll.as_raw_fd(), source.fd, interest | EPOLLONESHOT, token);
+                }
+            }
+            let now = Instant::now();
+            while let Some(timer) = state.timers.first_entry() {
+                if timer.key().0 > now {
+                    break;
+                }
+                wakers.push(timer.remove());
+            }
+            drop(state);
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! TCP streams that wait through the reactor instead of blocking.
+
+use std::future::{poll_fn, Future};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::os::fd::AsRawFd;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::Duration;
+
+use futures::channel::oneshot;
+use futures::io::{AsyncRead, AsyncWrite};
+
+use super::{sys, timeout, Direction, Reactor};
+
+/// How long resolving a host name may take at most.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long connecting to one address of a host may take at most.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A non-blocking TCP stream registered with the reactor.
+pub(crate) struct AsyncTcpStream {
+    stream: TcpStream,
+    reactor: &'static Reactor,
+    token: u64,
+}
+
+impl AsyncTcpStream {
+    /// Registers a connected stream with the reactor.
+    pub(crate) fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        let reactor = Reactor::get()?;
+        let token = reactor.register(stream.as_raw_fd())?;
+        Ok(AsyncTcpStream { stream, reactor, token })
+    }
+
+    /// Connects to `host`, trying its addresses in turn. Fails with
+    /// `ErrorKind::TimedOut` if the name is not resolved in time, or if no
+    /// address answers in time.
+    pub(crate) async fn connect(host: &str, port: u16) -> io::Result<Self> {
+        let mut last_error = io::Error::new(ErrorKind::NotFound, "the host has no address");
+        for address in within(RESOLVE_TIMEOUT, resolve(host, port)).await? {
+            match within(CONNECT_TIMEOUT, AsyncTcpStream::connect_to(&address)).await {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    async fn connect_to(address: &SocketAddr) -> io::Result<Self> {
+        let stream = AsyncTcpStream::new(TcpStream::from(sys::start_connect(address)?))?;
+        // The socket becomes writable once connected, or once it failed
+        poll_fn(|cx| {
+            if let Some(error) = stream.stream.take_error()? {
+                return Poll::Ready(Err(error));
+            }
+            match stream.stream.peer_addr() {
+                Ok(_) => Poll::Ready(Ok(())),
+                Err(e) if e.kind() == ErrorKind::NotConnected => {
+                    stream.reactor.wait_for(stream.token, Direction::Write, cx.waker())?;
+                    Poll::Pending
+                }
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+        .await?;
+        Ok(stream)
+    }
+
+    pub(crate) fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    /// Runs `operation` on the stream, or waits for the stream to be ready
+    /// in `direction` if it would block.
+    fn poll_io<T>(
+        &self,
+        cx: &mut Context<'_>,
+        direction: Direction,
+        mut operation: impl FnMut(&TcpStream) -> io::Result<T>,
+    ) -> Poll<io::Result<T>> {
+        loop {
+            match operation(&self.stream) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    self.reactor.wait_for(self.token, direction, cx.waker())?;
+                    return Poll::Pending;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+}
+
+impl Drop for AsyncTcpStream {
+    fn drop(&mut self) {
+        self.reactor.deregister(self.token);
+    }
+}
+
+impl AsyncRead for AsyncTcpStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<io::Result<usize>> {
+        self.poll_io(cx, Direction::Read, |mut stream| stream.read(buffer))
+    }
+}
+
+impl AsyncWrite for AsyncTcpStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        self.poll_io(cx, Direction::Write, |mut stream| stream.write(data))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(self.stream.shutdown(Shutdown::Write))
+    }
+}
+
+/// Runs `future` for `duration` at most, failing with `ErrorKind::TimedOut`
+/// if the time runs out first.
+async fn within<T>(duration: Duration, future: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    timeout(duration, future)
+        .await
+        .unwrap_or_else(|| Err(io::Error::new(ErrorKind::TimedOut, "the operation timed out")))
+}
+
+/// The addresses of `host`. Names are resolved on a thread of their own, as
+/// the system's resolver blocks.
+async fn resolve(host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return Ok(vec![SocketAddr::new(ip, port)]);
+    }
+    let (sender, receiver) = oneshot::channel();
+    let host = host.to_string();
+    thread::Builder::new()
+        .name("flux-resolver".to_string())
+        .spawn(move || {
+            let _ = sender.send((host.as_str(), port).to_socket_addrs().map(Vec::from_iter));
+        })?;
+    receiver
+        .await
+        .map_err(|_| io::Error::other("the resolver thread failed"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_connect_and_exchange() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4];
+            stream.read_exact(&mut request).unwrap();
+            // Late, so that the client waits for the answer
+            thread::sleep(std::time::Duration::from_millis(50));
+            stream.write_all(&request.map(|byte| byte.to_ascii_uppercase())).unwrap();
+        });
+
+        block_on(async {
+            let mut stream = AsyncTcpStream::connect("localhost", port).await.unwrap();
+            stream.write_all(b"ping").await.unwrap();
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await.unwrap();
+            assert_eq!(response, b"PING");
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_connection_refused() {
+        // A port nothing listens on once the listener is gone
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let error = block_on(AsyncTcpStream::connect("127.0.0.1", port)).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::ConnectionRefused);
+    }
+
+    #[test]
+    fn test_within() {
+        let error = block_on(within(Duration::from_millis(20), futures::future::pending::<io::Result<()>>()));
+        assert_eq!(error.err().unwrap().kind(), ErrorKind::TimedOut);
+        assert_eq!(block_on(within(Duration::from_secs(10), async { Ok(5) })).unwrap(), 5);
+    }
+}
@@ -0,0 +1,128 @@
+//! The Linux system calls the reactor needs and the standard library does
+//! not expose: epoll, eventfd, and connecting a socket without blocking.
+
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::raw::{c_int, c_uint, c_void};
+
+pub(crate) const EPOLLIN: u32 = 0x001;
+pub(crate) const EPOLLOUT: u32 = 0x004;
+pub(crate) const EPOLLERR: u32 = 0x008;
+pub(crate) const EPOLLHUP: u32 = 0x010;
+pub(crate) const EPOLLRDHUP: u32 = 0x2000;
+pub(crate) const EPOLLONESHOT: u32 = 1 << 30;
+
+const EPOLL_CTL_ADD: c_int = 1;
+const EPOLL_CTL_DEL: c_int = 2;
+const EPOLL_CTL_MOD: c_int = 3;
+const O_NONBLOCK: c_int = 0o4000;
+const O_CLOEXEC: c_int = 0o2000000;
+const AF_INET: c_int = 2;
+const AF_INET6: c_int = 10;
+const SOCK_STREAM: c_int = 1;
+const EINPROGRESS: i32 = 115;
+
+/// `struct epoll_event`, which the kernel packs on x86-64.
+#[cfg_attr(target_arch = "x86_64", repr(C, packed))]
+#[cfg_attr(not(target_arch = "x86_64"), repr(C))]
+#[derive(Clone, Copy)]
+pub(crate) struct EpollEvent {
+    pub(crate) events: u32,
+    pub(crate) data: u64,
+}
+
+extern "C" {
+    fn epoll_create1(flags: c_int) -> c_int;
+    fn epoll_ctl(epfd: c_int, op: c_int, fd: c_int, event: *mut EpollEvent) -> c_int;
+    fn epoll_wait(epfd: c_int, events: *mut EpollEvent, maxevents: c_int, timeout: c_int) -> c_int;
+    fn eventfd(initval: c_uint, flags: c_int) -> c_int;
+    fn socket(domain: c_int, kind: c_int, protocol: c_int) -> c_int;
+    fn connect(fd: c_int, address: *const c_void, length: u32) -> c_int;
+}
+
+/// Turns the -1 of a failed call into the error it set.
+fn check(result: c_int) -> io::Result<c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+pub(crate) fn epoll_create() -> io::Result<OwnedFd> {
+    // SAFETY: the descriptor was just created, and is owned by no one else
+    unsafe { Ok(OwnedFd::from_raw_fd(check(epoll_create1(O_CLOEXEC))?)) }
+}
+
+fn control(epoll: RawFd, operation: c_int, fd: RawFd, events: u32, token: u64) -> io::Result<()> {
+    let mut event = EpollEvent { events, data: token };
+    // SAFETY: `event` lives through the call, which only reads it
+    check(unsafe { epoll_ctl(epoll, operation, fd, &mut event) }).map(drop)
+}
+
+/// Adds `fd` to the descriptors `epoll` watches, reporting its events with
+/// `token`.
+pub(crate) fn epoll_add(epoll: RawFd, fd: RawFd, events: u32, token: u64) -> io::Result<()> {
+    control(epoll, EPOLL_CTL_ADD, fd, events, token)
+}
+
+/// Changes the events watched for `fd`.
+pub(crate) fn epoll_modify(epoll: RawFd, fd: RawFd, events: u32, token: u64) -> io::Result<()> {
+    control(epoll, EPOLL_CTL_MOD, fd, events, token)
+}
+
+pub(crate) fn epoll_delete(epoll: RawFd, fd: RawFd) -> io::Result<()> {
+    control(epoll, EPOLL_CTL_DEL, fd, 0, 0)
+}
+
+/// Waits for events for `timeout` milliseconds at most, or forever if it is
+/// negative. A wait interrupted by a signal reports no event.
+pub(crate) fn epoll_wait_events(epoll: RawFd, events: &mut [EpollEvent], timeout: c_int) -> io::Result<usize> {
+    // SAFETY: the kernel writes `events.len()` events at most
+    match check(unsafe { epoll_wait(epoll, events.as_mut_ptr(), events.len() as c_int, timeout) }) {
+        Ok(count) => Ok(count as usize),
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// A non-blocking eventfd, a counter other threads write to to wake the
+/// one waiting on it.
+pub(crate) fn event_fd() -> io::Result<OwnedFd> {
+    // SAFETY: the descriptor was just created, and is owned by no one else
+    unsafe { Ok(OwnedFd::from_raw_fd(check(eventfd(0, O_NONBLOCK | O_CLOEXEC))?)) }
+}
+
+/// Creates a non-blocking TCP socket and starts connecting it to `address`.
+/// The socket becomes writable once the connection succeeds or fails.
+pub(crate) fn start_connect(address: &SocketAddr) -> io::Result<OwnedFd> {
+    // `struct sockaddr_in` or `struct sockaddr_in6`
+    let mut raw = Vec::with_capacity(28);
+    let domain = match address {
+        SocketAddr::V4(address) => {
+            raw.extend_from_slice(&(AF_INET as u16).to_ne_bytes());
+            raw.extend_from_slice(&address.port().to_be_bytes());
+            raw.extend_from_slice(&address.ip().octets());
+            raw.extend_from_slice(&[0; 8]);
+            AF_INET
+        }
+        SocketAddr::V6(address) => {
+            raw.extend_from_slice(&(AF_INET6 as u16).to_ne_bytes());
+            raw.extend_from_slice(&address.port().to_be_bytes());
+            raw.extend_from_slice(&address.flowinfo().to_ne_bytes());
+            raw.extend_from_slice(&address.ip().octets());
+            raw.extend_from_slice(&address.scope_id().to_ne_bytes());
+            AF_INET6
+        }
+    };
+    // SAFETY: the descriptor was just created, and is owned by no one else;
+    // `raw` lives through the call to connect, which only reads it
+    unsafe {
+        let fd = OwnedFd::from_raw_fd(check(socket(domain, SOCK_STREAM | O_NONBLOCK | O_CLOEXEC, 0))?);
+        match check(connect(std::os::fd::AsRawFd::as_raw_fd(&fd), raw.as_ptr().cast(), raw.len() as u32)) {
+            Err(e) if e.raw_os_error() != Some(EINPROGRESS) => Err(e),
+            _ => Ok(fd),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Timers, for the timeouts of network operations.
+
+use std::future::{poll_fn, Future};
+use std::pin::{pin, Pin};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::Reactor;
+
+/// A future that completes at a given instant.
+pub(crate) struct Timer {
+    deadline: Instant,
+    /// Set once the timer is known to the reactor.
+    id: Option<u64>,
+}
+
+impl Timer {
+    pub(crate) fn after(duration: Duration) -> Self {
+        Timer {
+            deadline: Instant::now() + duration,
+            id: None,
+        }
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        // Without a reactor, nothing else can wait either
+        let Ok(reactor) = Reactor::get() else {
+            return Poll::Ready(());
+        };
+        let id = *self.id.get_or_insert_with(|| reactor.timer_id());
+        reactor.set_timer(self.deadline, id, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let (Some(id), Ok(reactor)) = (self.id, Reactor::get()) {
+            reactor.remove_timer(self.deadline, id);
+        }
+    }
+}
+
+/// Runs `future` for `duration` at most.
+///
+/// # Returns
+/// Its output, or `None` if the time ran out first.
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let mut timer = Timer::after(duration);
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        Pin::new(&mut timer).poll(cx).map(|()| None)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::future::pending;
+
+    #[test]
+    fn test_timers() {
+        let start = Instant::now();
+        block_on(Timer::after(Duration::from_millis(30)));
+        assert!(start.elapsed() >= Duration::from_millis(30));
+
+        let start = Instant::now();
+        assert_eq!(block_on(timeout(Duration::from_millis(20), pending::<()>())), None);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(block_on(timeout(Duration::from_secs(10), async { 5 })), Some(5));
+
+        // A timer added while the reactor waits for a later one is not late
+        let start = Instant::now();
+        block_on(futures::future::select(
+            Timer::after(Duration::from_secs(60)),
+            Timer::after(Duration::from_millis(20)),
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,137 @@
+//! Following HTTP redirects: where a redirect leads, and the request to
+//! send there, as browsers do (Fetch standard, "HTTP-redirect fetch").
+
+use crate::{parse_url, resolve_url, Method, NetworkError, Request, Url};
+
+/// Redirects followed for one fetch at most, like browsers.
+pub(crate) const DEFAULT_MAX_REDIRECTS: usize = 20;
+
+/// Headers describing the body of a request, dropped with the body when a
+/// redirect turns the request into a GET.
+const BODY_HEADERS: [&str; 5] = [
+    "Content-Type",
+    "Content-Length",
+    "Content-Encoding",
+    "Content-Language",
+    "Content-Location",
+];
+
+/// Whether `status_code` asks to fetch the resource at `Location` instead.
+pub(crate) fn is_redirect(status_code: u16) -> bool {
+    matches!(status_code, 301 | 302 | 303 | 307 | 308)
+}
+
+/// The URL a redirect from `url` leads to.
+///
+/// # Arguments
+/// * `url` - The URL that was redirected.
+/// * `location` - The `Location` header of the redirect, absolute or
+///   relative to `url`. Without a fragment, the one of `url` is kept.
+///
+/// # Returns
+/// The URL to fetch next, or an error if it is not an `http` or `https` one.
+pub(crate) fn redirect_url(url: &Url, location: &str) -> Result<Url, NetworkError> {
+    let mut next = parse_url(&resolve_url(&url.to_string(), location)?)?;
+    if !next.scheme.eq_ignore_ascii_case("http") && !next.scheme.eq_ignore_ascii_case("https") {
+        return Err(NetworkError::InvalidData(format!(
+            "Redirect to an unsupported scheme: {}",
+            next.scheme
+        )));
+    }
+    if next.fragment.is_empty() {
+        next.fragment = url.fragment.clone();
+    }
+    Ok(next)
+}
+
+/// The request to send to `next` after `request` to `url` was answered by
+/// a redirect with `status_code`.
+///
+/// A 303 turns any request into a GET, and so do a 301 and a 302 for a
+/// POST, for compatibility; the body goes with the method. A 307 and a 308
+/// send the same request again. Credentials are not sent to another origin.
+pub(crate) fn redirect_request(request: &Request, status_code: u16, url: &Url, next: &Url) -> Request {
+    let to_get = match status_code {
+        301 | 302 => request.method == Method::POST,
+        303 => request.method != Method::GET,
+        _ => false,
+    };
+    let same_origin = url.scheme.eq_ignore_ascii_case(&next.scheme)
+        && url.host.eq_ignore_ascii_case(&next.host)
+        && url.port == next.port;
+
+    let mut redirected = Request::new(request.method, &next.request_target());
+    for (key, value) in &request.headers {
+        if key.eq_ignore_ascii_case("Host") {
+            redirected.add_header(key, &next.host_header());
+        } else if (to_get && BODY_HEADERS.iter().any(|name| key.eq_ignore_ascii_case(name)))
+            || (!same_origin && key.eq_ignore_ascii_case("Authorization"))
+        {
+            continue;
+        } else {
+            redirected.add_header(key, value);
+        }
+    }
+    if to_get {
+        redirected.method = Method::GET;
+    } else {
+        redirected.set_body(&request.body);
+    }
+    redirected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> Request {
+        let mut request = Request::new(Method::POST, "/form");
+        request.add_header("Host", "example.com");
+        request.add_header("Content-Type", "application/x-www-form-urlencoded");
+        request.add_header("Authorization", "Basic Zmx1eDpmbHV4");
+        request.set_body(b"q=flux");
+        request
+    }
+
+    #[test]
+    fn test_redirect_url() {
+        let url = parse_url("http://example.com/a/b?x=1#top").unwrap();
+        assert_eq!(redirect_url(&url, "c").unwrap().to_string(), "http://example.com/a/c#top");
+        assert_eq!(redirect_url(&url, "/d#end").unwrap().to_string(), "http://example.com/d#end");
+        assert_eq!(
+            redirect_url(&url, "https://www.example.com/").unwrap().to_string(),
+            "https://www.example.com/#top"
+        );
+        assert_eq!(redirect_url(&url, "HTTPS://example.com/").unwrap().scheme, "https");
+        assert!(matches!(redirect_url(&url, "ftp://example.com/"), Err(NetworkError::InvalidData(_))));
+    }
+
+    #[test]
+    fn test_redirect_request() {
+        let url = parse_url("http://example.com/form").unwrap();
+        let same_origin = parse_url("http://example.com/done?id=3").unwrap();
+
+        // 303: a GET, without the body or what describes it
+        let request = redirect_request(&post(), 303, &url, &same_origin);
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(request.path, "/done?id=3");
+        assert!(request.body.is_empty());
+        assert!(request.headers.iter().all(|(key, _)| key != "Content-Type"));
+        assert!(request.headers.iter().any(|(key, _)| key == "Authorization"));
+        // So do 301 and 302 for a POST
+        assert_eq!(redirect_request(&post(), 302, &url, &same_origin).method, Method::GET);
+
+        // 307 and 308: the same request, elsewhere
+        let other_origin = parse_url("https://www.example.com:8443/form").unwrap();
+        let request = redirect_request(&post(), 307, &url, &other_origin);
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.body, b"q=flux");
+        assert_eq!(
+            request.headers,
+            [
+                ("Host".to_string(), "www.example.com:8443".to_string()),
+                ("Content-Type".to_string(), "application/x-www-form-urlencoded".to_string()),
+            ]
+        );
+    }
+}
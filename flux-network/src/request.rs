@@ -3,7 +3,7 @@
 use std::fmt;
 
 /// Represents an HTTP method (GET, POST, etc.).
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Method {
     GET,
     POST,
@@ -12,6 +12,7 @@ pub enum Method {
 }
 
 /// Represents an HTTP request, including method, path, headers, and body.
+#[derive(Clone)]
 pub struct Request {
     /// The HTTP method to use (GET, POST, etc.).
     pub method: Method,
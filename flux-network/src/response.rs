@@ -11,6 +11,9 @@ pub struct Response {
     pub headers: Vec<(String, String)>,
     /// The response body as raw bytes.
     pub body: Vec<u8>,
+    /// The URL the response came from, after any redirect, or `None` if
+    /// it is not known, e.g. for a response parsed from raw bytes.
+    pub url: Option<String>,
 }
 
 impl Response {
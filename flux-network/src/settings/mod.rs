@@ -2,6 +2,7 @@ use crate::url::Url;
 use crate::{NetworkError, Response};
 
 pub(crate) fn fetch_settings(url: Url) -> Result<Response, NetworkError> {
+    let address = url.to_string();
     let host = url.host;
 
     let content = match host.as_str() {
@@ -12,7 +13,11 @@ pub(crate) fn fetch_settings(url: Url) -> Result<Response, NetworkError> {
     Ok(Response {
         status_code: 200,
         reason_phrase: "OK".to_string(),
-        headers: vec![],
+        headers: vec![(
+            "Content-Type".to_string(),
+            "text/html; charset=utf-8".to_string(),
+        )],
         body: content.as_bytes().to_vec(),
+        url: Some(address),
     })
 }
@@ -0,0 +1,288 @@
+//! A TLS 1.3 server for the tests, with fixed keys. It answers a single
+//! connection on a local port, with a canned response, and can be told to
+//! misbehave in the ways the client must catch.
+
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::key_schedule::{finished_verify_data, KeySchedule};
+use super::messages::{self, handshake_message, Reader};
+use super::record::{self, read_record, write_record, RecordKeys};
+use super::{alert_error, Alert, CipherSuite, HandshakeBuffer, TlsError};
+use crate::crypto::ecdsa::{self, Curve};
+use crate::crypto::from_hex;
+use crate::crypto::sha256::{sha256, Sha256};
+use crate::crypto::x25519::{x25519, x25519_base};
+use crate::x509::testdata::{der, INTERMEDIATE, LEAF, LEAF_KEY};
+
+/// How the server behaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Scenario {
+    /// A plain handshake, then the response.
+    Normal,
+    /// Updates its keys in the middle of the response, and asks the client
+    /// to update its own.
+    KeyUpdate,
+    /// Sends a Finished message that does not match the handshake.
+    BadFinished,
+    /// Signs the handshake with a key that is not its certificate's.
+    BadSignature,
+    /// Refuses the ClientHello with a handshake_failure alert.
+    RejectHello,
+}
+
+/// What the server received from the client.
+pub(crate) struct Received {
+    pub(crate) server_name: Option<String>,
+    pub(crate) request: Vec<u8>,
+}
+
+pub(crate) struct FixtureServer {
+    address: SocketAddr,
+    thread: JoinHandle<Result<Received, TlsError>>,
+}
+
+impl FixtureServer {
+    const PRIVATE_KEY: [u8; 32] = [0x42; 32];
+    const RANDOM: [u8; 32] = [0x24; 32];
+
+    pub(crate) fn start(suite: CipherSuite, scenario: Scenario, response: &[u8]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let response = response.to_vec();
+        let thread = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            serve(stream, suite, scenario, &response)
+        });
+        FixtureServer { address, thread }
+    }
+
+    pub(crate) fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Waits for the connection to end, and returns what the client sent.
+    pub(crate) fn finish(self) -> Result<Received, TlsError> {
+        self.thread.join().unwrap()
+    }
+}
+
+fn read_message(
+    stream: &mut TcpStream,
+    received: &mut HandshakeBuffer,
+    keys: Option<&mut RecordKeys>,
+) -> Result<Vec<u8>, TlsError> {
+    let mut keys = keys;
+    loop {
+        if let Some(message) = received.take_message()? {
+            return Ok(message);
+        }
+        let record = read_record(stream, keys.as_deref_mut())?.ok_or(TlsError::Protocol(
+            Alert::UNEXPECTED_MESSAGE,
+            "client closed the connection",
+        ))?;
+        match record.content_type {
+            record::HANDSHAKE => received.data.extend(record.payload),
+            record::ALERT => return Err(alert_error(&record.payload)),
+            _ => return Err(TlsError::Protocol(Alert::UNEXPECTED_MESSAGE, "unexpected record")),
+        }
+    }
+}
+
+/// The fields of the ClientHello the server needs.
+struct ClientHello {
+    server_name: Option<String>,
+    key_share: [u8; 32],
+}
+
+fn parse_client_hello(body: &[u8], suite: CipherSuite) -> Result<ClientHello, TlsError> {
+    let invalid = TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "unexpected ClientHello");
+    let mut reader = Reader::new(body);
+    reader.u16()?;
+    reader.bytes(32)?;
+    reader.vector8()?;
+    let suites = reader.vector16()?;
+    if !suites.chunks(2).any(|id| id == suite.id().to_be_bytes()) {
+        return Err(invalid);
+    }
+    reader.vector8()?;
+
+    let mut server_name = None;
+    let mut key_share = None;
+    for (extension_type, data) in reader.extensions()? {
+        let mut data = Reader::new(data);
+        match extension_type {
+            messages::SERVER_NAME => {
+                let mut names = Reader::new(data.vector16()?);
+                names.u8()?;
+                server_name = Some(String::from_utf8_lossy(names.vector16()?).into_owned());
+            }
+            messages::KEY_SHARE => {
+                let mut shares = Reader::new(data.vector16()?);
+                while !shares.is_empty() {
+                    let group = shares.u16()?;
+                    let key = shares.vector16()?;
+                    if group == messages::X25519 {
+                        key_share = key.try_into().ok();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    reader.finish()?;
+    Ok(ClientHello {
+        server_name,
+        key_share: key_share.ok_or(invalid)?,
+    })
+}
+
+/// Signs the transcript hash with the key of the test certificate, as an
+/// ecdsa_secp256r1_sha256 CertificateVerify.
+fn sign_transcript(transcript_hash: &[u8], private_key: &[u8]) -> Vec<u8> {
+    let mut content = vec![0x20; 64];
+    content.extend_from_slice(b"TLS 1.3, server CertificateVerify\0");
+    content.extend_from_slice(transcript_hash);
+    let digest = sha256(&content);
+    // Derived from the key and the message, so that nonces never repeat
+    let nonce = sha256(&[private_key, &digest[..]].concat());
+    let (r, s) = ecdsa::sign(Curve::P256, private_key, &digest, &nonce);
+
+    // Ecdsa-Sig-Value ::= SEQUENCE { r INTEGER, s INTEGER }
+    let mut integers = Vec::new();
+    for value in [r, s] {
+        let value = &value[value.iter().take_while(|&&b| b == 0).count()..];
+        let padding = value.first().is_none_or(|&b| b & 0x80 != 0);
+        integers.extend([0x02, value.len() as u8 + padding as u8]);
+        if padding {
+            integers.push(0);
+        }
+        integers.extend_from_slice(value);
+    }
+    let mut signature = vec![0x30, integers.len() as u8];
+    signature.extend(integers);
+    signature
+}
+
+fn serve(mut stream: TcpStream, suite: CipherSuite, scenario: Scenario, response: &[u8]) -> Result<Received, TlsError> {
+    let mut received = HandshakeBuffer::default();
+    let mut transcript = Sha256::new();
+
+    let client_hello = read_message(&mut stream, &mut received, None)?;
+    if scenario == Scenario::RejectHello {
+        write_record(&mut stream, record::ALERT, &[2, Alert::HANDSHAKE_FAILURE.0], None)?;
+        return Err(TlsError::Protocol(Alert::HANDSHAKE_FAILURE, "rejected the ClientHello"));
+    }
+    transcript.update(&client_hello);
+    let hello = parse_client_hello(&client_hello[4..], suite)?;
+
+    let server_hello = handshake_message(messages::SERVER_HELLO, |writer| {
+        writer.u16(messages::TLS_1_2);
+        writer.bytes(&FixtureServer::RANDOM);
+        writer.vector8(|_| {});
+        writer.u16(suite.id());
+        writer.u8(0);
+        writer.vector16(|writer| {
+            writer.extension(messages::SUPPORTED_VERSIONS, |writer| writer.u16(messages::TLS_1_3));
+            writer.extension(messages::KEY_SHARE, |writer| {
+                writer.u16(messages::X25519);
+                writer.vector16(|writer| writer.bytes(&x25519_base(&FixtureServer::PRIVATE_KEY)));
+            });
+        });
+    });
+    transcript.update(&server_hello);
+    write_record(&mut stream, record::HANDSHAKE, &server_hello, None)?;
+    // Like servers in middlebox compatibility mode
+    write_record(&mut stream, record::CHANGE_CIPHER_SPEC, &[1], None)?;
+
+    let schedule = KeySchedule::new(&x25519(&FixtureServer::PRIVATE_KEY, &hello.key_share));
+    let secrets = schedule.handshake_traffic_secrets(&transcript.clone().finish());
+    let mut write_keys = RecordKeys::new(suite, secrets.server);
+    let mut read_keys = RecordKeys::new(suite, secrets.client);
+
+    // The whole encrypted flight goes in one record
+    let mut flight = Vec::new();
+    let mut add = |message: Vec<u8>, transcript: &mut Sha256| {
+        transcript.update(&message);
+        flight.extend(message);
+    };
+    add(
+        handshake_message(messages::ENCRYPTED_EXTENSIONS, |writer| writer.vector16(|_| {})),
+        &mut transcript,
+    );
+    add(
+        handshake_message(messages::CERTIFICATE, |writer| {
+            writer.vector8(|_| {});
+            writer.vector24(|writer| {
+                for certificate in [der(LEAF), der(INTERMEDIATE)] {
+                    writer.vector24(|writer| writer.bytes(&certificate));
+                    writer.vector16(|_| {});
+                }
+            });
+        }),
+        &mut transcript,
+    );
+    let mut private_key = from_hex(LEAF_KEY);
+    if scenario == Scenario::BadSignature {
+        private_key[31] ^= 1;
+    }
+    let signature = sign_transcript(&transcript.clone().finish(), &private_key);
+    add(
+        handshake_message(messages::CERTIFICATE_VERIFY, |writer| {
+            writer.u16(0x0403);
+            writer.vector16(|writer| writer.bytes(&signature));
+        }),
+        &mut transcript,
+    );
+    let mut verify_data = finished_verify_data(&secrets.server, &transcript.clone().finish());
+    if scenario == Scenario::BadFinished {
+        verify_data[0] ^= 1;
+    }
+    add(messages::finished(&verify_data), &mut transcript);
+    write_record(&mut stream, record::HANDSHAKE, &flight, Some(&mut write_keys))?;
+
+    let application_secrets = schedule.application_traffic_secrets(&transcript.clone().finish());
+    let expected = finished_verify_data(&secrets.client, &transcript.clone().finish());
+    let finished = read_message(&mut stream, &mut received, Some(&mut read_keys))?;
+    if finished != messages::finished(&expected) {
+        return Err(TlsError::Protocol(Alert::DECRYPT_ERROR, "client Finished does not match"));
+    }
+    let mut write_keys = RecordKeys::new(suite, application_secrets.server);
+    let mut read_keys = RecordKeys::new(suite, application_secrets.client);
+
+    let mut request = Vec::new();
+    while !request.ends_with(b"\r\n\r\n") {
+        let record = read_record(&mut stream, Some(&mut read_keys))?
+            .ok_or(TlsError::Protocol(Alert::UNEXPECTED_MESSAGE, "client closed the connection"))?;
+        if record.content_type != record::APPLICATION_DATA {
+            return Err(TlsError::Protocol(Alert::UNEXPECTED_MESSAGE, "expected the request"));
+        }
+        request.extend(record.payload);
+    }
+
+    if scenario == Scenario::KeyUpdate {
+        let (first, second) = response.split_at(response.len() / 2);
+        write_record(&mut stream, record::APPLICATION_DATA, first, Some(&mut write_keys))?;
+        write_record(&mut stream, record::HANDSHAKE, &messages::key_update(true), Some(&mut write_keys))?;
+        write_keys.update();
+        write_record(&mut stream, record::APPLICATION_DATA, second, Some(&mut write_keys))?;
+    } else {
+        write_record(&mut stream, record::APPLICATION_DATA, response, Some(&mut write_keys))?;
+    }
+    write_record(&mut stream, record::ALERT, &[1, Alert::CLOSE_NOTIFY.0], Some(&mut write_keys))?;
+
+    if scenario == Scenario::KeyUpdate {
+        // The client answers with its own KeyUpdate, under its old keys
+        let message = read_message(&mut stream, &mut received, Some(&mut read_keys))?;
+        if message != messages::key_update(false) {
+            return Err(TlsError::Protocol(Alert::UNEXPECTED_MESSAGE, "expected a KeyUpdate"));
+        }
+    }
+
+    Ok(Received {
+        server_name: hello.server_name,
+        request,
+    })
+}
@@ -0,0 +1,104 @@
+//! The TLS 1.3 key schedule (RFC 8446, section 7.1), for handshakes without
+//! a pre-shared key. Both supported cipher suites hash with SHA-256.
+
+use crate::crypto::hmac::{hkdf_expand, hkdf_extract, hmac_sha256};
+use crate::crypto::sha256::{sha256, HASH_LEN};
+
+pub(crate) type Secret = [u8; HASH_LEN];
+
+/// HKDF-Expand-Label: expands `secret` into `length` bytes bound to a label
+/// and a context.
+pub(crate) fn hkdf_expand_label(secret: &[u8], label: &str, context: &[u8], length: usize) -> Vec<u8> {
+    let label = format!("tls13 {}", label);
+    let mut info = Vec::with_capacity(4 + label.len() + context.len());
+    info.extend_from_slice(&(length as u16).to_be_bytes());
+    info.push(label.len() as u8);
+    info.extend_from_slice(label.as_bytes());
+    info.push(context.len() as u8);
+    info.extend_from_slice(context);
+    hkdf_expand(secret, &info, length)
+}
+
+/// Derive-Secret: a secret bound to the transcript of the handshake so far.
+fn derive_secret(secret: &Secret, label: &str, transcript_hash: &[u8]) -> Secret {
+    let mut derived = [0; HASH_LEN];
+    derived.copy_from_slice(&hkdf_expand_label(secret, label, transcript_hash, HASH_LEN));
+    derived
+}
+
+/// The traffic secrets of both directions for one phase of the connection.
+pub(crate) struct TrafficSecrets {
+    pub(crate) client: Secret,
+    pub(crate) server: Secret,
+}
+
+pub(crate) struct KeySchedule {
+    handshake_secret: Secret,
+    master_secret: Secret,
+}
+
+impl KeySchedule {
+    /// Starts the schedule from the secret agreed on by the key exchange.
+    pub(crate) fn new(shared_secret: &[u8]) -> Self {
+        let zeros = [0; HASH_LEN];
+        let empty_hash = sha256(b"");
+        let early_secret = hkdf_extract(&zeros, &zeros);
+        let handshake_secret = hkdf_extract(&derive_secret(&early_secret, "derived", &empty_hash), shared_secret);
+        let master_secret = hkdf_extract(&derive_secret(&handshake_secret, "derived", &empty_hash), &zeros);
+        KeySchedule {
+            handshake_secret,
+            master_secret,
+        }
+    }
+
+    /// Secrets protecting the rest of the handshake, from the hash of the
+    /// transcript up to the ServerHello.
+    pub(crate) fn handshake_traffic_secrets(&self, transcript_hash: &[u8]) -> TrafficSecrets {
+        TrafficSecrets {
+            client: derive_secret(&self.handshake_secret, "c hs traffic", transcript_hash),
+            server: derive_secret(&self.handshake_secret, "s hs traffic", transcript_hash),
+        }
+    }
+
+    /// Secrets protecting application data, from the hash of the transcript
+    /// up to the server's Finished.
+    pub(crate) fn application_traffic_secrets(&self, transcript_hash: &[u8]) -> TrafficSecrets {
+        TrafficSecrets {
+            client: derive_secret(&self.master_secret, "c ap traffic", transcript_hash),
+            server: derive_secret(&self.master_secret, "s ap traffic", transcript_hash),
+        }
+    }
+}
+
+/// The content of a Finished message: a MAC of the transcript keyed by the
+/// handshake traffic secret of the side that sends it.
+pub(crate) fn finished_verify_data(traffic_secret: &Secret, transcript_hash: &[u8]) -> Secret {
+    let finished_key = hkdf_expand_label(traffic_secret, "finished", b"", HASH_LEN);
+    hmac_sha256(&finished_key, transcript_hash)
+}
+
+/// The traffic secret that replaces `secret` after a KeyUpdate.
+pub(crate) fn next_traffic_secret(secret: &Secret) -> Secret {
+    derive_secret(secret, "traffic upd", b"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::from_hex;
+
+    #[test]
+    fn test_early_secrets() {
+        // The start of the schedule, from RFC 8448, section 3
+        let zeros = [0; HASH_LEN];
+        let early_secret = hkdf_extract(&zeros, &zeros);
+        assert_eq!(
+            early_secret.to_vec(),
+            from_hex("33ad0a1c607ec03b09e6cd9893680ce210adf300aa1f2660e1b22e10f170f92a")
+        );
+        assert_eq!(
+            derive_secret(&early_secret, "derived", &sha256(b"")).to_vec(),
+            from_hex("6f2615a108c702c5678f54fc9dbab69716c076189c48250cebeac3576c3611ba")
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Encoding and decoding of the handshake messages (RFC 8446, section 4).
+
+use super::{Alert, CipherSuite, TlsError};
+
+pub(crate) const CLIENT_HELLO: u8 = 1;
+pub(crate) const SERVER_HELLO: u8 = 2;
+pub(crate) const NEW_SESSION_TICKET: u8 = 4;
+pub(crate) const ENCRYPTED_EXTENSIONS: u8 = 8;
+pub(crate) const CERTIFICATE: u8 = 11;
+pub(crate) const CERTIFICATE_REQUEST: u8 = 13;
+pub(crate) const CERTIFICATE_VERIFY: u8 = 15;
+pub(crate) const FINISHED: u8 = 20;
+pub(crate) const KEY_UPDATE: u8 = 24;
+
+pub(crate) const SERVER_NAME: u16 = 0;
+pub(crate) const SUPPORTED_GROUPS: u16 = 10;
+pub(crate) const SIGNATURE_ALGORITHMS: u16 = 13;
+pub(crate) const APPLICATION_LAYER_PROTOCOL: u16 = 16;
+pub(crate) const SUPPORTED_VERSIONS: u16 = 43;
+pub(crate) const KEY_SHARE: u16 = 51;
+
+pub(crate) const TLS_1_2: u16 = 0x0303;
+pub(crate) const TLS_1_3: u16 = 0x0304;
+/// The only key exchange group offered.
+pub(crate) const X25519: u16 = 0x001d;
+
+/// Signature schemes the server may use for its certificates and its
+/// CertificateVerify, from the most preferred.
+const SIGNATURE_SCHEMES: &[u16] = &[
+    0x0403, // ecdsa_secp256r1_sha256
+    0x0503, // ecdsa_secp384r1_sha384
+    0x0804, // rsa_pss_rsae_sha256
+    0x0805, // rsa_pss_rsae_sha384
+    0x0806, // rsa_pss_rsae_sha512
+    0x0401, // rsa_pkcs1_sha256
+    0x0501, // rsa_pkcs1_sha384
+    0x0601, // rsa_pkcs1_sha512
+];
+
+/// The random of a HelloRetryRequest, the ServerHello by which a server asks
+/// for a new ClientHello: SHA-256 of "HelloRetryRequest".
+const HELLO_RETRY_REQUEST_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+fn decode_error() -> TlsError {
+    TlsError::Protocol(Alert::DECODE_ERROR, "malformed handshake message")
+}
+
+/// Reads the fields of a message in order. Reading past its end is a decode
+/// error.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Fails if anything is left unread.
+    pub(crate) fn finish(&self) -> Result<(), TlsError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(decode_error())
+        }
+    }
+
+    pub(crate) fn bytes(&mut self, length: usize) -> Result<&'a [u8], TlsError> {
+        if length > self.data.len() {
+            return Err(decode_error());
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn number(&mut self, size: usize) -> Result<usize, TlsError> {
+        Ok(self.bytes(size)?.iter().fold(0, |number, &byte| (number << 8) | byte as usize))
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, TlsError> {
+        Ok(self.number(1)? as u8)
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, TlsError> {
+        Ok(self.number(2)? as u16)
+    }
+
+    /// A vector prefixed with its length on one byte.
+    pub(crate) fn vector8(&mut self) -> Result<&'a [u8], TlsError> {
+        let length = self.number(1)?;
+        self.bytes(length)
+    }
+
+    pub(crate) fn vector16(&mut self) -> Result<&'a [u8], TlsError> {
+        let length = self.number(2)?;
+        self.bytes(length)
+    }
+
+    pub(crate) fn vector24(&mut self) -> Result<&'a [u8], TlsError> {
+        let length = self.number(3)?;
+        self.bytes(length)
+    }
+
+    /// Reads a list of extensions, as `(type, data)` pairs.
+    pub(crate) fn extensions(&mut self) -> Result<Vec<(u16, &'a [u8])>, TlsError> {
+        let mut list = Reader::new(self.vector16()?);
+        let mut extensions = Vec::new();
+        while !list.is_empty() {
+            extensions.push((list.u16()?, list.vector16()?));
+        }
+        Ok(extensions)
+    }
+}
+
+/// Builds a message field by field.
+#[derive(Default)]
+pub(crate) struct Writer {
+    pub(crate) data: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Writes what `content` writes, prefixed with its length on `size`
+    /// bytes.
+    fn vector(&mut self, size: usize, content: impl FnOnce(&mut Writer)) {
+        let start = self.data.len();
+        self.data.resize(start + size, 0);
+        content(self);
+        let length = (self.data.len() - start - size) as u32;
+        self.data[start..start + size].copy_from_slice(&length.to_be_bytes()[4 - size..]);
+    }
+
+    pub(crate) fn vector8(&mut self, content: impl FnOnce(&mut Writer)) {
+        self.vector(1, content);
+    }
+
+    pub(crate) fn vector16(&mut self, content: impl FnOnce(&mut Writer)) {
+        self.vector(2, content);
+    }
+
+    pub(crate) fn vector24(&mut self, content: impl FnOnce(&mut Writer)) {
+        self.vector(3, content);
+    }
+
+    pub(crate) fn extension(&mut self, extension_type: u16, content: impl FnOnce(&mut Writer)) {
+        self.u16(extension_type);
+        self.vector16(content);
+    }
+}
+
+/// A whole handshake message: its type, its length, then what `body` writes.
+pub(crate) fn handshake_message(message_type: u8, body: impl FnOnce(&mut Writer)) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.u8(message_type);
+    writer.vector24(body);
+    writer.data
+}
+
+/// The ClientHello, offering TLS 1.3 only, both cipher suites, and an X25519
+/// key share. `server_name` is sent for virtual hosting; it must be a DNS
+/// name, not an IP address.
+pub(crate) fn client_hello(random: &[u8; 32], server_name: Option<&str>, public_key: &[u8; 32]) -> Vec<u8> {
+    handshake_message(CLIENT_HELLO, |writer| {
+        writer.u16(TLS_1_2);
+        writer.bytes(random);
+        // No legacy session id
+        writer.vector8(|_| {});
+        writer.vector16(|writer| {
+            for suite in CipherSuite::ALL {
+                writer.u16(suite.id());
+            }
+        });
+        // Only the null compression method
+        writer.vector8(|writer| writer.u8(0));
+        writer.vector16(|writer| {
+            if let Some(name) = server_name {
+                writer.extension(SERVER_NAME, |writer| {
+                    writer.vector16(|writer| {
+                        writer.u8(0); // host_name
+                        writer.vector16(|writer| writer.bytes(name.as_bytes()));
+                    })
+                });
+            }
+            writer.extension(SUPPORTED_VERSIONS, |writer| writer.vector8(|writer| writer.u16(TLS_1_3)));
+            writer.extension(SUPPORTED_GROUPS, |writer| writer.vector16(|writer| writer.u16(X25519)));
+            writer.extension(SIGNATURE_ALGORITHMS, |writer| {
+                writer.vector16(|writer| SIGNATURE_SCHEMES.iter().for_each(|&scheme| writer.u16(scheme)))
+            });
+            writer.extension(KEY_SHARE, |writer| {
+                writer.vector16(|writer| {
+                    writer.u16(X25519);
+                    writer.vector16(|writer| writer.bytes(public_key));
+                })
+            });
+            writer.extension(APPLICATION_LAYER_PROTOCOL, |writer| {
+                writer.vector16(|writer| writer.vector8(|writer| writer.bytes(b"http/1.1")))
+            });
+        });
+    })
+}
+
+/// The fields of a ServerHello this client looks at.
+pub(crate) struct ServerHello {
+    pub(crate) session_id: Vec<u8>,
+    pub(crate) cipher_suite: u16,
+    /// The version chosen in the supported_versions extension. Servers that
+    /// only speak older versions do not send it.
+    pub(crate) version: Option<u16>,
+    /// The group and public key of the server's key share.
+    pub(crate) key_share: Option<(u16, Vec<u8>)>,
+}
+
+impl ServerHello {
+    /// Whether the body of a ServerHello is actually a HelloRetryRequest.
+    pub(crate) fn is_hello_retry_request(body: &[u8]) -> bool {
+        body.get(2..34) == Some(&HELLO_RETRY_REQUEST_RANDOM[..])
+    }
+
+    pub(crate) fn parse(body: &[u8]) -> Result<Self, TlsError> {
+        let mut reader = Reader::new(body);
+        reader.u16()?; // legacy_version
+        reader.bytes(32)?; // random
+        let session_id = reader.vector8()?.to_vec();
+        let cipher_suite = reader.u16()?;
+        if reader.u8()? != 0 {
+            return Err(TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "server chose a compression method"));
+        }
+        let mut hello = ServerHello {
+            session_id,
+            cipher_suite,
+            version: None,
+            key_share: None,
+        };
+        for (extension_type, data) in reader.extensions()? {
+            let mut data = Reader::new(data);
+            match extension_type {
+                SUPPORTED_VERSIONS => hello.version = Some(data.u16()?),
+                KEY_SHARE => hello.key_share = Some((data.u16()?, data.vector16()?.to_vec())),
+                _ => continue,
+            }
+            data.finish()?;
+        }
+        reader.finish()?;
+        Ok(hello)
+    }
+}
+
+/// Checks that EncryptedExtensions is well-formed. None of the extensions
+/// it may carry changes anything for this client.
+pub(crate) fn parse_encrypted_extensions(body: &[u8]) -> Result<(), TlsError> {
+    let mut reader = Reader::new(body);
+    reader.extensions()?;
+    reader.finish()
+}
+
+/// Reads a CertificateRequest, returning its context, which the client's
+/// Certificate must echo.
+pub(crate) fn parse_certificate_request(body: &[u8]) -> Result<Vec<u8>, TlsError> {
+    let mut reader = Reader::new(body);
+    let context = reader.vector8()?.to_vec();
+    reader.extensions()?;
+    reader.finish()?;
+    Ok(context)
+}
+
+/// Reads the server's Certificate message: the DER-encoded certificates of
+/// its chain, its own first.
+pub(crate) fn parse_certificate(body: &[u8]) -> Result<Vec<Vec<u8>>, TlsError> {
+    let mut reader = Reader::new(body);
+    if !reader.vector8()?.is_empty() {
+        return Err(TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "unrequested certificate context"));
+    }
+    let mut list = Reader::new(reader.vector24()?);
+    reader.finish()?;
+    let mut certificates = Vec::new();
+    while !list.is_empty() {
+        certificates.push(list.vector24()?.to_vec());
+        list.vector16()?; // extensions of the entry
+    }
+    if certificates.is_empty() {
+        return Err(TlsError::Protocol(Alert::DECODE_ERROR, "server sent no certificate"));
+    }
+    Ok(certificates)
+}
+
+/// Reads a CertificateVerify: the signature scheme, and the signature.
+pub(crate) fn parse_certificate_verify(body: &[u8]) -> Result<(u16, Vec<u8>), TlsError> {
+    let mut reader = Reader::new(body);
+    let scheme = reader.u16()?;
+    let signature = reader.vector16()?.to_vec();
+    reader.finish()?;
+    Ok((scheme, signature))
+}
+
+/// A Certificate message without certificates, the answer of a client that
+/// has none to a CertificateRequest.
+pub(crate) fn empty_certificate(context: &[u8]) -> Vec<u8> {
+    handshake_message(CERTIFICATE, |writer| {
+        writer.vector8(|writer| writer.bytes(context));
+        writer.vector24(|_| {});
+    })
+}
+
+pub(crate) fn finished(verify_data: &[u8]) -> Vec<u8> {
+    handshake_message(FINISHED, |writer| writer.bytes(verify_data))
+}
+
+/// A KeyUpdate, asking the peer to update its own keys as well if
+/// `update_requested`.
+pub(crate) fn key_update(update_requested: bool) -> Vec<u8> {
+    handshake_message(KEY_UPDATE, |writer| writer.u8(update_requested as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_hello() {
+        let hello = client_hello(&[1; 32], Some("example.com"), &[2; 32]);
+        assert_eq!(hello[0], CLIENT_HELLO);
+        let mut reader = Reader::new(&hello[1..]);
+        let mut body = Reader::new(reader.vector24().unwrap());
+        reader.finish().unwrap();
+
+        assert_eq!(body.u16().unwrap(), TLS_1_2);
+        assert_eq!(body.bytes(32).unwrap(), &[1; 32]);
+        assert_eq!(body.vector8().unwrap(), b"");
+        assert_eq!(body.vector16().unwrap(), &[0x13, 0x01, 0x13, 0x03]);
+        assert_eq!(body.vector8().unwrap(), &[0]);
+        let extensions = body.extensions().unwrap();
+        body.finish().unwrap();
+
+        let types: Vec<u16> = extensions.iter().map(|(extension_type, _)| *extension_type).collect();
+        assert_eq!(
+            types,
+            [SERVER_NAME, SUPPORTED_VERSIONS, SUPPORTED_GROUPS, SIGNATURE_ALGORITHMS, KEY_SHARE, APPLICATION_LAYER_PROTOCOL]
+        );
+        assert_eq!(extensions[0].1, b"\x00\x0e\x00\x00\x0bexample.com");
+        assert_eq!(extensions[1].1, &[2, 3, 4]);
+        assert_eq!(&extensions[4].1[..6], &[0, 36, 0, 0x1d, 0, 32]);
+        assert_eq!(&extensions[4].1[6..], &[2; 32]);
+
+        // No server name for an IP address
+        let hello = client_hello(&[1; 32], None, &[2; 32]);
+        assert!(!hello.windows(11).any(|window| window == b"example.com"));
+    }
+
+    #[test]
+    fn test_truncated_message() {
+        let body = parse_certificate_verify(&[0x08, 0x04, 0x00, 0x05, 1, 2]);
+        assert!(matches!(body, Err(TlsError::Protocol(Alert::DECODE_ERROR, _))));
+        assert_eq!(parse_certificate_verify(&[0x08, 0x04, 0x00, 0x02, 1, 2]).unwrap(), (0x0804, vec![1, 2]));
+    }
+}
@@ -0,0 +1,913 @@
+//! A TLS 1.3 client (RFC 8446), so that `https` URLs can be fetched.
+//!
+//! [`TlsStream`] wraps a byte stream, usually a `TcpStream`: creating it runs
+//! the handshake, then what is written to it is encrypted and what is read
+//! from it decrypted, through `Read` and `Write` over a stream that blocks,
+//! or `AsyncRead` and `AsyncWrite` over one that does not. The handshake
+//! uses the X25519 key exchange and offers the TLS_AES_128_GCM_SHA256 and
+//! TLS_CHACHA20_POLY1305_SHA256 cipher suites, which every TLS 1.3 server
+//! supports.
+//!
+//! The server's certificate chain must lead to a root of a [`TrustStore`]
+//! and name the host, and the server must prove it holds the certificate's
+//! key by signing the handshake.
+
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::IpAddr;
+use std::pin::{pin, Pin};
+use std::task::{ready, Context, Poll, Waker};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::io::{AllowStdIo, AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::crypto::chacha20::ChaCha20Poly1305;
+use crate::crypto::ecdsa::Curve;
+use crate::crypto::gcm::Aes128Gcm;
+use crate::crypto::sha256::Sha256;
+use crate::crypto::x25519::{x25519, x25519_base};
+use crate::crypto::{constant_time_eq, random_bytes, Aead, HashAlgorithm};
+use crate::x509::{verify_chain, Certificate, CertificateError, PublicKey, SignatureAlgorithm, TrustStore};
+use key_schedule::{finished_verify_data, KeySchedule};
+use messages::ServerHello;
+use record::{seal_records, RecordKeys, RecordReader};
+
+#[cfg(test)]
+pub(crate) mod fixture;
+mod key_schedule;
+mod messages;
+mod record;
+
+/// Largest handshake message accepted, to bound the memory a server can
+/// make the client use. Certificate chains stay well below.
+const MAX_HANDSHAKE_MESSAGE_LEN: usize = 1 << 18;
+
+/// The cipher suites this client offers, which protect records after the
+/// key exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherSuite {
+    Aes128GcmSha256,
+    ChaCha20Poly1305Sha256,
+}
+
+impl CipherSuite {
+    /// All the suites, in order of preference.
+    pub(crate) const ALL: [CipherSuite; 2] = [CipherSuite::Aes128GcmSha256, CipherSuite::ChaCha20Poly1305Sha256];
+
+    pub(crate) fn id(self) -> u16 {
+        match self {
+            CipherSuite::Aes128GcmSha256 => 0x1301,
+            CipherSuite::ChaCha20Poly1305Sha256 => 0x1303,
+        }
+    }
+
+    pub(crate) fn from_id(id: u16) -> Option<Self> {
+        CipherSuite::ALL.into_iter().find(|suite| suite.id() == id)
+    }
+
+    pub(crate) fn key_len(self) -> usize {
+        match self {
+            CipherSuite::Aes128GcmSha256 => 16,
+            CipherSuite::ChaCha20Poly1305Sha256 => 32,
+        }
+    }
+
+    pub(crate) fn aead(self, key: &[u8]) -> Aead {
+        match self {
+            CipherSuite::Aes128GcmSha256 => Aead::Aes128Gcm(Aes128Gcm::new(key.try_into().unwrap())),
+            CipherSuite::ChaCha20Poly1305Sha256 => {
+                Aead::ChaCha20Poly1305(ChaCha20Poly1305::new(key.try_into().unwrap()))
+            }
+        }
+    }
+}
+
+/// The description of a TLS alert (RFC 8446, section 6), the message by
+/// which a side tells the other why it closes the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Alert(pub u8);
+
+impl Alert {
+    pub const CLOSE_NOTIFY: Alert = Alert(0);
+    pub const UNEXPECTED_MESSAGE: Alert = Alert(10);
+    pub const BAD_RECORD_MAC: Alert = Alert(20);
+    pub const RECORD_OVERFLOW: Alert = Alert(22);
+    pub const HANDSHAKE_FAILURE: Alert = Alert(40);
+    pub const BAD_CERTIFICATE: Alert = Alert(42);
+    pub const UNSUPPORTED_CERTIFICATE: Alert = Alert(43);
+    pub const CERTIFICATE_EXPIRED: Alert = Alert(45);
+    pub const CERTIFICATE_UNKNOWN: Alert = Alert(46);
+    pub const ILLEGAL_PARAMETER: Alert = Alert(47);
+    pub const UNKNOWN_CA: Alert = Alert(48);
+    pub const DECODE_ERROR: Alert = Alert(50);
+    pub const DECRYPT_ERROR: Alert = Alert(51);
+    pub const PROTOCOL_VERSION: Alert = Alert(70);
+    pub const INTERNAL_ERROR: Alert = Alert(80);
+    pub const USER_CANCELED: Alert = Alert(90);
+
+    /// The name the specification gives the alert.
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self.0 {
+            0 => "close_notify",
+            10 => "unexpected_message",
+            20 => "bad_record_mac",
+            22 => "record_overflow",
+            40 => "handshake_failure",
+            42 => "bad_certificate",
+            43 => "unsupported_certificate",
+            44 => "certificate_revoked",
+            45 => "certificate_expired",
+            46 => "certificate_unknown",
+            47 => "illegal_parameter",
+            48 => "unknown_ca",
+            49 => "access_denied",
+            50 => "decode_error",
+            51 => "decrypt_error",
+            70 => "protocol_version",
+            71 => "insufficient_security",
+            80 => "internal_error",
+            86 => "inappropriate_fallback",
+            90 => "user_canceled",
+            109 => "missing_extension",
+            110 => "unsupported_extension",
+            112 => "unrecognized_name",
+            113 => "bad_certificate_status_response",
+            115 => "unknown_psk_identity",
+            116 => "certificate_required",
+            120 => "no_application_protocol",
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "alert {}", self.0),
+        }
+    }
+}
+
+/// Why a TLS connection failed.
+#[derive(Debug)]
+pub enum TlsError {
+    /// Reading from or writing to the underlying stream failed.
+    Io(io::Error),
+    /// The server closed the connection with this alert.
+    AlertReceived(Alert),
+    /// The server broke the protocol, and the client closed the connection
+    /// with this alert: a malformed message, a parameter that was not
+    /// offered, a record that does not decrypt...
+    Protocol(Alert, &'static str),
+    /// The server's certificate chain was refused.
+    Certificate(CertificateError),
+}
+
+impl TlsError {
+    /// The alert that tells the server why the client gives up, unless the
+    /// failure came from the server or the stream.
+    fn alert(&self) -> Option<Alert> {
+        match self {
+            TlsError::Io(_) | TlsError::AlertReceived(_) => None,
+            TlsError::Protocol(alert, _) => Some(*alert),
+            TlsError::Certificate(error) => Some(match error {
+                CertificateError::Malformed(_) | CertificateError::BadSignature => Alert::BAD_CERTIFICATE,
+                CertificateError::UnsupportedAlgorithm
+                | CertificateError::UnsupportedCriticalExtension
+                | CertificateError::NotForServerAuth => Alert::UNSUPPORTED_CERTIFICATE,
+                CertificateError::Expired | CertificateError::NotYetValid => Alert::CERTIFICATE_EXPIRED,
+                CertificateError::UnknownIssuer | CertificateError::NotACertificateAuthority => Alert::UNKNOWN_CA,
+                CertificateError::HostnameMismatch(_) => Alert::CERTIFICATE_UNKNOWN,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Io(error) => write!(f, "{}", error),
+            TlsError::AlertReceived(alert) => write!(f, "the server sent a fatal alert: {}", alert),
+            TlsError::Protocol(alert, reason) => write!(f, "{} ({})", reason, alert),
+            TlsError::Certificate(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<io::Error> for TlsError {
+    fn from(error: io::Error) -> Self {
+        TlsError::Io(error)
+    }
+}
+
+impl From<CertificateError> for TlsError {
+    fn from(error: CertificateError) -> Self {
+        TlsError::Certificate(error)
+    }
+}
+
+impl From<TlsError> for io::Error {
+    fn from(error: TlsError) -> Self {
+        match error {
+            TlsError::Io(error) => error,
+            error => io::Error::new(ErrorKind::InvalidData, error),
+        }
+    }
+}
+
+fn unexpected_message(reason: &'static str) -> TlsError {
+    TlsError::Protocol(Alert::UNEXPECTED_MESSAGE, reason)
+}
+
+/// The error for an alert record sent by the server.
+fn alert_error(payload: &[u8]) -> TlsError {
+    match payload {
+        [_, description] => TlsError::AlertReceived(Alert(*description)),
+        _ => TlsError::Protocol(Alert::DECODE_ERROR, "malformed alert"),
+    }
+}
+
+/// Handshake messages received, which may be split across records or share
+/// one.
+#[derive(Default)]
+struct HandshakeBuffer {
+    data: Vec<u8>,
+}
+
+impl HandshakeBuffer {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Takes the next whole message, header included, if it has arrived.
+    fn take_message(&mut self) -> Result<Option<Vec<u8>>, TlsError> {
+        if self.data.len() < 4 {
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes([0, self.data[1], self.data[2], self.data[3]]) as usize;
+        if length > MAX_HANDSHAKE_MESSAGE_LEN {
+            return Err(TlsError::Protocol(Alert::DECODE_ERROR, "handshake message too long"));
+        }
+        if self.data.len() < 4 + length {
+            return Ok(None);
+        }
+        Ok(Some(self.data.drain(..4 + length).collect()))
+    }
+}
+
+/// Checks the signature of a CertificateVerify, made with the key of the
+/// server's certificate over the transcript up to that certificate.
+fn check_certificate_verify(
+    key: &PublicKey,
+    scheme: u16,
+    signature: &[u8],
+    transcript_hash: &[u8],
+) -> Result<(), TlsError> {
+    // In TLS 1.3, ECDSA schemes name their curve too
+    let (algorithm, curve) = match scheme {
+        0x0403 => (SignatureAlgorithm::Ecdsa(HashAlgorithm::Sha256), Some(Curve::P256)),
+        0x0503 => (SignatureAlgorithm::Ecdsa(HashAlgorithm::Sha384), Some(Curve::P384)),
+        0x0804 => (SignatureAlgorithm::RsaPss(HashAlgorithm::Sha256), None),
+        0x0805 => (SignatureAlgorithm::RsaPss(HashAlgorithm::Sha384), None),
+        0x0806 => (SignatureAlgorithm::RsaPss(HashAlgorithm::Sha512), None),
+        _ => return Err(TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "unexpected signature scheme")),
+    };
+    if key.curve() != curve {
+        return Err(TlsError::Protocol(
+            Alert::ILLEGAL_PARAMETER,
+            "signature scheme does not match the certificate",
+        ));
+    }
+    let mut content = vec![0x20; 64];
+    content.extend_from_slice(b"TLS 1.3, server CertificateVerify\0");
+    content.extend_from_slice(transcript_hash);
+    key.verify(algorithm, &content, signature)
+        .map_err(|_| TlsError::Protocol(Alert::DECRYPT_ERROR, "CertificateVerify signature is invalid"))
+}
+
+/// The state of the client while the handshake runs.
+struct Handshake<'a, S> {
+    stream: &'a mut S,
+    /// The roots the server's certificate must lead to.
+    trust_store: &'a TrustStore,
+    records: RecordReader,
+    received: HandshakeBuffer,
+    /// Hash of all the handshake messages so far.
+    transcript: Sha256,
+    read_keys: Option<RecordKeys>,
+    write_keys: Option<RecordKeys>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Handshake<'_, S> {
+    /// Reads the next handshake message, which must be of type `expected`,
+    /// and adds it to the transcript. Returns its body.
+    async fn expect_message(&mut self, expected: u8) -> Result<Vec<u8>, TlsError> {
+        let message = self.next_message().await?;
+        if message[0] != expected {
+            return Err(unexpected_message("unexpected handshake message"));
+        }
+        self.transcript.update(&message);
+        Ok(message[4..].to_vec())
+    }
+
+    async fn next_message(&mut self) -> Result<Vec<u8>, TlsError> {
+        loop {
+            if let Some(message) = self.received.take_message()? {
+                return Ok(message);
+            }
+            let record = poll_fn(|cx| self.records.poll_read_record(cx, self.stream, self.read_keys.as_mut()))
+                .await?
+                .ok_or(TlsError::Io(ErrorKind::UnexpectedEof.into()))?;
+            match record.content_type {
+                record::HANDSHAKE if !record.payload.is_empty() => self.received.data.extend(record.payload),
+                // Sent by servers that pass for TLS 1.2 to middleboxes
+                record::CHANGE_CIPHER_SPEC if record.payload == [1] => {}
+                record::ALERT => return Err(alert_error(&record.payload)),
+                _ => return Err(unexpected_message("unexpected record during the handshake")),
+            }
+        }
+    }
+
+    /// Keys change after this message: the next one must start a record.
+    fn expect_record_boundary(&self) -> Result<(), TlsError> {
+        if self.received.is_empty() {
+            Ok(())
+        } else {
+            Err(unexpected_message("handshake message across a key change"))
+        }
+    }
+
+    async fn send(&mut self, message: &[u8]) -> Result<(), TlsError> {
+        self.transcript.update(message);
+        write_all(self.stream, &seal_records(record::HANDSHAKE, message, self.write_keys.as_mut())).await
+    }
+
+    fn transcript_hash(&self) -> [u8; 32] {
+        self.transcript.clone().finish()
+    }
+
+    /// Runs the handshake with `host`, a domain name or an IP address, and
+    /// the keys `private_key` and `random`. Returns the connection once
+    /// application data can flow.
+    async fn run(&mut self, host: &str, private_key: &[u8; 32], random: &[u8; 32]) -> Result<Connection, TlsError> {
+        // Server names are domain names only
+        let server_name = match host.parse::<IpAddr>() {
+            Ok(_) => None,
+            Err(_) => Some(host),
+        };
+        self.send(&messages::client_hello(random, server_name, &x25519_base(private_key))).await?;
+
+        let server_hello = self.expect_message(messages::SERVER_HELLO).await?;
+        if ServerHello::is_hello_retry_request(&server_hello) {
+            // The only reason to retry would be a group other than X25519
+            return Err(TlsError::Protocol(Alert::HANDSHAKE_FAILURE, "server does not support X25519"));
+        }
+        let server_hello = ServerHello::parse(&server_hello)?;
+        if server_hello.version != Some(messages::TLS_1_3) {
+            return Err(TlsError::Protocol(Alert::PROTOCOL_VERSION, "server does not support TLS 1.3"));
+        }
+        if !server_hello.session_id.is_empty() {
+            return Err(TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "server echoed another session id"));
+        }
+        let suite = CipherSuite::from_id(server_hello.cipher_suite)
+            .ok_or(TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "server chose a cipher suite that was not offered"))?;
+        let server_key: [u8; 32] = match server_hello.key_share {
+            Some((messages::X25519, key)) => key
+                .try_into()
+                .map_err(|_| TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "invalid X25519 key share"))?,
+            _ => return Err(TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "server sent no X25519 key share")),
+        };
+        let shared_secret = x25519(private_key, &server_key);
+        if shared_secret == [0; 32] {
+            return Err(TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "invalid X25519 key share"));
+        }
+
+        self.expect_record_boundary()?;
+        let schedule = KeySchedule::new(&shared_secret);
+        let secrets = schedule.handshake_traffic_secrets(&self.transcript_hash());
+        self.read_keys = Some(RecordKeys::new(suite, secrets.server));
+
+        messages::parse_encrypted_extensions(&self.expect_message(messages::ENCRYPTED_EXTENSIONS).await?)?;
+
+        let mut message = self.next_message().await?;
+        let mut certificate_request = None;
+        if message[0] == messages::CERTIFICATE_REQUEST {
+            self.transcript.update(&message);
+            certificate_request = Some(messages::parse_certificate_request(&message[4..])?);
+            message = self.next_message().await?;
+        }
+        if message[0] != messages::CERTIFICATE {
+            return Err(unexpected_message("server sent no certificate"));
+        }
+        self.transcript.update(&message);
+        let certificates = messages::parse_certificate(&message[4..])?;
+        // Only the server's own certificate must be readable: the others may
+        // not be needed to reach a root
+        let mut chain = vec![Certificate::parse(&certificates[0])?];
+        chain.extend(certificates[1..].iter().filter_map(|der| Certificate::parse(der).ok()));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64);
+        verify_chain(&chain, host, self.trust_store, now)?;
+
+        // The signature proves the server holds the key of its certificate
+        let transcript_hash = self.transcript_hash();
+        let certificate_verify = self.expect_message(messages::CERTIFICATE_VERIFY).await?;
+        let (scheme, signature) = messages::parse_certificate_verify(&certificate_verify)?;
+        check_certificate_verify(chain[0].public_key(), scheme, &signature, &transcript_hash)?;
+
+        let expected = finished_verify_data(&secrets.server, &self.transcript_hash());
+        let finished = self.expect_message(messages::FINISHED).await?;
+        if !constant_time_eq(&finished, &expected) {
+            return Err(TlsError::Protocol(Alert::DECRYPT_ERROR, "server Finished does not match the handshake"));
+        }
+        self.expect_record_boundary()?;
+        let application_secrets = schedule.application_traffic_secrets(&self.transcript_hash());
+
+        self.write_keys = Some(RecordKeys::new(suite, secrets.client));
+        if let Some(context) = certificate_request {
+            // No client certificate: the server decides whether to go on
+            self.send(&messages::empty_certificate(&context)).await?;
+        }
+        let verify_data = finished_verify_data(&secrets.client, &self.transcript_hash());
+        self.send(&messages::finished(&verify_data)).await?;
+
+        Ok(Connection {
+            suite,
+            read_keys: RecordKeys::new(suite, application_secrets.server),
+            write_keys: RecordKeys::new(suite, application_secrets.client),
+            peer_certificates: certificates,
+        })
+    }
+}
+
+/// Writes `data` to `stream`, and flushes it.
+async fn write_all<S: AsyncWrite + Unpin>(stream: &mut S, data: &[u8]) -> Result<(), TlsError> {
+    stream.write_all(data).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Runs the handshake with the server at the other end of `stream`, as
+/// [`TlsStream::connect`] describes, and tells the server why if it fails.
+async fn handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    server_name: &str,
+    trust_store: &TrustStore,
+) -> Result<Session, TlsError> {
+    let mut private_key = [0u8; 32];
+    let mut random = [0u8; 32];
+    random_bytes(&mut private_key)?;
+    random_bytes(&mut random)?;
+    let host = server_name.trim_start_matches('[').trim_end_matches(']');
+
+    let mut handshake = Handshake {
+        stream,
+        trust_store,
+        records: RecordReader::default(),
+        received: HandshakeBuffer::default(),
+        transcript: Sha256::new(),
+        read_keys: None,
+        write_keys: None,
+    };
+    match handshake.run(host, &private_key, &random).await {
+        Ok(connection) => Ok(Session {
+            connection,
+            records: handshake.records,
+            received: HandshakeBuffer::default(),
+            plaintext: Vec::new(),
+            plaintext_position: 0,
+            pending: Vec::new(),
+            pending_position: 0,
+            closed: false,
+            close_queued: false,
+        }),
+        Err(error) => {
+            if let Some(alert) = error.alert() {
+                // Best effort: the connection is failing anyway
+                let record = seal_records(record::ALERT, &[2, alert.0], handshake.write_keys.as_mut());
+                let _ = write_all(handshake.stream, &record).await;
+            }
+            Err(error)
+        }
+    }
+}
+
+/// Polls once an operation on a stream that blocks, which is therefore
+/// never left waiting.
+pub(crate) fn complete<T>(poll: impl FnOnce(&mut Context<'_>) -> Poll<T>) -> T {
+    match poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("a stream that blocks is always ready"),
+    }
+}
+
+/// What the handshake establishes.
+struct Connection {
+    suite: CipherSuite,
+    read_keys: RecordKeys,
+    write_keys: RecordKeys,
+    peer_certificates: Vec<Vec<u8>>,
+}
+
+/// The state of an established connection, apart from its stream.
+struct Session {
+    connection: Connection,
+    records: RecordReader,
+    /// Post-handshake messages: session tickets and key updates.
+    received: HandshakeBuffer,
+    /// Decrypted application data not read yet.
+    plaintext: Vec<u8>,
+    plaintext_position: usize,
+    /// Records sealed but not written yet.
+    pending: Vec<u8>,
+    pending_position: usize,
+    /// Set once the server has closed its side of the connection.
+    closed: bool,
+    /// Set once close_notify is sealed.
+    close_queued: bool,
+}
+
+impl Session {
+    /// Seals `payload` in records, written before anything sent after.
+    fn queue(&mut self, content_type: u8, payload: &[u8]) {
+        let records = seal_records(content_type, payload, Some(&mut self.connection.write_keys));
+        self.pending.extend(records);
+    }
+
+    /// Tells the server that nothing more will be written.
+    fn queue_close(&mut self) {
+        if !self.close_queued {
+            self.queue(record::ALERT, &[1, Alert::CLOSE_NOTIFY.0]);
+            self.close_queued = true;
+        }
+    }
+
+    /// Writes the records sealed so far.
+    fn poll_write_pending<S: AsyncWrite + Unpin>(&mut self, cx: &mut Context<'_>, stream: &mut S) -> Poll<io::Result<()>> {
+        while self.pending_position < self.pending.len() {
+            let written = ready!(Pin::new(&mut *stream).poll_write(cx, &self.pending[self.pending_position..]))?;
+            if written == 0 {
+                return Poll::Ready(Err(ErrorKind::WriteZero.into()));
+            }
+            self.pending_position += written;
+        }
+        self.pending.clear();
+        self.pending_position = 0;
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_read<S: AsyncRead + AsyncWrite + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+        buffer: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        ready!(self.poll_fill_plaintext(cx, stream))?;
+        // Answers to key updates go out as soon as they can
+        if let Poll::Ready(Err(e)) = self.poll_write_pending(cx, stream) {
+            return Poll::Ready(Err(e));
+        }
+        let available = &self.plaintext[self.plaintext_position..];
+        let read = available.len().min(buffer.len());
+        buffer[..read].copy_from_slice(&available[..read]);
+        self.plaintext_position += read;
+        Poll::Ready(Ok(read))
+    }
+
+    /// Reads records until some application data arrives or the server
+    /// closes the connection.
+    fn poll_fill_plaintext<S: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+    ) -> Poll<Result<(), TlsError>> {
+        while self.plaintext_position == self.plaintext.len() && !self.closed {
+            let record = ready!(self.records.poll_read_record(cx, stream, Some(&mut self.connection.read_keys)))?;
+            let Some(record) = record else {
+                // Closed without close_notify: what was received is kept
+                self.closed = true;
+                break;
+            };
+            match record.content_type {
+                record::APPLICATION_DATA => {
+                    self.plaintext = record.payload;
+                    self.plaintext_position = 0;
+                }
+                record::HANDSHAKE => {
+                    self.received.data.extend(record.payload);
+                    self.process_post_handshake_messages()?;
+                }
+                record::ALERT => match record.payload[..] {
+                    [_, 0] => self.closed = true,
+                    // Warnings other than close_notify can be ignored
+                    [1, _] => {}
+                    _ => return Poll::Ready(Err(alert_error(&record.payload))),
+                },
+                _ => return Poll::Ready(Err(unexpected_message("unexpected record after the handshake"))),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn process_post_handshake_messages(&mut self) -> Result<(), TlsError> {
+        while let Some(message) = self.received.take_message()? {
+            match (message[0], &message[4..]) {
+                // Sessions are not resumed, tickets are of no use
+                (messages::NEW_SESSION_TICKET, _) => {}
+                (messages::KEY_UPDATE, [update_requested @ (0 | 1)]) => {
+                    if !self.received.is_empty() {
+                        return Err(unexpected_message("handshake message across a key change"));
+                    }
+                    self.connection.read_keys.update();
+                    if *update_requested == 1 {
+                        self.queue(record::HANDSHAKE, &messages::key_update(false));
+                        self.connection.write_keys.update();
+                    }
+                }
+                (messages::KEY_UPDATE, _) => {
+                    return Err(TlsError::Protocol(Alert::ILLEGAL_PARAMETER, "invalid KeyUpdate"));
+                }
+                _ => return Err(unexpected_message("unexpected handshake message after the handshake")),
+            }
+        }
+        Ok(())
+    }
+
+    fn poll_write<S: AsyncWrite + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        // Records sealed earlier go first, and bound what is kept in memory
+        ready!(self.poll_write_pending(cx, stream))?;
+        self.queue(record::APPLICATION_DATA, data);
+        // What cannot be written now goes with the next write or flush
+        if let Poll::Ready(Err(e)) = self.poll_write_pending(cx, stream) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(data.len()))
+    }
+
+    fn poll_flush<S: AsyncWrite + Unpin>(&mut self, cx: &mut Context<'_>, stream: &mut S) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_pending(cx, stream))?;
+        Pin::new(stream).poll_flush(cx)
+    }
+}
+
+/// A TLS 1.3 connection over the stream `S`.
+///
+/// # Example
+/// ```rust,no_run
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use flux_network::{TlsStream, TrustStore};
+///
+/// let trust_store = TrustStore::system().unwrap();
+/// let tcp = TcpStream::connect("example.com:443").unwrap();
+/// let mut tls = TlsStream::connect(tcp, "example.com", &trust_store).unwrap();
+/// tls.write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n").unwrap();
+/// let mut response = Vec::new();
+/// tls.read_to_end(&mut response).unwrap();
+/// ```
+pub struct TlsStream<S> {
+    stream: S,
+    session: Session,
+}
+
+impl<S: Read + Write> TlsStream<S> {
+    /// Runs the handshake with the server at the other end of `stream`.
+    ///
+    /// # Arguments
+    /// * `stream` - A connected stream, e.g. a `TcpStream` to port 443.
+    /// * `server_name` - The host name the connection is for, which the
+    ///   server uses to pick the site, and its certificate must name. IP
+    ///   addresses are not sent, but checked all the same.
+    /// * `trust_store` - The roots the server's certificate must lead to.
+    ///
+    /// # Returns
+    /// The connection, or `TlsError::Certificate` if the server's
+    /// certificate is not trusted for `server_name`.
+    pub fn connect(mut stream: S, server_name: &str, trust_store: &TrustStore) -> Result<Self, TlsError> {
+        let mut blocking = AllowStdIo::new(&mut stream);
+        let session = complete(|cx| pin!(handshake(&mut blocking, server_name, trust_store)).poll(cx))?;
+        Ok(TlsStream { stream, session })
+    }
+
+    /// Tells the server that nothing more will be written.
+    pub fn close(&mut self) -> Result<(), TlsError> {
+        self.session.queue_close();
+        self.flush()?;
+        Ok(())
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
+    /// Runs the handshake like [`TlsStream::connect`], over a stream that
+    /// does not block: the handshake waits for the server without holding
+    /// the thread.
+    pub async fn connect_async(mut stream: S, server_name: &str, trust_store: &TrustStore) -> Result<Self, TlsError> {
+        let session = handshake(&mut stream, server_name, trust_store).await?;
+        Ok(TlsStream { stream, session })
+    }
+}
+
+impl<S> TlsStream<S> {
+    /// The cipher suite chosen by the server.
+    pub fn cipher_suite(&self) -> CipherSuite {
+        self.session.connection.suite
+    }
+
+    /// The DER-encoded certificates sent by the server, its own first.
+    pub fn peer_certificates(&self) -> &[Vec<u8>] {
+        &self.session.connection.peer_certificates
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+}
+
+impl<S: Read + Write> Read for TlsStream<S> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let mut blocking = AllowStdIo::new(&mut self.stream);
+        complete(|cx| self.session.poll_read(cx, &mut blocking, buffer))
+    }
+}
+
+impl<S: Read + Write> Write for TlsStream<S> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut blocking = AllowStdIo::new(&mut self.stream);
+        complete(|cx| self.session.poll_write(cx, &mut blocking, data))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut blocking = AllowStdIo::new(&mut self.stream);
+        complete(|cx| self.session.poll_flush(cx, &mut blocking))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buffer: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.session.poll_read(cx, &mut this.stream, buffer)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, data: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.session.poll_write(cx, &mut this.stream, data)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.session.poll_flush(cx, &mut this.stream)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.session.queue_close();
+        ready!(this.session.poll_flush(cx, &mut this.stream))?;
+        Pin::new(&mut this.stream).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixture::{FixtureServer, Scenario};
+    use super::*;
+    use crate::x509::testdata::{der, INTERMEDIATE, LEAF, ROOT};
+    use std::net::TcpStream;
+
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+
+    fn trust_store() -> TrustStore {
+        let mut store = TrustStore::new();
+        store.add_pem(ROOT);
+        store
+    }
+
+    fn connect(server: &FixtureServer) -> Result<TlsStream<TcpStream>, TlsError> {
+        connect_to(server, "localhost", &trust_store())
+    }
+
+    fn connect_to(server: &FixtureServer, host: &str, store: &TrustStore) -> Result<TlsStream<TcpStream>, TlsError> {
+        let tcp = TcpStream::connect(server.address()).unwrap();
+        TlsStream::connect(tcp, host, store)
+    }
+
+    #[test]
+    fn test_handshake_and_data() {
+        for suite in CipherSuite::ALL {
+            let server = FixtureServer::start(suite, Scenario::Normal, RESPONSE);
+            let mut tls = connect(&server).unwrap();
+            assert_eq!(tls.cipher_suite(), suite);
+            assert_eq!(tls.peer_certificates(), [der(LEAF), der(INTERMEDIATE)]);
+
+            tls.write_all(REQUEST).unwrap();
+            let mut response = Vec::new();
+            tls.read_to_end(&mut response).unwrap();
+            assert_eq!(response, RESPONSE);
+
+            let received = server.finish().unwrap();
+            assert_eq!(received.server_name.as_deref(), Some("localhost"));
+            assert_eq!(received.request, REQUEST);
+        }
+    }
+
+    #[test]
+    fn test_key_update_and_large_data() {
+        // More than one record of data, and keys updated in the middle
+        let response = vec![b'z'; 40_000];
+        let server = FixtureServer::start(CipherSuite::Aes128GcmSha256, Scenario::KeyUpdate, &response);
+        let mut tls = connect(&server).unwrap();
+        tls.write_all(REQUEST).unwrap();
+        let mut received = Vec::new();
+        tls.read_to_end(&mut received).unwrap();
+        assert_eq!(received, response);
+        assert_eq!(server.finish().unwrap().request, REQUEST);
+    }
+
+    #[test]
+    fn test_connect_async() {
+        use crate::reactor::AsyncTcpStream;
+        use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+        let response = vec![b'z'; 40_000];
+        let server = FixtureServer::start(CipherSuite::ChaCha20Poly1305Sha256, Scenario::KeyUpdate, &response);
+        let received = futures::executor::block_on(async {
+            let address = server.address();
+            let tcp = AsyncTcpStream::connect(&address.ip().to_string(), address.port()).await.unwrap();
+            let mut tls = TlsStream::connect_async(tcp, "localhost", &trust_store()).await.unwrap();
+            assert_eq!(tls.cipher_suite(), CipherSuite::ChaCha20Poly1305Sha256);
+            tls.write_all(REQUEST).await.unwrap();
+            let mut received = Vec::new();
+            tls.read_to_end(&mut received).await.unwrap();
+            received
+        });
+        assert_eq!(received, response);
+        assert_eq!(server.finish().unwrap().request, REQUEST);
+    }
+
+    #[test]
+    fn test_bad_finished() {
+        let server = FixtureServer::start(CipherSuite::Aes128GcmSha256, Scenario::BadFinished, RESPONSE);
+        let error = connect(&server).err().unwrap();
+        assert!(matches!(error, TlsError::Protocol(Alert::DECRYPT_ERROR, _)), "{}", error);
+        // The server is told why
+        assert!(matches!(server.finish(), Err(TlsError::AlertReceived(Alert::DECRYPT_ERROR))));
+    }
+
+    #[test]
+    fn test_untrusted_certificate() {
+        let server = FixtureServer::start(CipherSuite::Aes128GcmSha256, Scenario::Normal, RESPONSE);
+        let error = connect_to(&server, "localhost", &TrustStore::new()).err().unwrap();
+        assert!(matches!(error, TlsError::Certificate(CertificateError::UnknownIssuer)), "{}", error);
+        assert!(matches!(server.finish(), Err(TlsError::AlertReceived(Alert::UNKNOWN_CA))));
+
+        let server = FixtureServer::start(CipherSuite::Aes128GcmSha256, Scenario::Normal, RESPONSE);
+        let error = connect_to(&server, "example.com", &trust_store()).err().unwrap();
+        assert!(
+            matches!(&error, TlsError::Certificate(CertificateError::HostnameMismatch(host)) if host == "example.com"),
+            "{}",
+            error
+        );
+        assert!(matches!(server.finish(), Err(TlsError::AlertReceived(Alert::CERTIFICATE_UNKNOWN))));
+    }
+
+    #[test]
+    fn test_ip_address() {
+        let server = FixtureServer::start(CipherSuite::Aes128GcmSha256, Scenario::Normal, RESPONSE);
+        let mut tls = connect_to(&server, "127.0.0.1", &trust_store()).unwrap();
+        tls.write_all(REQUEST).unwrap();
+        tls.read_to_end(&mut Vec::new()).unwrap();
+        assert_eq!(server.finish().unwrap().server_name, None);
+    }
+
+    #[test]
+    fn test_bad_signature() {
+        let server = FixtureServer::start(CipherSuite::Aes128GcmSha256, Scenario::BadSignature, RESPONSE);
+        let error = connect(&server).err().unwrap();
+        assert!(matches!(error, TlsError::Protocol(Alert::DECRYPT_ERROR, _)), "{}", error);
+        assert_eq!(error.to_string(), "CertificateVerify signature is invalid (decrypt_error)");
+    }
+
+    #[test]
+    fn test_server_alert() {
+        let server = FixtureServer::start(CipherSuite::Aes128GcmSha256, Scenario::RejectHello, RESPONSE);
+        let error = connect(&server).err().unwrap();
+        assert!(matches!(error, TlsError::AlertReceived(Alert::HANDSHAKE_FAILURE)), "{}", error);
+        assert_eq!(error.to_string(), "the server sent a fatal alert: handshake_failure");
+    }
+}
@@ -0,0 +1,253 @@
+//! The TLS record layer (RFC 8446, section 5): framing, and protection of
+//! records once keys are established.
+
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::io::AsyncRead;
+
+use super::key_schedule::{hkdf_expand_label, next_traffic_secret, Secret};
+use super::{Alert, CipherSuite, TlsError};
+use crate::crypto::{Aead, TAG_LEN};
+
+pub(crate) const CHANGE_CIPHER_SPEC: u8 = 20;
+pub(crate) const ALERT: u8 = 21;
+pub(crate) const HANDSHAKE: u8 = 22;
+pub(crate) const APPLICATION_DATA: u8 = 23;
+
+/// Largest plaintext a record may carry.
+const MAX_PLAINTEXT_LEN: usize = 1 << 14;
+/// Largest protected record: the plaintext, its content type, padding and tag.
+const MAX_CIPHERTEXT_LEN: usize = MAX_PLAINTEXT_LEN + 256;
+
+/// The keys protecting the records sent in one direction.
+pub(crate) struct RecordKeys {
+    suite: CipherSuite,
+    secret: Secret,
+    aead: Aead,
+    iv: [u8; 12],
+    /// Number of records protected with these keys so far.
+    sequence: u64,
+}
+
+impl RecordKeys {
+    pub(crate) fn new(suite: CipherSuite, secret: Secret) -> Self {
+        let key = hkdf_expand_label(&secret, "key", b"", suite.key_len());
+        let mut iv = [0; 12];
+        iv.copy_from_slice(&hkdf_expand_label(&secret, "iv", b"", 12));
+        RecordKeys {
+            suite,
+            secret,
+            aead: suite.aead(&key),
+            iv,
+            sequence: 0,
+        }
+    }
+
+    /// Replaces the keys with the next generation, after a KeyUpdate.
+    pub(crate) fn update(&mut self) {
+        *self = RecordKeys::new(self.suite, next_traffic_secret(&self.secret));
+    }
+
+    /// The nonce of the next record: the IV mixed with the sequence number.
+    fn next_nonce(&mut self) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (byte, sequence) in nonce[4..].iter_mut().zip(self.sequence.to_be_bytes()) {
+            *byte ^= sequence;
+        }
+        self.sequence += 1;
+        nonce
+    }
+}
+
+/// A record, decrypted if it was protected.
+pub(crate) struct Record {
+    pub(crate) content_type: u8,
+    pub(crate) payload: Vec<u8>,
+}
+
+/// Reads the records of a stream that may not have them whole yet, keeping
+/// what has arrived between calls.
+#[derive(Default)]
+pub(crate) struct RecordReader {
+    /// The start of the next record.
+    buffer: Vec<u8>,
+}
+
+impl RecordReader {
+    /// Reads the next record, waiting for the rest of it if needed. Once
+    /// `keys` are set, protected records are decrypted and their real
+    /// content type restored.
+    ///
+    /// Returns `None` if the stream ends cleanly between two records.
+    pub(crate) fn poll_read_record<S: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+        keys: Option<&mut RecordKeys>,
+    ) -> Poll<Result<Option<Record>, TlsError>> {
+        loop {
+            // Nothing past the record is read, which may not be for TLS
+            let mut needed = 5;
+            if self.buffer.len() >= 5 {
+                let length = u16::from_be_bytes([self.buffer[3], self.buffer[4]]) as usize;
+                if length > MAX_CIPHERTEXT_LEN {
+                    return Poll::Ready(Err(TlsError::Protocol(Alert::RECORD_OVERFLOW, "record too long")));
+                }
+                needed += length;
+                if self.buffer.len() == needed {
+                    let record = mem::take(&mut self.buffer);
+                    return Poll::Ready(open_record(&record, keys).map(Some));
+                }
+            }
+
+            let filled = self.buffer.len();
+            self.buffer.resize(needed, 0);
+            let result = Pin::new(&mut *stream).poll_read(cx, &mut self.buffer[filled..]);
+            let read = match result {
+                Poll::Ready(Ok(read)) => read,
+                _ => 0,
+            };
+            self.buffer.truncate(filled + read);
+            match result {
+                Poll::Ready(Ok(0)) if filled == 0 => return Poll::Ready(Ok(None)),
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::Error::from(ErrorKind::UnexpectedEof).into())),
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(e)) if e.kind() == ErrorKind::Interrupted => {}
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e.into())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Reads the next record from `stream`, as [`RecordReader::poll_read_record`]
+/// does from a stream that blocks, like the test server's.
+#[cfg(test)]
+pub(crate) fn read_record(stream: &mut impl std::io::Read, keys: Option<&mut RecordKeys>) -> Result<Option<Record>, TlsError> {
+    let mut stream = futures::io::AllowStdIo::new(stream);
+    super::complete(|cx| RecordReader::default().poll_read_record(cx, &mut stream, keys))
+}
+
+/// Decrypts a whole record, header included, if it is protected.
+fn open_record(record: &[u8], keys: Option<&mut RecordKeys>) -> Result<Record, TlsError> {
+    let (header, payload) = record.split_at(5);
+    let content_type = header[0];
+    let keys = match (keys, content_type) {
+        (Some(keys), APPLICATION_DATA) => keys,
+        // Change cipher spec records are never protected, and a server may
+        // still send a plaintext alert if it fails early
+        (_, CHANGE_CIPHER_SPEC | ALERT) | (None, HANDSHAKE) => {
+            return Ok(Record {
+                content_type,
+                payload: payload.to_vec(),
+            });
+        }
+        _ => return Err(TlsError::Protocol(Alert::UNEXPECTED_MESSAGE, "unexpected record type")),
+    };
+
+    let nonce = keys.next_nonce();
+    let mut plaintext = keys
+        .aead
+        .open(&nonce, header, payload)
+        .ok_or(TlsError::Protocol(Alert::BAD_RECORD_MAC, "record failed to decrypt"))?;
+    // The real content type is the last byte that is not padding
+    let end = plaintext
+        .iter()
+        .rposition(|&byte| byte != 0)
+        .ok_or(TlsError::Protocol(Alert::UNEXPECTED_MESSAGE, "record without content type"))?;
+    let content_type = plaintext[end];
+    plaintext.truncate(end);
+    if plaintext.len() > MAX_PLAINTEXT_LEN {
+        return Err(TlsError::Protocol(Alert::RECORD_OVERFLOW, "record too long"));
+    }
+    Ok(Record {
+        content_type,
+        payload: plaintext,
+    })
+}
+
+/// Writes `payload` to `stream` in as many records as needed, protected
+/// with `keys` if they are set.
+#[cfg(test)]
+pub(crate) fn write_record(
+    stream: &mut impl std::io::Write,
+    content_type: u8,
+    payload: &[u8],
+    keys: Option<&mut RecordKeys>,
+) -> Result<(), TlsError> {
+    stream.write_all(&seal_records(content_type, payload, keys))?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Puts `payload` in as many records as needed, protected with `keys` if
+/// they are set, and returns them as they are to be written.
+pub(crate) fn seal_records(content_type: u8, payload: &[u8], mut keys: Option<&mut RecordKeys>) -> Vec<u8> {
+    let mut records = Vec::new();
+    for fragment in payload.chunks(MAX_PLAINTEXT_LEN) {
+        let record = match keys.as_deref_mut() {
+            None => {
+                let mut record = vec![content_type, 3, 3];
+                record.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+                record.extend_from_slice(fragment);
+                record
+            }
+            Some(keys) => {
+                let length = fragment.len() + 1 + TAG_LEN;
+                let mut record = vec![APPLICATION_DATA, 3, 3];
+                record.extend_from_slice(&(length as u16).to_be_bytes());
+                let mut inner = fragment.to_vec();
+                inner.push(content_type);
+                let nonce = keys.next_nonce();
+                let sealed = keys.aead.seal(&nonce, &record, &inner);
+                record.extend_from_slice(&sealed);
+                record
+            }
+        };
+        records.extend(record);
+    }
+    records
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_protected_records() {
+        let secret = [7; 32];
+        for suite in CipherSuite::ALL {
+            let mut writer_keys = RecordKeys::new(suite, secret);
+            let mut reader_keys = RecordKeys::new(suite, secret);
+
+            // A payload longer than a record is split in two
+            let long = vec![b'x'; MAX_PLAINTEXT_LEN + 10];
+            let mut wire = Vec::new();
+            write_record(&mut wire, HANDSHAKE, b"hello", Some(&mut writer_keys)).unwrap();
+            write_record(&mut wire, APPLICATION_DATA, &long, Some(&mut writer_keys)).unwrap();
+            assert_eq!(wire[0], APPLICATION_DATA);
+
+            let mut wire = Cursor::new(wire);
+            let record = read_record(&mut wire, Some(&mut reader_keys)).unwrap().unwrap();
+            assert_eq!((record.content_type, record.payload.as_slice()), (HANDSHAKE, &b"hello"[..]));
+            let first = read_record(&mut wire, Some(&mut reader_keys)).unwrap().unwrap();
+            let second = read_record(&mut wire, Some(&mut reader_keys)).unwrap().unwrap();
+            assert_eq!([first.payload, second.payload].concat(), long);
+            assert!(read_record(&mut wire, Some(&mut reader_keys)).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_tampered_record() {
+        let suite = CipherSuite::Aes128GcmSha256;
+        let mut wire = Vec::new();
+        write_record(&mut wire, APPLICATION_DATA, b"secret", Some(&mut RecordKeys::new(suite, [1; 32]))).unwrap();
+        wire[7] ^= 1;
+        let result = read_record(&mut Cursor::new(wire), Some(&mut RecordKeys::new(suite, [1; 32])));
+        assert!(matches!(result, Err(TlsError::Protocol(Alert::BAD_RECORD_MAC, _))));
+    }
+}
@@ -0,0 +1,309 @@
+//! A reader for the DER encoding of ASN.1 (X.690), as far as certificates
+//! need it: single-byte tags and definite lengths only.
+
+use super::CertificateError;
+
+pub(crate) const BOOLEAN: u8 = 0x01;
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const NULL: u8 = 0x05;
+pub(crate) const OBJECT_IDENTIFIER: u8 = 0x06;
+pub(crate) const UTC_TIME: u8 = 0x17;
+pub(crate) const GENERALIZED_TIME: u8 = 0x18;
+pub(crate) const SEQUENCE: u8 = 0x30;
+
+/// The tag of an explicit, constructed, context-specific field `[number]`.
+pub(crate) const fn explicit(number: u8) -> u8 {
+    0xa0 | number
+}
+
+/// The tag of an implicit, primitive, context-specific field `[number]`.
+pub(crate) const fn implicit(number: u8) -> u8 {
+    0x80 | number
+}
+
+fn malformed(reason: &'static str) -> CertificateError {
+    CertificateError::Malformed(reason)
+}
+
+/// A tag-length-value element.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Element<'a> {
+    pub(crate) tag: u8,
+    pub(crate) contents: &'a [u8],
+    /// The whole encoding, tag and length included.
+    pub(crate) raw: &'a [u8],
+}
+
+impl<'a> Element<'a> {
+    /// A reader over the elements this one contains.
+    pub(crate) fn reader(&self) -> Reader<'a> {
+        Reader::new(self.contents)
+    }
+
+    pub(crate) fn boolean(&self) -> Result<bool, CertificateError> {
+        match self.contents {
+            [0x00] => Ok(false),
+            [0xff] => Ok(true),
+            _ => Err(malformed("invalid boolean")),
+        }
+    }
+
+    /// The big-endian magnitude of a non-negative integer, without its
+    /// leading zeros.
+    pub(crate) fn unsigned_integer(&self) -> Result<&'a [u8], CertificateError> {
+        match self.contents {
+            [] => Err(malformed("empty integer")),
+            [first, ..] if first & 0x80 != 0 => Err(malformed("negative integer")),
+            [0, second, ..] if second & 0x80 == 0 => Err(malformed("integer with a superfluous zero")),
+            contents => {
+                let start = contents.iter().position(|&byte| byte != 0).unwrap_or(contents.len());
+                Ok(&contents[start..])
+            }
+        }
+    }
+
+    /// A small non-negative integer.
+    pub(crate) fn small_integer(&self) -> Result<u32, CertificateError> {
+        let magnitude = self.unsigned_integer()?;
+        if magnitude.len() > 4 {
+            return Err(malformed("integer too large"));
+        }
+        Ok(magnitude.iter().fold(0, |value, &byte| (value << 8) | byte as u32))
+    }
+
+    /// The bytes of a bit string that is a whole number of bytes, like a key
+    /// or a signature.
+    pub(crate) fn bit_string_bytes(&self) -> Result<&'a [u8], CertificateError> {
+        match self.contents {
+            [0, bytes @ ..] => Ok(bytes),
+            _ => Err(malformed("bit string with unused bits")),
+        }
+    }
+
+    /// The bits of a bit string of flags, the first one in the highest bit
+    /// of the result.
+    pub(crate) fn bit_flags(&self) -> Result<u16, CertificateError> {
+        match self.contents {
+            [unused, bytes @ ..] if *unused < 8 && bytes.len() <= 2 && (*unused == 0 || !bytes.is_empty()) => {
+                Ok(bytes
+                    .iter()
+                    .enumerate()
+                    .fold(0, |flags, (i, &byte)| flags | ((byte as u16) << (8 - 8 * i))))
+            }
+            _ => Err(malformed("invalid bit string")),
+        }
+    }
+
+    /// A `UTCTime` or `GeneralizedTime` in UTC, as seconds since the Unix
+    /// epoch.
+    pub(crate) fn time(&self) -> Result<i64, CertificateError> {
+        let invalid = || malformed("invalid time");
+        let text = std::str::from_utf8(self.contents).map_err(|_| invalid())?;
+        let digits = text.strip_suffix('Z').ok_or_else(invalid)?;
+        if !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let (year, rest) = match (self.tag, digits.len()) {
+            // Two-digit years stand for 1950 to 2049 (RFC 5280, 4.1.2.5.1)
+            (UTC_TIME, 12) => {
+                let year: i64 = digits[..2].parse().unwrap();
+                (if year < 50 { 2000 + year } else { 1900 + year }, &digits[2..])
+            }
+            (GENERALIZED_TIME, 14) => (digits[..4].parse().unwrap(), &digits[4..]),
+            _ => return Err(invalid()),
+        };
+        let field = |i: usize| -> i64 { rest[2 * i..2 * i + 2].parse().unwrap() };
+        let (month, day, hour, minute, second) = (field(0), field(1), field(2), field(3), field(4));
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 59 {
+            return Err(invalid());
+        }
+        Ok(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second)
+    }
+}
+
+/// Number of days from 1970-01-01 to a date of the proleptic Gregorian
+/// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Counting from March makes the leap day the last of the year
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Reads the elements of a DER encoding one after another.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub(crate) fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    pub(crate) fn read(&mut self) -> Result<Element<'a>, CertificateError> {
+        let truncated = || malformed("truncated element");
+        let (&tag, rest) = self.data.split_first().ok_or_else(truncated)?;
+        if tag & 0x1f == 0x1f {
+            return Err(malformed("multi-byte tags are not supported"));
+        }
+        let (&first, mut rest) = rest.split_first().ok_or_else(truncated)?;
+        let length = match first {
+            0..=0x7f => first as usize,
+            0x80 => return Err(malformed("indefinite length")),
+            _ => {
+                let count = (first & 0x7f) as usize;
+                if count > 4 || rest.len() < count {
+                    return Err(truncated());
+                }
+                let length = rest[..count].iter().fold(0, |length, &byte| (length << 8) | byte as usize);
+                rest = &rest[count..];
+                // DER asks for the shortest form
+                if length < 0x80 || has_leading_zero(length, count) {
+                    return Err(malformed("non-minimal length"));
+                }
+                length
+            }
+        };
+        if rest.len() < length {
+            return Err(truncated());
+        }
+        let header_len = self.data.len() - rest.len();
+        let element = Element {
+            tag,
+            contents: &rest[..length],
+            raw: &self.data[..header_len + length],
+        };
+        self.data = &rest[length..];
+        Ok(element)
+    }
+
+    /// Reads an element that must have the tag `tag`.
+    pub(crate) fn expect(&mut self, tag: u8) -> Result<Element<'a>, CertificateError> {
+        let element = self.read()?;
+        if element.tag != tag {
+            return Err(malformed("unexpected element"));
+        }
+        Ok(element)
+    }
+
+    /// Reads the next element if it has the tag `tag`.
+    pub(crate) fn optional(&mut self, tag: u8) -> Result<Option<Element<'a>>, CertificateError> {
+        if self.peek_tag() == Some(tag) {
+            self.read().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reads a sequence, and returns a reader over its elements.
+    pub(crate) fn sequence(&mut self) -> Result<Reader<'a>, CertificateError> {
+        Ok(self.expect(SEQUENCE)?.reader())
+    }
+
+    /// Checks that everything was read.
+    pub(crate) fn finish(&self) -> Result<(), CertificateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(malformed("trailing data"))
+        }
+    }
+}
+
+/// Whether `length`, written on `count` bytes, starts with a zero byte.
+fn has_leading_zero(length: usize, count: usize) -> bool {
+    count > 1 && length >> (8 * (count - 1)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_elements() {
+        // SEQUENCE { INTEGER 5, [0] { BOOLEAN true }, OCTET STRING of 200 bytes }
+        let mut data = vec![0x30, 0x81, 0xd3, 0x02, 0x01, 0x05, 0xa0, 0x03, 0x01, 0x01, 0xff, 0x04, 0x81, 0xc8];
+        data.extend([7; 200]);
+        let mut reader = Reader::new(&data);
+        let mut sequence = reader.sequence().unwrap();
+        reader.finish().unwrap();
+
+        assert_eq!(sequence.expect(INTEGER).unwrap().small_integer().unwrap(), 5);
+        assert!(sequence.optional(explicit(1)).unwrap().is_none());
+        let tagged = sequence.optional(explicit(0)).unwrap().unwrap();
+        assert_eq!(tagged.raw, [0xa0, 0x03, 0x01, 0x01, 0xff]);
+        assert!(tagged.reader().expect(BOOLEAN).unwrap().boolean().unwrap());
+        assert_eq!(sequence.expect(OCTET_STRING).unwrap().contents, [7; 200]);
+        assert!(sequence.is_empty());
+    }
+
+    #[test]
+    fn test_reject_malformed() {
+        let cases: [&[u8]; 5] = [
+            &[0x04, 0x80, 0x00, 0x00],       // indefinite length
+            &[0x04, 0x81, 0x05, 1, 2, 3, 4, 5], // long form for a short length
+            &[0x04, 0x82, 0x00, 0x90],       // leading zero in the length
+            &[0x04, 0x05, 1, 2],             // truncated
+            &[0x1f, 0x81, 0x00, 0x00],       // multi-byte tag
+        ];
+        for case in cases {
+            assert!(Reader::new(case).read().is_err(), "{:02x?}", case);
+        }
+        let integer = |contents: &'static [u8]| Element {
+            tag: INTEGER,
+            contents,
+            raw: &[],
+        };
+        assert_eq!(integer(&[0x00, 0x80, 0x01]).unsigned_integer().unwrap(), [0x80, 0x01]);
+        assert!(integer(&[0x80]).unsigned_integer().is_err());
+        assert!(integer(&[0x00, 0x01]).unsigned_integer().is_err());
+    }
+
+    #[test]
+    fn test_time() {
+        let time = |tag, text: &'static str| {
+            Element {
+                tag,
+                contents: text.as_bytes(),
+                raw: &[],
+            }
+            .time()
+        };
+        assert_eq!(time(UTC_TIME, "700101000000Z").unwrap(), 0);
+        assert_eq!(time(UTC_TIME, "491231235959Z").unwrap(), 2524607999);
+        assert_eq!(time(UTC_TIME, "500101000000Z").unwrap(), -631152000);
+        assert_eq!(time(GENERALIZED_TIME, "20240229120000Z").unwrap(), 1709208000);
+        assert!(time(UTC_TIME, "20240229120000Z").is_err());
+        assert!(time(GENERALIZED_TIME, "20241301000000Z").is_err());
+        assert!(time(UTC_TIME, "700101000000+0100").is_err());
+    }
+
+    #[test]
+    fn test_bit_flags() {
+        let flags = |contents: &'static [u8]| {
+            Element {
+                tag: BIT_STRING,
+                contents,
+                raw: &[],
+            }
+            .bit_flags()
+        };
+        // digitalSignature and keyEncipherment: bits 0 and 2
+        assert_eq!(flags(&[0x05, 0xa0]).unwrap(), 0xa000);
+        assert_eq!(flags(&[0x07, 0x80, 0x80]).unwrap(), 0x8080);
+        assert!(flags(&[0x08, 0x80]).is_err());
+    }
+}
@@ -0,0 +1,580 @@
+//! X.509 certificates (RFC 5280), and the checks that tie a TLS server to
+//! the host name it was reached by.
+//!
+//! A certificate is parsed for what checking it needs: names, validity,
+//! public key, signature, and the extensions that limit what it may be used
+//! for. [`TrustStore`] holds the roots a chain must lead to.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::ops::Range;
+
+use crate::crypto::ecdsa::{Curve, EcdsaPublicKey};
+use crate::crypto::rsa::RsaPublicKey;
+use crate::crypto::HashAlgorithm;
+use der::{Element, Reader};
+
+mod der;
+pub(crate) mod pem;
+mod verify;
+
+pub use verify::{set_default_trust_store, TrustStore};
+pub(crate) use verify::{default_trust_store, verify_chain};
+
+// Object identifiers, as the contents of their DER encoding
+const RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const RSASSA_PSS: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0a];
+const SHA256_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b];
+const SHA384_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c];
+const SHA512_WITH_RSA: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d];
+const MGF1: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x08];
+const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const SECP384R1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const ECDSA_WITH_SHA384: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03];
+const ECDSA_WITH_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04];
+const SHA256: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01];
+const SHA384: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02];
+const SHA512: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03];
+const KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x0f];
+const SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+const BASIC_CONSTRAINTS: &[u8] = &[0x55, 0x1d, 0x13];
+const EXTENDED_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25];
+const ANY_EXTENDED_KEY_USAGE: &[u8] = &[0x55, 0x1d, 0x25, 0x00];
+const SERVER_AUTH: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x03, 0x01];
+
+/// Key usage flags, in the order `bit_flags` puts them.
+const DIGITAL_SIGNATURE: u16 = 0x8000;
+const KEY_CERT_SIGN: u16 = 0x0400;
+
+/// Why a certificate, or the chain it is part of, was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertificateError {
+    /// The certificate is not valid DER, or not a valid X.509 structure.
+    Malformed(&'static str),
+    /// The key or the signature uses an algorithm that is not supported.
+    UnsupportedAlgorithm,
+    /// The certificate has a critical extension that is not understood.
+    UnsupportedCriticalExtension,
+    Expired,
+    NotYetValid,
+    /// No trusted root could be reached from the certificate.
+    UnknownIssuer,
+    /// A signature does not match the certificate it signs.
+    BadSignature,
+    /// A certificate of the chain signs others without being allowed to.
+    NotACertificateAuthority,
+    /// The server's certificate is not meant to authenticate servers.
+    NotForServerAuth,
+    /// The server's certificate does not name the host.
+    HostnameMismatch(String),
+}
+
+impl fmt::Display for CertificateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CertificateError::Malformed(reason) => write!(f, "malformed certificate: {}", reason),
+            CertificateError::UnsupportedAlgorithm => write!(f, "certificate uses an unsupported algorithm"),
+            CertificateError::UnsupportedCriticalExtension => {
+                write!(f, "certificate has an unsupported critical extension")
+            }
+            CertificateError::Expired => write!(f, "certificate has expired"),
+            CertificateError::NotYetValid => write!(f, "certificate is not valid yet"),
+            CertificateError::UnknownIssuer => write!(f, "certificate is not issued by a trusted authority"),
+            CertificateError::BadSignature => write!(f, "certificate signature is invalid"),
+            CertificateError::NotACertificateAuthority => {
+                write!(f, "certificate is issued by a certificate that may not issue others")
+            }
+            CertificateError::NotForServerAuth => write!(f, "certificate may not authenticate a server"),
+            CertificateError::HostnameMismatch(host) => write!(f, "certificate is not valid for {}", host),
+        }
+    }
+}
+
+impl std::error::Error for CertificateError {}
+
+/// A signature algorithm, with its hash function. RSA-PSS always uses MGF1
+/// with the same hash, and a salt as long as the digest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SignatureAlgorithm {
+    RsaPkcs1(HashAlgorithm),
+    RsaPss(HashAlgorithm),
+    Ecdsa(HashAlgorithm),
+}
+
+impl SignatureAlgorithm {
+    /// Reads an `AlgorithmIdentifier` of a signature.
+    fn parse(element: Element) -> Result<Self, CertificateError> {
+        let mut reader = element.reader();
+        let oid = reader.expect(der::OBJECT_IDENTIFIER)?.contents;
+        let algorithm = match oid {
+            SHA256_WITH_RSA => SignatureAlgorithm::RsaPkcs1(HashAlgorithm::Sha256),
+            SHA384_WITH_RSA => SignatureAlgorithm::RsaPkcs1(HashAlgorithm::Sha384),
+            SHA512_WITH_RSA => SignatureAlgorithm::RsaPkcs1(HashAlgorithm::Sha512),
+            ECDSA_WITH_SHA256 => SignatureAlgorithm::Ecdsa(HashAlgorithm::Sha256),
+            ECDSA_WITH_SHA384 => SignatureAlgorithm::Ecdsa(HashAlgorithm::Sha384),
+            ECDSA_WITH_SHA512 => SignatureAlgorithm::Ecdsa(HashAlgorithm::Sha512),
+            RSASSA_PSS => SignatureAlgorithm::RsaPss(parse_pss_parameters(reader.read()?)?),
+            _ => return Err(CertificateError::UnsupportedAlgorithm),
+        };
+        // RSA takes NULL parameters, which some encoders leave out
+        if matches!(algorithm, SignatureAlgorithm::RsaPkcs1(_)) {
+            reader.optional(der::NULL)?;
+        }
+        reader.finish()?;
+        Ok(algorithm)
+    }
+}
+
+/// Reads the parameters of RSA-PSS, accepting only what TLS accepts: MGF1
+/// with the hash of the message, and a salt of the digest's length.
+fn parse_pss_parameters(element: Element) -> Result<HashAlgorithm, CertificateError> {
+    let unsupported = CertificateError::UnsupportedAlgorithm;
+    let hash_algorithm = |element: Element| -> Result<HashAlgorithm, CertificateError> {
+        let mut reader = element.reader();
+        let hash = match reader.expect(der::OBJECT_IDENTIFIER)?.contents {
+            SHA256 => HashAlgorithm::Sha256,
+            SHA384 => HashAlgorithm::Sha384,
+            SHA512 => HashAlgorithm::Sha512,
+            _ => return Err(CertificateError::UnsupportedAlgorithm),
+        };
+        reader.optional(der::NULL)?;
+        reader.finish()?;
+        Ok(hash)
+    };
+
+    let mut reader = Reader::new(element.contents);
+    if element.tag != der::SEQUENCE {
+        return Err(CertificateError::Malformed("invalid RSA-PSS parameters"));
+    }
+    // The defaults, SHA-1 and a salt of 20 bytes, are not supported
+    let hash = match reader.optional(der::explicit(0))? {
+        Some(field) => hash_algorithm(field.reader().expect(der::SEQUENCE)?)?,
+        None => return Err(unsupported),
+    };
+    let mask = reader.optional(der::explicit(1))?.ok_or(unsupported.clone())?;
+    let mut mask = mask.reader().sequence()?;
+    if mask.expect(der::OBJECT_IDENTIFIER)?.contents != MGF1 || hash_algorithm(mask.expect(der::SEQUENCE)?)? != hash {
+        return Err(unsupported);
+    }
+    let salt_len = reader.optional(der::explicit(2))?.ok_or(unsupported.clone())?;
+    if salt_len.reader().expect(der::INTEGER)?.small_integer()? as usize != hash.output_len() {
+        return Err(unsupported);
+    }
+    if let Some(trailer) = reader.optional(der::explicit(3))? {
+        if trailer.reader().expect(der::INTEGER)?.small_integer()? != 1 {
+            return Err(CertificateError::Malformed("invalid RSA-PSS trailer field"));
+        }
+    }
+    reader.finish()?;
+    Ok(hash)
+}
+
+/// The public key a certificate binds to its subject.
+#[derive(Debug, Clone)]
+pub(crate) enum PublicKey {
+    Rsa(RsaPublicKey),
+    Ecdsa(EcdsaPublicKey),
+}
+
+impl PublicKey {
+    /// Reads a `SubjectPublicKeyInfo`.
+    fn parse(element: Element) -> Result<Self, CertificateError> {
+        let mut reader = element.reader();
+        let mut algorithm = reader.sequence()?;
+        let key = reader.expect(der::BIT_STRING)?.bit_string_bytes()?;
+        reader.finish()?;
+
+        let invalid = CertificateError::Malformed("invalid public key");
+        match algorithm.expect(der::OBJECT_IDENTIFIER)?.contents {
+            RSA_ENCRYPTION => {
+                algorithm.optional(der::NULL)?;
+                algorithm.finish()?;
+                let mut key = Reader::new(key).sequence()?;
+                let modulus = key.expect(der::INTEGER)?.unsigned_integer()?;
+                let exponent = key.expect(der::INTEGER)?.unsigned_integer()?;
+                key.finish()?;
+                // Keys below 2048 bits are refused along with invalid ones
+                let key = RsaPublicKey::new(modulus, exponent).ok_or(CertificateError::UnsupportedAlgorithm)?;
+                Ok(PublicKey::Rsa(key))
+            }
+            EC_PUBLIC_KEY => {
+                let curve = match algorithm.read()? {
+                    element if element.tag == der::OBJECT_IDENTIFIER && element.contents == PRIME256V1 => Curve::P256,
+                    element if element.tag == der::OBJECT_IDENTIFIER && element.contents == SECP384R1 => Curve::P384,
+                    _ => return Err(CertificateError::UnsupportedAlgorithm),
+                };
+                algorithm.finish()?;
+                Ok(PublicKey::Ecdsa(EcdsaPublicKey::new(curve, key).ok_or(invalid)?))
+            }
+            _ => Err(CertificateError::UnsupportedAlgorithm),
+        }
+    }
+
+    /// The curve of an elliptic curve key.
+    pub(crate) fn curve(&self) -> Option<Curve> {
+        match self {
+            PublicKey::Ecdsa(key) => Some(key.curve()),
+            PublicKey::Rsa(_) => None,
+        }
+    }
+
+    /// Checks `signature`, made with `algorithm`, of `message`.
+    pub(crate) fn verify(
+        &self,
+        algorithm: SignatureAlgorithm,
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CertificateError> {
+        let valid = match (self, algorithm) {
+            (PublicKey::Rsa(key), SignatureAlgorithm::RsaPkcs1(hash)) => key.verify_pkcs1(hash, message, signature),
+            (PublicKey::Rsa(key), SignatureAlgorithm::RsaPss(hash)) => {
+                key.verify_pss(hash, hash.output_len(), message, signature)
+            }
+            (PublicKey::Ecdsa(key), SignatureAlgorithm::Ecdsa(hash)) => {
+                // The signature is a DER sequence of its two numbers
+                let (r, s) = parse_ecdsa_signature(signature).ok_or(CertificateError::BadSignature)?;
+                key.verify(&hash.hash(message), r, s)
+            }
+            _ => return Err(CertificateError::BadSignature),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(CertificateError::BadSignature)
+        }
+    }
+}
+
+fn parse_ecdsa_signature(signature: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut reader = Reader::new(signature);
+    let mut sequence = reader.sequence().ok()?;
+    let r = sequence.expect(der::INTEGER).ok()?.unsigned_integer().ok()?;
+    let s = sequence.expect(der::INTEGER).ok()?.unsigned_integer().ok()?;
+    sequence.finish().ok()?;
+    reader.finish().ok()?;
+    Some((r, s))
+}
+
+/// A parsed certificate.
+#[derive(Debug, Clone)]
+pub(crate) struct Certificate {
+    der: Vec<u8>,
+    /// Where the signed part, `tbsCertificate`, lies in `der`.
+    signed: Range<usize>,
+    signature_algorithm: SignatureAlgorithm,
+    signature: Vec<u8>,
+    /// The DER encoding of the issuer's and the subject's names, which are
+    /// compared as they are to link a certificate to its issuer.
+    issuer: Vec<u8>,
+    subject: Vec<u8>,
+    not_before: i64,
+    not_after: i64,
+    public_key: PublicKey,
+    dns_names: Vec<String>,
+    ip_addresses: Vec<IpAddr>,
+    is_ca: bool,
+    /// How many intermediate certificates may follow this one down a chain.
+    path_len: Option<u32>,
+    key_usage: Option<u16>,
+    /// Whether the extended key usage, if any, allows authenticating a
+    /// server.
+    server_auth: bool,
+}
+
+impl Certificate {
+    /// Parses a DER-encoded certificate.
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, CertificateError> {
+        let mut outer = Reader::new(data);
+        let mut certificate = outer.sequence()?;
+        outer.finish()?;
+        let signed = certificate.expect(der::SEQUENCE)?;
+        let signature_algorithm = certificate.expect(der::SEQUENCE)?;
+        let signature = certificate.expect(der::BIT_STRING)?.bit_string_bytes()?;
+        certificate.finish()?;
+
+        let mut tbs = signed.reader();
+        let version = match tbs.optional(der::explicit(0))? {
+            Some(version) => version.reader().expect(der::INTEGER)?.small_integer()?,
+            None => 0,
+        };
+        if version > 2 {
+            return Err(CertificateError::Malformed("unknown version"));
+        }
+        // Serial numbers are compared by no one here, and may be negative
+        tbs.expect(der::INTEGER)?;
+        if tbs.expect(der::SEQUENCE)?.raw != signature_algorithm.raw {
+            return Err(CertificateError::Malformed("signature algorithms differ"));
+        }
+        let issuer = tbs.expect(der::SEQUENCE)?.raw.to_vec();
+        let mut validity = tbs.sequence()?;
+        let not_before = validity.read()?.time()?;
+        let not_after = validity.read()?.time()?;
+        validity.finish()?;
+        let subject = tbs.expect(der::SEQUENCE)?.raw.to_vec();
+        let public_key = PublicKey::parse(tbs.expect(der::SEQUENCE)?)?;
+        tbs.optional(der::implicit(1))?;
+        tbs.optional(der::implicit(2))?;
+
+        let mut certificate = Certificate {
+            der: data.to_vec(),
+            signed: range_in(data, signed.raw),
+            signature_algorithm: SignatureAlgorithm::parse(signature_algorithm)?,
+            signature: signature.to_vec(),
+            issuer,
+            subject,
+            not_before,
+            not_after,
+            public_key,
+            dns_names: Vec::new(),
+            ip_addresses: Vec::new(),
+            is_ca: false,
+            path_len: None,
+            key_usage: None,
+            server_auth: true,
+        };
+        if let Some(extensions) = tbs.optional(der::explicit(3))? {
+            if version != 2 {
+                return Err(CertificateError::Malformed("extensions in a certificate before version 3"));
+            }
+            certificate.parse_extensions(extensions.reader().sequence()?)?;
+        }
+        tbs.finish()?;
+        Ok(certificate)
+    }
+
+    fn parse_extensions(&mut self, mut extensions: Reader) -> Result<(), CertificateError> {
+        let mut seen: Vec<&[u8]> = Vec::new();
+        while !extensions.is_empty() {
+            let mut extension = extensions.sequence()?;
+            let id = extension.expect(der::OBJECT_IDENTIFIER)?.contents;
+            let critical = match extension.optional(der::BOOLEAN)? {
+                Some(critical) => critical.boolean()?,
+                None => false,
+            };
+            let value = extension.expect(der::OCTET_STRING)?.contents;
+            extension.finish()?;
+            if seen.contains(&id) {
+                return Err(CertificateError::Malformed("duplicate extension"));
+            }
+            seen.push(id);
+
+            let mut value = Reader::new(value);
+            match id {
+                SUBJECT_ALT_NAME => {
+                    let mut names = value.sequence()?;
+                    while !names.is_empty() {
+                        let name = names.read()?;
+                        match name.tag {
+                            // dNSName
+                            0x82 => self.dns_names.push(String::from_utf8_lossy(name.contents).into_owned()),
+                            // iPAddress
+                            0x87 => match name.contents.len() {
+                                4 => self.ip_addresses.push(<[u8; 4]>::try_from(name.contents).unwrap().into()),
+                                16 => self.ip_addresses.push(<[u8; 16]>::try_from(name.contents).unwrap().into()),
+                                _ => return Err(CertificateError::Malformed("invalid IP address")),
+                            },
+                            _ => {}
+                        }
+                    }
+                }
+                BASIC_CONSTRAINTS => {
+                    let mut constraints = value.sequence()?;
+                    if let Some(is_ca) = constraints.optional(der::BOOLEAN)? {
+                        self.is_ca = is_ca.boolean()?;
+                    }
+                    if let Some(path_len) = constraints.optional(der::INTEGER)? {
+                        self.path_len = Some(path_len.small_integer()?);
+                    }
+                    constraints.finish()?;
+                }
+                KEY_USAGE => self.key_usage = Some(value.expect(der::BIT_STRING)?.bit_flags()?),
+                EXTENDED_KEY_USAGE => {
+                    let mut purposes = value.sequence()?;
+                    self.server_auth = false;
+                    while !purposes.is_empty() {
+                        let purpose = purposes.expect(der::OBJECT_IDENTIFIER)?.contents;
+                        self.server_auth |= purpose == SERVER_AUTH || purpose == ANY_EXTENDED_KEY_USAGE;
+                    }
+                }
+                _ if critical => return Err(CertificateError::UnsupportedCriticalExtension),
+                _ => continue,
+            }
+            value.finish()?;
+        }
+        Ok(())
+    }
+
+    /// The certificate as it was received.
+    pub(crate) fn der(&self) -> &[u8] {
+        &self.der
+    }
+
+    pub(crate) fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    /// Checks that `now`, in seconds since the Unix epoch, falls within the
+    /// validity period.
+    pub(crate) fn check_validity(&self, now: i64) -> Result<(), CertificateError> {
+        if now < self.not_before {
+            Err(CertificateError::NotYetValid)
+        } else if now > self.not_after {
+            Err(CertificateError::Expired)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that `issuer` holds the key this certificate was signed with.
+    pub(crate) fn check_signed_by(&self, issuer: &Certificate) -> Result<(), CertificateError> {
+        issuer
+            .public_key
+            .verify(self.signature_algorithm, &self.der[self.signed.clone()], &self.signature)
+    }
+
+    /// Whether the certificate may sign other certificates.
+    pub(crate) fn may_issue(&self) -> bool {
+        self.is_ca && self.key_usage.is_none_or(|usage| usage & KEY_CERT_SIGN != 0)
+    }
+
+    /// Whether the certificate may authenticate a TLS server, whose
+    /// handshake it signs.
+    pub(crate) fn may_authenticate_server(&self) -> bool {
+        self.server_auth && self.key_usage.is_none_or(|usage| usage & DIGITAL_SIGNATURE != 0)
+    }
+
+    /// Whether the certificate is for `host`, a domain name or an IP
+    /// address. Only the subject alternative names count, not the common
+    /// name (RFC 6125); a wildcard stands for exactly one label.
+    pub(crate) fn matches_host(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(address) = host.parse::<IpAddr>() {
+            return self.ip_addresses.contains(&address);
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.dns_names.iter().any(|name| {
+            let name = name.to_ascii_lowercase();
+            match name.strip_prefix("*.") {
+                // `*.com` would cover a whole top-level domain
+                Some(parent) if parent.contains('.') => host
+                    .split_once('.')
+                    .is_some_and(|(label, rest)| !label.is_empty() && rest == parent),
+                _ => name == host,
+            }
+        })
+    }
+}
+
+/// Where `part`, a slice of `whole`, lies in it.
+fn range_in(whole: &[u8], part: &[u8]) -> Range<usize> {
+    let start = part.as_ptr() as usize - whole.as_ptr() as usize;
+    start..start + part.len()
+}
+
+#[cfg(test)]
+pub(crate) mod testdata {
+    //! A chain made with OpenSSL: an RSA root, a P-384 intermediate, and a
+    //! P-256 certificate for `localhost`, `*.flux.test` and 127.0.0.1.
+
+    pub(crate) const ROOT: &str = include_str!("testdata/root.pem");
+    pub(crate) const INTERMEDIATE: &str = include_str!("testdata/intermediate.pem");
+    pub(crate) const LEAF: &str = include_str!("testdata/leaf.pem");
+    /// The private key of `LEAF`.
+    pub(crate) const LEAF_KEY: &str = "f7fd94bc44701779f15c30c01551380b4beb83f0ff03aded2f142c84729ec15e";
+    /// Like `LEAF`, but valid in 2000 only.
+    pub(crate) const EXPIRED: &str = include_str!("testdata/expired.pem");
+    /// An RSA certificate for `pss.flux.test`, signed by the root with
+    /// RSA-PSS.
+    pub(crate) const PSS_LEAF: &str = include_str!("testdata/pss-leaf.pem");
+    /// A certificate for `localhost` signed by `PSS_LEAF`, which may not
+    /// sign certificates.
+    pub(crate) const MISISSUED: &str = include_str!("testdata/misissued.pem");
+
+    /// The DER encoding of a PEM certificate.
+    pub(crate) fn der(pem: &str) -> Vec<u8> {
+        super::pem::certificates(pem).remove(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testdata::*;
+    use super::*;
+
+    /// 2026-01-01, within the validity of the test certificates.
+    const NOW: i64 = 1767225600;
+
+    fn parse(pem: &str) -> Certificate {
+        Certificate::parse(&der(pem)).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let leaf = parse(LEAF);
+        assert_eq!(leaf.signature_algorithm, SignatureAlgorithm::Ecdsa(HashAlgorithm::Sha384));
+        assert_eq!(leaf.public_key().curve(), Some(Curve::P256));
+        assert_eq!(leaf.dns_names, ["localhost", "*.flux.test"]);
+        assert_eq!(leaf.ip_addresses, [IpAddr::from([127, 0, 0, 1])]);
+        assert!(leaf.may_authenticate_server() && !leaf.may_issue());
+        // 2025-01-01 as a UTCTime, 2125-01-01 as a GeneralizedTime
+        assert_eq!((leaf.not_before, leaf.not_after), (1735689600, 4891363200));
+
+        let intermediate = parse(INTERMEDIATE);
+        assert_eq!(intermediate.subject, leaf.issuer);
+        assert!(intermediate.may_issue());
+        assert_eq!(intermediate.path_len, Some(0));
+        assert_eq!(intermediate.public_key().curve(), Some(Curve::P384));
+
+        let pss = parse(PSS_LEAF);
+        assert_eq!(pss.signature_algorithm, SignatureAlgorithm::RsaPss(HashAlgorithm::Sha256));
+        assert!(matches!(pss.public_key(), PublicKey::Rsa(key) if key.bits() == 2048));
+    }
+
+    #[test]
+    fn test_signatures() {
+        let root = parse(ROOT);
+        let intermediate = parse(INTERMEDIATE);
+        // RSA PKCS #1, ECDSA over P-384, and RSA-PSS
+        intermediate.check_signed_by(&root).unwrap();
+        parse(LEAF).check_signed_by(&intermediate).unwrap();
+        parse(PSS_LEAF).check_signed_by(&root).unwrap();
+
+        assert_eq!(parse(LEAF).check_signed_by(&root), Err(CertificateError::BadSignature));
+        // The last byte is the end of the signature
+        let mut tampered = der(LEAF);
+        *tampered.last_mut().unwrap() ^= 1;
+        let tampered = Certificate::parse(&tampered).unwrap();
+        assert_eq!(tampered.check_signed_by(&intermediate), Err(CertificateError::BadSignature));
+    }
+
+    #[test]
+    fn test_validity() {
+        let expired = parse(EXPIRED);
+        assert_eq!(expired.check_validity(NOW), Err(CertificateError::Expired));
+        assert_eq!(expired.check_validity(0), Err(CertificateError::NotYetValid));
+        assert_eq!(expired.check_validity(959817600), Ok(()));
+    }
+
+    #[test]
+    fn test_matches_host() {
+        let leaf = parse(LEAF);
+        for host in ["localhost", "LocalHost.", "www.flux.test", "127.0.0.1"] {
+            assert!(leaf.matches_host(host), "{}", host);
+        }
+        for host in ["flux.test", "a.b.flux.test", ".flux.test", "127.0.0.2", "[::1]", "example.com"] {
+            assert!(!leaf.matches_host(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn test_malformed() {
+        let leaf = der(LEAF);
+        assert!(matches!(Certificate::parse(&leaf[..leaf.len() - 1]), Err(CertificateError::Malformed(_))));
+        let mut trailing = leaf.clone();
+        trailing.push(0);
+        assert!(matches!(Certificate::parse(&trailing), Err(CertificateError::Malformed(_))));
+        assert!(Certificate::parse(b"").is_err());
+    }
+}
@@ -0,0 +1,260 @@
+//! The trust store, and the building of a path from a server's certificate
+//! to one of its roots.
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use super::{pem, Certificate, CertificateError};
+use crate::errors::NetworkError;
+
+/// Where Linux and BSD distributions keep the bundle of trusted roots.
+const SYSTEM_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/cert.pem",
+    "/usr/local/share/certs/ca-root-nss.crt",
+];
+
+/// Longest chain accepted, the server's certificate included.
+const MAX_CHAIN_LEN: usize = 8;
+
+/// The root certificates a server's chain must lead to for the connection
+/// to be trusted.
+///
+/// # Example
+/// ```rust,no_run
+/// use flux_network::TrustStore;
+///
+/// let mut store = TrustStore::system().unwrap();
+/// store.add_pem(&std::fs::read_to_string("my-root.pem").unwrap());
+/// flux_network::set_default_trust_store(store);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TrustStore {
+    roots: Vec<Certificate>,
+}
+
+impl TrustStore {
+    /// An empty store, which trusts no one.
+    pub fn new() -> Self {
+        TrustStore::default()
+    }
+
+    /// Adds the certificates of a PEM bundle.
+    ///
+    /// # Returns
+    /// How many certificates were added. Certificates that cannot be read,
+    /// or use algorithms that are not supported, are skipped.
+    pub fn add_pem(&mut self, pem: &str) -> usize {
+        let before = self.roots.len();
+        self.roots.extend(
+            pem::certificates(pem)
+                .iter()
+                .filter_map(|der| Certificate::parse(der).ok()),
+        );
+        self.roots.len() - before
+    }
+
+    /// Loads the roots of a PEM bundle on disk.
+    ///
+    /// # Returns
+    /// The store, or `NetworkError::TrustStoreUnavailable` if the file cannot
+    /// be read or holds no usable certificate.
+    pub fn from_pem_file(path: impl AsRef<Path>) -> Result<Self, NetworkError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| NetworkError::TrustStoreUnavailable(format!("{}: {}", path.display(), e)))?;
+        let mut store = TrustStore::new();
+        if store.add_pem(&text) == 0 {
+            return Err(NetworkError::TrustStoreUnavailable(format!(
+                "{}: no usable certificate",
+                path.display()
+            )));
+        }
+        Ok(store)
+    }
+
+    /// Loads the roots the system trusts: the bundle named by the
+    /// `SSL_CERT_FILE` environment variable if it is set, else the first
+    /// bundle found where distributions put it.
+    pub fn system() -> Result<Self, NetworkError> {
+        if let Some(path) = std::env::var_os("SSL_CERT_FILE") {
+            return TrustStore::from_pem_file(path);
+        }
+        let path = SYSTEM_BUNDLES
+            .iter()
+            .find(|path| Path::new(path).is_file())
+            .ok_or_else(|| NetworkError::TrustStoreUnavailable("no system certificate bundle".to_string()))?;
+        TrustStore::from_pem_file(path)
+    }
+
+    /// Number of roots in the store.
+    pub fn len(&self) -> usize {
+        self.roots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+}
+
+/// The store `https` connections are checked against, loaded on first use.
+static DEFAULT_TRUST_STORE: Mutex<Option<Arc<TrustStore>>> = Mutex::new(None);
+
+/// Replaces the trust store used by [`fetch`](crate::fetch) and
+/// [`HttpClient`](crate::HttpClient), which is otherwise loaded from the
+/// system's bundle the first time an `https` URL is fetched.
+pub fn set_default_trust_store(store: TrustStore) {
+    *DEFAULT_TRUST_STORE.lock().unwrap() = Some(Arc::new(store));
+}
+
+/// The default trust store, loading the system's on first use.
+pub(crate) fn default_trust_store() -> Result<Arc<TrustStore>, NetworkError> {
+    let mut store = DEFAULT_TRUST_STORE.lock().unwrap();
+    if let Some(store) = store.as_ref() {
+        return Ok(store.clone());
+    }
+    let loaded = Arc::new(TrustStore::system()?);
+    *store = Some(loaded.clone());
+    Ok(loaded)
+}
+
+/// Checks the chain a server sent, its own certificate first, for `host` at
+/// the time `now`, in seconds since the Unix epoch.
+///
+/// A path is built from the server's certificate up to a root of `store`,
+/// through the other certificates of the chain in any order; those that
+/// lead nowhere are ignored, as servers often send more than needed.
+pub(crate) fn verify_chain(
+    chain: &[Certificate],
+    host: &str,
+    store: &TrustStore,
+    now: i64,
+) -> Result<(), CertificateError> {
+    let leaf = chain.first().ok_or(CertificateError::Malformed("empty chain"))?;
+    leaf.check_validity(now)?;
+    if !leaf.matches_host(host) {
+        return Err(CertificateError::HostnameMismatch(host.to_string()));
+    }
+    if !leaf.may_authenticate_server() {
+        return Err(CertificateError::NotForServerAuth);
+    }
+
+    let mut current = leaf;
+    let mut used = vec![false; chain.len()];
+    // Number of intermediate certificates between `current` and the leaf
+    for depth in 0..MAX_CHAIN_LEN {
+        if store.roots.iter().any(|root| root.der() == current.der()) {
+            return Ok(());
+        }
+
+        // What went wrong with the last candidate issuer, if any was found
+        let mut error = CertificateError::UnknownIssuer;
+        // Roots are trusted as they are: only their key and validity matter
+        for root in store.roots.iter().filter(|root| root.subject == current.issuer) {
+            match root.check_validity(now).and_then(|()| current.check_signed_by(root)) {
+                Ok(()) => return Ok(()),
+                Err(e) => error = e,
+            }
+        }
+
+        let issuer = (1..chain.len()).find(|&i| {
+            let candidate = &chain[i];
+            if used[i] || candidate.subject != current.issuer {
+                return false;
+            }
+            let result = if !candidate.may_issue() || candidate.path_len.is_some_and(|len| depth > len as usize) {
+                Err(CertificateError::NotACertificateAuthority)
+            } else {
+                candidate.check_validity(now).and_then(|()| current.check_signed_by(candidate))
+            };
+            result.map_err(|e| error = e).is_ok()
+        });
+        match issuer {
+            Some(i) => {
+                used[i] = true;
+                current = &chain[i];
+            }
+            None => return Err(error),
+        }
+    }
+    Err(CertificateError::UnknownIssuer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testdata::*;
+    use super::*;
+
+    /// 2026-01-01, within the validity of the test certificates.
+    const NOW: i64 = 1767225600;
+
+    fn store() -> TrustStore {
+        let mut store = TrustStore::new();
+        assert_eq!(store.add_pem(ROOT), 1);
+        store
+    }
+
+    fn chain(pems: &[&str]) -> Vec<Certificate> {
+        pems.iter().map(|pem| Certificate::parse(&der(pem)).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_valid_chains() {
+        let store = store();
+        for host in ["localhost", "www.flux.test", "127.0.0.1"] {
+            verify_chain(&chain(&[LEAF, INTERMEDIATE]), host, &store, NOW).unwrap();
+        }
+        // Extra certificates, the root included, are of no harm
+        verify_chain(&chain(&[LEAF, PSS_LEAF, ROOT, INTERMEDIATE]), "localhost", &store, NOW).unwrap();
+        verify_chain(&chain(&[PSS_LEAF]), "pss.flux.test", &store, NOW).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_chains() {
+        let store = store();
+        let check = |pems: &[&str], host: &str, store: &TrustStore| verify_chain(&chain(pems), host, store, NOW);
+        assert_eq!(
+            check(&[LEAF, INTERMEDIATE], "example.com", &store),
+            Err(CertificateError::HostnameMismatch("example.com".to_string()))
+        );
+        assert_eq!(check(&[LEAF], "localhost", &store), Err(CertificateError::UnknownIssuer));
+        assert_eq!(
+            check(&[LEAF, INTERMEDIATE], "localhost", &TrustStore::new()),
+            Err(CertificateError::UnknownIssuer)
+        );
+        assert_eq!(check(&[EXPIRED, INTERMEDIATE], "localhost", &store), Err(CertificateError::Expired));
+        assert_eq!(
+            verify_chain(&chain(&[LEAF, INTERMEDIATE]), "localhost", &store, 4891363201),
+            Err(CertificateError::Expired)
+        );
+        // Signed by a certificate that is not a certificate authority
+        assert_eq!(
+            check(&[MISISSUED, PSS_LEAF], "localhost", &store),
+            Err(CertificateError::NotACertificateAuthority)
+        );
+        // Trusting the intermediate itself is enough
+        let mut intermediate = TrustStore::new();
+        intermediate.add_pem(INTERMEDIATE);
+        verify_chain(&chain(&[LEAF]), "localhost", &intermediate, NOW).unwrap();
+    }
+
+    #[test]
+    fn test_load_bundle() {
+        let path = std::env::temp_dir().join(format!("flux-trust-{}.pem", std::process::id()));
+        fs::write(&path, format!("{}\n{}", ROOT, INTERMEDIATE)).unwrap();
+        assert_eq!(TrustStore::from_pem_file(&path).unwrap().len(), 2);
+        fs::write(&path, "no certificate here").unwrap();
+        assert!(matches!(
+            TrustStore::from_pem_file(&path),
+            Err(NetworkError::TrustStoreUnavailable(_))
+        ));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            TrustStore::from_pem_file(&path),
+            Err(NetworkError::TrustStoreUnavailable(_))
+        ));
+    }
+}
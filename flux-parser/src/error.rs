@@ -1,12 +1,17 @@
 // src/error.rs
 
+use std::fmt;
+
+use crate::html::token::SourceSpan;
+
 #[derive(Debug)]
 pub enum ParserError {
-    UnexpectedToken(String),
-    SyntaxError(String),
+    /// An error that stops parsing: an XML well-formedness error or invalid
+    /// JSON. HTML parse errors never do, they are listed in
+    /// `HtmlDocument::errors` instead.
+    Syntax(ParseError),
     UnsupportedMimeType(String),
     IoError(std::io::Error),
-    Other(String),
 }
 
 impl From<std::io::Error> for ParserError {
@@ -14,3 +19,66 @@ impl From<std::io::Error> for ParserError {
         ParserError::IoError(err)
     }
 }
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParserError::Syntax(error) => write!(f, "{}", error),
+            ParserError::UnsupportedMimeType(mime_type) => write!(f, "unsupported MIME type {}", mime_type),
+            ParserError::IoError(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// How serious a parse error is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The parser recovered and went on, the way browsers do.
+    Error,
+    /// The parser stopped there.
+    Fatal,
+}
+
+/// A problem found in a document, with where it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Machine-readable name of the error. HTML errors use the names of the
+    /// WHATWG specification (`eof-in-tag`, `missing-attribute-value`), and
+    /// those of html5lib for the tree construction errors the specification
+    /// leaves unnamed (`unexpected-end-tag`).
+    pub code: &'static str,
+    pub severity: Severity,
+    /// Where the error is: the character the tokenizer was looking at, or the
+    /// token the tree builder was processing.
+    pub span: SourceSpan,
+    /// A description for humans, with what the code alone does not say, like
+    /// the names of mismatched tags.
+    pub message: Option<String>,
+}
+
+impl ParseError {
+    pub fn new(code: &'static str, severity: Severity, span: SourceSpan) -> Self {
+        ParseError {
+            code,
+            severity,
+            span,
+            message: None,
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    /// Formats the error as `line:column: code (message)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.span.line, self.span.column, self.code)?;
+        if let Some(message) = &self.message {
+            write!(f, " ({})", message)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+//! Namespaces and foreign content.
+//!
+//! Elements inside `<svg>` and `<math>` belong to the SVG and MathML
+//! namespaces and follow the "foreign content" rules of the tree builder
+//! (§13.2.6.5). Since the tokenizer lowercases every name, the camel-cased
+//! names of SVG and MathML are restored from the tables of the specification
+//! (§13.2.6.1, "adjust SVG attributes" and following).
+
+/// The namespace of an element or an attribute.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    #[default]
+    Html,
+    Svg,
+    MathMl,
+    XLink,
+    Xml,
+    Xmlns,
+}
+
+impl Namespace {
+    /// Returns the namespace URL.
+    pub fn url(&self) -> &'static str {
+        match self {
+            Namespace::Html => "http://www.w3.org/1999/xhtml",
+            Namespace::Svg => "http://www.w3.org/2000/svg",
+            Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+            Namespace::XLink => "http://www.w3.org/1999/xlink",
+            Namespace::Xml => "http://www.w3.org/XML/1998/namespace",
+            Namespace::Xmlns => "http://www.w3.org/2000/xmlns/",
+        }
+    }
+}
+
+/// Lowercased SVG element names and their real case.
+const SVG_TAG_NAMES: &[(&str, &str)] = &[
+    ("altglyph", "altGlyph"),
+    ("altglyphdef", "altGlyphDef"),
+    ("altglyphitem", "altGlyphItem"),
+    ("animatecolor", "animateColor"),
+    ("animatemotion", "animateMotion"),
+    ("animatetransform", "animateTransform"),
+    ("clippath", "clipPath"),
+    ("feblend", "feBlend"),
+    ("fecolormatrix", "feColorMatrix"),
+    ("fecomponenttransfer", "feComponentTransfer"),
+    ("fecomposite", "feComposite"),
+    ("feconvolvematrix", "feConvolveMatrix"),
+    ("fediffuselighting", "feDiffuseLighting"),
+    ("fedisplacementmap", "feDisplacementMap"),
+    ("fedistantlight", "feDistantLight"),
+    ("fedropshadow", "feDropShadow"),
+    ("feflood", "feFlood"),
+    ("fefunca", "feFuncA"),
+    ("fefuncb", "feFuncB"),
+    ("fefuncg", "feFuncG"),
+    ("fefuncr", "feFuncR"),
+    ("fegaussianblur", "feGaussianBlur"),
+    ("feimage", "feImage"),
+    ("femerge", "feMerge"),
+    ("femergenode", "feMergeNode"),
+    ("femorphology", "feMorphology"),
+    ("feoffset", "feOffset"),
+    ("fepointlight", "fePointLight"),
+    ("fespecularlighting", "feSpecularLighting"),
+    ("fespotlight", "feSpotLight"),
+    ("fetile", "feTile"),
+    ("feturbulence", "feTurbulence"),
+    ("foreignobject", "foreignObject"),
+    ("glyphref", "glyphRef"),
+    ("lineargradient", "linearGradient"),
+    ("radialgradient", "radialGradient"),
+    ("textpath", "textPath"),
+];
+
+/// Lowercased SVG attribute names and their real case.
+const SVG_ATTRIBUTES: &[(&str, &str)] = &[
+    ("attributename", "attributeName"),
+    ("attributetype", "attributeType"),
+    ("basefrequency", "baseFrequency"),
+    ("baseprofile", "baseProfile"),
+    ("calcmode", "calcMode"),
+    ("clippathunits", "clipPathUnits"),
+    ("diffuseconstant", "diffuseConstant"),
+    ("edgemode", "edgeMode"),
+    ("filterunits", "filterUnits"),
+    ("glyphref", "glyphRef"),
+    ("gradienttransform", "gradientTransform"),
+    ("gradientunits", "gradientUnits"),
+    ("kernelmatrix", "kernelMatrix"),
+    ("kernelunitlength", "kernelUnitLength"),
+    ("keypoints", "keyPoints"),
+    ("keysplines", "keySplines"),
+    ("keytimes", "keyTimes"),
+    ("lengthadjust", "lengthAdjust"),
+    ("limitingconeangle", "limitingConeAngle"),
+    ("markerheight", "markerHeight"),
+    ("markerunits", "markerUnits"),
+    ("markerwidth", "markerWidth"),
+    ("maskcontentunits", "maskContentUnits"),
+    ("maskunits", "maskUnits"),
+    ("numoctaves", "numOctaves"),
+    ("pathlength", "pathLength"),
+    ("patterncontentunits", "patternContentUnits"),
+    ("patterntransform", "patternTransform"),
+    ("patternunits", "patternUnits"),
+    ("pointsatx", "pointsAtX"),
+    ("pointsaty", "pointsAtY"),
+    ("pointsatz", "pointsAtZ"),
+    ("preservealpha", "preserveAlpha"),
+    ("preserveaspectratio", "preserveAspectRatio"),
+    ("primitiveunits", "primitiveUnits"),
+    ("refx", "refX"),
+    ("refy", "refY"),
+    ("repeatcount", "repeatCount"),
+    ("repeatdur", "repeatDur"),
+    ("requiredextensions", "requiredExtensions"),
+    ("requiredfeatures", "requiredFeatures"),
+    ("specularconstant", "specularConstant"),
+    ("specularexponent", "specularExponent"),
+    ("spreadmethod", "spreadMethod"),
+    ("startoffset", "startOffset"),
+    ("stddeviation", "stdDeviation"),
+    ("stitchtiles", "stitchTiles"),
+    ("surfacescale", "surfaceScale"),
+    ("systemlanguage", "systemLanguage"),
+    ("tablevalues", "tableValues"),
+    ("targetx", "targetX"),
+    ("targety", "targetY"),
+    ("textlength", "textLength"),
+    ("viewbox", "viewBox"),
+    ("viewtarget", "viewTarget"),
+    ("xchannelselector", "xChannelSelector"),
+    ("ychannelselector", "yChannelSelector"),
+    ("zoomandpan", "zoomAndPan"),
+];
+
+/// Attributes of foreign elements that live in a namespace of their own.
+const FOREIGN_ATTRIBUTES: &[(&str, Namespace)] = &[
+    ("xlink:actuate", Namespace::XLink),
+    ("xlink:arcrole", Namespace::XLink),
+    ("xlink:href", Namespace::XLink),
+    ("xlink:role", Namespace::XLink),
+    ("xlink:show", Namespace::XLink),
+    ("xlink:title", Namespace::XLink),
+    ("xlink:type", Namespace::XLink),
+    ("xml:lang", Namespace::Xml),
+    ("xml:space", Namespace::Xml),
+    ("xmlns", Namespace::Xmlns),
+    ("xmlns:xlink", Namespace::Xmlns),
+];
+
+/// Start tags that end foreign content: the open SVG or MathML elements are
+/// closed and the tag is processed as HTML.
+pub(crate) const BREAKOUT_ELEMENTS: &[&str] = &[
+    "b", "big", "blockquote", "body", "br", "center", "code", "dd", "div", "dl", "dt", "em",
+    "embed", "h1", "h2", "h3", "h4", "h5", "h6", "head", "hr", "i", "img", "li", "listing",
+    "menu", "meta", "nobr", "ol", "p", "pre", "ruby", "s", "small", "span", "strong", "strike",
+    "sub", "sup", "table", "tt", "u", "ul", "var",
+];
+
+/// Restores the case of an SVG element name.
+pub(crate) fn adjust_svg_tag_name(name: &str) -> &str {
+    SVG_TAG_NAMES
+        .iter()
+        .find(|(lower, _)| *lower == name)
+        .map_or(name, |(_, adjusted)| adjusted)
+}
+
+/// Restores the case of the attribute names of an SVG element.
+pub(crate) fn adjust_svg_attributes(attributes: &mut [(String, String)]) {
+    for (name, _) in attributes {
+        if let Some((_, adjusted)) = SVG_ATTRIBUTES.iter().find(|(lower, _)| lower == name) {
+            *name = adjusted.to_string();
+        }
+    }
+}
+
+/// Restores the case of the attribute names of a MathML element.
+pub(crate) fn adjust_mathml_attributes(attributes: &mut [(String, String)]) {
+    for (name, _) in attributes {
+        if name == "definitionurl" {
+            *name = "definitionURL".to_string();
+        }
+    }
+}
+
+/// Returns the namespace of an attribute of a foreign element, from its
+/// qualified name (`xlink:href`). Attributes of HTML elements, and most
+/// attributes of foreign elements, have none.
+pub(crate) fn foreign_attribute_namespace(name: &str) -> Option<Namespace> {
+    FOREIGN_ATTRIBUTES
+        .iter()
+        .find(|(qualified_name, _)| *qualified_name == name)
+        .map(|(_, namespace)| *namespace)
+}
+
+/// MathML text integration points hold text and HTML phrasing content.
+pub(crate) fn is_mathml_text_integration_point(namespace: Namespace, name: &str) -> bool {
+    namespace == Namespace::MathMl && matches!(name, "mi" | "mo" | "mn" | "ms" | "mtext")
+}
+
+/// HTML integration points hold HTML content inside SVG or MathML.
+pub(crate) fn is_html_integration_point(
+    namespace: Namespace,
+    name: &str,
+    attributes: &[(String, String)],
+) -> bool {
+    match namespace {
+        Namespace::MathMl => {
+            name == "annotation-xml"
+                && attributes.iter().any(|(key, value)| {
+                    key == "encoding"
+                        && (value.eq_ignore_ascii_case("text/html")
+                            || value.eq_ignore_ascii_case("application/xhtml+xml"))
+                })
+        }
+        Namespace::Svg => matches!(name, "foreignObject" | "desc" | "title"),
+        _ => false,
+    }
+}
+
+/// The foreign elements that are "special" and bound the default scopes.
+pub(crate) fn is_special_foreign(namespace: Namespace, name: &str) -> bool {
+    match namespace {
+        Namespace::MathMl => {
+            matches!(name, "mi" | "mo" | "mn" | "ms" | "mtext" | "annotation-xml")
+        }
+        Namespace::Svg => matches!(name, "foreignObject" | "desc" | "title"),
+        _ => false,
+    }
+}
@@ -1,18 +1,28 @@
 use crate::error::ParserError;
-pub use crate::html::parser::HtmlDocument;
-use crate::html::token::tokenize;
+pub use crate::html::parser::{HtmlDocument, HtmlFragment};
+use crate::html::parser::HtmlParser;
 
+pub(crate) mod entities;
+mod foreign;
 pub mod parser;
-mod token;
+pub mod serializer;
+mod stream;
+pub mod token;
 
-pub fn parse_html(input: &str) -> Result<HtmlDocument, ParserError> {
-    // 1. Tokenize the input
-    let tokens = tokenize(input)?;
+pub use crate::html::foreign::Namespace;
+pub use crate::html::stream::StreamingParser;
 
-    // 2. Parse the tokens into a DOM tree
-    let mut parser = crate::html::parser::HtmlParser::new(tokens);
-    let nodes = parser.parse_nodes()?;
+/// Parses a whole HTML document.
+///
+/// Parsing never fails on malformed markup: the parser recovers the way
+/// browsers do and records the problems in `HtmlDocument::errors`.
+pub fn parse_html(input: &str) -> Result<HtmlDocument, ParserError> {
+    Ok(HtmlParser::new(input).parse())
+}
 
-    // 3. Renvoie un HtmlDocument
-    Ok(HtmlDocument { root_nodes: nodes })
+/// Parses `input` as the content of a `context_tag` element, the way
+/// `innerHTML` does: `<td>x</td>` parsed in the context of a `<tr>` gives a
+/// cell, where a whole document would drop the stray tags.
+pub fn parse_fragment(input: &str, context_tag: &str) -> Result<HtmlFragment, ParserError> {
+    Ok(HtmlParser::new_fragment(input, &context_tag.to_ascii_lowercase()).parse_fragment())
 }
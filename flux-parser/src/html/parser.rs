@@ -1,182 +1,2919 @@
-/// html_parser.rs
+//! html_parser.rs
+//!
+//! Tree construction stage of the WHATWG HTML parser (§13.2.6). Tokens coming
+//! out of the tokenizer are dispatched according to the current insertion
+//! mode; the parser keeps a stack of open elements and a list of active
+//! formatting elements, and recovers from every kind of misnested markup
+//! instead of failing.
 
-use crate::error::ParserError;
-use crate::html::token::Token;
+use crate::error::{ParseError, Severity};
+use crate::html::foreign::{
+    adjust_mathml_attributes, adjust_svg_attributes, adjust_svg_tag_name, foreign_attribute_namespace,
+    is_html_integration_point, is_mathml_text_integration_point, is_special_foreign, Namespace,
+    BREAKOUT_ELEMENTS,
+};
+use crate::html::token::{raw_text_state, Doctype, SourceSpan, State, Token, Tokenizer};
 
+#[derive(Debug)]
+pub struct HtmlDocument {
+    pub root_nodes: Vec<HtmlNode>,
+    /// Whether the page is rendered in quirks mode, as decided by its DOCTYPE.
+    pub mode: DocumentMode,
+    /// Parse errors found while building the tree. They never stop parsing.
+    pub errors: Vec<ParseError>,
+}
+
+/// The rendering mode of a document, picked from its DOCTYPE.
+///
+/// Documents without a DOCTYPE, or with one of the legacy DOCTYPEs of the
+/// specification's list, are in quirks mode and get the historical layout
+/// behaviours of old browsers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocumentMode {
+    #[default]
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+impl HtmlDocument {
+    /// Returns the root `<html>` element.
+    pub fn document_element(&self) -> Option<&HtmlElement> {
+        self.root_nodes.iter().find_map(|node| match node {
+            HtmlNode::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    /// Returns the `<head>` element.
+    pub fn head(&self) -> Option<&HtmlElement> {
+        self.document_element()?.child_element("head")
+    }
+
+    /// Returns the `<body>` element (or the `<frameset>` of a frameset page).
+    pub fn body(&self) -> Option<&HtmlElement> {
+        let html = self.document_element()?;
+        html.child_element("body").or_else(|| html.child_element("frameset"))
+    }
+}
+
+#[derive(Debug)]
+pub enum HtmlNode {
+    Element(HtmlElement),
+    Text { text: String, span: SourceSpan },
+    Comment { text: String, span: SourceSpan },
+    /// The `<!DOCTYPE>` of the document. A missing name is empty; missing
+    /// identifiers are `None`, unlike empty ones (`PUBLIC ""`).
+    Doctype {
+        name: String,
+        public_id: Option<String>,
+        system_id: Option<String>,
+        span: SourceSpan,
+    },
+}
+
+/// The result of parsing a fragment: the nodes that would become the children
+/// of the context element.
+#[derive(Debug)]
+pub struct HtmlFragment {
+    pub nodes: Vec<HtmlNode>,
+    /// Parse errors found while building the nodes.
+    pub errors: Vec<ParseError>,
+}
+
+impl HtmlNode {
+    /// Returns where the node comes from in the source. Elements the parser
+    /// implied (a missing `<body>`, say) have an empty span where they were
+    /// inserted.
+    pub fn span(&self) -> SourceSpan {
+        match self {
+            HtmlNode::Element(element) => element.span,
+            HtmlNode::Text { span, .. }
+            | HtmlNode::Comment { span, .. }
+            | HtmlNode::Doctype { span, .. } => *span,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HtmlElement {
+    /// The name of the element, lowercase for HTML elements but camel-cased
+    /// for some SVG elements (`foreignObject`).
+    pub tag_name: String,
+    pub namespace: Namespace,
+    /// Attributes by qualified name (`xlink:href`).
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<HtmlNode>,
+    /// The content of a `<template>`, kept apart from its children: it is an
+    /// inert document fragment, not rendered with the rest of the page.
+    /// `None` for every other element.
+    pub template_contents: Option<Vec<HtmlNode>>,
+    /// The span of the start tag.
+    pub span: SourceSpan,
+}
+
+impl HtmlElement {
+    /// Returns the value of an attribute. On HTML elements, whose attribute
+    /// names are lowercase, `name` is matched ASCII case-insensitively, like
+    /// the DOM's `getAttribute`: `<A HREF=x>` has an `href`.
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        let index = self.attribute_index(name)?;
+        Some(&self.attributes[index].1)
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attribute_index(name).is_some()
+    }
+
+    /// Sets an attribute, replacing its value if it is already there. The
+    /// name is lowercased on HTML elements.
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        match self.attribute_index(name) {
+            Some(index) => self.attributes[index].1 = value.to_string(),
+            None => {
+                let name = match self.namespace {
+                    Namespace::Html => name.to_ascii_lowercase(),
+                    _ => name.to_string(),
+                };
+                self.attributes.push((name, value.to_string()));
+            }
+        }
+    }
+
+    /// Removes an attribute and returns its value.
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        let index = self.attribute_index(name)?;
+        Some(self.attributes.remove(index).1)
+    }
+
+    /// Returns the classes of the `class` attribute, without duplicates.
+    pub fn class_list(&self) -> Vec<&str> {
+        let mut classes = Vec::new();
+        for class in self.get_attribute("class").unwrap_or("").split_ascii_whitespace() {
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+        classes
+    }
+
+    /// Returns the `id` attribute, unless it is empty: an empty `id` does not
+    /// identify the element.
+    pub fn id(&self) -> Option<&str> {
+        self.get_attribute("id").filter(|id| !id.is_empty())
+    }
+
+    fn attribute_index(&self, name: &str) -> Option<usize> {
+        self.attributes.iter().position(|(key, _)| match self.namespace {
+            Namespace::Html => key.eq_ignore_ascii_case(name),
+            _ => key == name,
+        })
+    }
+
+    /// Returns the namespace of an attribute, given by its qualified name.
+    /// Only a few attributes of SVG and MathML elements, like `xlink:href`,
+    /// have one.
+    pub fn attribute_namespace(&self, name: &str) -> Option<Namespace> {
+        if self.namespace == Namespace::Html {
+            return None;
+        }
+        foreign_attribute_namespace(name)
+    }
+
+    /// Returns the concatenated text of all descendant text nodes. For raw text
+    /// elements (`<script>`, `<style>`, `<textarea>`, `<title>`) this is their
+    /// whole content.
+    pub fn text_content(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                HtmlNode::Element(element) => text.push_str(&element.text_content()),
+                HtmlNode::Text { text: content, .. } => text.push_str(content),
+                HtmlNode::Comment { .. } | HtmlNode::Doctype { .. } => {}
+            }
+        }
+        text
+    }
+
+    /// Returns the first child element with the given tag name.
+    pub fn child_element(&self, tag_name: &str) -> Option<&HtmlElement> {
+        self.children.iter().find_map(|node| match node {
+            HtmlNode::Element(element) if element.tag_name == tag_name => Some(element),
+            _ => None,
+        })
+    }
+}
+
+pub(crate) const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img",
+    "input", "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements with special parsing rules ("special" category of the spec).
+const SPECIAL_ELEMENTS: &[&str] = &[
+    "address", "applet", "area", "article", "aside", "base", "basefont", "bgsound",
+    "blockquote", "body", "br", "button", "caption", "center", "col", "colgroup", "dd",
+    "details", "dir", "div", "dl", "dt", "embed", "fieldset", "figcaption", "figure",
+    "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5", "h6", "head",
+    "header", "hgroup", "hr", "html", "iframe", "img", "input", "keygen", "li", "link",
+    "listing", "main", "marquee", "menu", "meta", "nav", "noembed", "noframes",
+    "noscript", "object", "ol", "p", "param", "plaintext", "pre", "script", "search",
+    "section", "select", "source", "style", "summary", "table", "tbody", "td",
+    "template", "textarea", "tfoot", "th", "thead", "title", "tr", "track", "ul", "wbr",
+    "xmp",
+];
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+/// Elements whose end tag may be omitted ("generate implied end tags").
+const IMPLIED_END_TAGS: &[&str] = &[
+    "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc",
+];
+
+const IMPLIED_END_TAGS_THOROUGHLY: &[&str] = &[
+    "caption", "colgroup", "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt",
+    "rtc", "tbody", "td", "tfoot", "th", "thead", "tr",
+];
+
+const DEFAULT_SCOPE: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+const LIST_ITEM_SCOPE: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template", "ol",
+    "ul",
+];
+
+const BUTTON_SCOPE: &[&str] = &[
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+    "button",
+];
+
+const TABLE_SCOPE: &[&str] = &["html", "table", "template"];
+
+/// Public identifiers that put a document in quirks mode when the DOCTYPE's
+/// public identifier starts with them (compared ASCII case-insensitively).
+const QUIRKS_PUBLIC_ID_PREFIXES: &[&str] = &[
+    "+//Silmaril//dtd html Pro v0r11 19970101//",
+    "-//AS//DTD HTML 3.0 asWedit + extensions//",
+    "-//AdvaSoft Ltd//DTD HTML 3.0 asWedit + extensions//",
+    "-//IETF//DTD HTML 2.0 Level 1//",
+    "-//IETF//DTD HTML 2.0 Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict Level 1//",
+    "-//IETF//DTD HTML 2.0 Strict Level 2//",
+    "-//IETF//DTD HTML 2.0 Strict//",
+    "-//IETF//DTD HTML 2.0//",
+    "-//IETF//DTD HTML 2.1E//",
+    "-//IETF//DTD HTML 3.0//",
+    "-//IETF//DTD HTML 3.2 Final//",
+    "-//IETF//DTD HTML 3.2//",
+    "-//IETF//DTD HTML 3//",
+    "-//IETF//DTD HTML Level 0//",
+    "-//IETF//DTD HTML Level 1//",
+    "-//IETF//DTD HTML Level 2//",
+    "-//IETF//DTD HTML Level 3//",
+    "-//IETF//DTD HTML Strict Level 0//",
+    "-//IETF//DTD HTML Strict Level 1//",
+    "-//IETF//DTD HTML Strict Level 2//",
+    "-//IETF//DTD HTML Strict Level 3//",
+    "-//IETF//DTD HTML Strict//",
+    "-//IETF//DTD HTML//",
+    "-//Metrius//DTD Metrius Presentational//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 2.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 2.0 Tables//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML Strict//",
+    "-//Microsoft//DTD Internet Explorer 3.0 HTML//",
+    "-//Microsoft//DTD Internet Explorer 3.0 Tables//",
+    "-//Netscape Comm. Corp.//DTD HTML//",
+    "-//Netscape Comm. Corp.//DTD Strict HTML//",
+    "-//O'Reilly and Associates//DTD HTML 2.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended 1.0//",
+    "-//O'Reilly and Associates//DTD HTML Extended Relaxed 1.0//",
+    "-//SQ//DTD HTML 2.0 HoTMetaL + extensions//",
+    "-//SoftQuad Software//DTD HoTMetaL PRO 6.0::19990601::extensions to HTML 4.0//",
+    "-//SoftQuad//DTD HoTMetaL PRO 4.0::19971010::extensions to HTML 4.0//",
+    "-//Spyglass//DTD HTML 2.0 Extended//",
+    "-//Sun Microsystems Corp.//DTD HotJava HTML//",
+    "-//Sun Microsystems Corp.//DTD HotJava Strict HTML//",
+    "-//W3C//DTD HTML 3 1995-03-24//",
+    "-//W3C//DTD HTML 3.2 Draft//",
+    "-//W3C//DTD HTML 3.2 Final//",
+    "-//W3C//DTD HTML 3.2//",
+    "-//W3C//DTD HTML 3.2S Draft//",
+    "-//W3C//DTD HTML 4.0 Frameset//",
+    "-//W3C//DTD HTML 4.0 Transitional//",
+    "-//W3C//DTD HTML Experimental 19960712//",
+    "-//W3C//DTD HTML Experimental 970421//",
+    "-//W3C//DTD W3 HTML//",
+    "-//W3O//DTD W3 HTML 3.0//",
+    "-//WebTechs//DTD Mozilla HTML 2.0//",
+    "-//WebTechs//DTD Mozilla HTML//",
+];
+
+/// Public identifiers that put a document in quirks mode.
+const QUIRKS_PUBLIC_IDS: &[&str] = &[
+    "-//W3O//DTD W3 HTML Strict 3.0//EN//",
+    "-/W3C/DTD HTML 4.0 Transitional/EN",
+    "HTML",
+];
+
+/// HTML 4.01 public identifier prefixes: quirks mode without a system
+/// identifier, limited-quirks mode with one.
+const HTML401_PUBLIC_ID_PREFIXES: &[&str] = &[
+    "-//W3C//DTD HTML 4.01 Frameset//",
+    "-//W3C//DTD HTML 4.01 Transitional//",
+];
+
+/// Public identifier prefixes that put a document in limited-quirks mode.
+const LIMITED_QUIRKS_PUBLIC_ID_PREFIXES: &[&str] = &[
+    "-//W3C//DTD XHTML 1.0 Frameset//",
+    "-//W3C//DTD XHTML 1.0 Transitional//",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertionMode {
+    Initial,
+    BeforeHtml,
+    BeforeHead,
+    InHead,
+    InHeadNoscript,
+    AfterHead,
+    InBody,
+    Text,
+    InTable,
+    InTableText,
+    InCaption,
+    InColumnGroup,
+    InTableBody,
+    InRow,
+    InCell,
+    InSelect,
+    InSelectInTable,
+    InTemplate,
+    AfterBody,
+    InFrameset,
+    AfterFrameset,
+    AfterAfterBody,
+    AfterAfterFrameset,
+}
+
+/// Scope used by the "has an element in scope" family of checks.
+#[derive(Clone, Copy)]
+enum Scope {
+    Default,
+    ListItem,
+    Button,
+    Table,
+    Select,
+}
+
+/// A node of the tree under construction. The tree is kept in an arena so
+/// that nodes can be moved around (adoption agency, foster parenting) before
+/// being turned into the owned `HtmlNode` tree.
+#[derive(Debug)]
+struct Node {
+    data: NodeData,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    /// The fragment holding the content of a `<template>` element.
+    template_contents: Option<usize>,
+    span: SourceSpan,
+}
+
+#[derive(Debug)]
+enum NodeData {
+    Document,
+    Element {
+        namespace: Namespace,
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    Text(String),
+    Comment(String),
+    Doctype {
+        name: String,
+        public_id: Option<String>,
+        system_id: Option<String>,
+    },
+    /// The root of the contents of a template, outside of the tree.
+    Fragment,
+}
+
+/// An entry of the list of active formatting elements. Elements remember the
+/// token that created them so they can be recreated when reconstructing.
+#[derive(Debug, Clone)]
+enum FormattingEntry {
+    Marker,
+    Element {
+        node: usize,
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+}
+
+/// The namespace, name and attributes of an element.
+type ElementInfo<'a> = (Namespace, &'a str, &'a [(String, String)]);
+
+/// Where a new node goes: appended to `parent`, or inserted before `before`.
+struct InsertionPoint {
+    parent: usize,
+    before: Option<usize>,
+}
+
+/// A small struct to hold state for our parser
+pub(crate) struct HtmlParser {
+    tokenizer: Tokenizer,
+    nodes: Vec<Node>,
+    mode: InsertionMode,
+    original_mode: InsertionMode,
+    template_modes: Vec<InsertionMode>,
+    open_elements: Vec<usize>,
+    active_formatting: Vec<FormattingEntry>,
+    head: Option<usize>,
+    form: Option<usize>,
+    document_mode: DocumentMode,
+    frameset_ok: bool,
+    foster_parenting: bool,
+    /// Set after `<pre>`, `<listing>` and `<textarea>`: a newline right after
+    /// the start tag is not part of the content.
+    skip_next_newline: bool,
+    pending_table_text: Vec<(String, SourceSpan)>,
+    stopped: bool,
+    /// Span of the token, or part of a text token, being processed.
+    current_span: SourceSpan,
+    /// Name of the start tag being processed, to tell the elements it creates
+    /// from the ones the parser implies.
+    current_tag: Option<String>,
+    /// Tag name of the context element when parsing a fragment.
+    context: Option<String>,
+    /// Namespace of the context element: `<svg>` and `<math>` contexts are
+    /// taken as SVG and MathML elements.
+    context_namespace: Namespace,
+    errors: Vec<ParseError>,
+}
+
+/// The document node always lives at index 0 of the arena.
+const DOCUMENT: usize = 0;
+
+/// Depth of the stack of open elements beyond which nodes are no longer
+/// nested, so that hostile documents cannot make a tree deep enough to
+/// overflow the call stack of whatever walks it. Browsers have a similar
+/// limit (512 in Blink).
+const MAX_DEPTH: usize = 512;
+
+impl HtmlParser {
+    pub(crate) fn new(input: &str) -> Self {
+        HtmlParser {
+            tokenizer: Tokenizer::new(input),
+            nodes: vec![Node {
+                data: NodeData::Document,
+                parent: None,
+                children: Vec::new(),
+                template_contents: None,
+                span: SourceSpan::default(),
+            }],
+            mode: InsertionMode::Initial,
+            original_mode: InsertionMode::Initial,
+            template_modes: Vec::new(),
+            open_elements: Vec::new(),
+            active_formatting: Vec::new(),
+            head: None,
+            form: None,
+            document_mode: DocumentMode::NoQuirks,
+            frameset_ok: true,
+            foster_parenting: false,
+            skip_next_newline: false,
+            pending_table_text: Vec::new(),
+            stopped: false,
+            current_span: SourceSpan::default(),
+            current_tag: None,
+            context: None,
+            context_namespace: Namespace::Html,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Creates a parser for the HTML fragment parsing algorithm (§13.4): the
+    /// input is parsed as the content of a `context` element.
+    ///
+    /// Only the tag name of the context is known, so there is no form element
+    /// pointer and the fragment is parsed in no-quirks mode.
+    pub(crate) fn new_fragment(input: &str, context: &str) -> Self {
+        let mut parser = HtmlParser::new(input);
+        if let Some(state) = raw_text_state(context).filter(|_| !matches!(context, "svg" | "math")) {
+            parser.tokenizer.set_state(state);
+            parser.tokenizer.set_last_start_tag(context);
+        }
+        parser.context = Some(context.to_string());
+        parser.context_namespace = match context {
+            "svg" => Namespace::Svg,
+            "math" => Namespace::MathMl,
+            _ => Namespace::Html,
+        };
+
+        let html = parser.create_element("html", Vec::new());
+        parser.append_child(DOCUMENT, html);
+        parser.open_elements.push(html);
+        if context == "template" {
+            parser.template_modes.push(InsertionMode::InTemplate);
+        }
+        parser.reset_insertion_mode();
+        parser
+    }
+
+    /// Creates a parser whose input is given in chunks with `push_str`.
+    pub(crate) fn streaming() -> Self {
+        HtmlParser {
+            tokenizer: Tokenizer::streaming(),
+            ..HtmlParser::new("")
+        }
+    }
+
+    /// Appends a chunk of input to a streaming parser and builds the tree as
+    /// far as the tokens received allow.
+    pub(crate) fn push_str(&mut self, text: &str) {
+        self.tokenizer.push_str(text);
+        while !self.stopped {
+            let Some(token) = self.next_token() else {
+                break;
+            };
+            self.process_token(token);
+        }
+    }
+
+    /// Ends the input of a streaming parser and returns the document.
+    pub(crate) fn finish(mut self) -> HtmlDocument {
+        self.tokenizer.finish();
+        self.parse()
+    }
+
+    /// Returns a copy of the tree built so far. Parse errors are only handed
+    /// over by `finish`.
+    pub(crate) fn snapshot(&self) -> HtmlDocument {
+        HtmlDocument {
+            root_nodes: self.build_children(DOCUMENT),
+            mode: self.document_mode,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Runs the tokenizer and the tree builder until the end of the input.
+    pub(crate) fn parse(mut self) -> HtmlDocument {
+        let errors = self.run();
+        HtmlDocument {
+            root_nodes: self.build_children(DOCUMENT),
+            mode: self.document_mode,
+            errors,
+        }
+    }
+
+    /// Same as `parse`, for a parser created with `new_fragment`. The nodes
+    /// are the children of the root `<html>` element.
+    pub(crate) fn parse_fragment(mut self) -> HtmlFragment {
+        let errors = self.run();
+        let html = self.nodes[DOCUMENT].children[0];
+        HtmlFragment {
+            nodes: self.build_children(html),
+            errors,
+        }
+    }
+
+    /// Feeds every token to the tree builder and returns the parse errors.
+    fn run(&mut self) -> Vec<ParseError> {
+        while !self.stopped {
+            let Some(token) = self.next_token() else {
+                break;
+            };
+            self.process_token(token);
+        }
+
+        let mut errors = self.tokenizer.take_errors();
+        errors.append(&mut self.errors);
+        // In source order, the tokenizer's errors first when they are at the
+        // same place
+        errors.sort_by_key(|error| error.span.start);
+        errors
+    }
+
+    /// Converts the children of an arena node into owned `HtmlNode`s.
+    fn build_children(&self, id: usize) -> Vec<HtmlNode> {
+        self.nodes[id]
+            .children
+            .iter()
+            .map(|&child| match &self.nodes[child].data {
+                NodeData::Element {
+                    namespace,
+                    name,
+                    attributes,
+                } => HtmlNode::Element(HtmlElement {
+                    tag_name: name.clone(),
+                    namespace: *namespace,
+                    attributes: attributes.clone(),
+                    children: self.build_children(child),
+                    template_contents: self.nodes[child]
+                        .template_contents
+                        .map(|contents| self.build_children(contents)),
+                    span: self.nodes[child].span,
+                }),
+                NodeData::Text(text) => HtmlNode::Text {
+                    text: text.clone(),
+                    span: self.nodes[child].span,
+                },
+                NodeData::Comment(text) => HtmlNode::Comment {
+                    text: text.clone(),
+                    span: self.nodes[child].span,
+                },
+                NodeData::Doctype { name, public_id, system_id } => HtmlNode::Doctype {
+                    name: name.clone(),
+                    public_id: public_id.clone(),
+                    system_id: system_id.clone(),
+                    span: self.nodes[child].span,
+                },
+                NodeData::Document | NodeData::Fragment => {
+                    unreachable!("documents and fragments are never children")
+                }
+            })
+            .collect()
+    }
+
+    /// Pulls the next token. CDATA sections are only recognized in foreign
+    /// content, so the tokenizer is told about it first.
+    fn next_token(&mut self) -> Option<Token> {
+        let foreign = self
+            .adjusted_current_element()
+            .is_some_and(|(namespace, ..)| namespace != Namespace::Html);
+        self.tokenizer.set_allow_cdata(foreign);
+        self.tokenizer.try_next_token()
+    }
+
+    fn process_token(&mut self, token: Token) {
+        let token = match token {
+            Token::Text(text) => {
+                self.current_tag = None;
+                let span = self.tokenizer.token_span();
+                let verbatim = self.tokenizer.is_verbatim(&text);
+                let mut text = text.as_str();
+                let mut position = 0;
+                if std::mem::take(&mut self.skip_next_newline) && text.starts_with('\n') {
+                    text = &text[1..];
+                    position = 1;
+                }
+                // Whitespace, NULs and other characters are treated differently
+                // by most insertion modes, so text is handed over in runs of a
+                // single kind.
+                for run in split_text_runs(text) {
+                    let length = run.chars().count();
+                    self.current_span = if verbatim {
+                        self.tokenizer.token_subspan(position..position + length)
+                    } else {
+                        span
+                    };
+                    position += length;
+                    self.process(Token::Text(run.to_string()));
+                }
+                return;
+            }
+            token => token,
+        };
+        self.skip_next_newline = false;
+        self.current_span = self.tokenizer.token_span();
+        self.current_tag = match &token {
+            Token::StartTag { name, .. } => Some(name.clone()),
+            _ => None,
+        };
+        self.process(token);
+    }
+
+    /// The tree construction dispatcher: tokens go to the current insertion
+    /// mode, or to the foreign content rules inside SVG and MathML.
+    fn process(&mut self, token: Token) {
+        if self.is_foreign_content(&token) {
+            self.foreign_content(token);
+        } else {
+            self.process_in(self.mode, token);
+        }
+    }
+
+    fn is_foreign_content(&self, token: &Token) -> bool {
+        let Some((namespace, name, attributes)) = self.adjusted_current_element() else {
+            return false;
+        };
+        if namespace == Namespace::Html || *token == Token::Eof {
+            return false;
+        }
+        let start_tag = match token {
+            Token::StartTag { name, .. } => Some(name.as_str()),
+            _ => None,
+        };
+        let text = matches!(token, Token::Text(_));
+        if is_mathml_text_integration_point(namespace, name)
+            && (text || start_tag.is_some_and(|tag| tag != "mglyph" && tag != "malignmark"))
+        {
+            return false;
+        }
+        if namespace == Namespace::MathMl && name == "annotation-xml" && start_tag == Some("svg") {
+            return false;
+        }
+        if is_html_integration_point(namespace, name, attributes) && (text || start_tag.is_some()) {
+            return false;
+        }
+        true
+    }
+
+    /// Processes the token using the rules of `mode`. The current insertion
+    /// mode is left untouched unless the rules themselves switch it.
+    fn process_using(&mut self, mode: InsertionMode, token: Token) {
+        self.process_in(mode, token);
+    }
+
+    /// Switches to `mode` and processes the token again.
+    fn reprocess(&mut self, mode: InsertionMode, token: Token) {
+        self.mode = mode;
+        self.process(token);
+    }
+
+    fn process_in(&mut self, mode: InsertionMode, token: Token) {
+        match mode {
+            InsertionMode::Initial => self.initial(token),
+            InsertionMode::BeforeHtml => self.before_html(token),
+            InsertionMode::BeforeHead => self.before_head(token),
+            InsertionMode::InHead => self.in_head(token),
+            InsertionMode::InHeadNoscript => self.in_head_noscript(token),
+            InsertionMode::AfterHead => self.after_head(token),
+            InsertionMode::InBody => self.in_body(token),
+            InsertionMode::Text => self.text(token),
+            InsertionMode::InTable => self.in_table(token),
+            InsertionMode::InTableText => self.in_table_text(token),
+            InsertionMode::InCaption => self.in_caption(token),
+            InsertionMode::InColumnGroup => self.in_column_group(token),
+            InsertionMode::InTableBody => self.in_table_body(token),
+            InsertionMode::InRow => self.in_row(token),
+            InsertionMode::InCell => self.in_cell(token),
+            InsertionMode::InSelect => self.in_select(token),
+            InsertionMode::InSelectInTable => self.in_select_in_table(token),
+            InsertionMode::InTemplate => self.in_template(token),
+            InsertionMode::AfterBody => self.after_body(token),
+            InsertionMode::InFrameset => self.in_frameset(token),
+            InsertionMode::AfterFrameset => self.after_frameset(token),
+            InsertionMode::AfterAfterBody => self.after_after_body(token),
+            InsertionMode::AfterAfterFrameset => self.after_after_frameset(token),
+        }
+    }
+
+    /// Records a parse error at the token being processed.
+    fn error(&mut self, code: &'static str) {
+        self.errors.push(ParseError::new(code, Severity::Error, self.current_span));
+    }
+
+    fn initial(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => {}
+            Token::Comment(text) => self.insert_comment_in(text, DOCUMENT),
+            Token::Doctype(doctype) => {
+                let name = doctype.name.as_deref();
+                let system_id = doctype.system_id.as_deref();
+                if name != Some("html")
+                    || doctype.public_id.is_some()
+                    || system_id.is_some_and(|id| id != "about:legacy-compat")
+                {
+                    self.error("unknown-doctype");
+                }
+                self.document_mode = document_mode_of(&doctype);
+                let node = self.new_node(NodeData::Doctype {
+                    name: doctype.name.unwrap_or_default(),
+                    public_id: doctype.public_id,
+                    system_id: doctype.system_id,
+                });
+                self.append_child(DOCUMENT, node);
+                self.mode = InsertionMode::BeforeHtml;
+            }
+            token => {
+                self.error("expected-doctype-but-got-other");
+                self.document_mode = DocumentMode::Quirks;
+                self.reprocess(InsertionMode::BeforeHtml, token);
+            }
+        }
+    }
+
+    fn before_html(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::Comment(text) => self.insert_comment_in(text, DOCUMENT),
+            Token::Text(ref text) if is_whitespace(text) => {}
+            Token::StartTag { ref name, ref attributes, .. } if name == "html" => {
+                let html = self.create_element(name, attributes.clone());
+                self.append_child(DOCUMENT, html);
+                self.open_elements.push(html);
+                self.mode = InsertionMode::BeforeHead;
+            }
+            Token::EndTag(ref name) if !matches!(name.as_str(), "head" | "body" | "html" | "br") => {
+                self.error("unexpected-end-tag-before-html");
+            }
+            token => {
+                let html = self.create_element("html", Vec::new());
+                self.append_child(DOCUMENT, html);
+                self.open_elements.push(html);
+                self.reprocess(InsertionMode::BeforeHead, token);
+            }
+        }
+    }
+
+    fn before_head(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => {}
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } if name == "html" => self.process_using(InsertionMode::InBody, token),
+            Token::StartTag { ref name, .. } if name == "head" => {
+                let head = self.insert_html_element(&token);
+                self.head = Some(head);
+                self.mode = InsertionMode::InHead;
+            }
+            Token::EndTag(ref name) if !matches!(name.as_str(), "head" | "body" | "html" | "br") => {
+                self.error("unexpected-end-tag-before-head");
+            }
+            token => {
+                let head = self.insert_html_element(&start_tag("head"));
+                self.head = Some(head);
+                self.reprocess(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    fn in_head(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.insert_text(text),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } => match name.as_str() {
+                "html" => self.process_using(InsertionMode::InBody, token),
+                "base" | "basefont" | "bgsound" | "link" | "meta" => {
+                    self.insert_void_element(&token);
+                }
+                "title" => self.parse_raw_text(&token, State::Rcdata),
+                "noscript" => {
+                    // Scripting is disabled: the content is parsed as markup.
+                    self.insert_html_element(&token);
+                    self.mode = InsertionMode::InHeadNoscript;
+                }
+                "noframes" | "style" => self.parse_raw_text(&token, State::Rawtext),
+                "script" => self.parse_raw_text(&token, State::ScriptData),
+                "template" => {
+                    self.insert_html_element(&token);
+                    self.active_formatting.push(FormattingEntry::Marker);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InTemplate;
+                    self.template_modes.push(InsertionMode::InTemplate);
+                }
+                "head" => self.error("unexpected-start-tag-head"),
+                _ => self.in_head_anything_else(token),
+            },
+            Token::EndTag(ref name) => match name.as_str() {
+                "head" => {
+                    self.open_elements.pop();
+                    self.mode = InsertionMode::AfterHead;
+                }
+                "body" | "html" | "br" => self.in_head_anything_else(token),
+                "template" => self.end_template(),
+                _ => self.error("unexpected-end-tag"),
+            },
+            token => self.in_head_anything_else(token),
+        }
+    }
+
+    fn in_head_anything_else(&mut self, token: Token) {
+        self.open_elements.pop();
+        self.reprocess(InsertionMode::AfterHead, token);
+    }
+
+    fn end_template(&mut self) {
+        if !self.is_on_stack("template") {
+            self.error("unexpected-end-tag-template");
+            return;
+        }
+        self.generate_implied_end_tags_thoroughly();
+        if !self.current_is("template") {
+            self.error("end-tag-too-early");
+        }
+        self.pop_until_one_of(&["template"]);
+        self.clear_formatting_to_last_marker();
+        self.template_modes.pop();
+        self.reset_insertion_mode();
+    }
+
+    fn in_head_noscript(&mut self, token: Token) {
+        match token {
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } if name == "html" => self.process_using(InsertionMode::InBody, token),
+            Token::EndTag(ref name) if name == "noscript" => {
+                self.open_elements.pop();
+                self.mode = InsertionMode::InHead;
+            }
+            Token::Text(ref text) if is_whitespace(text) => self.process_using(InsertionMode::InHead, token),
+            Token::Comment(_) => self.process_using(InsertionMode::InHead, token),
+            Token::StartTag { ref name, .. }
+                if matches!(name.as_str(), "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style") =>
+            {
+                self.process_using(InsertionMode::InHead, token)
+            }
+            Token::StartTag { ref name, .. } if matches!(name.as_str(), "head" | "noscript") => {
+                self.error("unexpected-start-tag");
+            }
+            Token::EndTag(ref name) if name != "br" => self.error("unexpected-end-tag"),
+            token => {
+                self.error("unexpected-token-in-head-noscript");
+                self.open_elements.pop();
+                self.reprocess(InsertionMode::InHead, token);
+            }
+        }
+    }
+
+    fn after_head(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.insert_text(text),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } => match name.as_str() {
+                "html" => self.process_using(InsertionMode::InBody, token),
+                "body" => {
+                    self.insert_html_element(&token);
+                    self.frameset_ok = false;
+                    self.mode = InsertionMode::InBody;
+                }
+                "frameset" => {
+                    self.insert_html_element(&token);
+                    self.mode = InsertionMode::InFrameset;
+                }
+                "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                | "style" | "template" | "title" => {
+                    self.error("unexpected-start-tag-out-of-head");
+                    let head = self.head.expect("the head element exists after head");
+                    self.open_elements.push(head);
+                    self.process_using(InsertionMode::InHead, token);
+                    if let Some(index) = self.open_elements.iter().rposition(|&id| id == head) {
+                        self.open_elements.remove(index);
+                    }
+                }
+                "head" => self.error("unexpected-start-tag-head"),
+                _ => self.after_head_anything_else(token),
+            },
+            Token::EndTag(ref name) => match name.as_str() {
+                "template" => self.process_using(InsertionMode::InHead, token),
+                "body" | "html" | "br" => self.after_head_anything_else(token),
+                _ => self.error("unexpected-end-tag"),
+            },
+            token => self.after_head_anything_else(token),
+        }
+    }
+
+    fn after_head_anything_else(&mut self, token: Token) {
+        self.insert_html_element(&start_tag("body"));
+        self.reprocess(InsertionMode::InBody, token);
+    }
+
+    fn in_body(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => {
+                if text.starts_with('\0') {
+                    self.error("unexpected-null-character");
+                    return;
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_text(&text);
+                if !is_whitespace(&text) {
+                    self.frameset_ok = false;
+                }
+            }
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { .. } => self.in_body_start_tag(token),
+            Token::EndTag(_) => self.in_body_end_tag(token),
+            Token::Eof => {
+                if !self.template_modes.is_empty() {
+                    self.process_using(InsertionMode::InTemplate, Token::Eof);
+                    return;
+                }
+                self.check_body_can_close("expected-closing-tag-but-got-eof");
+                self.stop_parsing();
+            }
+        }
+    }
+
+    fn in_body_start_tag(&mut self, token: Token) {
+        let (name, attributes) = match &token {
+            Token::StartTag { name, attributes, .. } => (name.clone(), attributes.clone()),
+            _ => return,
+        };
+        match name.as_str() {
+            "html" => {
+                self.error("unexpected-start-tag-html");
+                if self.is_on_stack("template") {
+                    return;
+                }
+                let html = self.open_elements[0];
+                self.add_missing_attributes(html, attributes);
+            }
+            "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style"
+            | "template" | "title" => self.process_using(InsertionMode::InHead, token),
+            "body" => {
+                self.error("unexpected-start-tag-body");
+                if self.open_elements.len() == 1
+                    || !self.node_is(self.open_elements[1], "body")
+                    || self.is_on_stack("template")
+                {
+                    return;
+                }
+                self.frameset_ok = false;
+                let body = self.open_elements[1];
+                self.add_missing_attributes(body, attributes);
+            }
+            "frameset" => {
+                self.error("unexpected-start-tag-frameset");
+                if self.open_elements.len() == 1
+                    || !self.node_is(self.open_elements[1], "body")
+                    || !self.frameset_ok
+                {
+                    return;
+                }
+                let body = self.open_elements[1];
+                self.detach(body);
+                self.open_elements.truncate(1);
+                self.insert_html_element(&token);
+                self.mode = InsertionMode::InFrameset;
+            }
+            "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog"
+            | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer" | "header"
+            | "hgroup" | "main" | "menu" | "nav" | "ol" | "p" | "search" | "section" | "summary"
+            | "ul" => {
+                self.close_p_if_in_button_scope();
+                self.insert_html_element(&token);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.close_p_if_in_button_scope();
+                if self.current_is_one_of(HEADINGS) {
+                    self.error("nested-heading");
+                    self.open_elements.pop();
+                }
+                self.insert_html_element(&token);
+            }
+            "pre" | "listing" => {
+                self.close_p_if_in_button_scope();
+                self.insert_html_element(&token);
+                self.skip_next_newline = true;
+                self.frameset_ok = false;
+            }
+            "form" => {
+                let in_template = self.is_on_stack("template");
+                if self.form.is_some() && !in_template {
+                    self.error("unexpected-start-tag-form");
+                    return;
+                }
+                self.close_p_if_in_button_scope();
+                let form = self.insert_html_element(&token);
+                if !in_template {
+                    self.form = Some(form);
+                }
+            }
+            "li" => {
+                self.frameset_ok = false;
+                self.close_list_item(&["li"]);
+                self.close_p_if_in_button_scope();
+                self.insert_html_element(&token);
+            }
+            "dd" | "dt" => {
+                self.frameset_ok = false;
+                self.close_list_item(&["dd", "dt"]);
+                self.close_p_if_in_button_scope();
+                self.insert_html_element(&token);
+            }
+            "plaintext" => {
+                self.close_p_if_in_button_scope();
+                self.insert_html_element(&token);
+                self.tokenizer.set_state(State::Plaintext);
+            }
+            "button" => {
+                if self.has_in_scope("button", Scope::Default) {
+                    self.error("unexpected-start-tag-button");
+                    self.generate_implied_end_tags(None);
+                    self.pop_until_one_of(&["button"]);
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&token);
+                self.frameset_ok = false;
+            }
+            "a" => {
+                let existing = self.formatting_element_after_last_marker("a");
+                if let Some(existing) = existing {
+                    self.error("unexpected-start-tag-implies-end-tag");
+                    self.adoption_agency("a");
+                    self.remove_from_formatting(existing);
+                    if let Some(index) = self.open_elements.iter().position(|&id| id == existing) {
+                        self.open_elements.remove(index);
+                    }
+                }
+                self.reconstruct_active_formatting_elements();
+                let element = self.insert_html_element(&token);
+                self.push_formatting_element(element, name, attributes);
+            }
+            "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike" | "strong"
+            | "tt" | "u" => {
+                self.reconstruct_active_formatting_elements();
+                let element = self.insert_html_element(&token);
+                self.push_formatting_element(element, name, attributes);
+            }
+            "nobr" => {
+                self.reconstruct_active_formatting_elements();
+                if self.has_in_scope("nobr", Scope::Default) {
+                    self.error("unexpected-start-tag-implies-end-tag");
+                    self.adoption_agency("nobr");
+                    self.reconstruct_active_formatting_elements();
+                }
+                let element = self.insert_html_element(&token);
+                self.push_formatting_element(element, name, attributes);
+            }
+            "applet" | "marquee" | "object" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&token);
+                self.active_formatting.push(FormattingEntry::Marker);
+                self.frameset_ok = false;
+            }
+            "table" => {
+                if self.document_mode != DocumentMode::Quirks && self.has_in_scope("p", Scope::Button) {
+                    self.close_p_element();
+                }
+                self.insert_html_element(&token);
+                self.frameset_ok = false;
+                self.mode = InsertionMode::InTable;
+            }
+            "area" | "br" | "embed" | "img" | "keygen" | "wbr" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_void_element(&token);
+                self.frameset_ok = false;
+            }
+            "input" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_void_element(&token);
+                if !is_hidden_input(&attributes) {
+                    self.frameset_ok = false;
+                }
+            }
+            "param" | "source" | "track" => {
+                self.insert_void_element(&token);
+            }
+            "hr" => {
+                self.close_p_if_in_button_scope();
+                self.insert_void_element(&token);
+                self.frameset_ok = false;
+            }
+            "image" => {
+                self.error("unexpected-start-tag-image");
+                let token = match token {
+                    Token::StartTag { attributes, self_closing, .. } => Token::StartTag {
+                        name: "img".to_string(),
+                        attributes,
+                        self_closing,
+                    },
+                    token => token,
+                };
+                self.process(token);
+            }
+            "textarea" => {
+                self.skip_next_newline = true;
+                self.frameset_ok = false;
+                self.parse_raw_text(&token, State::Rcdata);
+            }
+            "xmp" => {
+                self.close_p_if_in_button_scope();
+                self.reconstruct_active_formatting_elements();
+                self.frameset_ok = false;
+                self.parse_raw_text(&token, State::Rawtext);
+            }
+            "iframe" => {
+                self.frameset_ok = false;
+                self.parse_raw_text(&token, State::Rawtext);
+            }
+            "noembed" => self.parse_raw_text(&token, State::Rawtext),
+            "select" => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&token);
+                self.frameset_ok = false;
+                self.mode = match self.mode {
+                    InsertionMode::InTable
+                    | InsertionMode::InCaption
+                    | InsertionMode::InTableBody
+                    | InsertionMode::InRow
+                    | InsertionMode::InCell => InsertionMode::InSelectInTable,
+                    _ => InsertionMode::InSelect,
+                };
+            }
+            "optgroup" | "option" => {
+                if self.current_is("option") {
+                    self.open_elements.pop();
+                }
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&token);
+            }
+            "rb" | "rtc" => {
+                if self.has_in_scope("ruby", Scope::Default) {
+                    self.generate_implied_end_tags(None);
+                    if !self.current_is("ruby") {
+                        self.error("unexpected-start-tag");
+                    }
+                }
+                self.insert_html_element(&token);
+            }
+            "rp" | "rt" => {
+                if self.has_in_scope("ruby", Scope::Default) {
+                    self.generate_implied_end_tags(Some("rtc"));
+                    if !self.current_is_one_of(&["ruby", "rtc"]) {
+                        self.error("unexpected-start-tag");
+                    }
+                }
+                self.insert_html_element(&token);
+            }
+            "math" | "svg" => {
+                self.reconstruct_active_formatting_elements();
+                let Token::StartTag { name, mut attributes, self_closing } = token else {
+                    unreachable!()
+                };
+                let namespace = if name == "math" {
+                    adjust_mathml_attributes(&mut attributes);
+                    Namespace::MathMl
+                } else {
+                    adjust_svg_attributes(&mut attributes);
+                    Namespace::Svg
+                };
+                self.insert_foreign_element(namespace, &name, attributes);
+                if self_closing {
+                    self.open_elements.pop();
+                }
+            }
+            "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th"
+            | "thead" | "tr" => self.error("unexpected-start-tag-ignored"),
+            _ => {
+                self.reconstruct_active_formatting_elements();
+                self.insert_html_element(&token);
+            }
+        }
+    }
+
+    fn in_body_end_tag(&mut self, token: Token) {
+        let name = match &token {
+            Token::EndTag(name) => name.clone(),
+            _ => return,
+        };
+        match name.as_str() {
+            "template" => self.process_using(InsertionMode::InHead, token),
+            "body" => {
+                if !self.has_in_scope("body", Scope::Default) {
+                    self.error("unexpected-end-tag-body");
+                    return;
+                }
+                self.check_body_can_close("expected-one-end-tag-but-got-another");
+                self.mode = InsertionMode::AfterBody;
+            }
+            "html" => {
+                if !self.has_in_scope("body", Scope::Default) {
+                    self.error("unexpected-end-tag-html");
+                    return;
+                }
+                self.check_body_can_close("expected-one-end-tag-but-got-another");
+                self.reprocess(InsertionMode::AfterBody, token);
+            }
+            "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details"
+            | "dialog" | "dir" | "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer"
+            | "header" | "hgroup" | "listing" | "main" | "menu" | "nav" | "ol" | "pre"
+            | "search" | "section" | "summary" | "ul" => {
+                if !self.has_in_scope(&name, Scope::Default) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_is(&name) {
+                    self.error("end-tag-too-early");
+                }
+                self.pop_until_one_of(&[name.as_str()]);
+            }
+            "form" => {
+                if !self.is_on_stack("template") {
+                    let node = self.form.take();
+                    let node = match node {
+                        Some(node) if self.node_in_scope(node, Scope::Default) => node,
+                        _ => {
+                            self.error("unexpected-end-tag-form");
+                            return;
+                        }
+                    };
+                    self.generate_implied_end_tags(None);
+                    if self.current_node() != Some(node) {
+                        self.error("end-tag-too-early");
+                    }
+                    if let Some(index) = self.open_elements.iter().position(|&id| id == node) {
+                        self.open_elements.remove(index);
+                    }
+                } else {
+                    if !self.has_in_scope("form", Scope::Default) {
+                        self.error("unexpected-end-tag-form");
+                        return;
+                    }
+                    self.generate_implied_end_tags(None);
+                    if !self.current_is("form") {
+                        self.error("end-tag-too-early");
+                    }
+                    self.pop_until_one_of(&["form"]);
+                }
+            }
+            "p" => {
+                if !self.has_in_scope("p", Scope::Button) {
+                    self.error("unexpected-end-tag-p");
+                    self.insert_html_element(&start_tag("p"));
+                }
+                self.close_p_element();
+            }
+            "li" => {
+                if !self.has_in_scope("li", Scope::ListItem) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(Some("li"));
+                if !self.current_is("li") {
+                    self.error("end-tag-too-early");
+                }
+                self.pop_until_one_of(&["li"]);
+            }
+            "dd" | "dt" => {
+                if !self.has_in_scope(&name, Scope::Default) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(Some(&name));
+                if !self.current_is(&name) {
+                    self.error("end-tag-too-early");
+                }
+                self.pop_until_one_of(&[name.as_str()]);
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                if !HEADINGS.iter().any(|h| self.has_in_scope(h, Scope::Default)) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_is(&name) {
+                    self.error("end-tag-too-early");
+                }
+                self.pop_until_one_of(HEADINGS);
+            }
+            "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s" | "small"
+            | "strike" | "strong" | "tt" | "u" => self.adoption_agency(&name),
+            "applet" | "marquee" | "object" => {
+                if !self.has_in_scope(&name, Scope::Default) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_is(&name) {
+                    self.error("end-tag-too-early");
+                }
+                self.pop_until_one_of(&[name.as_str()]);
+                self.clear_formatting_to_last_marker();
+            }
+            "br" => {
+                self.error("unexpected-end-tag-br");
+                self.in_body_start_tag(start_tag("br"));
+            }
+            _ => self.any_other_end_tag(&name),
+        }
+    }
+
+    /// "Any other end tag" in the "in body" insertion mode.
+    fn any_other_end_tag(&mut self, name: &str) {
+        for index in (0..self.open_elements.len()).rev() {
+            let node = self.open_elements[index];
+            if self.node_is(node, name) {
+                self.generate_implied_end_tags(Some(name));
+                if self.current_node() != Some(node) {
+                    self.error("end-tag-too-early");
+                }
+                self.open_elements.truncate(index);
+                return;
+            }
+            if self.is_special(node) {
+                self.error("unexpected-end-tag");
+                return;
+            }
+        }
+    }
+
+    /// Reports a parse error if an element other than the ones allowed to be
+    /// left open is still on the stack when the body ends.
+    fn check_body_can_close(&mut self, code: &'static str) {
+        let allowed = [
+            "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc", "tbody", "td",
+            "tfoot", "th", "thead", "tr", "body", "html",
+        ];
+        let unclosed = self
+            .open_elements
+            .iter()
+            .any(|&id| !allowed.contains(&self.name_of(id).unwrap_or("")));
+        if unclosed {
+            self.error(code);
+        }
+    }
+
+    /// The `li`, `dd` and `dt` start tags close an open list item of the same kind.
+    fn close_list_item(&mut self, names: &[&str]) {
+        for index in (0..self.open_elements.len()).rev() {
+            let node = self.open_elements[index];
+            let node_name = self.name_of(node).unwrap_or("").to_string();
+            if names.contains(&node_name.as_str()) {
+                self.generate_implied_end_tags(Some(&node_name));
+                if !self.current_is(&node_name) {
+                    self.error("end-tag-too-early");
+                }
+                self.pop_until_one_of(&[node_name.as_str()]);
+                return;
+            }
+            if self.is_special(node) && !matches!(node_name.as_str(), "address" | "div" | "p") {
+                return;
+            }
+        }
+    }
+
+    fn text(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => self.insert_text(&text),
+            Token::Eof => {
+                self.error("eof-in-text");
+                self.open_elements.pop();
+                let mode = self.original_mode;
+                self.reprocess(mode, Token::Eof);
+            }
+            Token::EndTag(_) => {
+                self.open_elements.pop();
+                self.mode = self.original_mode;
+            }
+            _ => {}
+        }
+    }
+
+    fn in_table(&mut self, token: Token) {
+        match token {
+            Token::Text(_) if self.current_is_one_of(&["table", "tbody", "template", "tfoot", "thead", "tr"]) => {
+                self.pending_table_text.clear();
+                self.original_mode = self.mode;
+                self.reprocess(InsertionMode::InTableText, token);
+            }
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, ref attributes, .. } => match name.as_str() {
+                "caption" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.active_formatting.push(FormattingEntry::Marker);
+                    self.insert_html_element(&token);
+                    self.mode = InsertionMode::InCaption;
+                }
+                "colgroup" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_html_element(&token);
+                    self.mode = InsertionMode::InColumnGroup;
+                }
+                "col" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_html_element(&start_tag("colgroup"));
+                    self.reprocess(InsertionMode::InColumnGroup, token);
+                }
+                "tbody" | "tfoot" | "thead" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_html_element(&token);
+                    self.mode = InsertionMode::InTableBody;
+                }
+                "td" | "th" | "tr" => {
+                    self.clear_stack_back_to(&["table", "template", "html"]);
+                    self.insert_html_element(&start_tag("tbody"));
+                    self.reprocess(InsertionMode::InTableBody, token);
+                }
+                "table" => {
+                    self.error("unexpected-start-tag-implies-end-tag");
+                    if !self.has_in_scope("table", Scope::Table) {
+                        return;
+                    }
+                    self.pop_until_one_of(&["table"]);
+                    self.reset_insertion_mode();
+                    self.process(token);
+                }
+                "style" | "script" | "template" => self.process_using(InsertionMode::InHead, token),
+                "input" if is_hidden_input(attributes) => {
+                    self.error("unexpected-hidden-input-in-table");
+                    self.insert_void_element(&token);
+                }
+                "form" => {
+                    self.error("unexpected-form-in-table");
+                    if self.is_on_stack("template") || self.form.is_some() {
+                        return;
+                    }
+                    let form = self.insert_html_element(&token);
+                    self.form = Some(form);
+                    self.open_elements.pop();
+                }
+                _ => self.in_table_anything_else(token),
+            },
+            Token::EndTag(ref name) => match name.as_str() {
+                "table" => {
+                    if !self.has_in_scope("table", Scope::Table) {
+                        self.error("unexpected-end-tag");
+                        return;
+                    }
+                    self.pop_until_one_of(&["table"]);
+                    self.reset_insertion_mode();
+                }
+                "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot"
+                | "th" | "thead" | "tr" => self.error("unexpected-end-tag"),
+                "template" => self.process_using(InsertionMode::InHead, token),
+                _ => self.in_table_anything_else(token),
+            },
+            Token::Eof => self.process_using(InsertionMode::InBody, token),
+            token => self.in_table_anything_else(token),
+        }
+    }
+
+    fn in_table_anything_else(&mut self, token: Token) {
+        self.error("unexpected-token-in-table");
+        self.foster_parenting = true;
+        self.process_using(InsertionMode::InBody, token);
+        self.foster_parenting = false;
+    }
+
+    fn in_table_text(&mut self, token: Token) {
+        match token {
+            Token::Text(text) if text.starts_with('\0') => self.error("unexpected-null-character"),
+            Token::Text(text) => self.pending_table_text.push((text, self.current_span)),
+            token => {
+                let pending = std::mem::take(&mut self.pending_table_text);
+                let span = self.current_span;
+                if pending.iter().any(|(text, _)| !is_whitespace(text)) {
+                    // Non-whitespace text is moved out of the table.
+                    for (text, text_span) in pending {
+                        self.current_span = text_span;
+                        self.in_table_anything_else(Token::Text(text));
+                    }
+                } else {
+                    for (text, text_span) in pending {
+                        self.current_span = text_span;
+                        self.insert_text(&text);
+                    }
+                }
+                self.current_span = span;
+                let mode = self.original_mode;
+                self.reprocess(mode, token);
+            }
+        }
+    }
+
+    fn in_caption(&mut self, token: Token) {
+        match token {
+            Token::EndTag(ref name) if name == "caption" => {
+                self.close_caption();
+            }
+            Token::StartTag { ref name, .. }
+                if matches!(
+                    name.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr"
+                ) =>
+            {
+                if self.close_caption() {
+                    self.process(token);
+                }
+            }
+            Token::EndTag(ref name) if name == "table" => {
+                if self.close_caption() {
+                    self.process(token);
+                }
+            }
+            Token::EndTag(ref name)
+                if matches!(
+                    name.as_str(),
+                    "body" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr"
+                ) =>
+            {
+                self.error("unexpected-end-tag");
+            }
+            token => self.process_using(InsertionMode::InBody, token),
+        }
+    }
+
+    /// Closes the current caption. Returns `false` if there was none.
+    fn close_caption(&mut self) -> bool {
+        if !self.has_in_scope("caption", Scope::Table) {
+            self.error("unexpected-end-tag");
+            return false;
+        }
+        self.generate_implied_end_tags(None);
+        if !self.current_is("caption") {
+            self.error("end-tag-too-early");
+        }
+        self.pop_until_one_of(&["caption"]);
+        self.clear_formatting_to_last_marker();
+        self.mode = InsertionMode::InTable;
+        true
+    }
+
+    fn in_column_group(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.insert_text(text),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } if name == "html" => self.process_using(InsertionMode::InBody, token),
+            Token::StartTag { ref name, .. } if name == "col" => {
+                self.insert_void_element(&token);
+            }
+            Token::EndTag(ref name) if name == "colgroup" => {
+                if !self.current_is("colgroup") {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.open_elements.pop();
+                self.mode = InsertionMode::InTable;
+            }
+            Token::EndTag(ref name) if name == "col" => self.error("unexpected-end-tag"),
+            Token::StartTag { ref name, .. } | Token::EndTag(ref name) if name == "template" => {
+                self.process_using(InsertionMode::InHead, token)
+            }
+            Token::Eof => self.process_using(InsertionMode::InBody, token),
+            token => {
+                if !self.current_is("colgroup") {
+                    self.error("unexpected-token-in-column-group");
+                    return;
+                }
+                self.open_elements.pop();
+                self.reprocess(InsertionMode::InTable, token);
+            }
+        }
+    }
+
+    fn in_table_body(&mut self, token: Token) {
+        match token {
+            Token::StartTag { ref name, .. } if name == "tr" => {
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                self.insert_html_element(&token);
+                self.mode = InsertionMode::InRow;
+            }
+            Token::StartTag { ref name, .. } if name == "th" || name == "td" => {
+                self.error("unexpected-cell-in-table-body");
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                self.insert_html_element(&start_tag("tr"));
+                self.reprocess(InsertionMode::InRow, token);
+            }
+            Token::EndTag(ref name) if matches!(name.as_str(), "tbody" | "tfoot" | "thead") => {
+                if !self.has_in_scope(name, Scope::Table) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+                self.open_elements.pop();
+                self.mode = InsertionMode::InTable;
+            }
+            Token::StartTag { ref name, .. }
+                if matches!(name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead") =>
+            {
+                self.close_table_body(token);
+            }
+            Token::EndTag(ref name) if name == "table" => self.close_table_body(token),
+            Token::EndTag(ref name)
+                if matches!(
+                    name.as_str(),
+                    "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr"
+                ) =>
+            {
+                self.error("unexpected-end-tag");
+            }
+            token => self.process_using(InsertionMode::InTable, token),
+        }
+    }
+
+    fn close_table_body(&mut self, token: Token) {
+        if !["tbody", "thead", "tfoot"].iter().any(|name| self.has_in_scope(name, Scope::Table)) {
+            self.error("unexpected-token-in-table-body");
+            return;
+        }
+        self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+        self.open_elements.pop();
+        self.reprocess(InsertionMode::InTable, token);
+    }
+
+    fn in_row(&mut self, token: Token) {
+        match token {
+            Token::StartTag { ref name, .. } if name == "th" || name == "td" => {
+                self.clear_stack_back_to(&["tr", "template", "html"]);
+                self.insert_html_element(&token);
+                self.mode = InsertionMode::InCell;
+                self.active_formatting.push(FormattingEntry::Marker);
+            }
+            Token::EndTag(ref name) if name == "tr" => {
+                self.close_row();
+            }
+            Token::StartTag { ref name, .. }
+                if matches!(
+                    name.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr"
+                ) =>
+            {
+                if self.close_row() {
+                    self.process(token);
+                }
+            }
+            Token::EndTag(ref name) if name == "table" => {
+                if self.close_row() {
+                    self.process(token);
+                }
+            }
+            Token::EndTag(ref name) if matches!(name.as_str(), "tbody" | "tfoot" | "thead") => {
+                if !self.has_in_scope(name, Scope::Table) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                if self.close_row() {
+                    self.process(token);
+                }
+            }
+            Token::EndTag(ref name)
+                if matches!(name.as_str(), "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th") =>
+            {
+                self.error("unexpected-end-tag");
+            }
+            token => self.process_using(InsertionMode::InTable, token),
+        }
+    }
+
+    /// Closes the current table row. Returns `false` if there was none.
+    fn close_row(&mut self) -> bool {
+        if !self.has_in_scope("tr", Scope::Table) {
+            self.error("unexpected-end-tag");
+            return false;
+        }
+        self.clear_stack_back_to(&["tr", "template", "html"]);
+        self.open_elements.pop();
+        self.mode = InsertionMode::InTableBody;
+        true
+    }
+
+    fn in_cell(&mut self, token: Token) {
+        match token {
+            Token::EndTag(ref name) if name == "td" || name == "th" => {
+                if !self.has_in_scope(name, Scope::Table) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.generate_implied_end_tags(None);
+                if !self.current_is(name) {
+                    self.error("end-tag-too-early");
+                }
+                self.pop_until_one_of(&[name.as_str()]);
+                self.clear_formatting_to_last_marker();
+                self.mode = InsertionMode::InRow;
+            }
+            Token::StartTag { ref name, .. }
+                if matches!(
+                    name.as_str(),
+                    "caption" | "col" | "colgroup" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr"
+                ) =>
+            {
+                if !self.has_in_scope("td", Scope::Table) && !self.has_in_scope("th", Scope::Table) {
+                    self.error("unexpected-start-tag");
+                    return;
+                }
+                self.close_cell();
+                self.process(token);
+            }
+            Token::EndTag(ref name) if matches!(name.as_str(), "body" | "caption" | "col" | "colgroup" | "html") => {
+                self.error("unexpected-end-tag");
+            }
+            Token::EndTag(ref name) if matches!(name.as_str(), "table" | "tbody" | "tfoot" | "thead" | "tr") => {
+                if !self.has_in_scope(name, Scope::Table) {
+                    self.error("unexpected-end-tag");
+                    return;
+                }
+                self.close_cell();
+                self.process(token);
+            }
+            token => self.process_using(InsertionMode::InBody, token),
+        }
+    }
+
+    fn close_cell(&mut self) {
+        self.generate_implied_end_tags(None);
+        if !self.current_is_one_of(&["td", "th"]) {
+            self.error("end-tag-too-early");
+        }
+        self.pop_until_one_of(&["td", "th"]);
+        self.clear_formatting_to_last_marker();
+        self.mode = InsertionMode::InRow;
+    }
+
+    fn in_select(&mut self, token: Token) {
+        match token {
+            Token::Text(text) => {
+                if text.starts_with('\0') {
+                    self.error("unexpected-null-character");
+                } else {
+                    self.insert_text(&text);
+                }
+            }
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } => match name.as_str() {
+                "html" => self.process_using(InsertionMode::InBody, token),
+                "option" => {
+                    if self.current_is("option") {
+                        self.open_elements.pop();
+                    }
+                    self.insert_html_element(&token);
+                }
+                "optgroup" => {
+                    if self.current_is("option") {
+                        self.open_elements.pop();
+                    }
+                    if self.current_is("optgroup") {
+                        self.open_elements.pop();
+                    }
+                    self.insert_html_element(&token);
+                }
+                "hr" => {
+                    if self.current_is("option") {
+                        self.open_elements.pop();
+                    }
+                    if self.current_is("optgroup") {
+                        self.open_elements.pop();
+                    }
+                    self.insert_void_element(&token);
+                }
+                "select" => {
+                    self.error("unexpected-select-in-select");
+                    if self.has_in_scope("select", Scope::Select) {
+                        self.pop_until_one_of(&["select"]);
+                        self.reset_insertion_mode();
+                    }
+                }
+                "input" | "keygen" | "textarea" => {
+                    self.error("unexpected-input-in-select");
+                    if !self.has_in_scope("select", Scope::Select) {
+                        return;
+                    }
+                    self.pop_until_one_of(&["select"]);
+                    self.reset_insertion_mode();
+                    self.process(token);
+                }
+                "script" | "template" => self.process_using(InsertionMode::InHead, token),
+                _ => self.error("unexpected-start-tag-in-select"),
+            },
+            Token::EndTag(ref name) => match name.as_str() {
+                "optgroup" => {
+                    let len = self.open_elements.len();
+                    if self.current_is("option")
+                        && len >= 2
+                        && self.node_is(self.open_elements[len - 2], "optgroup")
+                    {
+                        self.open_elements.pop();
+                    }
+                    if self.current_is("optgroup") {
+                        self.open_elements.pop();
+                    } else {
+                        self.error("unexpected-end-tag-in-select");
+                    }
+                }
+                "option" => {
+                    if self.current_is("option") {
+                        self.open_elements.pop();
+                    } else {
+                        self.error("unexpected-end-tag-in-select");
+                    }
+                }
+                "select" => {
+                    if !self.has_in_scope("select", Scope::Select) {
+                        self.error("unexpected-end-tag-in-select");
+                        return;
+                    }
+                    self.pop_until_one_of(&["select"]);
+                    self.reset_insertion_mode();
+                }
+                "template" => self.process_using(InsertionMode::InHead, token),
+                _ => self.error("unexpected-end-tag-in-select"),
+            },
+            Token::Eof => self.process_using(InsertionMode::InBody, token),
+        }
+    }
+
+    fn in_select_in_table(&mut self, token: Token) {
+        const TABLE_TAGS: &[&str] = &["caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th"];
+        match token {
+            Token::StartTag { ref name, .. } if TABLE_TAGS.contains(&name.as_str()) => {
+                self.error("unexpected-table-element-start-tag-in-select-in-table");
+                self.pop_until_one_of(&["select"]);
+                self.reset_insertion_mode();
+                self.process(token);
+            }
+            Token::EndTag(ref name) if TABLE_TAGS.contains(&name.as_str()) => {
+                self.error("unexpected-table-element-end-tag-in-select-in-table");
+                if !self.has_in_scope(name, Scope::Table) {
+                    return;
+                }
+                self.pop_until_one_of(&["select"]);
+                self.reset_insertion_mode();
+                self.process(token);
+            }
+            token => self.process_using(InsertionMode::InSelect, token),
+        }
+    }
+
+    fn in_template(&mut self, token: Token) {
+        match token {
+            Token::Text(_) | Token::Comment(_) | Token::Doctype(_) => {
+                self.process_using(InsertionMode::InBody, token)
+            }
+            Token::StartTag { ref name, .. } => {
+                let mode = match name.as_str() {
+                    "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script"
+                    | "style" | "template" | "title" => {
+                        self.process_using(InsertionMode::InHead, token);
+                        return;
+                    }
+                    "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => InsertionMode::InTable,
+                    "col" => InsertionMode::InColumnGroup,
+                    "tr" => InsertionMode::InTableBody,
+                    "td" | "th" => InsertionMode::InRow,
+                    _ => InsertionMode::InBody,
+                };
+                self.template_modes.pop();
+                self.template_modes.push(mode);
+                self.reprocess(mode, token);
+            }
+            Token::EndTag(ref name) if name == "template" => self.process_using(InsertionMode::InHead, token),
+            Token::EndTag(_) => self.error("unexpected-end-tag-in-template"),
+            Token::Eof => {
+                if !self.is_on_stack("template") {
+                    self.stop_parsing();
+                    return;
+                }
+                self.error("eof-in-template");
+                self.pop_until_one_of(&["template"]);
+                self.clear_formatting_to_last_marker();
+                self.template_modes.pop();
+                self.reset_insertion_mode();
+                self.process(Token::Eof);
+            }
+        }
+    }
+
+    fn after_body(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.process_using(InsertionMode::InBody, token),
+            Token::Comment(text) => {
+                let html = self.open_elements[0];
+                self.insert_comment_in(text, html);
+            }
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } if name == "html" => self.process_using(InsertionMode::InBody, token),
+            Token::EndTag(ref name) if name == "html" => {
+                if self.context.is_some() {
+                    self.error("unexpected-end-tag-after-body-innerhtml");
+                } else {
+                    self.mode = InsertionMode::AfterAfterBody;
+                }
+            }
+            Token::Eof => self.stop_parsing(),
+            token => {
+                self.error("unexpected-token-after-body");
+                self.reprocess(InsertionMode::InBody, token);
+            }
+        }
+    }
+
+    fn in_frameset(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.insert_text(text),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } => match name.as_str() {
+                "html" => self.process_using(InsertionMode::InBody, token),
+                "frameset" => {
+                    self.insert_html_element(&token);
+                }
+                "frame" => {
+                    self.insert_void_element(&token);
+                }
+                "noframes" => self.process_using(InsertionMode::InHead, token),
+                _ => self.error("unexpected-start-tag-in-frameset"),
+            },
+            Token::EndTag(ref name) if name == "frameset" => {
+                if self.open_elements.len() == 1 {
+                    self.error("unexpected-frameset-in-frameset-innerhtml");
+                    return;
+                }
+                self.open_elements.pop();
+                if !self.current_is("frameset") {
+                    self.mode = InsertionMode::AfterFrameset;
+                }
+            }
+            Token::Eof => {
+                if self.open_elements.len() != 1 {
+                    self.error("eof-in-frameset");
+                }
+                self.stop_parsing();
+            }
+            _ => self.error("unexpected-token-in-frameset"),
+        }
+    }
+
+    fn after_frameset(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if is_whitespace(text) => self.insert_text(text),
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, .. } if name == "html" => self.process_using(InsertionMode::InBody, token),
+            Token::EndTag(ref name) if name == "html" => self.mode = InsertionMode::AfterAfterFrameset,
+            Token::StartTag { ref name, .. } if name == "noframes" => {
+                self.process_using(InsertionMode::InHead, token)
+            }
+            Token::Eof => self.stop_parsing(),
+            _ => self.error("unexpected-token-after-frameset"),
+        }
+    }
+
+    fn after_after_body(&mut self, token: Token) {
+        match token {
+            Token::Comment(text) => self.insert_comment_in(text, DOCUMENT),
+            Token::Doctype(_) => self.process_using(InsertionMode::InBody, token),
+            Token::Text(ref text) if is_whitespace(text) => self.process_using(InsertionMode::InBody, token),
+            Token::StartTag { ref name, .. } if name == "html" => self.process_using(InsertionMode::InBody, token),
+            Token::Eof => self.stop_parsing(),
+            token => {
+                self.error("unexpected-token-after-after-body");
+                self.reprocess(InsertionMode::InBody, token);
+            }
+        }
+    }
+
+    fn after_after_frameset(&mut self, token: Token) {
+        match token {
+            Token::Comment(text) => self.insert_comment_in(text, DOCUMENT),
+            Token::Doctype(_) => self.process_using(InsertionMode::InBody, token),
+            Token::Text(ref text) if is_whitespace(text) => self.process_using(InsertionMode::InBody, token),
+            Token::StartTag { ref name, .. } if name == "html" => self.process_using(InsertionMode::InBody, token),
+            Token::StartTag { ref name, .. } if name == "noframes" => {
+                self.process_using(InsertionMode::InHead, token)
+            }
+            Token::Eof => self.stop_parsing(),
+            _ => self.error("unexpected-token-after-after-frameset"),
+        }
+    }
+
+    /// The rules for parsing tokens in foreign content (§13.2.6.5).
+    fn foreign_content(&mut self, token: Token) {
+        match token {
+            Token::Text(ref text) if text.starts_with('\0') => {
+                self.error("unexpected-null-character");
+                self.insert_text(&"\u{FFFD}".repeat(text.chars().count()));
+            }
+            Token::Text(ref text) => {
+                if !is_whitespace(text) {
+                    self.frameset_ok = false;
+                }
+                self.insert_text(text);
+            }
+            Token::Comment(text) => self.insert_comment(text),
+            Token::Doctype(_) => self.error("unexpected-doctype"),
+            Token::StartTag { ref name, ref attributes, .. }
+                if BREAKOUT_ELEMENTS.contains(&name.as_str())
+                    || (name == "font"
+                        && attributes.iter().any(|(key, _)| matches!(key.as_str(), "color" | "face" | "size"))) =>
+            {
+                self.breakout_of_foreign_content(token);
+            }
+            Token::EndTag(ref name) if name == "br" || name == "p" => self.breakout_of_foreign_content(token),
+            Token::StartTag { name, mut attributes, self_closing } => {
+                let namespace = self.adjusted_current_element().map_or(Namespace::Html, |(namespace, ..)| namespace);
+                let name = match namespace {
+                    Namespace::MathMl => {
+                        adjust_mathml_attributes(&mut attributes);
+                        name
+                    }
+                    Namespace::Svg => {
+                        adjust_svg_attributes(&mut attributes);
+                        adjust_svg_tag_name(&name).to_string()
+                    }
+                    _ => name,
+                };
+                self.insert_foreign_element(namespace, &name, attributes);
+                if self_closing {
+                    // Also covers a self-closing SVG `<script/>`, which is not run.
+                    self.open_elements.pop();
+                }
+            }
+            Token::EndTag(ref name)
+                if name == "script"
+                    && self
+                        .current_node()
+                        .is_some_and(|id| self.element_name(id) == Some((Namespace::Svg, "script"))) =>
+            {
+                self.open_elements.pop();
+            }
+            Token::EndTag(ref name) => {
+                let mut index = self.open_elements.len() - 1;
+                let matches = |parser: &Self, index: usize| {
+                    parser
+                        .element_name(parser.open_elements[index])
+                        .is_some_and(|(_, node_name)| node_name.eq_ignore_ascii_case(name))
+                };
+                if !matches(self, index) {
+                    self.error("unexpected-end-tag");
+                }
+                loop {
+                    if index == 0 {
+                        return;
+                    }
+                    if matches(self, index) {
+                        self.open_elements.truncate(index);
+                        return;
+                    }
+                    index -= 1;
+                    if self.element_name(self.open_elements[index]).is_some_and(|(namespace, _)| namespace == Namespace::Html) {
+                        break;
+                    }
+                }
+                self.process_in(self.mode, token);
+            }
+            Token::Eof => unreachable!("the end of the input is never foreign content"),
+        }
+    }
+
+    /// Closes the open SVG and MathML elements for an HTML tag found in
+    /// foreign content, and processes the tag as HTML.
+    fn breakout_of_foreign_content(&mut self, token: Token) {
+        self.error("unexpected-html-element-in-foreign-content");
+        while let Some(current) = self.current_node() {
+            let (namespace, name, attributes) = self.element_of(current);
+            if namespace == Namespace::Html
+                || is_mathml_text_integration_point(namespace, name)
+                || is_html_integration_point(namespace, name, attributes)
+            {
+                break;
+            }
+            self.open_elements.pop();
+        }
+        self.process_in(self.mode, token);
+    }
+
+    fn stop_parsing(&mut self) {
+        self.open_elements.clear();
+        self.stopped = true;
+    }
+
+    /// Creates a detached node for the token being processed.
+    fn new_node(&mut self, data: NodeData) -> usize {
+        self.nodes.push(Node {
+            data,
+            parent: None,
+            children: Vec::new(),
+            template_contents: None,
+            span: self.current_span,
+        });
+        self.nodes.len() - 1
+    }
+
+    fn create_element(&mut self, name: &str, attributes: Vec<(String, String)>) -> usize {
+        self.create_element_in(Namespace::Html, name, attributes)
+    }
+
+    fn create_element_in(&mut self, namespace: Namespace, name: &str, attributes: Vec<(String, String)>) -> usize {
+        let element = self.new_node(NodeData::Element {
+            namespace,
+            name: name.to_string(),
+            attributes,
+        });
+        if !self.current_tag.as_deref().is_some_and(|tag| tag.eq_ignore_ascii_case(name)) {
+            // An implied element: it has no source of its own.
+            let span = &mut self.nodes[element].span;
+            span.end = span.start;
+        }
+        if namespace == Namespace::Html && name == "template" {
+            let contents = self.new_node(NodeData::Fragment);
+            self.nodes[element].template_contents = Some(contents);
+        }
+        element
+    }
+
+    fn append_child(&mut self, parent: usize, child: usize) {
+        self.detach(child);
+        self.nodes[child].parent = Some(parent);
+        self.nodes[parent].children.push(child);
+    }
+
+    fn insert_at(&mut self, point: &InsertionPoint, child: usize) {
+        match point.before {
+            None => self.append_child(point.parent, child),
+            Some(before) => {
+                self.detach(child);
+                let index = self.nodes[point.parent]
+                    .children
+                    .iter()
+                    .position(|&id| id == before)
+                    .unwrap_or(self.nodes[point.parent].children.len());
+                self.nodes[child].parent = Some(point.parent);
+                self.nodes[point.parent].children.insert(index, child);
+            }
+        }
+    }
+
+    /// Removes a node from its parent, if it has one.
+    fn detach(&mut self, id: usize) {
+        if let Some(parent) = self.nodes[id].parent.take() {
+            self.nodes[parent].children.retain(|&child| child != id);
+        }
+    }
+
+    /// The "appropriate place for inserting a node", taking foster parenting
+    /// into account. Nodes inserted into a `<template>` go to its contents.
+    /// Past [`MAX_DEPTH`] open elements, nodes go next to the target instead
+    /// of inside it.
+    fn appropriate_insertion_point(&self, override_target: Option<usize>) -> InsertionPoint {
+        let mut point = self.adjusted_insertion_location(override_target);
+        if self.open_elements.len() > MAX_DEPTH && point.before.is_none() {
+            if let Some(parent) = self.nodes[point.parent].parent {
+                point.parent = parent;
+            }
+        }
+        match self.nodes[point.parent].template_contents {
+            Some(contents) => InsertionPoint {
+                parent: contents,
+                before: None,
+            },
+            None => point,
+        }
+    }
+
+    fn adjusted_insertion_location(&self, override_target: Option<usize>) -> InsertionPoint {
+        let target = override_target
+            .or_else(|| self.current_node())
+            .unwrap_or(DOCUMENT);
+
+        if self.foster_parenting
+            && matches!(self.name_of(target), Some("table" | "tbody" | "tfoot" | "thead" | "tr"))
+        {
+            let last_template = self.open_elements.iter().rposition(|&id| self.node_is(id, "template"));
+            let last_table = self.open_elements.iter().rposition(|&id| self.node_is(id, "table"));
+
+            if let Some(template) = last_template {
+                if last_table.is_none_or(|table| template > table) {
+                    return InsertionPoint {
+                        parent: self.open_elements[template],
+                        before: None,
+                    };
+                }
+            }
+            let Some(table_index) = last_table else {
+                return InsertionPoint {
+                    parent: self.open_elements[0],
+                    before: None,
+                };
+            };
+            let table = self.open_elements[table_index];
+            if let Some(parent) = self.nodes[table].parent {
+                return InsertionPoint {
+                    parent,
+                    before: Some(table),
+                };
+            }
+            return InsertionPoint {
+                parent: self.open_elements[table_index - 1],
+                before: None,
+            };
+        }
+
+        InsertionPoint {
+            parent: target,
+            before: None,
+        }
+    }
+
+    /// Creates an element for a start tag token, inserts it at the
+    /// appropriate place and pushes it onto the stack of open elements.
+    fn insert_html_element(&mut self, token: &Token) -> usize {
+        let (name, attributes) = match token {
+            Token::StartTag { name, attributes, self_closing } => {
+                if *self_closing && !VOID_ELEMENTS.contains(&name.as_str()) {
+                    self.error("non-void-html-element-start-tag-with-trailing-solidus");
+                }
+                (name.as_str(), attributes.clone())
+            }
+            _ => unreachable!("elements are only created from start tags"),
+        };
+        let point = self.appropriate_insertion_point(None);
+        let element = self.create_element(name, attributes);
+        self.insert_at(&point, element);
+        self.open_elements.push(element);
+        element
+    }
+
+    /// Inserts an SVG or MathML element and pushes it onto the stack of open
+    /// elements. Its attributes are expected to have their case restored.
+    fn insert_foreign_element(&mut self, namespace: Namespace, name: &str, attributes: Vec<(String, String)>) {
+        let point = self.appropriate_insertion_point(None);
+        let element = self.create_element_in(namespace, name, attributes);
+        self.insert_at(&point, element);
+        self.open_elements.push(element);
+    }
+
+    /// Inserts an element that is immediately popped (void elements). Their
+    /// trailing `/>`, if any, is acknowledged.
+    fn insert_void_element(&mut self, token: &Token) -> usize {
+        let element = self.insert_html_element(token);
+        self.open_elements.pop();
+        element
+    }
+
+    /// The generic RCDATA and raw text element parsing algorithms.
+    fn parse_raw_text(&mut self, token: &Token, state: State) {
+        self.insert_html_element(token);
+        self.tokenizer.set_state(state);
+        self.original_mode = self.mode;
+        self.mode = InsertionMode::Text;
+    }
+
+    fn insert_text(&mut self, text: &str) {
+        let point = self.appropriate_insertion_point(None);
+        if point.parent == DOCUMENT {
+            return;
+        }
+        let siblings = &self.nodes[point.parent].children;
+        let previous = match point.before {
+            None => siblings.last().copied(),
+            Some(before) => siblings
+                .iter()
+                .position(|&id| id == before)
+                .and_then(|index| index.checked_sub(1))
+                .map(|index| siblings[index]),
+        };
+        if let Some(previous) = previous {
+            if let NodeData::Text(existing) = &mut self.nodes[previous].data {
+                existing.push_str(text);
+                let span = &mut self.nodes[previous].span;
+                span.end = span.end.max(self.current_span.end);
+                return;
+            }
+        }
+        let node = self.new_node(NodeData::Text(text.to_string()));
+        self.insert_at(&point, node);
+    }
+
+    fn insert_comment(&mut self, text: String) {
+        let point = self.appropriate_insertion_point(None);
+        let node = self.new_node(NodeData::Comment(text));
+        self.insert_at(&point, node);
+    }
+
+    fn insert_comment_in(&mut self, text: String, parent: usize) {
+        let node = self.new_node(NodeData::Comment(text));
+        self.append_child(parent, node);
+    }
+
+    fn add_missing_attributes(&mut self, element: usize, attributes: Vec<(String, String)>) {
+        if let NodeData::Element { attributes: existing, .. } = &mut self.nodes[element].data {
+            for (key, value) in attributes {
+                if !existing.iter().any(|(k, _)| *k == key) {
+                    existing.push((key, value));
+                }
+            }
+        }
+    }
+
+    fn current_node(&self) -> Option<usize> {
+        self.open_elements.last().copied()
+    }
+
+    /// The "adjusted current node": the context element when parsing a
+    /// fragment with only the root element open, the current node otherwise.
+    /// Returns its namespace, name and attributes.
+    fn adjusted_current_element(&self) -> Option<ElementInfo<'_>> {
+        match &self.context {
+            Some(context) if self.open_elements.len() == 1 => Some((self.context_namespace, context.as_str(), &[])),
+            _ => self.current_node().map(|id| self.element_of(id)),
+        }
+    }
+
+    fn element_of(&self, id: usize) -> ElementInfo<'_> {
+        match &self.nodes[id].data {
+            NodeData::Element {
+                namespace,
+                name,
+                attributes,
+            } => (*namespace, name.as_str(), attributes.as_slice()),
+            _ => (Namespace::Html, "", &[]),
+        }
+    }
+
+    /// The namespace and name of an element, whatever its namespace.
+    fn element_name(&self, id: usize) -> Option<(Namespace, &str)> {
+        match &self.nodes[id].data {
+            NodeData::Element { namespace, name, .. } => Some((*namespace, name.as_str())),
+            _ => None,
+        }
+    }
+
+    /// The name of an HTML element. The insertion modes only ever look for
+    /// HTML elements, so SVG and MathML elements have none.
+    fn name_of(&self, id: usize) -> Option<&str> {
+        match &self.nodes[id].data {
+            NodeData::Element {
+                namespace: Namespace::Html,
+                name,
+                ..
+            } => Some(name.as_str()),
+            _ => None,
+        }
+    }
+
+    fn node_is(&self, id: usize, name: &str) -> bool {
+        self.name_of(id) == Some(name)
+    }
+
+    fn current_is(&self, name: &str) -> bool {
+        self.current_node().is_some_and(|id| self.node_is(id, name))
+    }
+
+    fn current_is_one_of(&self, names: &[&str]) -> bool {
+        self.current_node()
+            .and_then(|id| self.name_of(id))
+            .is_some_and(|name| names.contains(&name))
+    }
 
-#[derive(Debug)]
-pub struct HtmlDocument {
-    pub root_nodes: Vec<HtmlNode>,
-}
+    fn is_on_stack(&self, name: &str) -> bool {
+        self.open_elements.iter().any(|&id| self.node_is(id, name))
+    }
 
-#[derive(Debug)]
-pub enum HtmlNode {
-    Element(HtmlElement),
-    Text(String),
-    Comment(String),
-}
+    fn is_special(&self, id: usize) -> bool {
+        match self.element_name(id) {
+            Some((Namespace::Html, name)) => SPECIAL_ELEMENTS.contains(&name),
+            Some((namespace, name)) => is_special_foreign(namespace, name),
+            None => false,
+        }
+    }
 
-#[derive(Debug)]
-pub struct HtmlElement {
-    pub tag_name: String,
-    pub attributes: Vec<(String, String)>,
-    pub children: Vec<HtmlNode>,
-}
+    /// Whether `id` acts as a boundary for the given kind of scope.
+    fn is_scope_boundary(&self, id: usize, scope: Scope) -> bool {
+        if let Some((namespace, name)) = self.element_name(id).filter(|(namespace, _)| *namespace != Namespace::Html) {
+            return match scope {
+                Scope::Default | Scope::ListItem | Scope::Button => is_special_foreign(namespace, name),
+                Scope::Table => false,
+                Scope::Select => true,
+            };
+        }
+        let name = self.name_of(id).unwrap_or("");
+        match scope {
+            Scope::Default => DEFAULT_SCOPE.contains(&name),
+            Scope::ListItem => LIST_ITEM_SCOPE.contains(&name),
+            Scope::Button => BUTTON_SCOPE.contains(&name),
+            Scope::Table => TABLE_SCOPE.contains(&name),
+            Scope::Select => !matches!(name, "optgroup" | "option"),
+        }
+    }
 
-impl HtmlElement {
-    pub fn get_attribute(&self, name: &str) -> Option<&str> {
-        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    /// "Has an element in scope" for an element with the given tag name.
+    fn has_in_scope(&self, target: &str, scope: Scope) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if self.node_is(id, target) {
+                return true;
+            }
+            if self.is_scope_boundary(id, scope) {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// "Has an element in scope" for one specific node.
+    fn node_in_scope(&self, target: usize, scope: Scope) -> bool {
+        for &id in self.open_elements.iter().rev() {
+            if id == target {
+                return true;
+            }
+            if self.is_scope_boundary(id, scope) {
+                return false;
+            }
+        }
+        false
     }
-}
 
-/// Fonction principale de parsing HTML
+    /// Pops elements until one with one of the given names has been popped.
+    fn pop_until_one_of(&mut self, names: &[&str]) {
+        while let Some(id) = self.open_elements.pop() {
+            if self.name_of(id).is_some_and(|name| names.contains(&name)) {
+                break;
+            }
+        }
+    }
 
-/// A small struct to hold state for our parser
-pub(crate) struct HtmlParser {
-    tokens: Vec<Token>,
-    pos: usize,
-}
+    /// Pops elements until the current node has one of the given names.
+    fn clear_stack_back_to(&mut self, names: &[&str]) {
+        while !self.current_is_one_of(names) && !self.open_elements.is_empty() {
+            self.open_elements.pop();
+        }
+    }
 
-const VOID_ELEMENTS: &[&str] = &[
-    "area", "base", "br", "col", "embed", "hr", "img", "input",
-    "link", "meta", "param", "source", "track", "wbr", "!doctype",
-];
+    fn generate_implied_end_tags(&mut self, except: Option<&str>) {
+        while self.current_is_one_of(IMPLIED_END_TAGS) && !except.is_some_and(|name| self.current_is(name)) {
+            self.open_elements.pop();
+        }
+    }
 
-impl HtmlParser {
-    pub(crate) fn new(tokens: Vec<Token>) -> Self {
-        HtmlParser { tokens, pos: 0 }
+    fn generate_implied_end_tags_thoroughly(&mut self) {
+        while self.current_is_one_of(IMPLIED_END_TAGS_THOROUGHLY) {
+            self.open_elements.pop();
+        }
+    }
+
+    fn close_p_element(&mut self) {
+        self.generate_implied_end_tags(Some("p"));
+        if !self.current_is("p") {
+            self.error("unexpected-end-tag-p");
+        }
+        self.pop_until_one_of(&["p"]);
+    }
+
+    fn close_p_if_in_button_scope(&mut self) {
+        if self.has_in_scope("p", Scope::Button) {
+            self.close_p_element();
+        }
     }
 
-    /// Parse multiple top-level nodes
-    pub(crate) fn parse_nodes(&mut self) -> Result<Vec<HtmlNode>, ParserError> {
-        let mut nodes = Vec::new();
-        loop {
-            match self.current_token()? {
-                Token::Eof => break,
-                _ => {
-                    // Attempt to parse a node
-                    let node = self.parse_node()?;
-                    nodes.push(node);
+    /// "Reset the insertion mode appropriately".
+    fn reset_insertion_mode(&mut self) {
+        for index in (0..self.open_elements.len()).rev() {
+            let node = self.open_elements[index];
+            let last = index == 0;
+            // In the fragment case the root element stands for the context.
+            let name = match &self.context {
+                Some(context) if last => context.as_str(),
+                _ => self.name_of(node).unwrap_or(""),
+            };
+            self.mode = match name {
+                "select" if !last => {
+                    let in_table = self.open_elements[..index]
+                        .iter()
+                        .rev()
+                        .take_while(|&&ancestor| !self.node_is(ancestor, "template"))
+                        .any(|&ancestor| self.node_is(ancestor, "table"));
+                    if in_table {
+                        InsertionMode::InSelectInTable
+                    } else {
+                        InsertionMode::InSelect
+                    }
                 }
-            }
+                "td" | "th" if !last => InsertionMode::InCell,
+                "tr" => InsertionMode::InRow,
+                "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+                "caption" => InsertionMode::InCaption,
+                "colgroup" => InsertionMode::InColumnGroup,
+                "table" => InsertionMode::InTable,
+                "template" => *self.template_modes.last().unwrap_or(&InsertionMode::InTemplate),
+                "head" if !last => InsertionMode::InHead,
+                "body" => InsertionMode::InBody,
+                "frameset" => InsertionMode::InFrameset,
+                "html" => {
+                    if self.head.is_none() {
+                        InsertionMode::BeforeHead
+                    } else {
+                        InsertionMode::AfterHead
+                    }
+                }
+                _ if last => InsertionMode::InBody,
+                _ => continue,
+            };
+            return;
         }
-        Ok(nodes)
     }
 
-    /// Parse a single node: could be an element, comment, or text
-    fn parse_node(&mut self) -> Result<HtmlNode, ParserError> {
-        match self.current_token()? {
-            Token::StartTag(_, _) => Ok(HtmlNode::Element(self.parse_element()?)),
-            Token::Comment(text) => {
-                let comment_text = text.clone();
-                self.advance()?; // consume the Token::Comment
-                Ok(HtmlNode::Comment(comment_text))
+    fn formatting_index(&self, node: usize) -> Option<usize> {
+        self.active_formatting
+            .iter()
+            .position(|entry| matches!(entry, FormattingEntry::Element { node: id, .. } if *id == node))
+    }
+
+    fn remove_from_formatting(&mut self, node: usize) {
+        if let Some(index) = self.formatting_index(node) {
+            self.active_formatting.remove(index);
+        }
+    }
+
+    /// Finds the last element with the given name after the last marker.
+    fn formatting_element_after_last_marker(&self, name: &str) -> Option<usize> {
+        for entry in self.active_formatting.iter().rev() {
+            match entry {
+                FormattingEntry::Marker => return None,
+                FormattingEntry::Element { node, name: entry_name, .. } if entry_name == name => {
+                    return Some(*node)
+                }
+                _ => {}
             }
-            Token::Text(text) => {
-                let text_content = text.clone();
-                self.advance()?; // consume the Token::Text
-                Ok(HtmlNode::Text(text_content))
+        }
+        None
+    }
+
+    /// Pushes an element onto the list, applying the "Noah's Ark" clause: at
+    /// most three identical elements are kept after the last marker.
+    fn push_formatting_element(&mut self, node: usize, name: String, attributes: Vec<(String, String)>) {
+        let mut identical = Vec::new();
+        for (index, entry) in self.active_formatting.iter().enumerate().rev() {
+            match entry {
+                FormattingEntry::Marker => break,
+                FormattingEntry::Element { name: n, attributes: a, .. }
+                    if *n == name && same_attributes(a, &attributes) =>
+                {
+                    identical.push(index)
+                }
+                _ => {}
             }
-            other => Err(ParserError::Other(format!(
-                "Unexpected token in parse_node: {:?}",
-                other
-            ))),
         }
+        if identical.len() >= 3 {
+            let earliest = *identical.last().unwrap();
+            self.active_formatting.remove(earliest);
+        }
+        self.active_formatting.push(FormattingEntry::Element {
+            node,
+            name,
+            attributes,
+        });
     }
 
-    /// Parse an element: assumes current token is Token::StartTag
-    fn parse_element(&mut self) -> Result<HtmlElement, ParserError> {
-        // e.g., <div ...>
-        let (tag_name, attributes) = match self.current_token()? {
-            Token::StartTag(name, attrs) => (name.clone(), attrs.clone()),
-            other => {
-                return Err(ParserError::Other(format!(
-                    "Expected StartTag, found: {:?}",
-                    other
-                )))
+    fn clear_formatting_to_last_marker(&mut self) {
+        while let Some(entry) = self.active_formatting.pop() {
+            if matches!(entry, FormattingEntry::Marker) {
+                break;
             }
+        }
+    }
+
+    /// "Reconstruct the active formatting elements": reopens formatting
+    /// elements that were implicitly closed, e.g. `<b>` in `<p><b>x<p>y`.
+    fn reconstruct_active_formatting_elements(&mut self) {
+        let is_open = |parser: &Self, entry: &FormattingEntry| match entry {
+            FormattingEntry::Marker => true,
+            FormattingEntry::Element { node, .. } => parser.open_elements.contains(node),
         };
 
-        // Consume start tag
-        self.advance()?;
+        let Some(last) = self.active_formatting.last() else {
+            return;
+        };
+        if is_open(self, last) {
+            return;
+        }
 
-        // Check if the tag is a void element
-        if VOID_ELEMENTS.contains(&tag_name.to_lowercase().as_str()) {
-            // Void elements do not have children and do not require end tags
-            return Ok(HtmlElement {
-                tag_name,
-                attributes,
-                children: Vec::new(),
-            });
+        // Rewind to the first entry that needs to be reopened.
+        let mut index = self.active_formatting.len() - 1;
+        while index > 0 && !is_open(self, &self.active_formatting[index - 1]) {
+            index -= 1;
         }
 
-        // Parse children until we see a matching end tag or EOF
-        let children = self.parse_children(&tag_name)?;
+        // Create the elements again, advancing to the end of the list.
+        for entry_index in index..self.active_formatting.len() {
+            let (name, attributes) = match &self.active_formatting[entry_index] {
+                FormattingEntry::Element { name, attributes, .. } => (name.clone(), attributes.clone()),
+                FormattingEntry::Marker => continue,
+            };
+            let token = Token::StartTag {
+                name: name.clone(),
+                attributes: attributes.clone(),
+                self_closing: false,
+            };
+            let node = self.insert_html_element(&token);
+            if let FormattingEntry::Element { node: original, .. } = self.active_formatting[entry_index] {
+                self.nodes[node].span = self.nodes[original].span;
+            }
+            self.active_formatting[entry_index] = FormattingEntry::Element { node, name, attributes };
+        }
+    }
 
-        // Expect an EndTag
-        match self.current_token()? {
-            Token::EndTag(ref end_name) if end_name.eq_ignore_ascii_case(&tag_name) => {
-                self.advance()?; // Consume </tag_name>
+    /// The adoption agency algorithm, which fixes misnested formatting
+    /// elements such as `<b><i></b></i>`.
+    fn adoption_agency(&mut self, subject: &str) {
+        if let Some(current) = self.current_node() {
+            if self.node_is(current, subject) && self.formatting_index(current).is_none() {
+                self.open_elements.pop();
+                return;
             }
-            Token::EndTag(ref end_name) => {
-                return Err(ParserError::Other(format!(
-                    "Expected </{}>, but found </{}>",
-                    tag_name, end_name
-                )));
+        }
+
+        for _ in 0..8 {
+            let Some(formatting_element) = self.formatting_element_after_last_marker(subject) else {
+                self.any_other_end_tag(subject);
+                return;
+            };
+
+            let Some(formatting_stack_index) =
+                self.open_elements.iter().position(|&id| id == formatting_element)
+            else {
+                self.error("adoption-agency-1.2");
+                self.remove_from_formatting(formatting_element);
+                return;
+            };
+
+            if !self.node_in_scope(formatting_element, Scope::Default) {
+                self.error("adoption-agency-4.4");
+                return;
             }
-            other => {
-                return Err(ParserError::Other(format!(
-                    "Expected end tag </{}>, found: {:?}",
-                    tag_name, other
-                )));
+
+            if self.current_node() != Some(formatting_element) {
+                self.error("adoption-agency-1.3");
             }
-        }
 
-        Ok(HtmlElement {
-            tag_name: tag_name.to_lowercase(),
-            attributes,
-            children,
-        })
-    }
+            let furthest_block_index = (formatting_stack_index + 1..self.open_elements.len())
+                .find(|&index| self.is_special(self.open_elements[index]));
+
+            let Some(furthest_block_index) = furthest_block_index else {
+                self.open_elements.truncate(formatting_stack_index);
+                self.remove_from_formatting(formatting_element);
+                return;
+            };
+            let furthest_block = self.open_elements[furthest_block_index];
+            let common_ancestor = self.open_elements[formatting_stack_index - 1];
 
-    /// Parse children (HtmlNode) until we see a matching `</tag_name>` or EOF
-    fn parse_children(&mut self, parent_tag: &str) -> Result<Vec<HtmlNode>, ParserError> {
-        let mut children = Vec::new();
+            let mut bookmark = self.formatting_index(formatting_element).unwrap();
+            let mut node_index = furthest_block_index;
+            let mut last_node = furthest_block;
+            let mut inner_loop_counter = 0;
 
-        loop {
-            match self.current_token()? {
-                Token::EndTag(end_name) if end_name == parent_tag => {
-                    // Stop, let parse_element handle this end tag
+            loop {
+                inner_loop_counter += 1;
+                node_index -= 1;
+                let mut node = self.open_elements[node_index];
+                if node == formatting_element {
                     break;
                 }
-                Token::Eof => {
-                    // No more tokens; might be an unclosed parent, but let's just break
-                    break;
+
+                if inner_loop_counter > 3 {
+                    if let Some(index) = self.formatting_index(node) {
+                        self.active_formatting.remove(index);
+                        if index < bookmark {
+                            bookmark -= 1;
+                        }
+                    }
                 }
-                _ => {
-                    let child = self.parse_node()?;
-                    children.push(child);
+
+                let Some(entry_index) = self.formatting_index(node) else {
+                    self.open_elements.remove(node_index);
+                    continue;
+                };
+
+                let (name, attributes) = match &self.active_formatting[entry_index] {
+                    FormattingEntry::Element { name, attributes, .. } => (name.clone(), attributes.clone()),
+                    FormattingEntry::Marker => unreachable!(),
+                };
+                let new_element = self.create_element(&name, attributes.clone());
+                self.nodes[new_element].span = self.nodes[node].span;
+                self.active_formatting[entry_index] = FormattingEntry::Element {
+                    node: new_element,
+                    name,
+                    attributes,
+                };
+                self.open_elements[node_index] = new_element;
+                node = new_element;
+
+                if last_node == furthest_block {
+                    bookmark = entry_index + 1;
                 }
+
+                self.append_child(node, last_node);
+                last_node = node;
+            }
+
+            let point = self.appropriate_insertion_point(Some(common_ancestor));
+            self.insert_at(&point, last_node);
+
+            let (name, attributes) = match &self.active_formatting[self.formatting_index(formatting_element).unwrap()] {
+                FormattingEntry::Element { name, attributes, .. } => (name.clone(), attributes.clone()),
+                FormattingEntry::Marker => unreachable!(),
+            };
+            let new_element = self.create_element(&name, attributes.clone());
+            self.nodes[new_element].span = self.nodes[formatting_element].span;
+            let children = std::mem::take(&mut self.nodes[furthest_block].children);
+            for child in children {
+                self.nodes[child].parent = None;
+                self.append_child(new_element, child);
+            }
+            self.append_child(furthest_block, new_element);
+
+            let old_index = self.formatting_index(formatting_element).unwrap();
+            self.active_formatting.remove(old_index);
+            if old_index < bookmark {
+                bookmark -= 1;
             }
+            let bookmark = bookmark.min(self.active_formatting.len());
+            self.active_formatting.insert(
+                bookmark,
+                FormattingEntry::Element {
+                    node: new_element,
+                    name,
+                    attributes,
+                },
+            );
+
+            self.open_elements.retain(|&id| id != formatting_element);
+            let furthest_block_index = self
+                .open_elements
+                .iter()
+                .position(|&id| id == furthest_block)
+                .unwrap();
+            self.open_elements.insert(furthest_block_index + 1, new_element);
         }
+    }
+}
+
+/// Builds a start tag token without attributes, for implied elements.
+fn start_tag(name: &str) -> Token {
+    Token::StartTag {
+        name: name.to_string(),
+        attributes: Vec::new(),
+        self_closing: false,
+    }
+}
+
+/// Whitespace as understood by the tree construction stage.
+fn is_html_whitespace(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+fn is_whitespace(text: &str) -> bool {
+    text.chars().all(is_html_whitespace)
+}
 
-        Ok(children)
+/// Picks the document mode for a DOCTYPE token, following the rules of the
+/// "initial" insertion mode.
+fn document_mode_of(doctype: &Doctype) -> DocumentMode {
+    fn starts_with_any(id: &str, prefixes: &[&str]) -> bool {
+        prefixes.iter().any(|prefix| {
+            id.len() >= prefix.len() && id.as_bytes()[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+        })
     }
 
-    /// Returns the current token without consuming it
-    fn current_token(&self) -> Result<&Token, ParserError> {
-        self.tokens.get(self.pos).ok_or_else(|| {
-            ParserError::Other("Attempted to read beyond end of tokens".to_string())
+    let public_id = doctype.public_id.as_deref().unwrap_or("");
+    let system_id = doctype.system_id.as_deref();
+    let html401 = starts_with_any(public_id, HTML401_PUBLIC_ID_PREFIXES);
+
+    if doctype.force_quirks
+        || doctype.name.as_deref() != Some("html")
+        || QUIRKS_PUBLIC_IDS.iter().any(|id| id.eq_ignore_ascii_case(public_id))
+        || system_id.is_some_and(|id| {
+            id.eq_ignore_ascii_case("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd")
         })
+        || starts_with_any(public_id, QUIRKS_PUBLIC_ID_PREFIXES)
+        || (system_id.is_none() && html401)
+    {
+        DocumentMode::Quirks
+    } else if starts_with_any(public_id, LIMITED_QUIRKS_PUBLIC_ID_PREFIXES) || (system_id.is_some() && html401) {
+        DocumentMode::LimitedQuirks
+    } else {
+        DocumentMode::NoQuirks
+    }
+}
+
+/// Splits text into runs of whitespace, runs of NUL characters and runs of
+/// other characters.
+fn split_text_runs(text: &str) -> Vec<&str> {
+    fn kind(c: char) -> u8 {
+        if is_html_whitespace(c) {
+            0
+        } else if c == '\0' {
+            1
+        } else {
+            2
+        }
     }
 
-    /// Advances the parser to the next token
-    fn advance(&mut self) -> Result<(), ParserError> {
-        if self.pos < self.tokens.len() {
-            self.pos += 1;
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut current_kind = None;
+    for (index, c) in text.char_indices() {
+        let k = kind(c);
+        if current_kind.is_some_and(|current| current != k) {
+            runs.push(&text[start..index]);
+            start = index;
         }
-        Ok(())
+        current_kind = Some(k);
     }
+    if start < text.len() {
+        runs.push(&text[start..]);
+    }
+    runs
+}
+
+fn is_hidden_input(attributes: &[(String, String)]) -> bool {
+    attributes
+        .iter()
+        .any(|(key, value)| key == "type" && value.eq_ignore_ascii_case("hidden"))
+}
+
+/// Attribute lists are compared regardless of order.
+fn same_attributes(a: &[(String, String)], b: &[(String, String)]) -> bool {
+    a.len() == b.len() && a.iter().all(|attr| b.contains(attr))
 }
 
 
@@ -184,14 +2921,17 @@ impl HtmlParser {
 mod tests {
     use super::*;
     // imports from the same module where parse_html is defined
-    use crate::error::ParserError;
+    use crate::error::{ParserError, Severity};
     use crate::html::parse_html;
 
     #[test]
     fn test_parse_empty_input() -> Result<(), ParserError> {
         let input = "";
         let doc = parse_html(input)?;
-        assert!(doc.root_nodes.is_empty(), "Root nodes should be empty for empty input");
+        // The html, head and body elements are always created
+        assert_eq!(doc.root_nodes.len(), 1);
+        let body = doc.body().expect("Expected an implied <body>");
+        assert!(body.children.is_empty(), "Body should be empty for empty input");
         Ok(())
     }
 
@@ -199,8 +2939,9 @@ mod tests {
     fn test_parse_single_element_no_children() -> Result<(), ParserError> {
         let input = "<div></div>";
         let doc = parse_html(input)?;
-        assert_eq!(doc.root_nodes.len(), 1);
-        match &doc.root_nodes[0] {
+        let body = doc.body().expect("Expected an implied <body>");
+        assert_eq!(body.children.len(), 1);
+        match &body.children[0] {
             HtmlNode::Element(element) => {
                 assert_eq!(element.tag_name, "div");
                 assert!(element.children.is_empty(), "Expected no children for <div></div>");
@@ -214,9 +2955,10 @@ mod tests {
     fn test_parse_single_element_with_text_child() -> Result<(), ParserError> {
         let input = "<div>Hello World</div>";
         let doc = parse_html(input)?;
-        assert_eq!(doc.root_nodes.len(), 1);
+        let body = doc.body().expect("Expected an implied <body>");
+        assert_eq!(body.children.len(), 1);
 
-        match &doc.root_nodes[0] {
+        match &body.children[0] {
             HtmlNode::Element(element) => {
                 // Check tag name
                 assert_eq!(element.tag_name, "div");
@@ -224,7 +2966,7 @@ mod tests {
                 assert_eq!(element.children.len(), 1);
 
                 match &element.children[0] {
-                    HtmlNode::Text(text) => {
+                    HtmlNode::Text { text, .. } => {
                         assert_eq!(text, "Hello World");
                     }
                     _ => panic!("Expected a text node child"),
@@ -239,9 +2981,10 @@ mod tests {
     fn test_parse_nested_elements() -> Result<(), ParserError> {
         let input = "<div><span>Test</span></div>";
         let doc = parse_html(input)?;
-        assert_eq!(doc.root_nodes.len(), 1);
+        let body = doc.body().expect("Expected an implied <body>");
+        assert_eq!(body.children.len(), 1);
 
-        match &doc.root_nodes[0] {
+        match &body.children[0] {
             HtmlNode::Element(element) => {
                 assert_eq!(element.tag_name, "div");
                 assert_eq!(element.children.len(), 1);
@@ -252,7 +2995,7 @@ mod tests {
                         assert_eq!(child_elem.children.len(), 1);
 
                         match &child_elem.children[0] {
-                            HtmlNode::Text(text) => {
+                            HtmlNode::Text { text, .. } => {
                                 assert_eq!(text, "Test");
                             }
                             _ => panic!("Expected text node within <span>"),
@@ -271,26 +3014,27 @@ mod tests {
         let input = "<!-- A comment --><div>Content</div>";
         let doc = parse_html(input)?;
         assert_eq!(doc.root_nodes.len(), 2);
+        let body = doc.body().expect("Expected an implied <body>");
 
         match &doc.root_nodes[0] {
-            HtmlNode::Comment(comment_text) => {
+            HtmlNode::Comment { text: comment_text, .. } => {
                 assert_eq!(comment_text, " A comment ");
             }
             _ => panic!("First node should be a comment"),
         }
 
-        match &doc.root_nodes[1] {
+        match &body.children[0] {
             HtmlNode::Element(element) => {
                 assert_eq!(element.tag_name, "div");
                 assert_eq!(element.children.len(), 1);
                 match &element.children[0] {
-                    HtmlNode::Text(text) => {
+                    HtmlNode::Text { text, .. } => {
                         assert_eq!(text, "Content");
                     }
                     _ => panic!("Expected text node in <div>"),
                 }
             }
-            _ => panic!("Expected an element node as second child"),
+            _ => panic!("Expected an element node in <body>"),
         }
 
         Ok(())
@@ -300,11 +3044,12 @@ mod tests {
     fn test_parse_multiple_siblings() -> Result<(), ParserError> {
         let input = "<p>One</p><p>Two</p><p>Three</p>";
         let doc = parse_html(input)?;
-        assert_eq!(doc.root_nodes.len(), 3);
+        let body = doc.body().expect("Expected an implied <body>");
+        assert_eq!(body.children.len(), 3);
 
-        let text_values: Vec<String> = doc.root_nodes.iter().map(|node| {
+        let text_values: Vec<String> = body.children.iter().map(|node| {
             if let HtmlNode::Element(e) = node {
-                if let Some(HtmlNode::Text(t)) = e.children.get(0) {
+                if let Some(HtmlNode::Text { text: t, .. }) = e.children.first() {
                     return t.clone();
                 }
             }
@@ -319,9 +3064,10 @@ mod tests {
     fn test_parse_attributes() -> Result<(), ParserError> {
         let input = r#"<div id="main" class="container"></div>"#;
         let doc = parse_html(input)?;
-        assert_eq!(doc.root_nodes.len(), 1);
+        let body = doc.body().expect("Expected an implied <body>");
+        assert_eq!(body.children.len(), 1);
 
-        match &doc.root_nodes[0] {
+        match &body.children[0] {
             HtmlNode::Element(element) => {
                 assert_eq!(element.tag_name, "div");
                 // Check attributes
@@ -341,11 +3087,14 @@ mod tests {
     #[test]
     fn test_unclosed_element() {
         let input = "<div>";
-        let result = parse_html(input);
-        // Depending on how you handle errors, this may be Ok or Err:
-        // If your parser stops at EOF without matching </div>, it could succeed or return an error
-        // Here we assume it's an error:
-        assert!(result.is_err(), "Parser should error on unclosed element <div>");
+        let doc = parse_html(input).expect("Parsing never fails on malformed markup");
+        // The element is closed at EOF, and the problem is reported on the side
+        let body = doc.body().expect("Expected an implied <body>");
+        match &body.children[0] {
+            HtmlNode::Element(element) => assert_eq!(element.tag_name, "div"),
+            _ => panic!("Expected the unclosed <div> in <body>"),
+        }
+        assert!(!doc.errors.is_empty(), "An unclosed <div> should be reported as a parse error");
     }
 
     #[test]
@@ -361,20 +3110,63 @@ The document has moved
         assert_eq!(doc.root_nodes.len(), 1);
         match &doc.root_nodes[0] {
             HtmlNode::Element(element) => {
-                assert_eq!(element.tag_name, "HTML");
+                assert_eq!(element.tag_name, "html");
                 assert_eq!(element.children.len(), 2);
             }
             _ => panic!("Expected a single element node"),
         }
+
+        let link = doc.body().unwrap().child_element("a").expect("Expected the <A> link");
+        assert_eq!(link.attributes, [("href".to_string(), "http://www.google.fr/".to_string())]);
+        assert_eq!(link.get_attribute("href"), Some("http://www.google.fr/"));
+        assert_eq!(link.get_attribute("HREF"), Some("http://www.google.fr/"));
+        assert!(link.has_attribute("Href"));
+    }
+
+    #[test]
+    fn test_attribute_api() {
+        let doc = parse_html("<p id=intro ID=other class=' a b  a\tc '>x</p><svg viewBox='0 0 1 1'></svg>").unwrap();
+        let body = doc.body().unwrap();
+        let p = body.child_element("p").unwrap();
+        assert_eq!(p.id(), Some("intro"));
+        assert_eq!(p.class_list(), ["a", "b", "c"]);
+        assert_eq!(p.attributes.len(), 2);
+
+        let mut p = HtmlElement {
+            tag_name: "p".to_string(),
+            namespace: Namespace::Html,
+            attributes: vec![("title".to_string(), "a".to_string())],
+            children: Vec::new(),
+            template_contents: None,
+            span: SourceSpan::default(),
+        };
+        p.set_attribute("TITLE", "b");
+        p.set_attribute("Lang", "fr");
+        assert_eq!(
+            p.attributes,
+            [("title".to_string(), "b".to_string()), ("lang".to_string(), "fr".to_string())]
+        );
+        assert_eq!(p.remove_attribute("title"), Some("b".to_string()));
+        assert_eq!(p.remove_attribute("title"), None);
+        assert!(!p.has_attribute("title"));
+        assert_eq!(p.id(), None);
+        p.set_attribute("id", "");
+        assert_eq!(p.id(), None);
+
+        // Names of foreign attributes are case-sensitive
+        let svg = body.child_element("svg").unwrap();
+        assert_eq!(svg.get_attribute("viewBox"), Some("0 0 1 1"));
+        assert_eq!(svg.get_attribute("viewbox"), None);
     }
 
     #[test]
     fn test_button_value_with_spaces() -> Result<(), ParserError> {
         let input = r#"<button value="Et un bouton"></button>"#;
         let doc = parse_html(input)?;
-        assert_eq!(doc.root_nodes.len(), 1);
+        let body = doc.body().expect("Expected an implied <body>");
+        assert_eq!(body.children.len(), 1);
 
-        match &doc.root_nodes[0] {
+        match &body.children[0] {
             HtmlNode::Element(element) => {
                 // Check the tag name
                 assert_eq!(element.tag_name.to_lowercase(), "button");
@@ -387,4 +3179,384 @@ The document has moved
 
         Ok(())
     }
+
+    /// Serializes nodes in a compact form, to compare trees in one assertion.
+    fn dump(nodes: &[HtmlNode]) -> String {
+        nodes
+            .iter()
+            .map(|node| match node {
+                HtmlNode::Element(e) => {
+                    let contents = match &e.template_contents {
+                        Some(contents) => format!("#content({})", dump(contents)),
+                        None => String::new(),
+                    };
+                    format!("<{}>{}{}</{}>", e.tag_name, contents, dump(&e.children), e.tag_name)
+                }
+                HtmlNode::Text { text, .. } => text.clone(),
+                HtmlNode::Comment { text, .. } => format!("<!--{}-->", text),
+                HtmlNode::Doctype { name, .. } => format!("<!DOCTYPE {}>", name),
+            })
+            .collect()
+    }
+
+    fn body_of(input: &str) -> String {
+        let doc = parse_html(input).unwrap();
+        dump(&doc.body().expect("Expected a <body>").children)
+    }
+
+    #[test]
+    fn test_implied_end_tags() {
+        assert_eq!(body_of("<p>One<p>Two"), "<p>One</p><p>Two</p>");
+        assert_eq!(body_of("<ul><li>a<li>b</ul>"), "<ul><li>a</li><li>b</li></ul>");
+        assert_eq!(body_of("<dl><dt>t<dd>d</dl>"), "<dl><dt>t</dt><dd>d</dd></dl>");
+        assert_eq!(body_of("<p>a<div>b</div>"), "<p>a</p><div>b</div>");
+    }
+
+    #[test]
+    fn test_document_skeleton_is_implied() {
+        let doc = parse_html("<title>T</title><p>x").unwrap();
+        assert_eq!(
+            dump(&doc.root_nodes),
+            "<html><head><title>T</title></head><body><p>x</p></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_adoption_agency_for_misnested_formatting() {
+        assert_eq!(body_of("<b>1<p>2</b>3</p>"), "<b>1</b><p><b>2</b>3</p>");
+        assert_eq!(body_of("<b><i>x</b>y</i>"), "<b><i>x</i></b><i>y</i>");
+        assert_eq!(body_of("<a href=x>1<a href=y>2"), "<a>1</a><a>2</a>");
+    }
+
+    #[test]
+    fn test_formatting_elements_are_reopened() {
+        assert_eq!(body_of("<p><b>x<p>y"), "<p><b>x</b></p><p><b>y</b></p>");
+    }
+
+    #[test]
+    fn test_foster_parenting_in_tables() {
+        assert_eq!(
+            body_of("<table>a<tr><td>b</td></tr></table>"),
+            "a<table><tbody><tr><td>b</td></tr></tbody></table>"
+        );
+        assert_eq!(
+            body_of("<table><td>x<td>y</table>"),
+            "<table><tbody><tr><td>x</td><td>y</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn test_script_content_is_raw_text() {
+        let doc = parse_html("<script>if (a<b) { x = '</p>'; }</script>").unwrap();
+        assert_eq!(
+            dump(&doc.head().unwrap().children),
+            "<script>if (a<b) { x = '</p>'; }</script>"
+        );
+    }
+
+    #[test]
+    fn test_mismatched_end_tags_are_recovered() {
+        let doc = parse_html("<div><span>a</div>b</span>").unwrap();
+        assert_eq!(dump(&doc.body().unwrap().children), "<div><span>a</span></div>b");
+        assert!(!doc.errors.is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors_have_codes_and_positions() {
+        let doc = parse_html("<!DOCTYPE html>\n<p id=>x</i>\n<div").unwrap();
+        let errors: Vec<_> = doc
+            .errors
+            .iter()
+            .map(|error| (error.code, error.span.line, error.span.column))
+            .collect();
+        assert_eq!(
+            errors,
+            [("missing-attribute-value", 2, 7), ("unexpected-end-tag", 2, 9), ("eof-in-tag", 3, 4)]
+        );
+        assert!(doc.errors.iter().all(|error| error.severity == Severity::Error));
+    }
+
+    /// Asserts that `element` has exactly one child, a text node with `expected`.
+    fn assert_single_text_child(element: &HtmlElement, expected: &str) {
+        match &element.children[..] {
+            [HtmlNode::Text { text, .. }] => assert_eq!(text, expected),
+            other => panic!("Expected a single text child in <{}>, got {:?}", element.tag_name, other),
+        }
+    }
+
+    #[test]
+    fn test_raw_text_elements_have_a_single_text_child() {
+        let input = "<title>a < b &amp; c</title><style>p > a { x: '</p>' }</style>\
+            <script>if (a < b) { document.write('<div>'); } <!-- </div> --></script>\
+            <body><textarea><b>&lt;bold&gt;</b></textarea>";
+        let doc = parse_html(input).unwrap();
+        let head = doc.head().unwrap();
+        assert_single_text_child(head.child_element("title").unwrap(), "a < b & c");
+        assert_single_text_child(head.child_element("style").unwrap(), "p > a { x: '</p>' }");
+        assert_single_text_child(
+            head.child_element("script").unwrap(),
+            "if (a < b) { document.write('<div>'); } <!-- </div> -->",
+        );
+        let body = doc.body().unwrap();
+        assert_single_text_child(body.child_element("textarea").unwrap(), "<b><bold></b>");
+    }
+
+    #[test]
+    fn test_textarea_and_pre_drop_leading_newline() {
+        let doc = parse_html("<textarea>\nline 1\nline 2</textarea><pre>\n\ncode</pre>").unwrap();
+        let body = doc.body().unwrap();
+        assert_eq!(body.child_element("textarea").unwrap().text_content(), "line 1\nline 2");
+        assert_eq!(body.child_element("pre").unwrap().text_content(), "\ncode");
+    }
+
+    #[test]
+    fn test_unclosed_raw_text_runs_to_eof() {
+        let doc = parse_html("<script>var a = '<p>';").unwrap();
+        assert_single_text_child(doc.head().unwrap().child_element("script").unwrap(), "var a = '<p>';");
+        assert!(!doc.errors.is_empty());
+    }
+
+    #[test]
+    fn test_whitespace_is_preserved() {
+        assert_eq!(body_of("<b>a</b> <i>b</i>"), "<b>a</b> <i>b</i>");
+        assert_eq!(body_of("<pre>  two\n\tlines  </pre>"), "<pre>  two\n\tlines  </pre>");
+        assert_eq!(body_of("<p>\n  text\n</p>\n"), "<p>\n  text\n</p>\n");
+    }
+
+    #[test]
+    fn test_source_spans() {
+        let input = "<p class=a>\r\n  h\u{e9}llo <b>x</b></p>";
+        let doc = parse_html(input).unwrap();
+        let p = doc.body().unwrap().child_element("p").unwrap();
+        assert_eq!(&input[p.span.start..p.span.end], "<p class=a>");
+        assert_eq!((p.span.line, p.span.column), (1, 1));
+
+        let text = &p.children[0];
+        assert_eq!(&input[text.span().start..text.span().end], "\r\n  h\u{e9}llo ");
+        let b = p.child_element("b").unwrap();
+        assert_eq!(&input[b.span.start..b.span.end], "<b>");
+        assert_eq!((b.span.line, b.span.column), (2, 9));
+
+        // Implied elements have an empty span where they were inserted.
+        let body = doc.body().unwrap();
+        assert_eq!((body.span.start, body.span.end), (0, 0));
+    }
+
+    #[test]
+    fn test_deep_nesting_is_capped() {
+        fn depth(nodes: &[HtmlNode]) -> usize {
+            nodes
+                .iter()
+                .map(|node| match node {
+                    HtmlNode::Element(element) => 1 + depth(&element.children),
+                    _ => 1,
+                })
+                .max()
+                .unwrap_or(0)
+        }
+
+        let input = format!("{}x", "<div>".repeat(4 * MAX_DEPTH));
+        let doc = parse_html(&input).unwrap();
+        assert!(depth(&doc.root_nodes) <= MAX_DEPTH + 2);
+        assert_eq!(doc.body().unwrap().text_content(), "x");
+
+        // The same goes for the snapshots of a streaming parse
+        let mut parser = crate::html::StreamingParser::new();
+        parser.feed(input.as_bytes());
+        assert!(depth(&parser.document().root_nodes) <= MAX_DEPTH + 2);
+    }
+
+    #[test]
+    fn test_doctype_node() {
+        let doc = parse_html("<!DOCTYPE html><p>x").unwrap();
+        match &doc.root_nodes[0] {
+            HtmlNode::Doctype { name, public_id, system_id, .. } => {
+                assert_eq!((name.as_str(), public_id, system_id), ("html", &None, &None));
+            }
+            other => panic!("Expected a DOCTYPE node, got {:?}", other),
+        }
+        assert_eq!(doc.document_element().unwrap().tag_name, "html");
+        assert_eq!(doc.mode, DocumentMode::NoQuirks);
+        assert!(doc.errors.is_empty());
+    }
+
+    #[test]
+    fn test_doctype_empty_identifiers() {
+        // An empty identifier is not a missing one
+        let doc = parse_html("<!DOCTYPE html PUBLIC \"\"><p>x").unwrap();
+        match &doc.root_nodes[0] {
+            HtmlNode::Doctype { public_id, system_id, .. } => {
+                assert_eq!((public_id.as_deref(), system_id.as_deref()), (Some(""), None));
+            }
+            other => panic!("Expected a DOCTYPE node, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_document_mode() {
+        let mode_of = |input: &str| parse_html(input).unwrap().mode;
+        assert_eq!(mode_of("<p>no doctype"), DocumentMode::Quirks);
+        assert_eq!(mode_of("<!DOCTYPE html SYSTEM \"about:legacy-compat\">"), DocumentMode::NoQuirks);
+        assert_eq!(
+            mode_of("<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 3.2 Final//EN\">"),
+            DocumentMode::Quirks
+        );
+        assert_eq!(
+            mode_of("<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\">"),
+            DocumentMode::Quirks
+        );
+        assert_eq!(
+            mode_of(
+                "<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \
+                 \"http://www.w3.org/TR/html4/loose.dtd\">"
+            ),
+            DocumentMode::LimitedQuirks
+        );
+        assert_eq!(
+            mode_of("<!doctype html public \"-//w3c//dtd xhtml 1.0 transitional//en\" \"x\">"),
+            DocumentMode::LimitedQuirks
+        );
+        assert_eq!(mode_of("<!DOCTYPE svg>"), DocumentMode::Quirks);
+        assert_eq!(mode_of("<!DOCTYPE>"), DocumentMode::Quirks);
+    }
+
+    #[test]
+    fn test_quirks_mode_table_does_not_close_p() {
+        assert_eq!(body_of("<p><table></table>"), "<p><table></table></p>");
+        assert_eq!(body_of("<!DOCTYPE html><p><table></table>"), "<p></p><table></table>");
+    }
+
+    #[test]
+    fn test_template_contents() {
+        let doc = parse_html("<template><td>x</td><b>y</template><p>z").unwrap();
+        let head = doc.head().unwrap();
+        assert_eq!(dump(&head.children), "<template>#content(<td>x</td><b>y</b>)</template>");
+        let template = head.child_element("template").unwrap();
+        assert!(template.children.is_empty());
+        assert_eq!(template.text_content(), "");
+        assert_eq!(body_of("<template><td>x</td><b>y</template><p>z"), "<p>z</p>");
+
+        assert_eq!(
+            body_of("<div><template><tr><td>a</template>b</div>"),
+            "<div><template>#content(<tr><td>a</td></tr>)</template>b</div>"
+        );
+        assert_eq!(
+            body_of("<body><template><template><li>a</template><li>b</template>"),
+            "<template>#content(<template>#content(<li>a</li>)</template><li>b</li>)</template>"
+        );
+        assert_eq!(
+            body_of("<table><template><col></template><tr><td>c</table>"),
+            "<table><template>#content(<col></col>)</template><tbody><tr><td>c</td></tr></tbody></table>"
+        );
+    }
+
+    #[test]
+    fn test_template_without_end_tag() {
+        assert_eq!(body_of("<body><template>a<p>b"), "<template>#content(a<p>b</p>)</template>");
+        assert_eq!(fragment_of("<td>x</td>", "template"), "<td>x</td>");
+    }
+
+    #[test]
+    fn test_svg_names_keep_their_case() {
+        let doc =
+            parse_html("<svg VIEWBOX='0 0 10 10'><linearGradient/><foreignObject><p>x</foreignObject></svg>")
+                .unwrap();
+        let svg = doc.body().unwrap().child_element("svg").unwrap();
+        assert_eq!(svg.namespace, Namespace::Svg);
+        assert_eq!(svg.attributes, vec![("viewBox".to_string(), "0 0 10 10".to_string())]);
+        assert_eq!(
+            dump(&svg.children),
+            "<linearGradient></linearGradient><foreignObject><p>x</p></foreignObject>"
+        );
+
+        // Inside foreignObject, elements are HTML again.
+        let foreign_object = svg.child_element("foreignObject").unwrap();
+        let p = foreign_object.child_element("p").unwrap();
+        assert_eq!(p.namespace, Namespace::Html);
+    }
+
+    #[test]
+    fn test_mathml() {
+        let doc = parse_html("<math definitionurl=u><mi>x</mi><annotation-xml encoding='TEXT/HTML'><div>d</div>\
+                              </annotation-xml></math>")
+            .unwrap();
+        let math = doc.body().unwrap().child_element("math").unwrap();
+        assert_eq!(math.namespace, Namespace::MathMl);
+        assert_eq!(math.get_attribute("definitionURL"), Some("u"));
+        let annotation = math.child_element("annotation-xml").unwrap();
+        assert_eq!(annotation.namespace, Namespace::MathMl);
+        assert_eq!(annotation.child_element("div").unwrap().namespace, Namespace::Html);
+
+        // Without the encoding, the div breaks out of the math element.
+        assert_eq!(
+            body_of("<math><annotation-xml><div>d</div></annotation-xml></math>"),
+            "<math><annotation-xml></annotation-xml></math><div>d</div>"
+        );
+    }
+
+    #[test]
+    fn test_breakout_of_foreign_content() {
+        assert_eq!(body_of("<svg><g><p>x</p></g></svg>"), "<svg><g></g></svg><p>x</p>");
+        assert_eq!(body_of("<svg><font color=red>x</font></svg>"), "<svg></svg><font>x</font>");
+        assert_eq!(body_of("<svg><font>x</font></svg>"), "<svg><font>x</font></svg>");
+        assert_eq!(
+            body_of("<svg><title><b>t</b></title><rect/></svg>"),
+            "<svg><title><b>t</b></title><rect></rect></svg>"
+        );
+        assert_eq!(body_of("<p><svg><circle></p>x"), "<p><svg><circle></circle></svg></p>x");
+    }
+
+    #[test]
+    fn test_foreign_attribute_namespaces() {
+        let doc = parse_html("<svg><use xlink:href=#a xml:lang=fr class=c></use></svg><a xlink:href=b>").unwrap();
+        let body = doc.body().unwrap();
+        let svg_use = body.child_element("svg").unwrap().child_element("use").unwrap();
+        assert_eq!(svg_use.attribute_namespace("xlink:href"), Some(Namespace::XLink));
+        assert_eq!(svg_use.attribute_namespace("xml:lang"), Some(Namespace::Xml));
+        assert_eq!(svg_use.attribute_namespace("class"), None);
+        let a = body.child_element("a").unwrap();
+        assert_eq!(a.attribute_namespace("xlink:href"), None);
+    }
+
+    #[test]
+    fn test_cdata_in_foreign_content() {
+        assert_eq!(body_of("<svg><![CDATA[a<b]]></svg>"), "<svg>a<b</svg>");
+        assert_eq!(body_of("<div><![CDATA[a]]></div>"), "<div><!--[CDATA[a]]--></div>");
+        // The foreignObject is an SVG element, but not its HTML children.
+        assert_eq!(
+            body_of("<svg><foreignObject><![CDATA[a]]><p><![CDATA[b]]></foreignObject></svg>"),
+            "<svg><foreignObject>a<p><!--[CDATA[b]]--></p></foreignObject></svg>"
+        );
+    }
+
+    #[test]
+    fn test_svg_fragment_context() {
+        let fragment = crate::html::parse_fragment("<rect/><clippath></clippath>", "svg").unwrap();
+        assert_eq!(dump(&fragment.nodes), "<rect></rect><clipPath></clipPath>");
+        match &fragment.nodes[0] {
+            HtmlNode::Element(rect) => assert_eq!(rect.namespace, Namespace::Svg),
+            _ => panic!("Expected an element"),
+        }
+    }
+
+    fn fragment_of(input: &str, context: &str) -> String {
+        dump(&crate::html::parse_fragment(input, context).unwrap().nodes)
+    }
+
+    #[test]
+    fn test_parse_fragment() {
+        assert_eq!(fragment_of("<td>x</td>", "tr"), "<td>x</td>");
+        assert_eq!(fragment_of("<td>x</td>", "div"), "x");
+        assert_eq!(fragment_of("<tr><td>x", "table"), "<tbody><tr><td>x</td></tr></tbody>");
+        assert_eq!(fragment_of("<p>a<b>b</p>c", "DIV"), "<p>a<b>b</b></p><b>c</b>");
+        assert_eq!(fragment_of("<option>a<option>b", "select"), "<option>a</option><option>b</option>");
+        assert_eq!(fragment_of("<title>x</title><p>", "head"), "<title>x</title><p></p>");
+    }
+
+    #[test]
+    fn test_parse_fragment_raw_text_context() {
+        assert_eq!(fragment_of("a &amp; <b>c</b>", "textarea"), "a & <b>c</b>");
+        assert_eq!(fragment_of("a &amp; <b>c</b>", "script"), "a &amp; <b>c</b>");
+        assert_eq!(fragment_of("a &amp; <b>c</b>", "noscript"), "a & <b>c</b>");
+    }
 }
@@ -0,0 +1,289 @@
+//! HTML serializer.
+//!
+//! Turns a parsed tree back into markup, following the HTML fragment
+//! serialization algorithm of the WHATWG specification (§13.3). Text and
+//! attribute values are escaped, void elements get no end tag and the content
+//! of raw text elements is written as is, so serializing a parsed document and
+//! parsing the result again gives back the same tree.
+//!
+//! A pretty-printing mode puts every node on its own indented line. It is
+//! meant for reading: whitespace between nodes is dropped or added, which can
+//! change the rendering of inline content.
+
+use crate::html::foreign::Namespace;
+use crate::html::parser::{HtmlDocument, HtmlElement, HtmlNode, VOID_ELEMENTS};
+
+/// Elements whose text content is serialized without escaping. `<noscript>`
+/// is not part of the list since the parser runs with scripting disabled.
+const RAW_TEXT_ELEMENTS: &[&str] =
+    &["style", "script", "xmp", "iframe", "noembed", "noframes", "plaintext"];
+
+/// Elements whose whitespace is significant, kept on one line when pretty
+/// printing.
+const PREFORMATTED_ELEMENTS: &[&str] = &["pre", "textarea", "listing"];
+
+const INDENT: &str = "  ";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SerializeOptions {
+    /// Puts each node on its own line, indented by its depth in the tree.
+    pub pretty: bool,
+}
+
+/// Serializes a list of sibling nodes, as found in `HtmlDocument::root_nodes`
+/// or `HtmlElement::children`.
+pub fn serialize_nodes(nodes: &[HtmlNode], options: SerializeOptions) -> String {
+    let mut serializer = Serializer {
+        output: String::new(),
+        pretty: options.pretty,
+    };
+    serializer.children(None, nodes, 0);
+    serializer.output
+}
+
+impl HtmlDocument {
+    /// Serializes the whole document.
+    pub fn serialize(&self, options: SerializeOptions) -> String {
+        serialize_nodes(&self.root_nodes, options)
+    }
+}
+
+impl HtmlElement {
+    /// Returns the markup of the element and its content, like the DOM's
+    /// `outerHTML`.
+    pub fn outer_html(&self) -> String {
+        let mut serializer = Serializer {
+            output: String::new(),
+            pretty: false,
+        };
+        serializer.element(self, 0);
+        serializer.output
+    }
+
+    /// Returns the markup of the element's content, like the DOM's
+    /// `innerHTML`.
+    pub fn inner_html(&self) -> String {
+        let mut serializer = Serializer {
+            output: String::new(),
+            pretty: false,
+        };
+        let children = self.template_contents.as_ref().unwrap_or(&self.children);
+        let parent = (self.namespace == Namespace::Html).then_some(self.tag_name.as_str());
+        serializer.children(parent, children, 0);
+        serializer.output
+    }
+}
+
+struct Serializer {
+    output: String,
+    pretty: bool,
+}
+
+impl Serializer {
+    /// Serializes `nodes`, the children of the HTML element named `parent`.
+    /// SVG and MathML parents are given as `None`: their text is always
+    /// escaped.
+    fn children(&mut self, parent: Option<&str>, nodes: &[HtmlNode], depth: usize) {
+        for node in nodes {
+            if self.pretty {
+                if let HtmlNode::Text { text, .. } = node {
+                    if text.trim().is_empty() {
+                        continue;
+                    }
+                }
+                self.new_line(depth);
+            }
+            self.node(parent, node, depth);
+        }
+    }
+
+    fn node(&mut self, parent: Option<&str>, node: &HtmlNode, depth: usize) {
+        match node {
+            HtmlNode::Element(element) => self.element(element, depth),
+            HtmlNode::Text { text, .. } => {
+                if parent.is_some_and(|parent| RAW_TEXT_ELEMENTS.contains(&parent)) {
+                    self.output.push_str(text);
+                } else if self.pretty {
+                    escape(&mut self.output, text.trim(), false);
+                } else {
+                    escape(&mut self.output, text, false);
+                }
+            }
+            HtmlNode::Comment { text, .. } => {
+                self.output.push_str("<!--");
+                self.output.push_str(text);
+                self.output.push_str("-->");
+            }
+            HtmlNode::Doctype { name, .. } => {
+                self.output.push_str("<!DOCTYPE ");
+                self.output.push_str(name);
+                self.output.push('>');
+            }
+        }
+    }
+
+    fn element(&mut self, element: &HtmlElement, depth: usize) {
+        let tag_name = element.tag_name.as_str();
+        self.output.push('<');
+        self.output.push_str(tag_name);
+        for (name, value) in &element.attributes {
+            self.output.push(' ');
+            self.output.push_str(name);
+            self.output.push_str("=\"");
+            escape(&mut self.output, value, true);
+            self.output.push('"');
+        }
+        self.output.push('>');
+        // Foreign elements are never void nor raw text, even with an HTML name
+        let html = (element.namespace == Namespace::Html).then_some(tag_name);
+        if html.is_some_and(|name| VOID_ELEMENTS.contains(&name)) {
+            return;
+        }
+
+        // The markup of a template is that of its contents
+        let children = element.template_contents.as_ref().unwrap_or(&element.children);
+        let inline = !self.pretty
+            || html.is_some_and(|name| RAW_TEXT_ELEMENTS.contains(&name))
+            || html.is_some_and(|name| PREFORMATTED_ELEMENTS.contains(&name))
+            || children.iter().all(|child| matches!(child, HtmlNode::Text { .. }));
+        if inline {
+            // The content is written as is, on the same line
+            let pretty = std::mem::replace(&mut self.pretty, false);
+            self.children(html, children, depth);
+            self.pretty = pretty;
+        } else {
+            self.children(html, children, depth + 1);
+            self.new_line(depth);
+        }
+
+        self.output.push_str("</");
+        self.output.push_str(tag_name);
+        self.output.push('>');
+    }
+
+    fn new_line(&mut self, depth: usize) {
+        if !self.output.is_empty() {
+            self.output.push('\n');
+        }
+        for _ in 0..depth {
+            self.output.push_str(INDENT);
+        }
+    }
+}
+
+/// Escapes a string (§13.3, "escaping a string"). In attribute mode the
+/// quotes are escaped as well.
+pub(crate) fn escape(output: &mut String, text: &str, attribute_mode: bool) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '\u{A0}' => output.push_str("&nbsp;"),
+            '"' if attribute_mode => output.push_str("&quot;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            _ => output.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::parse_html;
+
+    fn round_trip(input: &str) -> String {
+        parse_html(input).unwrap().serialize(SerializeOptions::default())
+    }
+
+    #[test]
+    fn test_serialize_document() {
+        assert_eq!(
+            round_trip("<!DOCTYPE html><title>A</title><p class=x>Hello <b>world</b>"),
+            "<!DOCTYPE html><html><head><title>A</title></head>\
+             <body><p class=\"x\">Hello <b>world</b></p></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_escaping() {
+        assert_eq!(
+            round_trip("<p title='a \"b\" &amp; <c>'>1 &lt; 2 &amp;&nbsp;3 \"4\"</p>"),
+            "<html><head></head><body><p title=\"a &quot;b&quot; &amp; &lt;c&gt;\">\
+             1 &lt; 2 &amp;&nbsp;3 \"4\"</p></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_void_and_raw_text_elements() {
+        assert_eq!(
+            round_trip("<br><img src=a.png><script>if (a < b && c) {}</script><!-- x -->"),
+            "<html><head></head><body><br><img src=\"a.png\">\
+             <script>if (a < b && c) {}</script><!-- x --></body></html>"
+        );
+        assert_eq!(
+            round_trip("<textarea><b>&amp;</b></textarea>"),
+            "<html><head></head><body><textarea>&lt;b&gt;&amp;&lt;/b&gt;</textarea></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_outer_and_inner_html() {
+        let document = parse_html("<div id=a><p>x</p><br>y</div>").unwrap();
+        let div = document.body().unwrap().child_element("div").unwrap();
+        assert_eq!(div.outer_html(), "<div id=\"a\"><p>x</p><br>y</div>");
+        assert_eq!(div.inner_html(), "<p>x</p><br>y");
+    }
+
+    #[test]
+    fn test_template_contents() {
+        let document = parse_html("<template><tr><td>a</td></tr></template>").unwrap();
+        let template = document.head().unwrap().child_element("template").unwrap();
+        assert_eq!(template.inner_html(), "<tr><td>a</td></tr>");
+        assert_eq!(template.outer_html(), "<template><tr><td>a</td></tr></template>");
+    }
+
+    #[test]
+    fn test_foreign_elements() {
+        assert_eq!(
+            round_trip("<svg viewbox='0 0 1 1'><style>a>b</style><img/><br/></svg>"),
+            "<html><head></head><body><svg viewBox=\"0 0 1 1\"><style>a&gt;b</style></svg>\
+             <img><br></body></html>"
+        );
+        assert_eq!(
+            round_trip("<math><mi xlink:href=a>x</mi></math>"),
+            "<html><head></head><body><math><mi xlink:href=\"a\">x</mi></math></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_reparse_gives_same_tree() {
+        let input = "<!DOCTYPE html><table><tr><td>a<td>b</table><ul><li>1<li>2</ul>\
+                     <pre>\n x</pre><p>a<i>b<p>c</i>d";
+        let serialized = round_trip(input);
+        assert_eq!(round_trip(&serialized), serialized);
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        let document =
+            parse_html("<!DOCTYPE html><ul>\n <li>One</li>\n <li>Two <b>2</b></li></ul><pre> a\n b</pre>")
+                .unwrap();
+        assert_eq!(
+            document.serialize(SerializeOptions { pretty: true }),
+            "<!DOCTYPE html>\n\
+             <html>\n\
+             \x20 <head></head>\n\
+             \x20 <body>\n\
+             \x20   <ul>\n\
+             \x20     <li>One</li>\n\
+             \x20     <li>\n\
+             \x20       Two\n\
+             \x20       <b>2</b>\n\
+             \x20     </li>\n\
+             \x20   </ul>\n\
+             \x20   <pre> a\n b</pre>\n\
+             \x20 </body>\n\
+             </html>"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Incremental parsing of a document received in chunks.
+
+use crate::html::parser::{HtmlDocument, HtmlParser};
+
+/// Parses an HTML document as its bytes arrive from the network.
+///
+/// Every chunk given to [`StreamingParser::feed`] is tokenized and added to
+/// the tree right away, so [`StreamingParser::document`] can be rendered
+/// before the page is fully loaded. The result of
+/// [`StreamingParser::finish`] is the same as parsing the whole input at once.
+///
+/// ```
+/// use flux_parser::html::StreamingParser;
+///
+/// let mut parser = StreamingParser::new();
+/// parser.feed(b"<p>Hel");
+/// parser.feed(b"lo</p><p>wor");
+/// assert_eq!(parser.document().body().unwrap().children.len(), 2);
+/// let document = parser.finish();
+/// assert_eq!(document.body().unwrap().text_content(), "Hellowor");
+/// ```
+pub struct StreamingParser {
+    parser: HtmlParser,
+    /// Bytes of a UTF-8 sequence cut by the end of the last chunk.
+    incomplete: Vec<u8>,
+}
+
+impl Default for StreamingParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingParser {
+    pub fn new() -> Self {
+        StreamingParser {
+            parser: HtmlParser::streaming(),
+            incomplete: Vec::new(),
+        }
+    }
+
+    /// Parses a chunk of UTF-8 input. A character may be split between two
+    /// chunks; invalid sequences become U+FFFD.
+    ///
+    /// Documents in another encoding are decoded by the caller and given to
+    /// [`StreamingParser::feed_str`].
+    pub fn feed(&mut self, bytes: &[u8]) {
+        let mut bytes = bytes;
+        let mut buffer;
+        if !self.incomplete.is_empty() {
+            buffer = std::mem::take(&mut self.incomplete);
+            buffer.extend_from_slice(bytes);
+            bytes = &buffer;
+        }
+
+        let mut text = String::new();
+        loop {
+            match std::str::from_utf8(bytes) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    break;
+                }
+                Err(error) => {
+                    let (valid, rest) = bytes.split_at(error.valid_up_to());
+                    // `valid_up_to` guarantees that this prefix is UTF-8.
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match error.error_len() {
+                        Some(len) => {
+                            text.push('\u{FFFD}');
+                            bytes = &rest[len..];
+                        }
+                        None => {
+                            self.incomplete = rest.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.parser.push_str(&text);
+    }
+
+    /// Parses a chunk of already decoded input.
+    pub fn feed_str(&mut self, text: &str) {
+        self.parser.push_str(text);
+    }
+
+    /// Returns the document as parsed so far, with the elements still open
+    /// at the end of the input received. Parse errors are only reported by
+    /// [`StreamingParser::finish`].
+    pub fn document(&self) -> HtmlDocument {
+        self.parser.snapshot()
+    }
+
+    /// Ends the input and returns the complete document.
+    pub fn finish(mut self) -> HtmlDocument {
+        if !self.incomplete.is_empty() {
+            // The input ends in the middle of a character.
+            self.parser.push_str("\u{FFFD}");
+        }
+        self.parser.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::parse_html;
+    use crate::html::serializer::SerializeOptions;
+
+    /// Feeds `input` in chunks of `size` bytes and serializes the result.
+    fn parse_in_chunks(input: &str, size: usize) -> String {
+        let mut parser = StreamingParser::new();
+        for chunk in input.as_bytes().chunks(size) {
+            parser.feed(chunk);
+        }
+        parser.finish().serialize(SerializeOptions::default())
+    }
+
+    #[test]
+    fn test_same_tree_whatever_the_chunks() {
+        let input = "<!DOCTYPE html>\r\n<html><head><title>A &amp; B</title>\
+                     <script>if (a < b) { c = '</scr' + 'ipt>'; }</script></head>\r\n\
+                     <body><!-- comment --><p class=\"x\" id=y>Caf\u{e9} &eacute;t&eacutea &#x263A;\
+                     <table><tr><td>1<td>2</table><pre>\r\n\r\nz</pre><textarea>&lt;</textarea>";
+        let expected = parse_html(input).unwrap().serialize(SerializeOptions::default());
+        for size in 1..=16 {
+            assert_eq!(parse_in_chunks(input, size), expected, "chunks of {} bytes", size);
+        }
+    }
+
+    #[test]
+    fn test_partial_document() {
+        let mut parser = StreamingParser::new();
+        parser.feed(b"<ul><li>One<li>Tw");
+        let body = parser.document().body().unwrap().children.len();
+        assert_eq!(body, 1);
+        assert_eq!(parser.document().body().unwrap().text_content(), "OneTw");
+
+        // An unfinished tag is not part of the tree yet.
+        parser.feed(b"o<li cla");
+        assert_eq!(parser.document().body().unwrap().text_content(), "OneTwo");
+        parser.feed(b"ss=x>Three</ul>");
+        let document = parser.finish();
+        let list = document.body().unwrap().child_element("ul").unwrap();
+        assert_eq!(list.children.len(), 3);
+    }
+
+    #[test]
+    fn test_split_characters() {
+        let mut parser = StreamingParser::new();
+        parser.feed(&[b'<', b'p', b'>', 0xC3]);
+        parser.feed(&[0xA9, 0xFF, b'x', 0xE2, 0x98]);
+        let document = parser.finish();
+        assert_eq!(document.body().unwrap().text_content(), "\u{e9}\u{FFFD}x\u{FFFD}");
+    }
+
+    #[test]
+    fn test_spans_count_from_the_first_chunk() {
+        let mut parser = StreamingParser::new();
+        parser.feed_str("<p>a\r");
+        parser.feed_str("\n<b>b</b>");
+        let document = parser.finish();
+        let p = document.body().unwrap().child_element("p").unwrap();
+        let b = p.child_element("b").unwrap();
+        assert_eq!((b.span.start, b.span.end, b.span.line, b.span.column), (6, 9, 2, 1));
+    }
+}
@@ -1,9 +1,28 @@
-use crate::error::ParserError;
+//! HTML tokenizer.
+//!
+//! This is an implementation of the tokenization stage of the WHATWG HTML
+//! specification (§13.2.5). The tokenizer is a state machine that consumes the
+//! input one character at a time and emits tokens. It never fails: malformed
+//! markup is recovered from the way browsers do it, and every problem found on
+//! the way is recorded as a parse error.
 
-#[derive(Debug, PartialEq)]
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use crate::error::{ParseError, ParserError, Severity};
+use crate::html::entities;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
-    /// A start tag: `<div ...>`
-    StartTag(String, Vec<(String, String)>),
+    /// A DOCTYPE: `<!DOCTYPE html>`
+    Doctype(Doctype),
+
+    /// A start tag: `<div ...>`, or `<br/>` when `self_closing` is set
+    StartTag {
+        name: String,
+        attributes: Vec<(String, String)>,
+        self_closing: bool,
+    },
 
     /// An end tag: `</div>`
     EndTag(String),
@@ -18,245 +37,2255 @@ pub enum Token {
     Eof,
 }
 
-/// A very naive tokenizer that splits the input into tokens.
-/// Now updated with a more robust way of parsing attributes
-/// so that spaces inside quotes are not lost.
-pub fn tokenize(input: &str) -> Result<Vec<Token>, ParserError> {
-    let mut tokens = Vec::new();
-    let mut i = 0;
-    let chars: Vec<char> = input.chars().collect();
-
-    while i < chars.len() {
-        // Skip leading whitespace (outside of tags)
-        if chars[i].is_whitespace() {
-            i += 1;
-            continue;
-        }
-
-        // Check for comment: `<!-- ... -->`
-        if i + 3 < chars.len() && &chars[i..i + 4] == ['<', '!', '-', '-'] {
-            // Try to find `-->`
-            if let Some(end) = find_sequence(&chars, i + 4, &['-', '-', '>']) {
-                let comment_text: String = chars[i + 4..end].iter().collect();
-                tokens.push(Token::Comment(comment_text));
-                i = end + 3; // skip past `-->`
-                continue;
-            } else {
-                return Err(ParserError::Other("Unclosed comment".into()));
-            }
-        }
-
-        // Check for a start/end tag `< ... >`
-        if chars[i] == '<' {
-            // Find the '>' that closes this tag
-            if let Some(end) = find_char(&chars, i + 1, '>') {
-                // Everything between `<` and `>` is our tag body
-                let tag_body: String = chars[i + 1..end].iter().collect();
-                i = end + 1; // move index past `>`
-
-                // Check if it's an end tag: `</...>`
-                if tag_body.starts_with('/') {
-                    // e.g. "</div>"
-                    let tag_name = tag_body[1..].trim().to_string();
-                    tokens.push(Token::EndTag(tag_name));
-                } else {
-                    // It's a start tag. Example: `div class="something"`
-                    let (tag_name, attributes) = parse_tag_and_attributes(tag_body)?;
-                    tokens.push(Token::StartTag(tag_name, attributes));
+/// The content of a DOCTYPE token.
+///
+/// Missing identifiers are `None`, which is different from an empty
+/// identifier (`<!DOCTYPE html PUBLIC "">`).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Doctype {
+    pub name: Option<String>,
+    pub public_id: Option<String>,
+    pub system_id: Option<String>,
+    pub force_quirks: bool,
+}
+
+/// A range of the source text, with the line and column where it starts.
+///
+/// Offsets are in bytes of the original input, before newlines are
+/// normalized, so that `&input[span.start..span.end]` is the source of the
+/// node or token.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SourceSpan {
+    pub start: usize,
+    pub end: usize,
+    /// Line of `start`, counting from 1.
+    pub line: usize,
+    /// Column of `start` in characters, counting from 1.
+    pub column: usize,
+}
+
+/// The states of the tokenizer state machine, named after the specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Data,
+    Rcdata,
+    Rawtext,
+    ScriptData,
+    Plaintext,
+    TagOpen,
+    EndTagOpen,
+    TagName,
+    RcdataLessThanSign,
+    RcdataEndTagOpen,
+    RcdataEndTagName,
+    RawtextLessThanSign,
+    RawtextEndTagOpen,
+    RawtextEndTagName,
+    ScriptDataLessThanSign,
+    ScriptDataEndTagOpen,
+    ScriptDataEndTagName,
+    ScriptDataEscapeStart,
+    ScriptDataEscapeStartDash,
+    ScriptDataEscaped,
+    ScriptDataEscapedDash,
+    ScriptDataEscapedDashDash,
+    ScriptDataEscapedLessThanSign,
+    ScriptDataEscapedEndTagOpen,
+    ScriptDataEscapedEndTagName,
+    ScriptDataDoubleEscapeStart,
+    ScriptDataDoubleEscaped,
+    ScriptDataDoubleEscapedDash,
+    ScriptDataDoubleEscapedDashDash,
+    ScriptDataDoubleEscapedLessThanSign,
+    ScriptDataDoubleEscapeEnd,
+    BeforeAttributeName,
+    AttributeName,
+    AfterAttributeName,
+    BeforeAttributeValue,
+    AttributeValueDoubleQuoted,
+    AttributeValueSingleQuoted,
+    AttributeValueUnquoted,
+    AfterAttributeValueQuoted,
+    SelfClosingStartTag,
+    BogusComment,
+    MarkupDeclarationOpen,
+    CommentStart,
+    CommentStartDash,
+    Comment,
+    CommentLessThanSign,
+    CommentLessThanSignBang,
+    CommentLessThanSignBangDash,
+    CommentLessThanSignBangDashDash,
+    CommentEndDash,
+    CommentEnd,
+    CommentEndBang,
+    Doctype,
+    BeforeDoctypeName,
+    DoctypeName,
+    AfterDoctypeName,
+    AfterDoctypePublicKeyword,
+    BeforeDoctypePublicIdentifier,
+    DoctypePublicIdentifierDoubleQuoted,
+    DoctypePublicIdentifierSingleQuoted,
+    AfterDoctypePublicIdentifier,
+    BetweenDoctypePublicAndSystemIdentifiers,
+    AfterDoctypeSystemKeyword,
+    BeforeDoctypeSystemIdentifier,
+    DoctypeSystemIdentifierDoubleQuoted,
+    DoctypeSystemIdentifierSingleQuoted,
+    AfterDoctypeSystemIdentifier,
+    BogusDoctype,
+    CdataSection,
+    CdataSectionBracket,
+    CdataSectionEnd,
+    CharacterReference,
+    NamedCharacterReference,
+    AmbiguousAmpersand,
+    NumericCharacterReference,
+    HexadecimalCharacterReferenceStart,
+    DecimalCharacterReferenceStart,
+    HexadecimalCharacterReference,
+    DecimalCharacterReference,
+    NumericCharacterReferenceEnd,
+}
+
+/// The tag token currently being built by the tokenizer.
+#[derive(Debug, Default, Clone)]
+struct TagBuilder {
+    is_end: bool,
+    name: String,
+    self_closing: bool,
+    attributes: Vec<(String, String)>,
+    /// Set when the last attribute has the name of an earlier one: it is
+    /// dropped once its value has been read.
+    duplicate_attribute: bool,
+}
+
+impl TagBuilder {
+    fn drop_duplicate_attribute(&mut self) {
+        if std::mem::take(&mut self.duplicate_attribute) {
+            self.attributes.pop();
+        }
+    }
+}
+
+/// The HTML tokenizer state machine.
+///
+/// Tokens are pulled one at a time with [`Tokenizer::next_token`]. The tree
+/// builder is expected to switch the tokenizer into the RCDATA, RAWTEXT,
+/// script data or PLAINTEXT states with [`Tokenizer::set_state`] after it
+/// inserts an element whose content is not markup.
+///
+/// A tokenizer created with [`Tokenizer::streaming`] receives its input in
+/// chunks with [`Tokenizer::push_str`] and hands tokens out with
+/// [`Tokenizer::try_next_token`] as soon as they are complete.
+pub struct Tokenizer {
+    input: Vec<char>,
+    pos: usize,
+    state: State,
+
+    /// Byte offset in the original input of every character of `input`, and
+    /// of the end of the input.
+    offsets: Vec<usize>,
+    /// Index in `input` of the first character of every line.
+    line_starts: Vec<usize>,
+    /// Index of the `<` that started the markup being tokenized.
+    markup_start: usize,
+    /// Index just past the last token that was not text.
+    token_end: usize,
+    /// Characters of `input` the token last returned came from.
+    current_range: Range<usize>,
+
+    /// Tokens that are ready to be handed out, with their source ranges.
+    queue: VecDeque<(Token, Range<usize>)>,
+    /// Consecutive characters are merged into a single `Token::Text`.
+    pending_text: String,
+
+    current_tag: TagBuilder,
+    current_comment: String,
+    current_doctype: Doctype,
+    /// The `temporary buffer` of the specification.
+    temp_buffer: String,
+    /// Name of the last start tag emitted, used to find "appropriate end tags".
+    last_start_tag: Option<String>,
+    /// State to go back to once a character reference has been consumed.
+    return_state: State,
+    /// Value of the numeric character reference being consumed.
+    character_reference_code: u32,
+
+    /// Whether `<![CDATA[` opens a CDATA section (only in foreign content).
+    allow_cdata: bool,
+    eof_emitted: bool,
+    errors: Vec<ParseError>,
+
+    /// Whether the whole input has been received. Until then, reaching the
+    /// end of `input` means waiting for more.
+    finished: bool,
+    /// Set when a step ran out of input before the end of the document.
+    starved: bool,
+    /// Whether the last chunk ended with a CR, whose LF may start the next.
+    ends_with_cr: bool,
+}
+
+/// The state of the tokenizer between two tokens, to go back to when the
+/// input received so far ends in the middle of a token.
+struct Checkpoint {
+    pos: usize,
+    state: State,
+    markup_start: usize,
+    token_end: usize,
+    pending_text: String,
+    current_tag: TagBuilder,
+    current_comment: String,
+    current_doctype: Doctype,
+    temp_buffer: String,
+    last_start_tag: Option<String>,
+    return_state: State,
+    character_reference_code: u32,
+    errors: usize,
+}
+
+impl Tokenizer {
+    /// Creates a tokenizer over `input`, starting in the data state.
+    pub fn new(input: &str) -> Self {
+        let (input, offsets) = preprocess(input);
+        let line_starts = std::iter::once(0)
+            .chain(input.iter().enumerate().filter(|(_, &c)| c == '\n').map(|(index, _)| index + 1))
+            .collect();
+        Tokenizer {
+            input,
+            pos: 0,
+            state: State::Data,
+            offsets,
+            line_starts,
+            markup_start: 0,
+            token_end: 0,
+            current_range: 0..0,
+            queue: VecDeque::new(),
+            pending_text: String::new(),
+            current_tag: TagBuilder::default(),
+            current_comment: String::new(),
+            current_doctype: Doctype::default(),
+            temp_buffer: String::new(),
+            last_start_tag: None,
+            return_state: State::Data,
+            character_reference_code: 0,
+            allow_cdata: false,
+            eof_emitted: false,
+            errors: Vec::new(),
+            finished: true,
+            starved: false,
+            ends_with_cr: false,
+        }
+    }
+
+    /// Creates a tokenizer whose input will be given in chunks with
+    /// [`Tokenizer::push_str`], until [`Tokenizer::finish`] is called.
+    pub fn streaming() -> Self {
+        Tokenizer {
+            finished: false,
+            ..Tokenizer::new("")
+        }
+    }
+
+    /// Appends a chunk of input. Byte offsets in spans count from the start of
+    /// the first chunk.
+    pub fn push_str(&mut self, text: &str) {
+        // The LF of a CR LF pair split between two chunks was already
+        // accounted for by the CR.
+        let skip = usize::from(self.ends_with_cr && text.starts_with('\n'));
+        if !text.is_empty() {
+            self.ends_with_cr = text.ends_with('\r');
+        }
+
+        let base = self.offsets.pop().unwrap_or(0) + skip;
+        let (chars, offsets) = preprocess(&text[skip..]);
+        let start = self.input.len();
+        self.line_starts.extend(
+            chars.iter().enumerate().filter(|(_, &c)| c == '\n').map(|(index, _)| start + index + 1),
+        );
+        self.input.extend(chars);
+        self.offsets.extend(offsets.into_iter().map(|offset| base + offset));
+    }
+
+    /// Tells the tokenizer that all the input has been pushed.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    /// Switches the tokenizer to another state.
+    pub fn set_state(&mut self, state: State) {
+        self.state = state;
+    }
+
+    /// Tells the tokenizer whether CDATA sections are allowed at this point.
+    pub fn set_allow_cdata(&mut self, allow: bool) {
+        self.allow_cdata = allow;
+    }
+
+    /// Overrides the last start tag name, used when tokenizing a fragment.
+    pub fn set_last_start_tag(&mut self, name: &str) {
+        self.last_start_tag = Some(name.to_string());
+    }
+
+    /// Returns the parse errors found so far and clears the list.
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Runs the state machine until the next token is available.
+    /// Once the input is exhausted, `Token::Eof` is returned forever.
+    ///
+    /// The whole input must be known: a streaming tokenizer uses
+    /// [`Tokenizer::try_next_token`] until it is finished.
+    pub fn next_token(&mut self) -> Token {
+        self.try_next_token()
+            .expect("the input of a streaming tokenizer is not finished")
+    }
+
+    /// Same as [`Tokenizer::next_token`], but returns `None` when the input
+    /// received so far ends before the next token does. The tokenizer then
+    /// goes back to where that token starts, to read it again once more input
+    /// has been pushed.
+    ///
+    /// Text is handed out as soon as it is received, so a long run of text can
+    /// come in several `Token::Text`.
+    pub fn try_next_token(&mut self) -> Option<Token> {
+        let mut checkpoint = None;
+        loop {
+            if let Some((token, range)) = self.queue.pop_front() {
+                self.current_range = range;
+                return Some(token);
+            }
+            if self.eof_emitted {
+                self.current_range = self.pos..self.pos;
+                return Some(Token::Eof);
+            }
+            if !self.finished && checkpoint.is_none() {
+                checkpoint = Some(self.checkpoint());
+            }
+            self.step();
+            if std::mem::take(&mut self.starved) {
+                if let Some(checkpoint) = checkpoint {
+                    self.restore(checkpoint);
                 }
-                continue;
-            } else {
-                return Err(ParserError::Other("Tag not closed".into()));
+                return None;
             }
         }
+    }
 
-        // Otherwise, it's text until the next '<' or EOF
-        let start_text = i;
-        while i < chars.len() && chars[i] != '<' {
-            i += 1;
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            pos: self.pos,
+            state: self.state,
+            markup_start: self.markup_start,
+            token_end: self.token_end,
+            pending_text: self.pending_text.clone(),
+            current_tag: self.current_tag.clone(),
+            current_comment: self.current_comment.clone(),
+            current_doctype: self.current_doctype.clone(),
+            temp_buffer: self.temp_buffer.clone(),
+            last_start_tag: self.last_start_tag.clone(),
+            return_state: self.return_state,
+            character_reference_code: self.character_reference_code,
+            errors: self.errors.len(),
         }
-        let text_content: String = chars[start_text..i].iter().collect();
-        tokens.push(Token::Text(text_content));
     }
 
-    // Finally, add EOF token
-    tokens.push(Token::Eof);
-    Ok(tokens)
-}
+    fn restore(&mut self, checkpoint: Checkpoint) {
+        self.pos = checkpoint.pos;
+        self.state = checkpoint.state;
+        self.markup_start = checkpoint.markup_start;
+        self.token_end = checkpoint.token_end;
+        self.pending_text = checkpoint.pending_text;
+        self.current_tag = checkpoint.current_tag;
+        self.current_comment = checkpoint.current_comment;
+        self.current_doctype = checkpoint.current_doctype;
+        self.temp_buffer = checkpoint.temp_buffer;
+        self.last_start_tag = checkpoint.last_start_tag;
+        self.return_state = checkpoint.return_state;
+        self.character_reference_code = checkpoint.character_reference_code;
+        self.errors.truncate(checkpoint.errors);
+        self.queue.clear();
+    }
 
-/// Helper function to find a single character `c` in `chars` starting at `start`.
-fn find_char(chars: &[char], start: usize, c: char) -> Option<usize> {
-    for (index, &ch) in chars.iter().enumerate().skip(start) {
-        if ch == c {
-            return Some(index);
+    /// Whether the tokenizer must wait for more input to go past `pos`.
+    fn waits_for_input(&self, pos: usize) -> bool {
+        !self.finished && pos >= self.input.len()
+    }
+
+    /// Returns where the token last returned by `next_token` comes from.
+    pub fn token_span(&self) -> SourceSpan {
+        self.span(self.current_range.clone())
+    }
+
+    /// Whether `text` is exactly the source of the current token, i.e. it
+    /// contains no character reference nor replaced character. Only then can
+    /// [`Tokenizer::token_subspan`] locate parts of it.
+    pub fn is_verbatim(&self, text: &str) -> bool {
+        text.chars().eq(self.input[self.current_range.clone()].iter().copied())
+    }
+
+    /// Returns the span of the characters `chars` of the current token.
+    pub fn token_subspan(&self, chars: Range<usize>) -> SourceSpan {
+        let start = self.current_range.start;
+        let end = self.current_range.end;
+        self.span((start + chars.start).min(end)..(start + chars.end).min(end))
+    }
+
+    fn span(&self, range: Range<usize>) -> SourceSpan {
+        let line = self.line_starts.partition_point(|&start| start <= range.start);
+        SourceSpan {
+            start: self.offsets[range.start],
+            end: self.offsets[range.end],
+            line,
+            column: range.start - self.line_starts[line - 1] + 1,
         }
     }
-    None
-}
 
-/// Helper function to find a sequence of characters `seq` in `chars` starting at `start`.
-/// Returns the index of the sequence start if found.
-fn find_sequence(chars: &[char], start: usize, seq: &[char]) -> Option<usize> {
-    let seq_len = seq.len();
-    'outer: for i in start..=chars.len().saturating_sub(seq_len) {
-        for j in 0..seq_len {
-            if chars[i + j] != seq[j] {
-                continue 'outer;
+    fn consume(&mut self) -> Option<char> {
+        let c = self.input.get(self.pos).copied();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+
+    /// Puts the character back and switches to `state`, so that the character
+    /// is consumed again in the new state.
+    fn reconsume(&mut self, c: Option<char>, state: State) {
+        if c.is_some() {
+            self.pos -= 1;
+        }
+        self.state = state;
+    }
+
+    /// Checks whether the upcoming characters are `expected`, comparing ASCII
+    /// case-insensitively if asked to. Consumes them on success.
+    fn consume_if_matches(&mut self, expected: &str, ignore_case: bool) -> bool {
+        let len = expected.chars().count();
+        if self.pos + len > self.input.len() {
+            // The upcoming characters may still turn out to match.
+            if self.waits_for_input(self.pos + len - 1) {
+                self.starved = true;
             }
+            return false;
         }
-        return Some(i);
+        let matches = self.input[self.pos..self.pos + len]
+            .iter()
+            .zip(expected.chars())
+            .all(|(&a, b)| {
+                if ignore_case {
+                    a.eq_ignore_ascii_case(&b)
+                } else {
+                    a == b
+                }
+            });
+        if matches {
+            self.pos += len;
+        }
+        matches
     }
-    None
-}
 
-/// Parse a full tag body like:  `div class="hello" id="main"`
-/// and return `(tag_name, Vec<(attribute, value)>)`.
-/// This version does a small character-by-character parse
-/// so that spaces inside quoted values are preserved.
-fn parse_tag_and_attributes(tag_body: String) -> Result<(String, Vec<(String, String)>), ParserError> {
-    let mut chars = tag_body.trim().chars().peekable();
+    /// Records a parse error at the character being consumed, or at the end
+    /// of the input.
+    fn error(&mut self, code: &'static str) {
+        let start = self.pos.saturating_sub(1);
+        let span = self.span(start..self.pos);
+        self.errors.push(ParseError::new(code, Severity::Error, span));
+    }
 
-    // 1) Parse the tag name
-    let tag_name = parse_tag_name(&mut chars)?;
+    fn emit_char(&mut self, c: char) {
+        self.pending_text.push(c);
+    }
 
-    // 2) Parse all attributes until the end of the tag body
-    let attributes = parse_attributes(&mut chars)?;
+    fn emit_str(&mut self, s: &str) {
+        self.pending_text.push_str(s);
+    }
 
-    Ok((tag_name, attributes))
-}
+    fn emit(&mut self, token: Token) {
+        // Text runs up to the markup of the token; markup always starts with a
+        // `<`, except the end of the input which has no source.
+        let start = if token == Token::Eof {
+            self.pos
+        } else {
+            self.markup_start.max(self.token_end)
+        };
+        if !self.pending_text.is_empty() {
+            self.flush_text(start);
+        }
+        if token == Token::Eof {
+            self.eof_emitted = true;
+        }
+        self.queue.push_back((token, start..self.pos));
+        self.token_end = self.pos;
+    }
+
+    /// Emits the text pending so far, which ends at `end` in the input.
+    fn flush_text(&mut self, end: usize) {
+        let text = std::mem::take(&mut self.pending_text);
+        self.queue.push_back((Token::Text(text), self.token_end..end));
+        self.token_end = end;
+    }
 
-/// Parse the first "word" from `chars` as the tag name.
-fn parse_tag_name<I: Iterator<Item=char>>(chars: &mut std::iter::Peekable<I>) -> Result<String, ParserError> {
-    skip_whitespace(chars);
+    fn emit_eof(&mut self) {
+        self.emit(Token::Eof);
+    }
+
+    fn new_tag(&mut self, is_end: bool) {
+        self.current_tag = TagBuilder {
+            is_end,
+            ..TagBuilder::default()
+        };
+    }
 
-    let mut tag_name = String::new();
-    while let Some(&c) = chars.peek() {
-        if c.is_whitespace() {
-            break;
+    fn emit_current_tag(&mut self) {
+        let mut tag = std::mem::take(&mut self.current_tag);
+        tag.drop_duplicate_attribute();
+        if tag.is_end {
+            if !tag.attributes.is_empty() {
+                self.error("end-tag-with-attributes");
+            }
+            if tag.self_closing {
+                self.error("end-tag-with-trailing-solidus");
+            }
+            self.emit(Token::EndTag(tag.name));
+        } else {
+            self.last_start_tag = Some(tag.name.clone());
+            self.emit(Token::StartTag {
+                name: tag.name,
+                attributes: tag.attributes,
+                self_closing: tag.self_closing,
+            });
         }
-        tag_name.push(c);
-        chars.next();
     }
 
-    if tag_name.is_empty() {
-        return Err(ParserError::Other("Empty tag body".into()));
+    fn start_attribute(&mut self) {
+        self.current_tag.drop_duplicate_attribute();
+        self.current_tag.attributes.push((String::new(), String::new()));
     }
 
-    Ok(tag_name)
-}
+    /// Called when leaving the attribute name state: the first of several
+    /// attributes with the same name wins.
+    fn check_duplicate_attribute(&mut self) {
+        if let Some(((name, _), previous)) = self.current_tag.attributes.split_last() {
+            if previous.iter().any(|(other, _)| other == name) {
+                self.error("duplicate-attribute");
+                self.current_tag.duplicate_attribute = true;
+            }
+        }
+    }
 
-/// Parse all attributes from the current cursor until we reach the end of the tag
-/// (i.e., `'>'` or we've consumed all characters).
-fn parse_attributes<I: Iterator<Item=char>>(chars: &mut std::iter::Peekable<I>) -> Result<Vec<(String, String)>, ParserError> {
-    let mut attributes = Vec::new();
+    fn push_attribute_name(&mut self, c: char) {
+        if let Some(attr) = self.current_tag.attributes.last_mut() {
+            attr.0.push(c);
+        }
+    }
 
-    loop {
-        skip_whitespace(chars);
+    fn push_attribute_value(&mut self, c: char) {
+        if let Some(attr) = self.current_tag.attributes.last_mut() {
+            attr.1.push(c);
+        }
+    }
+
+    fn emit_comment(&mut self) {
+        let comment = std::mem::take(&mut self.current_comment);
+        self.emit(Token::Comment(comment));
+    }
+
+    fn emit_doctype(&mut self) {
+        let doctype = std::mem::take(&mut self.current_doctype);
+        self.emit(Token::Doctype(doctype));
+    }
+
+    /// An end tag is "appropriate" if its name matches the last start tag.
+    fn is_appropriate_end_tag(&self) -> bool {
+        self.last_start_tag.as_deref() == Some(self.current_tag.name.as_str())
+    }
 
-        // If we've exhausted the tag body, break
-        if chars.peek().is_none() {
-            // No more chars
-            break;
+    /// Emits `</` followed by the temporary buffer, used when something that
+    /// looked like an end tag inside raw text turned out not to be one.
+    fn emit_end_tag_prefix(&mut self) {
+        self.emit_str("</");
+        let buffer = std::mem::take(&mut self.temp_buffer);
+        self.emit_str(&buffer);
+    }
+
+    /// Consumes one character (or the end of the input) in the current state.
+    fn step(&mut self) {
+        if self.waits_for_input(self.pos) {
+            self.starved = true;
+            // Between two characters of text, the text received so far can be
+            // handed out already.
+            if matches!(
+                self.state,
+                State::Data
+                    | State::Rcdata
+                    | State::Rawtext
+                    | State::ScriptData
+                    | State::Plaintext
+                    | State::ScriptDataEscaped
+                    | State::ScriptDataDoubleEscaped
+            ) && !self.pending_text.is_empty()
+            {
+                self.flush_text(self.pos);
+                self.starved = false;
+            }
+            return;
         }
+        let c = self.consume();
+        if c == Some('<')
+            && matches!(
+                self.state,
+                State::Data
+                    | State::Rcdata
+                    | State::Rawtext
+                    | State::ScriptData
+                    | State::ScriptDataEscaped
+                    | State::ScriptDataEscapedDash
+                    | State::ScriptDataEscapedDashDash
+            )
+        {
+            self.markup_start = self.pos - 1;
+            // The text before the markup is complete: hand it out without
+            // waiting for the end of a tag that may not be received yet.
+            if !self.finished && !self.pending_text.is_empty() {
+                self.flush_text(self.markup_start);
+            }
+        }
+        match self.state {
+            State::Data => match c {
+                Some('&') => {
+                    self.return_state = State::Data;
+                    self.state = State::CharacterReference;
+                }
+                Some('<') => self.state = State::TagOpen,
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.emit_char('\0');
+                }
+                Some(c) => self.emit_char(c),
+                None => self.emit_eof(),
+            },
 
-        // If we see something like `>` or `/`, we've reached the tag's end
-        match chars.peek() {
-            Some('>') | Some('/') => {
-                // Just break; main tokenizer logic will handle the rest
-                break;
+            State::Rcdata => match c {
+                Some('&') => {
+                    self.return_state = State::Rcdata;
+                    self.state = State::CharacterReference;
+                }
+                Some('<') => self.state = State::RcdataLessThanSign,
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => self.emit_char(c),
+                None => self.emit_eof(),
+            },
+
+            State::Rawtext => match c {
+                Some('<') => self.state = State::RawtextLessThanSign,
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => self.emit_char(c),
+                None => self.emit_eof(),
+            },
+
+            State::ScriptData => match c {
+                Some('<') => self.state = State::ScriptDataLessThanSign,
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => self.emit_char(c),
+                None => self.emit_eof(),
+            },
+
+            State::Plaintext => match c {
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => self.emit_char(c),
+                None => self.emit_eof(),
+            },
+
+            State::TagOpen => match c {
+                Some('!') => self.state = State::MarkupDeclarationOpen,
+                Some('/') => self.state = State::EndTagOpen,
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.new_tag(false);
+                    self.reconsume(Some(c), State::TagName);
+                }
+                Some('?') => {
+                    self.error("unexpected-question-mark-instead-of-tag-name");
+                    self.current_comment.clear();
+                    self.reconsume(c, State::BogusComment);
+                }
+                None => {
+                    self.error("eof-before-tag-name");
+                    self.emit_char('<');
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.error("invalid-first-character-of-tag-name");
+                    self.emit_char('<');
+                    self.reconsume(c, State::Data);
+                }
+            },
+
+            State::EndTagOpen => match c {
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.new_tag(true);
+                    self.reconsume(Some(c), State::TagName);
+                }
+                Some('>') => {
+                    self.error("missing-end-tag-name");
+                    self.state = State::Data;
+                }
+                None => {
+                    self.error("eof-before-tag-name");
+                    self.emit_str("</");
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.error("invalid-first-character-of-tag-name");
+                    self.current_comment.clear();
+                    self.reconsume(c, State::BogusComment);
+                }
+            },
+
+            State::TagName => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeAttributeName,
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.current_tag.name.push('\u{FFFD}');
+                }
+                Some(c) => self.current_tag.name.push(c.to_ascii_lowercase()),
+                None => {
+                    self.error("eof-in-tag");
+                    self.emit_eof();
+                }
+            },
+
+            State::RcdataLessThanSign => self.raw_less_than_sign(c, State::Rcdata, State::RcdataEndTagOpen),
+            State::RcdataEndTagOpen => self.raw_end_tag_open(c, State::Rcdata, State::RcdataEndTagName),
+            State::RcdataEndTagName => self.raw_end_tag_name(c, State::Rcdata),
+
+            State::RawtextLessThanSign => self.raw_less_than_sign(c, State::Rawtext, State::RawtextEndTagOpen),
+            State::RawtextEndTagOpen => self.raw_end_tag_open(c, State::Rawtext, State::RawtextEndTagName),
+            State::RawtextEndTagName => self.raw_end_tag_name(c, State::Rawtext),
+
+            State::ScriptDataLessThanSign => match c {
+                Some('/') => {
+                    self.temp_buffer.clear();
+                    self.state = State::ScriptDataEndTagOpen;
+                }
+                Some('!') => {
+                    self.state = State::ScriptDataEscapeStart;
+                    self.emit_str("<!");
+                }
+                _ => {
+                    self.emit_char('<');
+                    self.reconsume(c, State::ScriptData);
+                }
+            },
+            State::ScriptDataEndTagOpen => self.raw_end_tag_open(c, State::ScriptData, State::ScriptDataEndTagName),
+            State::ScriptDataEndTagName => self.raw_end_tag_name(c, State::ScriptData),
+
+            State::ScriptDataEscapeStart => match c {
+                Some('-') => {
+                    self.state = State::ScriptDataEscapeStartDash;
+                    self.emit_char('-');
+                }
+                _ => self.reconsume(c, State::ScriptData),
+            },
+
+            State::ScriptDataEscapeStartDash => match c {
+                Some('-') => {
+                    self.state = State::ScriptDataEscapedDashDash;
+                    self.emit_char('-');
+                }
+                _ => self.reconsume(c, State::ScriptData),
+            },
+
+            State::ScriptDataEscaped => match c {
+                Some('-') => {
+                    self.state = State::ScriptDataEscapedDash;
+                    self.emit_char('-');
+                }
+                Some('<') => self.state = State::ScriptDataEscapedLessThanSign,
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => self.emit_char(c),
+                None => {
+                    self.error("eof-in-script-html-comment-like-text");
+                    self.emit_eof();
+                }
+            },
+
+            State::ScriptDataEscapedDash => match c {
+                Some('-') => {
+                    self.state = State::ScriptDataEscapedDashDash;
+                    self.emit_char('-');
+                }
+                Some('<') => self.state = State::ScriptDataEscapedLessThanSign,
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.state = State::ScriptDataEscaped;
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataEscaped;
+                    self.emit_char(c);
+                }
+                None => {
+                    self.error("eof-in-script-html-comment-like-text");
+                    self.emit_eof();
+                }
+            },
+
+            State::ScriptDataEscapedDashDash => match c {
+                Some('-') => self.emit_char('-'),
+                Some('<') => self.state = State::ScriptDataEscapedLessThanSign,
+                Some('>') => {
+                    self.state = State::ScriptData;
+                    self.emit_char('>');
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.state = State::ScriptDataEscaped;
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataEscaped;
+                    self.emit_char(c);
+                }
+                None => {
+                    self.error("eof-in-script-html-comment-like-text");
+                    self.emit_eof();
+                }
+            },
+
+            State::ScriptDataEscapedLessThanSign => match c {
+                Some('/') => {
+                    self.temp_buffer.clear();
+                    self.state = State::ScriptDataEscapedEndTagOpen;
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.temp_buffer.clear();
+                    self.emit_char('<');
+                    self.reconsume(Some(c), State::ScriptDataDoubleEscapeStart);
+                }
+                _ => {
+                    self.emit_char('<');
+                    self.reconsume(c, State::ScriptDataEscaped);
+                }
+            },
+            State::ScriptDataEscapedEndTagOpen => {
+                self.raw_end_tag_open(c, State::ScriptDataEscaped, State::ScriptDataEscapedEndTagName)
+            }
+            State::ScriptDataEscapedEndTagName => self.raw_end_tag_name(c, State::ScriptDataEscaped),
+
+            State::ScriptDataDoubleEscapeStart => match c {
+                Some(c @ ('\t' | '\n' | '\x0C' | ' ' | '/' | '>')) => {
+                    self.state = if self.temp_buffer == "script" {
+                        State::ScriptDataDoubleEscaped
+                    } else {
+                        State::ScriptDataEscaped
+                    };
+                    self.emit_char(c);
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.temp_buffer.push(c.to_ascii_lowercase());
+                    self.emit_char(c);
+                }
+                _ => self.reconsume(c, State::ScriptDataEscaped),
+            },
+
+            State::ScriptDataDoubleEscaped => match c {
+                Some('-') => {
+                    self.state = State::ScriptDataDoubleEscapedDash;
+                    self.emit_char('-');
+                }
+                Some('<') => {
+                    self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                    self.emit_char('<');
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => self.emit_char(c),
+                None => {
+                    self.error("eof-in-script-html-comment-like-text");
+                    self.emit_eof();
+                }
+            },
+
+            State::ScriptDataDoubleEscapedDash => match c {
+                Some('-') => {
+                    self.state = State::ScriptDataDoubleEscapedDashDash;
+                    self.emit_char('-');
+                }
+                Some('<') => {
+                    self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                    self.emit_char('<');
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit_char(c);
+                }
+                None => {
+                    self.error("eof-in-script-html-comment-like-text");
+                    self.emit_eof();
+                }
+            },
+
+            State::ScriptDataDoubleEscapedDashDash => match c {
+                Some('-') => self.emit_char('-'),
+                Some('<') => {
+                    self.state = State::ScriptDataDoubleEscapedLessThanSign;
+                    self.emit_char('<');
+                }
+                Some('>') => {
+                    self.state = State::ScriptData;
+                    self.emit_char('>');
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit_char('\u{FFFD}');
+                }
+                Some(c) => {
+                    self.state = State::ScriptDataDoubleEscaped;
+                    self.emit_char(c);
+                }
+                None => {
+                    self.error("eof-in-script-html-comment-like-text");
+                    self.emit_eof();
+                }
+            },
+
+            State::ScriptDataDoubleEscapedLessThanSign => match c {
+                Some('/') => {
+                    self.temp_buffer.clear();
+                    self.state = State::ScriptDataDoubleEscapeEnd;
+                    self.emit_char('/');
+                }
+                _ => self.reconsume(c, State::ScriptDataDoubleEscaped),
+            },
+
+            State::ScriptDataDoubleEscapeEnd => match c {
+                Some(c @ ('\t' | '\n' | '\x0C' | ' ' | '/' | '>')) => {
+                    self.state = if self.temp_buffer == "script" {
+                        State::ScriptDataEscaped
+                    } else {
+                        State::ScriptDataDoubleEscaped
+                    };
+                    self.emit_char(c);
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    self.temp_buffer.push(c.to_ascii_lowercase());
+                    self.emit_char(c);
+                }
+                _ => self.reconsume(c, State::ScriptDataDoubleEscaped),
+            },
+
+            State::BeforeAttributeName => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('/' | '>') | None => self.reconsume(c, State::AfterAttributeName),
+                Some('=') => {
+                    self.error("unexpected-equals-sign-before-attribute-name");
+                    self.start_attribute();
+                    self.push_attribute_name('=');
+                    self.state = State::AttributeName;
+                }
+                Some(_) => {
+                    self.start_attribute();
+                    self.reconsume(c, State::AttributeName);
+                }
+            },
+
+            State::AttributeName => match c {
+                Some('\t' | '\n' | '\x0C' | ' ' | '/' | '>') | None => {
+                    self.check_duplicate_attribute();
+                    self.reconsume(c, State::AfterAttributeName)
+                }
+                Some('=') => {
+                    self.check_duplicate_attribute();
+                    self.state = State::BeforeAttributeValue;
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.push_attribute_name('\u{FFFD}');
+                }
+                Some(c) => {
+                    if matches!(c, '"' | '\'' | '<') {
+                        self.error("unexpected-character-in-attribute-name");
+                    }
+                    self.push_attribute_name(c.to_ascii_lowercase());
+                }
+            },
+
+            State::AfterAttributeName => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('=') => self.state = State::BeforeAttributeValue,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                None => {
+                    self.error("eof-in-tag");
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.start_attribute();
+                    self.reconsume(c, State::AttributeName);
+                }
+            },
+
+            State::BeforeAttributeValue => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('"') => self.state = State::AttributeValueDoubleQuoted,
+                Some('\'') => self.state = State::AttributeValueSingleQuoted,
+                Some('>') => {
+                    self.error("missing-attribute-value");
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                _ => self.reconsume(c, State::AttributeValueUnquoted),
+            },
+
+            State::AttributeValueDoubleQuoted => self.attribute_value_quoted(c, '"'),
+            State::AttributeValueSingleQuoted => self.attribute_value_quoted(c, '\''),
+
+            State::AttributeValueUnquoted => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeAttributeName,
+                Some('&') => {
+                    self.return_state = State::AttributeValueUnquoted;
+                    self.state = State::CharacterReference;
+                }
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.push_attribute_value('\u{FFFD}');
+                }
+                Some(c) => {
+                    if matches!(c, '"' | '\'' | '<' | '=' | '`') {
+                        self.error("unexpected-character-in-unquoted-attribute-value");
+                    }
+                    self.push_attribute_value(c);
+                }
+                None => {
+                    self.error("eof-in-tag");
+                    self.emit_eof();
+                }
+            },
+
+            State::AfterAttributeValueQuoted => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeAttributeName,
+                Some('/') => self.state = State::SelfClosingStartTag,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                None => {
+                    self.error("eof-in-tag");
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.error("missing-whitespace-between-attributes");
+                    self.reconsume(c, State::BeforeAttributeName);
+                }
+            },
+
+            State::SelfClosingStartTag => match c {
+                Some('>') => {
+                    self.current_tag.self_closing = true;
+                    self.state = State::Data;
+                    self.emit_current_tag();
+                }
+                None => {
+                    self.error("eof-in-tag");
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.error("unexpected-solidus-in-tag");
+                    self.reconsume(c, State::BeforeAttributeName);
+                }
+            },
+
+            State::BogusComment => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.current_comment.push('\u{FFFD}');
+                }
+                Some(c) => self.current_comment.push(c),
+                None => {
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+            },
+
+            State::MarkupDeclarationOpen => {
+                // This state looks ahead instead of consuming a single character.
+                self.reconsume(c, State::MarkupDeclarationOpen);
+                if self.consume_if_matches("--", false) {
+                    self.current_comment.clear();
+                    self.state = State::CommentStart;
+                } else if self.consume_if_matches("DOCTYPE", true) {
+                    self.state = State::Doctype;
+                } else if self.consume_if_matches("[CDATA[", false) {
+                    if self.allow_cdata {
+                        self.state = State::CdataSection;
+                    } else {
+                        self.error("cdata-in-html-content");
+                        self.current_comment = "[CDATA[".to_string();
+                        self.state = State::BogusComment;
+                    }
+                } else {
+                    self.error("incorrectly-opened-comment");
+                    self.current_comment.clear();
+                    self.state = State::BogusComment;
+                }
+            }
+
+            State::CommentStart => match c {
+                Some('-') => self.state = State::CommentStartDash,
+                Some('>') => {
+                    self.error("abrupt-closing-of-empty-comment");
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                _ => self.reconsume(c, State::Comment),
+            },
+
+            State::CommentStartDash => match c {
+                Some('-') => self.state = State::CommentEnd,
+                Some('>') => {
+                    self.error("abrupt-closing-of-empty-comment");
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                None => {
+                    self.error("eof-in-comment");
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.current_comment.push('-');
+                    self.reconsume(c, State::Comment);
+                }
+            },
+
+            State::Comment => match c {
+                Some('<') => {
+                    self.current_comment.push('<');
+                    self.state = State::CommentLessThanSign;
+                }
+                Some('-') => self.state = State::CommentEndDash,
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.current_comment.push('\u{FFFD}');
+                }
+                Some(c) => self.current_comment.push(c),
+                None => {
+                    self.error("eof-in-comment");
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+            },
+
+            State::CommentLessThanSign => match c {
+                Some('!') => {
+                    self.current_comment.push('!');
+                    self.state = State::CommentLessThanSignBang;
+                }
+                Some('<') => self.current_comment.push('<'),
+                _ => self.reconsume(c, State::Comment),
+            },
+
+            State::CommentLessThanSignBang => match c {
+                Some('-') => self.state = State::CommentLessThanSignBangDash,
+                _ => self.reconsume(c, State::Comment),
+            },
+
+            State::CommentLessThanSignBangDash => match c {
+                Some('-') => self.state = State::CommentLessThanSignBangDashDash,
+                _ => self.reconsume(c, State::CommentEndDash),
+            },
+
+            State::CommentLessThanSignBangDashDash => match c {
+                Some('>') | None => self.reconsume(c, State::CommentEnd),
+                _ => {
+                    self.error("nested-comment");
+                    self.reconsume(c, State::CommentEnd);
+                }
+            },
+
+            State::CommentEndDash => match c {
+                Some('-') => self.state = State::CommentEnd,
+                None => {
+                    self.error("eof-in-comment");
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.current_comment.push('-');
+                    self.reconsume(c, State::Comment);
+                }
+            },
+
+            State::CommentEnd => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                Some('!') => self.state = State::CommentEndBang,
+                Some('-') => self.current_comment.push('-'),
+                None => {
+                    self.error("eof-in-comment");
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.current_comment.push_str("--");
+                    self.reconsume(c, State::Comment);
+                }
+            },
+
+            State::CommentEndBang => match c {
+                Some('-') => {
+                    self.current_comment.push_str("--!");
+                    self.state = State::CommentEndDash;
+                }
+                Some('>') => {
+                    self.error("incorrectly-closed-comment");
+                    self.state = State::Data;
+                    self.emit_comment();
+                }
+                None => {
+                    self.error("eof-in-comment");
+                    self.emit_comment();
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.current_comment.push_str("--!");
+                    self.reconsume(c, State::Comment);
+                }
+            },
+
+            State::Doctype => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeDoctypeName,
+                Some('>') => self.reconsume(c, State::BeforeDoctypeName),
+                None => {
+                    self.error("eof-in-doctype");
+                    self.current_doctype = Doctype {
+                        force_quirks: true,
+                        ..Doctype::default()
+                    };
+                    self.emit_doctype();
+                    self.emit_eof();
+                }
+                Some(_) => {
+                    self.error("missing-whitespace-before-doctype-name");
+                    self.reconsume(c, State::BeforeDoctypeName);
+                }
+            },
+
+            State::BeforeDoctypeName => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.current_doctype = Doctype {
+                        name: Some('\u{FFFD}'.to_string()),
+                        ..Doctype::default()
+                    };
+                    self.state = State::DoctypeName;
+                }
+                Some('>') => {
+                    self.error("missing-doctype-name");
+                    self.current_doctype = Doctype {
+                        force_quirks: true,
+                        ..Doctype::default()
+                    };
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some(c) => {
+                    self.current_doctype = Doctype {
+                        name: Some(c.to_ascii_lowercase().to_string()),
+                        ..Doctype::default()
+                    };
+                    self.state = State::DoctypeName;
+                }
+                None => {
+                    self.error("eof-in-doctype");
+                    self.current_doctype = Doctype {
+                        force_quirks: true,
+                        ..Doctype::default()
+                    };
+                    self.emit_doctype();
+                    self.emit_eof();
+                }
+            },
+
+            State::DoctypeName => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::AfterDoctypeName,
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some('\0') => {
+                    self.error("unexpected-null-character");
+                    self.doctype_name_push('\u{FFFD}');
+                }
+                Some(c) => self.doctype_name_push(c.to_ascii_lowercase()),
+                None => self.eof_in_doctype(),
+            },
+
+            State::AfterDoctypeName => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                None => self.eof_in_doctype(),
+                Some(_) => {
+                    self.reconsume(c, State::AfterDoctypeName);
+                    if self.consume_if_matches("PUBLIC", true) {
+                        self.state = State::AfterDoctypePublicKeyword;
+                    } else if self.consume_if_matches("SYSTEM", true) {
+                        self.state = State::AfterDoctypeSystemKeyword;
+                    } else {
+                        self.error("invalid-character-sequence-after-doctype-name");
+                        self.current_doctype.force_quirks = true;
+                        self.state = State::BogusDoctype;
+                    }
+                }
+            },
+
+            State::AfterDoctypePublicKeyword => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeDoctypePublicIdentifier,
+                Some('"') => {
+                    self.error("missing-whitespace-after-doctype-public-keyword");
+                    self.current_doctype.public_id = Some(String::new());
+                    self.state = State::DoctypePublicIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.error("missing-whitespace-after-doctype-public-keyword");
+                    self.current_doctype.public_id = Some(String::new());
+                    self.state = State::DoctypePublicIdentifierSingleQuoted;
+                }
+                Some('>') => self.missing_doctype_identifier("missing-doctype-public-identifier"),
+                None => self.eof_in_doctype(),
+                Some(_) => self.missing_quote_before_doctype_identifier(c, "missing-quote-before-doctype-public-identifier"),
+            },
+
+            State::BeforeDoctypePublicIdentifier => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('"') => {
+                    self.current_doctype.public_id = Some(String::new());
+                    self.state = State::DoctypePublicIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.current_doctype.public_id = Some(String::new());
+                    self.state = State::DoctypePublicIdentifierSingleQuoted;
+                }
+                Some('>') => self.missing_doctype_identifier("missing-doctype-public-identifier"),
+                None => self.eof_in_doctype(),
+                Some(_) => self.missing_quote_before_doctype_identifier(c, "missing-quote-before-doctype-public-identifier"),
+            },
+
+            State::DoctypePublicIdentifierDoubleQuoted => {
+                self.doctype_identifier(c, '"', true, State::AfterDoctypePublicIdentifier)
+            }
+            State::DoctypePublicIdentifierSingleQuoted => {
+                self.doctype_identifier(c, '\'', true, State::AfterDoctypePublicIdentifier)
+            }
+
+            State::AfterDoctypePublicIdentifier => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {
+                    self.state = State::BetweenDoctypePublicAndSystemIdentifiers
+                }
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some('"') => {
+                    self.error("missing-whitespace-between-doctype-public-and-system-identifiers");
+                    self.current_doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.error("missing-whitespace-between-doctype-public-and-system-identifiers");
+                    self.current_doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                None => self.eof_in_doctype(),
+                Some(_) => self.missing_quote_before_doctype_identifier(c, "missing-quote-before-doctype-system-identifier"),
+            },
+
+            State::BetweenDoctypePublicAndSystemIdentifiers => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some('"') => {
+                    self.current_doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.current_doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                None => self.eof_in_doctype(),
+                Some(_) => self.missing_quote_before_doctype_identifier(c, "missing-quote-before-doctype-system-identifier"),
+            },
+
+            State::AfterDoctypeSystemKeyword => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => self.state = State::BeforeDoctypeSystemIdentifier,
+                Some('"') => {
+                    self.error("missing-whitespace-after-doctype-system-keyword");
+                    self.current_doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.error("missing-whitespace-after-doctype-system-keyword");
+                    self.current_doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                Some('>') => self.missing_doctype_identifier("missing-doctype-system-identifier"),
+                None => self.eof_in_doctype(),
+                Some(_) => self.missing_quote_before_doctype_identifier(c, "missing-quote-before-doctype-system-identifier"),
+            },
+
+            State::BeforeDoctypeSystemIdentifier => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('"') => {
+                    self.current_doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierDoubleQuoted;
+                }
+                Some('\'') => {
+                    self.current_doctype.system_id = Some(String::new());
+                    self.state = State::DoctypeSystemIdentifierSingleQuoted;
+                }
+                Some('>') => self.missing_doctype_identifier("missing-doctype-system-identifier"),
+                None => self.eof_in_doctype(),
+                Some(_) => self.missing_quote_before_doctype_identifier(c, "missing-quote-before-doctype-system-identifier"),
+            },
+
+            State::DoctypeSystemIdentifierDoubleQuoted => {
+                self.doctype_identifier(c, '"', false, State::AfterDoctypeSystemIdentifier)
+            }
+            State::DoctypeSystemIdentifierSingleQuoted => {
+                self.doctype_identifier(c, '\'', false, State::AfterDoctypeSystemIdentifier)
+            }
+
+            State::AfterDoctypeSystemIdentifier => match c {
+                Some('\t' | '\n' | '\x0C' | ' ') => {}
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                None => self.eof_in_doctype(),
+                Some(_) => {
+                    // Unlike the other bogus cases, this does not force quirks mode.
+                    self.error("unexpected-character-after-doctype-system-identifier");
+                    self.reconsume(c, State::BogusDoctype);
+                }
+            },
+
+            State::BogusDoctype => match c {
+                Some('>') => {
+                    self.state = State::Data;
+                    self.emit_doctype();
+                }
+                Some('\0') => self.error("unexpected-null-character"),
+                Some(_) => {}
+                None => {
+                    self.emit_doctype();
+                    self.emit_eof();
+                }
+            },
+
+            State::CdataSection => match c {
+                Some(']') => self.state = State::CdataSectionBracket,
+                Some(c) => self.emit_char(c),
+                None => {
+                    self.error("eof-in-cdata");
+                    self.emit_eof();
+                }
+            },
+
+            State::CdataSectionBracket => match c {
+                Some(']') => self.state = State::CdataSectionEnd,
+                _ => {
+                    self.emit_char(']');
+                    self.reconsume(c, State::CdataSection);
+                }
+            },
+
+            State::CdataSectionEnd => match c {
+                Some(']') => self.emit_char(']'),
+                Some('>') => self.state = State::Data,
+                _ => {
+                    self.emit_str("]]");
+                    self.reconsume(c, State::CdataSection);
+                }
+            },
+
+            State::CharacterReference => {
+                self.temp_buffer.clear();
+                self.temp_buffer.push('&');
+                match c {
+                    Some(c) if c.is_ascii_alphanumeric() => {
+                        self.reconsume(Some(c), State::NamedCharacterReference)
+                    }
+                    Some('#') => {
+                        self.temp_buffer.push('#');
+                        self.state = State::NumericCharacterReference;
+                    }
+                    _ => {
+                        self.flush_character_reference();
+                        let return_state = self.return_state;
+                        self.reconsume(c, return_state);
+                    }
+                }
+            }
+
+            State::NamedCharacterReference => {
+                // This state looks ahead instead of consuming a single character.
+                self.reconsume(c, State::NamedCharacterReference);
+                self.named_character_reference();
+            }
+
+            State::AmbiguousAmpersand => match c {
+                Some(c) if c.is_ascii_alphanumeric() => {
+                    if self.in_attribute_value() {
+                        self.push_attribute_value(c);
+                    } else {
+                        self.emit_char(c);
+                    }
+                }
+                Some(';') => {
+                    self.error("unknown-named-character-reference");
+                    let return_state = self.return_state;
+                    self.reconsume(c, return_state);
+                }
+                _ => {
+                    let return_state = self.return_state;
+                    self.reconsume(c, return_state);
+                }
+            },
+
+            State::NumericCharacterReference => {
+                self.character_reference_code = 0;
+                match c {
+                    Some(c @ ('x' | 'X')) => {
+                        self.temp_buffer.push(c);
+                        self.state = State::HexadecimalCharacterReferenceStart;
+                    }
+                    _ => self.reconsume(c, State::DecimalCharacterReferenceStart),
+                }
+            }
+
+            State::HexadecimalCharacterReferenceStart => match c {
+                Some(c) if c.is_ascii_hexdigit() => {
+                    self.reconsume(Some(c), State::HexadecimalCharacterReference)
+                }
+                _ => self.absence_of_digits(c),
+            },
+
+            State::DecimalCharacterReferenceStart => match c {
+                Some(c) if c.is_ascii_digit() => self.reconsume(Some(c), State::DecimalCharacterReference),
+                _ => self.absence_of_digits(c),
+            },
+
+            State::HexadecimalCharacterReference => match c {
+                Some(c) if c.is_ascii_hexdigit() => self.push_reference_digit(c, 16),
+                Some(';') => self.state = State::NumericCharacterReferenceEnd,
+                _ => {
+                    self.error("missing-semicolon-after-character-reference");
+                    self.reconsume(c, State::NumericCharacterReferenceEnd);
+                }
+            },
+
+            State::DecimalCharacterReference => match c {
+                Some(c) if c.is_ascii_digit() => self.push_reference_digit(c, 10),
+                Some(';') => self.state = State::NumericCharacterReferenceEnd,
+                _ => {
+                    self.error("missing-semicolon-after-character-reference");
+                    self.reconsume(c, State::NumericCharacterReferenceEnd);
+                }
+            },
+
+            State::NumericCharacterReferenceEnd => {
+                // This state does not consume anything.
+                self.reconsume(c, State::NumericCharacterReferenceEnd);
+                self.numeric_character_reference_end();
             }
-            _ => {}
         }
+    }
 
-        // Parse attribute name
-        let name = match parse_attribute_name(chars) {
-            Ok(n) => n,
-            Err(_) => {
-                // If we fail here, it could be that there's trailing slash or something
-                break;
+    fn in_attribute_value(&self) -> bool {
+        matches!(
+            self.return_state,
+            State::AttributeValueDoubleQuoted
+                | State::AttributeValueSingleQuoted
+                | State::AttributeValueUnquoted
+        )
+    }
+
+    /// "Flush code points consumed as a character reference": the temporary
+    /// buffer goes to the attribute value or is emitted as text.
+    fn flush_character_reference(&mut self) {
+        let buffer = std::mem::take(&mut self.temp_buffer);
+        if self.in_attribute_value() {
+            if let Some(attr) = self.current_tag.attributes.last_mut() {
+                attr.1.push_str(&buffer);
             }
+        } else {
+            self.emit_str(&buffer);
+        }
+    }
+
+    /// Finds the longest name of the named character references table that
+    /// matches the upcoming input.
+    fn named_character_reference(&mut self) {
+        let candidate: String = self.input[self.pos..]
+            .iter()
+            .take(entities::LONGEST_NAME)
+            .take_while(|c| c.is_ascii_alphanumeric() || **c == ';')
+            .collect();
+        // A longer name, or the semicolon, may be in the input still to come.
+        if candidate.len() < entities::LONGEST_NAME
+            && !candidate.contains(';')
+            && self.waits_for_input(self.pos + candidate.len())
+        {
+            self.starved = true;
+            return;
+        }
+        // A semicolon ends the name.
+        let candidate = match candidate.find(';') {
+            Some(index) => &candidate[..=index],
+            None => &candidate[..],
+        };
+
+        let matched = (1..=candidate.len())
+            .rev()
+            .find_map(|len| entities::lookup(&candidate[..len]).map(|value| (len, value)));
+
+        let Some((len, value)) = matched else {
+            self.flush_character_reference();
+            self.state = State::AmbiguousAmpersand;
+            return;
         };
 
-        skip_whitespace(chars);
+        let name = &candidate[..len];
+        self.pos += len;
+        let next = self.input.get(self.pos).copied();
+        let ends_with_semicolon = name.ends_with(';');
 
-        // Parse optional `= value`
-        let mut value = String::new();
-        if let Some('=') = chars.peek() {
-            // Consume '='
-            chars.next();
-            skip_whitespace(chars);
+        // For historical reasons, `&amp=` or `&ampx` in an attribute value are
+        // not references at all.
+        if self.in_attribute_value()
+            && !ends_with_semicolon
+            && next.is_some_and(|n| n == '=' || n.is_ascii_alphanumeric())
+        {
+            self.temp_buffer.push_str(name);
+            self.flush_character_reference();
+            self.state = self.return_state;
+            return;
+        }
 
-            // Parse either a quoted or unquoted value
-            value = parse_attribute_value(chars)?;
+        if !ends_with_semicolon {
+            self.error("missing-semicolon-after-character-reference");
         }
+        self.temp_buffer = value.to_string();
+        self.flush_character_reference();
+        self.state = self.return_state;
+    }
 
-        attributes.push((name, value));
+    fn push_reference_digit(&mut self, c: char, radix: u32) {
+        let digit = c.to_digit(radix).unwrap_or(0);
+        // Saturate: anything above U+10FFFF is an error anyway.
+        self.character_reference_code = self
+            .character_reference_code
+            .saturating_mul(radix)
+            .saturating_add(digit)
+            .min(0x110000);
     }
 
-    Ok(attributes)
-}
+    fn absence_of_digits(&mut self, c: Option<char>) {
+        self.error("absence-of-digits-in-numeric-character-reference");
+        self.flush_character_reference();
+        let return_state = self.return_state;
+        self.reconsume(c, return_state);
+    }
+
+    fn numeric_character_reference_end(&mut self) {
+        let code = self.character_reference_code;
+        let replacement = match code {
+            0 => {
+                self.error("null-character-reference");
+                0xFFFD
+            }
+            0x110000.. => {
+                self.error("character-reference-outside-unicode-range");
+                0xFFFD
+            }
+            0xD800..=0xDFFF => {
+                self.error("surrogate-character-reference");
+                0xFFFD
+            }
+            _ => {
+                if is_noncharacter(code) {
+                    self.error("noncharacter-character-reference");
+                } else if code == 0x0D || (is_control(code) && !is_ascii_whitespace(code)) {
+                    self.error("control-character-reference");
+                }
+                windows_1252_replacement(code).unwrap_or(code)
+            }
+        };
+        self.temp_buffer.clear();
+        self.temp_buffer.push(char::from_u32(replacement).unwrap_or('\u{FFFD}'));
+        self.flush_character_reference();
+        self.state = self.return_state;
+    }
+
+    /// Shared by the RCDATA and RAWTEXT "less-than sign" states.
+    fn raw_less_than_sign(&mut self, c: Option<char>, text_state: State, end_tag_open: State) {
+        match c {
+            Some('/') => {
+                self.temp_buffer.clear();
+                self.state = end_tag_open;
+            }
+            _ => {
+                self.emit_char('<');
+                self.reconsume(c, text_state);
+            }
+        }
+    }
+
+    /// Shared by the RCDATA, RAWTEXT and script data "end tag open" states.
+    fn raw_end_tag_open(&mut self, c: Option<char>, text_state: State, end_tag_name: State) {
+        match c {
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.new_tag(true);
+                self.reconsume(Some(c), end_tag_name);
+            }
+            _ => {
+                self.emit_str("</");
+                self.reconsume(c, text_state);
+            }
+        }
+    }
+
+    /// Shared by the RCDATA, RAWTEXT and script data "end tag name" states.
+    /// Only an end tag matching the element that opened the raw text ends it.
+    fn raw_end_tag_name(&mut self, c: Option<char>, text_state: State) {
+        match c {
+            Some('\t' | '\n' | '\x0C' | ' ') if self.is_appropriate_end_tag() => {
+                self.state = State::BeforeAttributeName;
+            }
+            Some('/') if self.is_appropriate_end_tag() => {
+                self.state = State::SelfClosingStartTag;
+            }
+            Some('>') if self.is_appropriate_end_tag() => {
+                self.state = State::Data;
+                self.emit_current_tag();
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                self.current_tag.name.push(c.to_ascii_lowercase());
+                self.temp_buffer.push(c);
+            }
+            _ => {
+                self.emit_end_tag_prefix();
+                self.reconsume(c, text_state);
+            }
+        }
+    }
+
+    /// Shared by the double- and single-quoted attribute value states.
+    fn attribute_value_quoted(&mut self, c: Option<char>, quote: char) {
+        match c {
+            Some(c) if c == quote => self.state = State::AfterAttributeValueQuoted,
+            Some('&') => {
+                self.return_state = self.state;
+                self.state = State::CharacterReference;
+            }
+            Some('\0') => {
+                self.error("unexpected-null-character");
+                self.push_attribute_value('\u{FFFD}');
+            }
+            Some(c) => self.push_attribute_value(c),
+            None => {
+                self.error("eof-in-tag");
+                self.emit_eof();
+            }
+        }
+    }
 
-/// Parse a single attribute name, stopping at whitespace, `=`, `>` or `/`.
-fn parse_attribute_name<I: Iterator<Item=char>>(chars: &mut std::iter::Peekable<I>) -> Result<String, ParserError> {
-    let mut name = String::new();
-    while let Some(&c) = chars.peek() {
-        if c.is_whitespace() || c == '=' || c == '>' || c == '/' {
-            break;
+    /// Shared by the four quoted DOCTYPE identifier states.
+    fn doctype_identifier(&mut self, c: Option<char>, quote: char, public: bool, after: State) {
+        match c {
+            Some(c) if c == quote => self.state = after,
+            Some('\0') => {
+                self.error("unexpected-null-character");
+                self.doctype_identifier_push(public, '\u{FFFD}');
+            }
+            Some('>') => {
+                self.error(if public {
+                    "abrupt-doctype-public-identifier"
+                } else {
+                    "abrupt-doctype-system-identifier"
+                });
+                self.current_doctype.force_quirks = true;
+                self.state = State::Data;
+                self.emit_doctype();
+            }
+            Some(c) => self.doctype_identifier_push(public, c),
+            None => self.eof_in_doctype(),
         }
-        name.push(c);
-        chars.next();
     }
 
-    if name.is_empty() {
-        return Err(ParserError::Other("Missing attribute name".into()));
+    fn doctype_identifier_push(&mut self, public: bool, c: char) {
+        let identifier = if public {
+            &mut self.current_doctype.public_id
+        } else {
+            &mut self.current_doctype.system_id
+        };
+        identifier.get_or_insert_with(String::new).push(c);
+    }
+
+    fn doctype_name_push(&mut self, c: char) {
+        self.current_doctype.name.get_or_insert_with(String::new).push(c);
     }
 
-    Ok(name)
+    fn eof_in_doctype(&mut self) {
+        self.error("eof-in-doctype");
+        self.current_doctype.force_quirks = true;
+        self.emit_doctype();
+        self.emit_eof();
+    }
+
+    fn missing_doctype_identifier(&mut self, code: &'static str) {
+        self.error(code);
+        self.current_doctype.force_quirks = true;
+        self.state = State::Data;
+        self.emit_doctype();
+    }
+
+    fn missing_quote_before_doctype_identifier(&mut self, c: Option<char>, code: &'static str) {
+        self.error(code);
+        self.current_doctype.force_quirks = true;
+        self.reconsume(c, State::BogusDoctype);
+    }
 }
 
-/// Parse a single attribute value, which may be quoted or unquoted.
-fn parse_attribute_value<I: Iterator<Item=char>>(chars: &mut std::iter::Peekable<I>) -> Result<String, ParserError> {
-    let mut value = String::new();
+fn is_noncharacter(code: u32) -> bool {
+    (0xFDD0..=0xFDEF).contains(&code) || (code & 0xFFFE) == 0xFFFE
+}
 
-    // If it's a quoted value
-    if let Some(&quote_char) = chars.peek() {
-        if quote_char == '"' || quote_char == '\'' {
-            // Consume the quote
-            chars.next();
+fn is_control(code: u32) -> bool {
+    code <= 0x1F || (0x7F..=0x9F).contains(&code)
+}
 
-            // Read until the matching quote
-            while let Some(&c) = chars.peek() {
-                chars.next();
-                if c == quote_char {
-                    // End of quoted value
-                    break;
-                }
-                value.push(c);
+fn is_ascii_whitespace(code: u32) -> bool {
+    matches!(code, 0x09 | 0x0A | 0x0C | 0x0D | 0x20)
+}
+
+/// Numeric references to C1 controls are interpreted as Windows-1252, as
+/// legacy pages expect (`&#150;` is an en dash).
+fn windows_1252_replacement(code: u32) -> Option<u32> {
+    let replacement = match code {
+        0x80 => 0x20AC,
+        0x82 => 0x201A,
+        0x83 => 0x0192,
+        0x84 => 0x201E,
+        0x85 => 0x2026,
+        0x86 => 0x2020,
+        0x87 => 0x2021,
+        0x88 => 0x02C6,
+        0x89 => 0x2030,
+        0x8A => 0x0160,
+        0x8B => 0x2039,
+        0x8C => 0x0152,
+        0x8E => 0x017D,
+        0x91 => 0x2018,
+        0x92 => 0x2019,
+        0x93 => 0x201C,
+        0x94 => 0x201D,
+        0x95 => 0x2022,
+        0x96 => 0x2013,
+        0x97 => 0x2014,
+        0x98 => 0x02DC,
+        0x99 => 0x2122,
+        0x9A => 0x0161,
+        0x9B => 0x203A,
+        0x9C => 0x0153,
+        0x9E => 0x017E,
+        0x9F => 0x0178,
+        _ => return None,
+    };
+    Some(replacement)
+}
+
+/// Normalizes newlines as required by the "preprocessing the input stream"
+/// step: every CR LF pair and every lone CR become a single LF.
+///
+/// Also returns the byte offset in `input` of every character, followed by
+/// the length of `input`.
+fn preprocess(input: &str) -> (Vec<char>, Vec<usize>) {
+    let mut chars = Vec::with_capacity(input.len());
+    let mut offsets = Vec::with_capacity(input.len() + 1);
+    let mut iter = input.char_indices().peekable();
+    while let Some((offset, c)) = iter.next() {
+        offsets.push(offset);
+        if c == '\r' {
+            if iter.peek().is_some_and(|&(_, next)| next == '\n') {
+                iter.next();
             }
-            return Ok(value);
+            chars.push('\n');
+        } else {
+            chars.push(c);
         }
     }
+    offsets.push(input.len());
+    (chars, offsets)
+}
+
+/// Returns the tokenizer state used for the content of `tag_name`, for the
+/// elements whose content is not parsed as markup.
+pub fn raw_text_state(tag_name: &str) -> Option<State> {
+    match tag_name {
+        "title" | "textarea" => Some(State::Rcdata),
+        "style" | "xmp" | "iframe" | "noembed" | "noframes" => Some(State::Rawtext),
+        "script" => Some(State::ScriptData),
+        "plaintext" => Some(State::Plaintext),
+        _ => None,
+    }
+}
 
-    // Otherwise, parse unquoted value until whitespace or tag end
-    while let Some(&c) = chars.peek() {
-        // Stop on whitespace, or possible tag delimiter
-        if c.is_whitespace() || c == '>' || c == '/' {
-            break;
+/// Tokenizes a whole document in one go.
+///
+/// Without a tree builder to drive it, the tokenizer switches to the raw text
+/// states on its own after the start tag of a raw text element, so that
+/// `<script>if (a < b)</script>` is a single text token.
+///
+/// Text is kept exactly as written, whitespace included. The tree builder
+/// pulls tokens from a [`Tokenizer`] instead.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParserError> {
+    let mut tokenizer = Tokenizer::new(input);
+    let mut tokens = Vec::new();
+
+    loop {
+        match tokenizer.next_token() {
+            Token::Eof => break,
+            token => {
+                if let Token::StartTag { name, .. } = &token {
+                    if let Some(state) = raw_text_state(name) {
+                        tokenizer.set_state(state);
+                    }
+                }
+                tokens.push(token);
+            }
         }
-        value.push(c);
-        chars.next();
     }
 
-    Ok(value)
+    // Finally, add EOF token
+    tokens.push(Token::Eof);
+    Ok(tokens)
 }
 
-/// Utility: consume and discard any consecutive whitespace from the current cursor
-fn skip_whitespace<I: Iterator<Item=char>>(chars: &mut std::iter::Peekable<I>) {
-    while let Some(&c) = chars.peek() {
-        if c.is_whitespace() {
-            chars.next();
-        } else {
-            break;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects every token produced by a fresh tokenizer, `Eof` excluded.
+    fn tokens_of(input: &str) -> Vec<Token> {
+        let mut tokenizer = Tokenizer::new(input);
+        let mut tokens = Vec::new();
+        loop {
+            match tokenizer.next_token() {
+                Token::Eof => return tokens,
+                token => tokens.push(token),
+            }
+        }
+    }
+
+    fn start_tag(name: &str, attributes: &[(&str, &str)], self_closing: bool) -> Token {
+        Token::StartTag {
+            name: name.to_string(),
+            attributes: attributes
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            self_closing,
+        }
+    }
+
+    #[test]
+    fn test_simple_tags_and_text() {
+        assert_eq!(
+            tokens_of("<p>Hello <b>world</b></p>"),
+            vec![
+                start_tag("p", &[], false),
+                Token::Text("Hello ".to_string()),
+                start_tag("b", &[], false),
+                Token::Text("world".to_string()),
+                Token::EndTag("b".to_string()),
+                Token::EndTag("p".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tag_and_attribute_names_are_lowercased() {
+        assert_eq!(
+            tokens_of(r#"<A HREF="http://www.google.fr/">"#),
+            vec![start_tag("a", &[("href", "http://www.google.fr/")], false)]
+        );
+    }
+
+    #[test]
+    fn test_attribute_value_forms() {
+        assert_eq!(
+            tokens_of(r#"<input type=checkbox value='a b' checked name="x">"#),
+            vec![start_tag(
+                "input",
+                &[("type", "checkbox"), ("value", "a b"), ("checked", ""), ("name", "x")],
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_attributes_are_dropped() {
+        let mut tokenizer = Tokenizer::new("<p ID=a class=b id=c CLASS>");
+        assert_eq!(tokenizer.next_token(), start_tag("p", &[("id", "a"), ("class", "b")], false));
+        let errors: Vec<_> = tokenizer.take_errors().iter().map(|error| error.code).collect();
+        assert_eq!(errors, ["duplicate-attribute", "duplicate-attribute"]);
+    }
+
+    #[test]
+    fn test_self_closing_flag() {
+        assert_eq!(tokens_of("<br/>"), vec![start_tag("br", &[], true)]);
+        assert_eq!(
+            tokens_of("<img src=a.png />"),
+            vec![start_tag("img", &[("src", "a.png")], true)]
+        );
+    }
+
+    #[test]
+    fn test_doctype() {
+        assert_eq!(
+            tokens_of("<!DOCTYPE html>"),
+            vec![Token::Doctype(Doctype {
+                name: Some("html".to_string()),
+                ..Doctype::default()
+            })]
+        );
+
+        let legacy = r#"<!DOCTYPE HTML PUBLIC "-//W3C//DTD HTML 4.01//EN" "http://www.w3.org/TR/html4/strict.dtd">"#;
+        assert_eq!(
+            tokens_of(legacy),
+            vec![Token::Doctype(Doctype {
+                name: Some("html".to_string()),
+                public_id: Some("-//W3C//DTD HTML 4.01//EN".to_string()),
+                system_id: Some("http://www.w3.org/TR/html4/strict.dtd".to_string()),
+                force_quirks: false,
+            })]
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(tokens_of("<!-- a -- b -->"), vec![Token::Comment(" a -- b ".to_string())]);
+        assert_eq!(tokens_of("<!---->"), vec![Token::Comment(String::new())]);
+        assert_eq!(tokens_of("<!-->"), vec![Token::Comment(String::new())]);
+        assert_eq!(tokens_of("<?xml version?>"), vec![Token::Comment("?xml version?".to_string())]);
+        assert_eq!(tokens_of("<!foo>"), vec![Token::Comment("foo".to_string())]);
+    }
+
+    #[test]
+    fn test_lone_less_than_sign_is_text() {
+        assert_eq!(tokens_of("a < b"), vec![Token::Text("a < b".to_string())]);
+        assert_eq!(tokens_of("a <"), vec![Token::Text("a <".to_string())]);
+        assert_eq!(tokens_of("</>x"), vec![Token::Text("x".to_string())]);
+    }
+
+    #[test]
+    fn test_unclosed_constructs_do_not_fail() {
+        let mut tokenizer = Tokenizer::new("<div class=\"oops");
+        assert_eq!(tokenizer.next_token(), Token::Eof);
+        let errors = tokenizer.take_errors();
+        assert!(matches!(&errors[..], [error] if error.code == "eof-in-tag"));
+
+        assert_eq!(
+            tokens_of("<!-- never closed"),
+            vec![Token::Comment(" never closed".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_newlines_are_normalized() {
+        assert_eq!(tokens_of("a\r\nb\rc"), vec![Token::Text("a\nb\nc".to_string())]);
+    }
+
+    #[test]
+    fn test_rawtext_states() {
+        let mut tokenizer = Tokenizer::new("<style>a < b { }</style>");
+        assert_eq!(tokenizer.next_token(), start_tag("style", &[], false));
+        tokenizer.set_state(State::Rawtext);
+        assert_eq!(tokenizer.next_token(), Token::Text("a < b { }".to_string()));
+        assert_eq!(tokenizer.next_token(), Token::EndTag("style".to_string()));
+
+        let mut tokenizer = Tokenizer::new("<script>if (a</b) {} <!-- </script> --></script>");
+        assert_eq!(tokenizer.next_token(), start_tag("script", &[], false));
+        tokenizer.set_state(State::ScriptData);
+        assert_eq!(tokenizer.next_token(), Token::Text("if (a</b) {} <!-- ".to_string()));
+        assert_eq!(tokenizer.next_token(), Token::EndTag("script".to_string()));
+    }
+
+    #[test]
+    fn test_cdata_only_when_allowed() {
+        assert_eq!(
+            tokens_of("<![CDATA[x]]>"),
+            vec![Token::Comment("[CDATA[x]]".to_string())]
+        );
+
+        let mut tokenizer = Tokenizer::new("<![CDATA[a<b]]>");
+        tokenizer.set_allow_cdata(true);
+        assert_eq!(tokenizer.next_token(), Token::Text("a<b".to_string()));
+    }
+
+    #[test]
+    fn test_named_character_references() {
+        assert_eq!(
+            tokens_of("&lt;p&gt; &amp; &eacute;t&eacute; &NotEqualTilde;"),
+            vec![Token::Text("<p> & été \u{2242}\u{338}".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_legacy_references_without_semicolon() {
+        assert_eq!(tokens_of("&copy 2024"), vec![Token::Text("\u{A9} 2024".to_string())]);
+        // The longest match wins: `&notin;` is a reference, `&notit;` is `&not` + "it;"
+        assert_eq!(tokens_of("&notin;"), vec![Token::Text("\u{2209}".to_string())]);
+        assert_eq!(tokens_of("&notit;"), vec![Token::Text("\u{AC}it;".to_string())]);
+        // Unknown names are left alone
+        assert_eq!(tokens_of("&foo; & x"), vec![Token::Text("&foo; & x".to_string())]);
+    }
+
+    #[test]
+    fn test_numeric_character_references() {
+        assert_eq!(
+            tokens_of("&#233;&#xE9;&#X41;&#65"),
+            vec![Token::Text("ééAA".to_string())]
+        );
+        // C1 controls are remapped through Windows-1252
+        assert_eq!(tokens_of("&#150;&#x80;"), vec![Token::Text("\u{2013}\u{20AC}".to_string())]);
+        // Invalid code points become U+FFFD
+        assert_eq!(
+            tokens_of("&#0;&#xD800;&#x110000;"),
+            vec![Token::Text("\u{FFFD}\u{FFFD}\u{FFFD}".to_string())]
+        );
+        assert_eq!(tokens_of("&#;&#x;"), vec![Token::Text("&#;&#x;".to_string())]);
+    }
+
+    #[test]
+    fn test_character_references_in_attributes() {
+        assert_eq!(
+            tokens_of(r#"<a href="?a=1&amp;b=2&copy=3&lang" title='&quot;x&quot;' alt=&lt;>"#),
+            vec![start_tag(
+                "a",
+                &[("href", "?a=1&b=2&copy=3&lang"), ("title", "\"x\""), ("alt", "<")],
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn test_character_references_in_rcdata_only() {
+        let mut tokenizer = Tokenizer::new("&amp;</title>");
+        tokenizer.set_last_start_tag("title");
+        tokenizer.set_state(State::Rcdata);
+        assert_eq!(tokenizer.next_token(), Token::Text("&".to_string()));
+
+        let mut tokenizer = Tokenizer::new("&amp;</style>");
+        tokenizer.set_last_start_tag("style");
+        tokenizer.set_state(State::Rawtext);
+        assert_eq!(tokenizer.next_token(), Token::Text("&amp;".to_string()));
+    }
+
+    #[test]
+    fn test_tokenize_switches_to_raw_text() {
+        let tokens = tokenize("<script>if (a < b && c) { d(\"<b>\"); }</script><p>x</p>").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                start_tag("script", &[], false),
+                Token::Text("if (a < b && c) { d(\"<b>\"); }".to_string()),
+                Token::EndTag("script".to_string()),
+                start_tag("p", &[], false),
+                Token::Text("x".to_string()),
+                Token::EndTag("p".to_string()),
+                Token::Eof,
+            ]
+        );
+
+        let tokens = tokenize("<textarea>  <b>&amp;</b></textarea>").unwrap();
+        assert_eq!(tokens[1], Token::Text("  <b>&</b>".to_string()));
+
+        let tokens = tokenize("<style>a > b { content: '&amp;' }</style>").unwrap();
+        assert_eq!(tokens[1], Token::Text("a > b { content: '&amp;' }".to_string()));
+    }
+
+    #[test]
+    fn test_tokenize_keeps_whitespace() {
+        let tokens = tokenize("<b>a</b> <i>\tb </i>\n").unwrap();
+        assert_eq!(tokens[3], Token::Text(" ".to_string()));
+        assert_eq!(tokens[5], Token::Text("\tb ".to_string()));
+        assert_eq!(tokens[7], Token::Text("\n".to_string()));
+    }
+
+    #[test]
+    fn test_token_spans() {
+        let input = "ab\r\n<p id=x>&amp;</p>";
+        let mut tokenizer = Tokenizer::new(input);
+        let mut spans = Vec::new();
+        while tokenizer.next_token() != Token::Eof {
+            let span = tokenizer.token_span();
+            spans.push((&input[span.start..span.end], span.line, span.column));
         }
+        assert_eq!(
+            spans,
+            vec![("ab\r\n", 1, 1), ("<p id=x>", 2, 1), ("&amp;", 2, 9), ("</p>", 2, 14)]
+        );
     }
 }
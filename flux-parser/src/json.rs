@@ -0,0 +1,281 @@
+//! A JSON parser (RFC 8259), used by the JSON viewer.
+
+use crate::error::{ParseError, ParserError, Severity};
+use crate::html::token::SourceSpan;
+
+/// Nesting depth beyond which parsing gives up, so that hostile documents
+/// cannot overflow the call stack.
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    /// A number as written in the source, so that big integers and the
+    /// exact notation are kept.
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// Members in source order. Duplicate keys are all kept.
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// Parses a JSON text. A byte order mark at the start is ignored.
+pub fn parse_json(input: &str) -> Result<JsonValue, ParserError> {
+    let mut parser = JsonParser {
+        input: input.strip_prefix('\u{FEFF}').unwrap_or(input),
+        pos: 0,
+        depth: 0,
+    };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.input.len() {
+        return Err(parser.error("trailing-content", "Unexpected content after the JSON value"));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    input: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn value(&mut self) -> Result<JsonValue, ParserError> {
+        match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(JsonValue::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) if self.eat("null") => Ok(JsonValue::Null),
+            Some(_) if self.eat("true") => Ok(JsonValue::Bool(true)),
+            Some(_) if self.eat("false") => Ok(JsonValue::Bool(false)),
+            Some(_) => Err(self.error("expected-value", "Expected a value")),
+            None => Err(self.error("unexpected-eof", "Unexpected end of input")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<JsonValue, ParserError>) -> Result<JsonValue, ParserError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("too-deeply-nested", "Too deeply nested"));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<JsonValue, ParserError> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected-string-key", "Expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            self.skip_whitespace();
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(JsonValue::Object(members));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn array(&mut self) -> Result<JsonValue, ParserError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat("]") {
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(JsonValue::Array(items));
+            }
+            self.expect(",")?;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParserError> {
+        self.pos += 1;
+        let mut string = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("unterminated-string", "Unterminated string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{C}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            self.pos += 1;
+                            string.push(self.unicode_escape()?);
+                            continue;
+                        }
+                        _ => return Err(self.error("invalid-escape", "Invalid escape sequence")),
+                    };
+                    self.pos += 1;
+                    string.push(escaped);
+                }
+                '\0'..='\u{1F}' => return Err(self.error("unescaped-control-character", "Control characters must be escaped in strings")),
+                _ => string.push(c),
+            }
+        }
+    }
+
+    /// Parses the digits of a `\u` escape, and of the low surrogate that
+    /// follows a high one. Lone surrogates become U+FFFD.
+    fn unicode_escape(&mut self) -> Result<char, ParserError> {
+        let first = self.hex4()?;
+        if (0xD800..0xDC00).contains(&first) && self.input[self.pos..].starts_with("\\u") {
+            let checkpoint = self.pos;
+            self.pos += 2;
+            let second = self.hex4()?;
+            if (0xDC00..0xE000).contains(&second) {
+                let code = 0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00);
+                return Ok(char::from_u32(code).unwrap_or('\u{FFFD}'));
+            }
+            self.pos = checkpoint;
+        }
+        Ok(char::from_u32(first).unwrap_or('\u{FFFD}'))
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParserError> {
+        let digits = self.input.get(self.pos..self.pos + 4).filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()));
+        let Some(digits) = digits else {
+            return Err(self.error("invalid-escape", "Invalid \\u escape"));
+        };
+        self.pos += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+
+    fn number(&mut self) -> Result<JsonValue, ParserError> {
+        let start = self.pos;
+        self.eat("-");
+        if !self.eat("0") && self.digits() == 0 {
+            return Err(self.error("invalid-number", "Invalid number"));
+        }
+        if self.eat(".") && self.digits() == 0 {
+            return Err(self.error("invalid-number", "Invalid number"));
+        }
+        if self.eat("e") || self.eat("E") {
+            let _ = self.eat("+") || self.eat("-");
+            if self.digits() == 0 {
+                return Err(self.error("invalid-number", "Invalid number"));
+            }
+        }
+        Ok(JsonValue::Number(self.input[start..self.pos].to_string()))
+    }
+
+    /// Consumes ASCII digits and returns how many there were.
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let matches = self.input[self.pos..].starts_with(s);
+        if matches {
+            self.pos += s.len();
+        }
+        matches
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), ParserError> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error("unexpected-character", &format!("Expected '{}'", s)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, code: &'static str, message: &str) -> ParserError {
+        let before = &self.input[..self.pos];
+        let span = SourceSpan {
+            start: self.pos,
+            end: self.pos,
+            line: before.matches('\n').count() + 1,
+            column: before.rsplit('\n').next().unwrap_or("").chars().count() + 1,
+        };
+        ParserError::Syntax(ParseError::new(code, Severity::Fatal, span).with_message(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_values() {
+        assert_eq!(
+            parse_json(" {\"a\": [1, -2.5e+3, true, null], \"b\": {}, \"c\": \"x\\n\\u00e9\\ud83d\\ude00\"} ").unwrap(),
+            JsonValue::Object(vec![
+                (
+                    "a".to_string(),
+                    JsonValue::Array(vec![
+                        JsonValue::Number("1".to_string()),
+                        JsonValue::Number("-2.5e+3".to_string()),
+                        JsonValue::Bool(true),
+                        JsonValue::Null,
+                    ])
+                ),
+                ("b".to_string(), JsonValue::Object(Vec::new())),
+                ("c".to_string(), JsonValue::String("x\né😀".to_string())),
+            ])
+        );
+        assert_eq!(parse_json("12345678901234567890").unwrap(), JsonValue::Number("12345678901234567890".to_string()));
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        for input in ["", "{", "[1,]", "{\"a\" 1}", "01", "1.", "'a'", "\"\\x\"", "\"a\nb\"", "[1] 2", "{a: 1}"] {
+            assert!(parse_json(input).is_err(), "{:?} should not parse", input);
+        }
+        match parse_json("{\n  \"a\": tru\n}") {
+            Err(ParserError::Syntax(error)) => assert_eq!(error.to_string(), "2:8: expected-value (Expected a value)"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        assert!(parse_json(&format!("{}{}", "[".repeat(100), "]".repeat(100))).is_ok());
+        assert!(parse_json(&"[".repeat(100_000)).is_err());
+    }
+}
@@ -2,19 +2,34 @@
 
 pub mod error;
 pub mod html;
+pub mod json;
+pub mod viewer;
+pub mod xml;
 
 use crate::html::{parse_html, HtmlDocument};
+use crate::xml::{parse_xml, XmlDocument};
 
 pub enum ParsedDocument {
     Html(HtmlDocument),
+    /// An XML document: XHTML, SVG, feeds and other XML formats.
+    Xml(XmlDocument),
 }
 
+/// Parses a resource according to its MIME type. Plain text and JSON are
+/// shown in a viewer document; images have no text to parse, their viewer is
+/// built from their URL by [`viewer::image_document`].
 pub fn parse_document(input: &str, mime_type: &str) -> Result<ParsedDocument, error::ParserError> {
     match mime_type {
         "text/html" => {
             let doc = parse_html(input)?;
             Ok(ParsedDocument::Html(doc))
         }
+        "text/xml" | "application/xml" => Ok(ParsedDocument::Xml(parse_xml(input)?)),
+        "text/plain" => Ok(ParsedDocument::Html(viewer::text_document(input))),
+        "application/json" | "text/json" => Ok(ParsedDocument::Html(viewer::json_document(input))),
+        // XHTML, SVG, RSS and Atom feeds...
+        _ if mime_type.ends_with("+xml") => Ok(ParsedDocument::Xml(parse_xml(input)?)),
+        _ if mime_type.ends_with("+json") => Ok(ParsedDocument::Html(viewer::json_document(input))),
         _ => Err(error::ParserError::UnsupportedMimeType(mime_type.to_string())),
     }
 }
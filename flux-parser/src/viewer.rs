@@ -0,0 +1,180 @@
+//! Viewer documents.
+//!
+//! Resources that are not markup are shown in a synthetic HTML document, the
+//! way browsers display them: plain text in a `<pre>`, JSON as a tree of
+//! collapsible `<details>` elements, images in a centered `<img>`.
+
+use crate::html::parser::{HtmlDocument, HtmlParser};
+use crate::html::serializer::escape;
+use crate::json::{parse_json, JsonValue};
+
+/// Builds the document showing a plain text resource.
+pub fn text_document(text: &str) -> HtmlDocument {
+    // The parser drops a newline right after <pre>: the one written here
+    let mut markup = String::from("<!DOCTYPE html><html><head></head><body><pre>\n");
+    escape(&mut markup, text, false);
+    markup.push_str("</pre></body></html>");
+    HtmlParser::new(&markup).parse()
+}
+
+/// Builds the document showing a JSON resource, pretty-printed as a tree
+/// whose objects and arrays can be collapsed. Invalid JSON is shown as
+/// plain text.
+pub fn json_document(text: &str) -> HtmlDocument {
+    let Ok(value) = parse_json(text) else {
+        return text_document(text);
+    };
+    let mut markup = String::from("<!DOCTYPE html><html><head></head><body><ul class=\"json\">");
+    json_value(&mut markup, None, &value, true);
+    markup.push_str("</ul></body></html>");
+    HtmlParser::new(&markup).parse()
+}
+
+/// Builds the document showing the image at `url`, centered in the view.
+pub fn image_document(url: &str) -> HtmlDocument {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let name = path.rsplit('/').find(|segment| !segment.is_empty()).unwrap_or(url);
+    let mut markup = String::from("<!DOCTYPE html><html><head><title>");
+    escape(&mut markup, name, false);
+    markup.push_str(
+        "</title></head><body style=\"margin: 0; min-height: 100vh; display: flex; \
+         align-items: center; justify-content: center\"><img src=\"",
+    );
+    escape(&mut markup, url, true);
+    markup.push_str("\" alt=\"");
+    escape(&mut markup, name, true);
+    markup.push_str("\"></body></html>");
+    HtmlParser::new(&markup).parse()
+}
+
+/// Writes a `<li>` for a value, with its key when it is an object member.
+/// Non-empty objects and arrays are written as open `<details>`, their
+/// opening bracket in the `<summary>` that stays visible once collapsed.
+fn json_value(output: &mut String, key: Option<&str>, value: &JsonValue, last: bool) {
+    let comma = if last { "" } else { "," };
+    let (open, close, children): (&str, &str, Vec<(Option<&str>, &JsonValue)>) = match value {
+        JsonValue::Object(members) => {
+            ("{", "}", members.iter().map(|(key, value)| (Some(key.as_str()), value)).collect())
+        }
+        JsonValue::Array(items) => ("[", "]", items.iter().map(|item| (None, item)).collect()),
+        _ => ("", "", Vec::new()),
+    };
+
+    output.push_str("<li>");
+    if children.is_empty() {
+        json_key(output, key);
+        json_scalar(output, value);
+        output.push_str(comma);
+    } else {
+        output.push_str("<details open><summary>");
+        json_key(output, key);
+        output.push_str(open);
+        output.push_str("</summary><ul>");
+        let count = children.len();
+        for (index, (key, child)) in children.into_iter().enumerate() {
+            json_value(output, key, child, index + 1 == count);
+        }
+        output.push_str("</ul>");
+        output.push_str(close);
+        output.push_str(comma);
+        output.push_str("</details>");
+    }
+    output.push_str("</li>");
+}
+
+fn json_key(output: &mut String, key: Option<&str>) {
+    if let Some(key) = key {
+        output.push_str("<span class=\"key\">");
+        json_string(output, key);
+        output.push_str("</span>: ");
+    }
+}
+
+/// Writes a value that has no children: a scalar, `{}` or `[]`.
+fn json_scalar(output: &mut String, value: &JsonValue) {
+    let class = match value {
+        JsonValue::Null => "null",
+        JsonValue::Bool(_) => "boolean",
+        JsonValue::Number(_) => "number",
+        JsonValue::String(_) => "string",
+        JsonValue::Object(_) => "object",
+        JsonValue::Array(_) => "array",
+    };
+    output.push_str("<span class=\"");
+    output.push_str(class);
+    output.push_str("\">");
+    match value {
+        JsonValue::Null => output.push_str("null"),
+        JsonValue::Bool(value) => output.push_str(if *value { "true" } else { "false" }),
+        JsonValue::Number(number) => output.push_str(number),
+        JsonValue::String(string) => json_string(output, string),
+        JsonValue::Object(_) => output.push_str("{}"),
+        JsonValue::Array(_) => output.push_str("[]"),
+    }
+    output.push_str("</span>");
+}
+
+/// Writes a string the way it would be written in JSON, escaped for HTML.
+fn json_string(output: &mut String, string: &str) {
+    let mut json = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            '\0'..='\u{1F}' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => json.push(c),
+        }
+    }
+    json.push('"');
+    escape(output, &json, false);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::serializer::SerializeOptions;
+
+    fn body_html(document: &HtmlDocument) -> String {
+        document.body().unwrap().inner_html()
+    }
+
+    #[test]
+    fn test_text_document() {
+        let document = text_document("\nfn main() {\n    a < b && c;\n}");
+        assert_eq!(body_html(&document), "<pre>\nfn main() {\n    a &lt; b &amp;&amp; c;\n}</pre>");
+        let pre = document.body().unwrap().child_element("pre").unwrap();
+        assert_eq!(pre.text_content(), "\nfn main() {\n    a < b && c;\n}");
+    }
+
+    #[test]
+    fn test_json_document() {
+        let document = json_document("{\"a\": [1, \"<x>\"], \"b\": {}, \"c\": null}");
+        assert_eq!(
+            body_html(&document),
+            "<ul class=\"json\"><li><details open=\"\"><summary>{</summary><ul>\
+             <li><details open=\"\"><summary><span class=\"key\">\"a\"</span>: [</summary><ul>\
+             <li><span class=\"number\">1</span>,</li>\
+             <li><span class=\"string\">\"&lt;x&gt;\"</span></li></ul>],</details></li>\
+             <li><span class=\"key\">\"b\"</span>: <span class=\"object\">{}</span>,</li>\
+             <li><span class=\"key\">\"c\"</span>: <span class=\"null\">null</span></li>\
+             </ul>}</details></li></ul>"
+        );
+    }
+
+    #[test]
+    fn test_invalid_json_is_shown_as_text() {
+        let document = json_document("{oops");
+        assert_eq!(body_html(&document), "<pre>{oops</pre>");
+    }
+
+    #[test]
+    fn test_image_document() {
+        let document = image_document("http://example.com/images/cat.png?size=2");
+        let html = document.serialize(SerializeOptions::default());
+        assert!(html.contains("<title>cat.png</title>"));
+        assert!(html.contains("<img src=\"http://example.com/images/cat.png?size=2\" alt=\"cat.png\">"));
+    }
+}
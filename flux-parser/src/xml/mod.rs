@@ -0,0 +1,14 @@
+use crate::error::ParserError;
+use crate::xml::parser::XmlParser;
+pub use crate::xml::parser::{XmlAttribute, XmlDeclaration, XmlDocument, XmlElement, XmlNode};
+
+pub mod parser;
+
+/// Parses a whole XML document, such as an XHTML page, an SVG image or an RSS
+/// feed.
+///
+/// Parsing stops at the first well-formedness error, returned as
+/// `ParserError::Syntax`, with its position.
+pub fn parse_xml(input: &str) -> Result<XmlDocument, ParserError> {
+    XmlParser::new(input).parse()
+}
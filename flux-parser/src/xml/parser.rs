@@ -0,0 +1,1145 @@
+//! xml_parser.rs
+//!
+//! A non-validating XML 1.0 parser with namespaces (Namespaces in XML 1.0).
+//! Unlike the HTML parser it does not recover from errors: the first
+//! well-formedness error stops parsing and is returned with its position.
+//!
+//! The internal subset of the DOCTYPE is read over without being interpreted,
+//! except for the declarations of internal general entities, whose
+//! replacement text is expanded as text. The external subset is never
+//! fetched; references to entities it may declare are looked up in the HTML
+//! table, which covers the XHTML DTDs, and skipped otherwise.
+
+use crate::error::{ParseError, ParserError, Severity};
+use crate::html::entities;
+use crate::html::token::SourceSpan;
+use crate::html::Namespace;
+
+#[derive(Debug)]
+pub struct XmlDocument {
+    /// The `<?xml ...?>` declaration, if the document starts with one.
+    pub declaration: Option<XmlDeclaration>,
+    /// The document element, with the comments, processing instructions and
+    /// DOCTYPE around it.
+    pub root_nodes: Vec<XmlNode>,
+}
+
+impl XmlDocument {
+    /// Returns the root element.
+    pub fn document_element(&self) -> Option<&XmlElement> {
+        self.root_nodes.iter().find_map(|node| match node {
+            XmlNode::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+}
+
+/// The content of the XML declaration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlDeclaration {
+    pub version: String,
+    pub encoding: Option<String>,
+    pub standalone: Option<bool>,
+}
+
+#[derive(Debug)]
+pub enum XmlNode {
+    Element(XmlElement),
+    /// Character data. CDATA sections are merged with the text around them.
+    Text { text: String, span: SourceSpan },
+    Comment { text: String, span: SourceSpan },
+    ProcessingInstruction {
+        target: String,
+        data: String,
+        span: SourceSpan,
+    },
+    Doctype {
+        name: String,
+        public_id: Option<String>,
+        system_id: Option<String>,
+        span: SourceSpan,
+    },
+}
+
+impl XmlNode {
+    /// Returns where the node comes from in the source.
+    pub fn span(&self) -> SourceSpan {
+        match self {
+            XmlNode::Element(element) => element.span,
+            XmlNode::Text { span, .. }
+            | XmlNode::Comment { span, .. }
+            | XmlNode::ProcessingInstruction { span, .. }
+            | XmlNode::Doctype { span, .. } => *span,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct XmlElement {
+    /// The qualified name, prefix included (`svg:rect`).
+    pub name: String,
+    /// The namespace URL, resolved from the `xmlns` declarations in scope.
+    pub namespace: Option<String>,
+    pub attributes: Vec<XmlAttribute>,
+    pub children: Vec<XmlNode>,
+    /// The span of the start tag.
+    pub span: SourceSpan,
+}
+
+impl XmlElement {
+    /// Returns the name without its prefix.
+    pub fn local_name(&self) -> &str {
+        local_name(&self.name)
+    }
+
+    /// Returns the prefix of the name, if it has one.
+    pub fn prefix(&self) -> Option<&str> {
+        self.name.split_once(':').map(|(prefix, _)| prefix)
+    }
+
+    /// Whether the element is in the namespace of `namespace`.
+    pub fn is_in(&self, namespace: Namespace) -> bool {
+        self.namespace.as_deref() == Some(namespace.url())
+    }
+
+    /// Returns an attribute value by qualified name.
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|attribute| attribute.name == name).map(|attribute| attribute.value.as_str())
+    }
+
+    /// Returns an attribute value by namespace URL and local name.
+    pub fn get_attribute_ns(&self, namespace: Option<&str>, local_name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.namespace.as_deref() == namespace && attribute.local_name() == local_name)
+            .map(|attribute| attribute.value.as_str())
+    }
+
+    /// Returns the child elements.
+    pub fn child_elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(|node| match node {
+            XmlNode::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    /// Returns the concatenated text of all descendant text nodes.
+    pub fn text_content(&self) -> String {
+        let mut text = String::new();
+        fn collect(nodes: &[XmlNode], text: &mut String) {
+            for node in nodes {
+                match node {
+                    XmlNode::Text { text: data, .. } => text.push_str(data),
+                    XmlNode::Element(element) => collect(&element.children, text),
+                    _ => {}
+                }
+            }
+        }
+        collect(&self.children, &mut text);
+        text
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlAttribute {
+    /// The qualified name, prefix included (`xlink:href`).
+    pub name: String,
+    /// The namespace URL. Attributes without a prefix are in no namespace.
+    pub namespace: Option<String>,
+    pub value: String,
+}
+
+impl XmlAttribute {
+    /// Returns the name without its prefix.
+    pub fn local_name(&self) -> &str {
+        local_name(&self.name)
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.split_once(':').map_or(name, |(_, local_name)| local_name)
+}
+
+/// The pseudo-attributes of the XML declaration, in their required order.
+const PSEUDO_ATTRIBUTES: &[&str] = &["version", "encoding", "standalone"];
+
+/// The entities every document knows without declaring them.
+const PREDEFINED_ENTITIES: &[(&str, &str)] =
+    &[("lt", "<"), ("gt", ">"), ("amp", "&"), ("apos", "'"), ("quot", "\"")];
+
+pub struct XmlParser<'a> {
+    input: &'a str,
+    /// Byte offset of the next character to read.
+    pos: usize,
+    /// Namespace declarations in scope, innermost last: the prefix (empty
+    /// for the default namespace) and the URL (empty when undeclared).
+    bindings: Vec<(String, String)>,
+    /// Internal general entities declared in the DOCTYPE, with their
+    /// replacement text. External ones have none.
+    entities: Vec<(String, Option<String>)>,
+    /// Whether some declarations could not be read, because they are in the
+    /// external subset or behind a parameter entity reference. References to
+    /// undeclared entities are then not an error.
+    unread_declarations: bool,
+    /// Whether the XML declaration says `standalone="yes"`.
+    standalone: bool,
+    /// The last position computed: byte offset, line and column. Positions
+    /// are mostly asked for in increasing order, so counting resumes from it.
+    cursor: (usize, usize, usize),
+}
+
+impl<'a> XmlParser<'a> {
+    pub fn new(input: &'a str) -> Self {
+        XmlParser {
+            input,
+            pos: 0,
+            bindings: Vec::new(),
+            entities: Vec::new(),
+            unread_declarations: false,
+            standalone: false,
+            cursor: (0, 1, 1),
+        }
+    }
+
+    pub fn parse(mut self) -> Result<XmlDocument, ParserError> {
+        if self.input.starts_with('\u{FEFF}') {
+            self.pos = '\u{FEFF}'.len_utf8();
+        }
+        let declaration = if self.starts_with("<?xml")
+            && self.input[self.pos + 5..].starts_with(|c: char| is_whitespace(c) || c == '?')
+        {
+            Some(self.xml_declaration()?)
+        } else {
+            None
+        };
+
+        let mut root_nodes = Vec::new();
+        let mut seen_doctype = false;
+        let mut seen_root = false;
+        loop {
+            self.skip_whitespace();
+            if self.pos == self.input.len() {
+                break;
+            }
+            if self.starts_with("<!--") {
+                root_nodes.push(self.comment()?);
+            } else if self.starts_with("<?") {
+                root_nodes.push(self.processing_instruction()?);
+            } else if self.starts_with("<!DOCTYPE") {
+                if seen_doctype || seen_root {
+                    return Err(self.error_at(self.pos, "misplaced-doctype", "The DOCTYPE must come before the root element"));
+                }
+                seen_doctype = true;
+                root_nodes.push(self.doctype()?);
+            } else if self.starts_with("<") && !seen_root && !self.starts_with("<!") {
+                seen_root = true;
+                root_nodes.push(XmlNode::Element(self.element()?));
+            } else if seen_root {
+                return Err(self.error_at(self.pos, "content-after-root-element", "Content after the root element"));
+            } else {
+                return Err(self.error_at(self.pos, "content-before-root-element", "Content before the root element"));
+            }
+        }
+        if !seen_root {
+            return Err(self.error_at(self.pos, "missing-root-element", "The document has no root element"));
+        }
+
+        Ok(XmlDocument { declaration, root_nodes })
+    }
+
+    /// Parses `<?xml version="1.0" encoding="..." standalone="..."?>`. The
+    /// pseudo-attributes must come in this order.
+    fn xml_declaration(&mut self) -> Result<XmlDeclaration, ParserError> {
+        self.pos += "<?xml".len();
+        let mut declaration = XmlDeclaration {
+            version: String::new(),
+            encoding: None,
+            standalone: None,
+        };
+        // Index in PSEUDO_ATTRIBUTES of the next allowed pseudo-attribute
+        let mut next = 0;
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            if self.eat("?>") {
+                break;
+            }
+            let start = self.pos;
+            if !had_whitespace {
+                return Err(self.error_at(start, "missing-whitespace-in-xml-declaration", "Expected whitespace in the XML declaration"));
+            }
+            let name = self.name()?;
+            self.equals()?;
+            let value = self.literal()?;
+            let index = PSEUDO_ATTRIBUTES
+                .iter()
+                .position(|pseudo_attribute| *pseudo_attribute == name)
+                .filter(|&index| index >= next && (next > 0 || index == 0));
+            match index {
+                Some(0) => {
+                    let valid = value
+                        .strip_prefix("1.")
+                        .is_some_and(|minor| !minor.is_empty() && minor.bytes().all(|b| b.is_ascii_digit()));
+                    if !valid {
+                        return Err(self.error_at(start, "unsupported-xml-version", &format!("Unsupported XML version '{}'", value)));
+                    }
+                    declaration.version = value;
+                }
+                Some(1) => declaration.encoding = Some(value),
+                Some(_) => {
+                    let standalone = match value.as_str() {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(self.error_at(start, "invalid-standalone-value", "standalone must be 'yes' or 'no'")),
+                    };
+                    declaration.standalone = Some(standalone);
+                    self.standalone = standalone;
+                }
+                None => return Err(self.error_at(start, "unexpected-pseudo-attribute", &format!("Unexpected '{}' in the XML declaration", name))),
+            }
+            next = index.unwrap() + 1;
+        }
+        if declaration.version.is_empty() {
+            return Err(self.error_at(self.pos, "missing-xml-version", "The XML declaration has no version"));
+        }
+        Ok(declaration)
+    }
+
+    /// Parses the DOCTYPE, skipping over the internal subset.
+    fn doctype(&mut self) -> Result<XmlNode, ParserError> {
+        let start = self.pos;
+        self.pos += "<!DOCTYPE".len();
+        self.require_whitespace()?;
+        let name = self.name()?;
+        let had_whitespace = self.skip_whitespace();
+        let (mut public_id, mut system_id) = (None, None);
+        if had_whitespace && self.eat("PUBLIC") {
+            self.require_whitespace()?;
+            let literal_start = self.pos;
+            let id = self.literal()?;
+            if !id.chars().all(is_pubid_char) {
+                return Err(self.error_at(literal_start, "invalid-public-identifier", "Invalid character in the public identifier"));
+            }
+            public_id = Some(id);
+            self.require_whitespace()?;
+            system_id = Some(self.literal()?);
+            self.skip_whitespace();
+        } else if had_whitespace && self.eat("SYSTEM") {
+            self.require_whitespace()?;
+            system_id = Some(self.literal()?);
+            self.skip_whitespace();
+        }
+        if system_id.is_some() {
+            self.unread_declarations = true;
+        }
+
+        if self.eat("[") {
+            self.internal_subset()?;
+            self.skip_whitespace();
+        }
+        if !self.eat(">") {
+            return Err(self.error_at(self.pos, "unterminated-doctype", "Expected '>' at the end of the DOCTYPE"));
+        }
+        Ok(XmlNode::Doctype {
+            name,
+            public_id,
+            system_id,
+            span: self.span(start),
+        })
+    }
+
+    /// Reads the internal subset up to its closing `]`, keeping only the
+    /// internal general entity declarations.
+    fn internal_subset(&mut self) -> Result<(), ParserError> {
+        loop {
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(());
+            }
+            if self.starts_with("<!--") {
+                self.comment()?;
+            } else if self.starts_with("<?") {
+                self.processing_instruction()?;
+            } else if self.starts_with("<!ENTITY") {
+                self.entity_declaration()?;
+            } else if self.starts_with("<!") {
+                self.skip_markup_declaration()?;
+            } else if self.eat("%") {
+                self.name()?;
+                self.expect(";")?;
+                self.unread_declarations = true;
+            } else if self.pos == self.input.len() {
+                return Err(self.error_at(self.pos, "eof-in-doctype", "Unexpected end of input in the DOCTYPE"));
+            } else {
+                return Err(self.error_at(self.pos, "unexpected-content-in-doctype", "Unexpected content in the DOCTYPE"));
+            }
+        }
+    }
+
+    /// Parses `<!ENTITY name "value">`. Parameter entities and external
+    /// entities are read over.
+    fn entity_declaration(&mut self) -> Result<(), ParserError> {
+        let start = self.pos;
+        self.pos += "<!ENTITY".len();
+        self.require_whitespace()?;
+        if self.starts_with("%") {
+            self.pos = start;
+            return self.skip_markup_declaration();
+        }
+        let name = self.name()?;
+        self.require_whitespace()?;
+        let value = if self.starts_with("\"") || self.starts_with("'") {
+            let literal_start = self.pos;
+            let literal = self.literal()?;
+            Some(self.expand_character_references(&literal, literal_start)?)
+        } else {
+            None
+        };
+        self.pos = start;
+        self.skip_markup_declaration()?;
+        // The first declaration of an entity is the one that counts
+        if !self.entities.iter().any(|(declared, _)| *declared == name) {
+            self.entities.push((name, value));
+        }
+        Ok(())
+    }
+
+    /// Expands the character references of an entity value. Other
+    /// references are kept as they are.
+    fn expand_character_references(&mut self, literal: &str, offset: usize) -> Result<String, ParserError> {
+        let mut value = String::new();
+        let mut rest = literal;
+        while let Some(index) = rest.find("&#") {
+            value.push_str(&rest[..index]);
+            let Some(end) = rest[index..].find(';') else {
+                return Err(self.error_at(offset, "unterminated-character-reference", "Unterminated character reference"));
+            };
+            let reference = &rest[index + 2..index + end];
+            let c = match reference.strip_prefix('x') {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => reference.parse(),
+            }
+            .ok()
+            .and_then(char::from_u32)
+            .filter(|&c| is_xml_char(c));
+            match c {
+                Some(c) => value.push(c),
+                None => return Err(self.error_at(offset, "invalid-character-reference", "Invalid character reference")),
+            }
+            rest = &rest[index + end + 1..];
+        }
+        value.push_str(rest);
+        Ok(value)
+    }
+
+    /// Skips a markup declaration (`<!ELEMENT ...>`, `<!ATTLIST ...>`) up to
+    /// its closing `>`, minding the quoted strings that may contain one.
+    fn skip_markup_declaration(&mut self) -> Result<(), ParserError> {
+        let start = self.pos;
+        let mut quote = None;
+        while let Some(c) = self.peek() {
+            self.pos += c.len_utf8();
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), _) if q == c => quote = None,
+                (None, '>') => return Ok(()),
+                _ => {}
+            }
+        }
+        Err(self.error_at(start, "unterminated-markup-declaration", "Unterminated markup declaration"))
+    }
+
+    /// Parses the root element and its content. Open elements are kept on a
+    /// stack, like in the HTML parser, rather than in recursive calls.
+    fn element(&mut self) -> Result<XmlElement, ParserError> {
+        let (element, empty, bindings) = self.start_tag()?;
+        if empty {
+            self.bindings.truncate(bindings);
+            return Ok(element);
+        }
+        let mut open = vec![(element, bindings)];
+        let mut text = String::new();
+        let mut text_start = self.pos;
+
+        loop {
+            if self.pos == self.input.len() {
+                let name = &open.last().unwrap().0.name;
+                return Err(self.error_at(self.pos, "eof-in-element", &format!("Unexpected end of input: <{}> is not closed", name)));
+            }
+            if !self.starts_with("<") && !self.starts_with("&") {
+                if text.is_empty() {
+                    text_start = self.pos;
+                }
+                self.character_data(&mut text)?;
+                continue;
+            }
+            if self.starts_with("<![CDATA[") {
+                if text.is_empty() {
+                    text_start = self.pos;
+                }
+                self.cdata_section(&mut text)?;
+                continue;
+            }
+            if self.starts_with("&") {
+                if text.is_empty() {
+                    text_start = self.pos;
+                }
+                let reference = self.reference()?;
+                text.push_str(&reference);
+                continue;
+            }
+
+            // Markup: the pending text becomes a node of its own
+            if !text.is_empty() {
+                let span = self.span(text_start);
+                open.last_mut().unwrap().0.children.push(XmlNode::Text {
+                    text: std::mem::take(&mut text),
+                    span,
+                });
+            }
+            if self.starts_with("</") {
+                let start = self.pos;
+                self.pos += 2;
+                let name = self.name()?;
+                self.skip_whitespace();
+                self.expect(">")?;
+                let (element, bindings) = open.pop().unwrap();
+                if name != element.name {
+                    return Err(self.error_at(start, "mismatched-end-tag",
+                        &format!("Expected </{}> but found </{}>", element.name, name),
+                    ));
+                }
+                self.bindings.truncate(bindings);
+                match open.last_mut() {
+                    Some((parent, _)) => parent.children.push(XmlNode::Element(element)),
+                    None => return Ok(element),
+                }
+            } else if self.starts_with("<!--") {
+                let comment = self.comment()?;
+                open.last_mut().unwrap().0.children.push(comment);
+            } else if self.starts_with("<?") {
+                let instruction = self.processing_instruction()?;
+                open.last_mut().unwrap().0.children.push(instruction);
+            } else if self.starts_with("<!") {
+                return Err(self.error_at(self.pos, "misplaced-markup-declaration", "Markup declarations are only allowed in the DOCTYPE"));
+            } else {
+                let (element, empty, bindings) = self.start_tag()?;
+                if empty {
+                    self.bindings.truncate(bindings);
+                    open.last_mut().unwrap().0.children.push(XmlNode::Element(element));
+                } else {
+                    open.push((element, bindings));
+                }
+            }
+        }
+    }
+
+    /// Parses a start tag or an empty-element tag, and brings its namespace
+    /// declarations in scope. Returns the element, whether the tag was empty
+    /// and the number of bindings in scope before the tag.
+    fn start_tag(&mut self) -> Result<(XmlElement, bool, usize), ParserError> {
+        let start = self.pos;
+        self.pos += 1;
+        let name = self.qualified_name()?;
+        let mut attributes: Vec<(String, String, usize)> = Vec::new();
+        let empty = loop {
+            let had_whitespace = self.skip_whitespace();
+            if self.eat("/>") {
+                break true;
+            }
+            if self.eat(">") {
+                break false;
+            }
+            if self.pos == self.input.len() {
+                return Err(self.error_at(self.pos, "eof-in-tag", "Unexpected end of input in a start tag"));
+            }
+            if !had_whitespace {
+                return Err(self.error_at(self.pos, "missing-whitespace-between-attributes", "Expected whitespace between attributes"));
+            }
+            let attribute_start = self.pos;
+            let attribute_name = self.qualified_name()?;
+            self.equals()?;
+            let value = self.attribute_value()?;
+            if attributes.iter().any(|(other, ..)| *other == attribute_name) {
+                return Err(self.error_at(attribute_start, "duplicate-attribute", &format!("Duplicate attribute '{}'", attribute_name)));
+            }
+            attributes.push((attribute_name, value, attribute_start));
+        };
+
+        // The declarations apply to the element that holds them
+        let bindings = self.bindings.len();
+        for (attribute_name, value, offset) in &attributes {
+            let prefix = match attribute_name.split_once(':') {
+                None if attribute_name == "xmlns" => "",
+                Some(("xmlns", prefix)) => prefix,
+                _ => continue,
+            };
+            let xml = Namespace::Xml.url();
+            let error = if prefix == "xmlns" || value == Namespace::Xmlns.url() {
+                Some(("reserved-namespace-declaration", "The xmlns prefix and namespace cannot be declared"))
+            } else if (prefix == "xml") != (value == xml) {
+                Some(("reserved-namespace-declaration", "The xml prefix is bound to its own namespace only"))
+            } else if !prefix.is_empty() && value.is_empty() {
+                Some(("empty-namespace-prefix-declaration", "A namespace prefix cannot be undeclared"))
+            } else {
+                None
+            };
+            if let Some((code, message)) = error {
+                return Err(self.error_at(*offset, code, message));
+            }
+            self.bindings.push((prefix.to_string(), value.clone()));
+        }
+
+        let namespace = self.resolve(&name, true, start)?;
+        let mut resolved: Vec<XmlAttribute> = Vec::with_capacity(attributes.len());
+        for (attribute_name, value, offset) in attributes {
+            let namespace = if attribute_name == "xmlns" || attribute_name.starts_with("xmlns:") {
+                Some(Namespace::Xmlns.url().to_string())
+            } else {
+                self.resolve(&attribute_name, false, offset)?
+            };
+            let duplicate = namespace.is_some()
+                && resolved
+                    .iter()
+                    .any(|other| other.namespace == namespace && other.local_name() == local_name(&attribute_name));
+            if duplicate {
+                return Err(self.error_at(offset, "duplicate-attribute", &format!("Duplicate attribute '{}'", attribute_name)));
+            }
+            resolved.push(XmlAttribute {
+                name: attribute_name,
+                namespace,
+                value,
+            });
+        }
+
+        let element = XmlElement {
+            name,
+            namespace,
+            attributes: resolved,
+            children: Vec::new(),
+            span: self.span(start),
+        };
+        Ok((element, empty, bindings))
+    }
+
+    /// Returns the namespace URL of a qualified name. Unprefixed names are in
+    /// the default namespace for elements, and in no namespace for
+    /// attributes.
+    fn resolve(&mut self, name: &str, element: bool, offset: usize) -> Result<Option<String>, ParserError> {
+        let prefix = match name.split_once(':') {
+            Some(("xml", _)) => return Ok(Some(Namespace::Xml.url().to_string())),
+            Some((prefix, _)) => prefix,
+            None if element => "",
+            None => return Ok(None),
+        };
+        match self.bindings.iter().rev().find(|(bound, _)| bound == prefix) {
+            Some((_, url)) if url.is_empty() => Ok(None),
+            Some((_, url)) => Ok(Some(url.clone())),
+            None if prefix.is_empty() => Ok(None),
+            None => Err(self.error_at(offset, "unbound-namespace-prefix", &format!("Unbound namespace prefix '{}'", prefix))),
+        }
+    }
+
+    /// Reads text up to the next markup or reference.
+    fn character_data(&mut self, text: &mut String) -> Result<(), ParserError> {
+        while let Some(c) = self.peek() {
+            match c {
+                '<' | '&' => break,
+                ']' if self.starts_with("]]>") => {
+                    return Err(self.error_at(self.pos, "cdata-end-in-text", "']]>' is not allowed in text"));
+                }
+                '\r' => {
+                    self.pos += 1;
+                    self.eat("\n");
+                    text.push('\n');
+                }
+                _ => {
+                    self.check_char(c)?;
+                    self.pos += c.len_utf8();
+                    text.push(c);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn cdata_section(&mut self, text: &mut String) -> Result<(), ParserError> {
+        let start = self.pos;
+        self.pos += "<![CDATA[".len();
+        let Some(length) = self.input[self.pos..].find("]]>") else {
+            return Err(self.error_at(start, "eof-in-cdata", "Unterminated CDATA section"));
+        };
+        let end = self.pos + length;
+        self.checked_text(end, text)?;
+        self.pos = end + "]]>".len();
+        Ok(())
+    }
+
+    fn comment(&mut self) -> Result<XmlNode, ParserError> {
+        let start = self.pos;
+        self.pos += "<!--".len();
+        let Some(length) = self.input[self.pos..].find("--") else {
+            return Err(self.error_at(start, "eof-in-comment", "Unterminated comment"));
+        };
+        let end = self.pos + length;
+        if !self.input[end..].starts_with("-->") {
+            return Err(self.error_at(end, "double-hyphen-in-comment", "'--' is not allowed in comments"));
+        }
+        let mut text = String::new();
+        self.checked_text(end, &mut text)?;
+        self.pos = end + "-->".len();
+        Ok(XmlNode::Comment {
+            text,
+            span: self.span(start),
+        })
+    }
+
+    fn processing_instruction(&mut self) -> Result<XmlNode, ParserError> {
+        let start = self.pos;
+        self.pos += "<?".len();
+        let target = self.name()?;
+        if target.eq_ignore_ascii_case("xml") {
+            return Err(self.error_at(start, "misplaced-xml-declaration", "The XML declaration must be at the very start of the document"));
+        }
+        if target.contains(':') {
+            return Err(self.error_at(start, "colon-in-processing-instruction-target", "Processing instruction targets cannot contain ':'"));
+        }
+        let mut data = String::new();
+        if !self.eat("?>") {
+            self.require_whitespace()?;
+            let Some(length) = self.input[self.pos..].find("?>") else {
+                return Err(self.error_at(start, "eof-in-processing-instruction", "Unterminated processing instruction"));
+            };
+            let end = self.pos + length;
+            self.checked_text(end, &mut data)?;
+            self.pos = end + "?>".len();
+        }
+        Ok(XmlNode::ProcessingInstruction {
+            target,
+            data,
+            span: self.span(start),
+        })
+    }
+
+    /// Appends the text up to `end` with its line breaks normalized, checking
+    /// that every character is allowed.
+    fn checked_text(&mut self, end: usize, text: &mut String) -> Result<(), ParserError> {
+        while self.pos < end {
+            let c = self.peek().unwrap();
+            self.check_char(c)?;
+            self.pos += c.len_utf8();
+            if c == '\r' {
+                if self.pos < end {
+                    self.eat("\n");
+                }
+                text.push('\n');
+            } else {
+                text.push(c);
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a quoted attribute value, expanding references and turning
+    /// whitespace characters into spaces (§3.3.3).
+    fn attribute_value(&mut self) -> Result<String, ParserError> {
+        let quote = match self.peek() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.error_at(self.pos, "unquoted-attribute-value", "Attribute values must be quoted")),
+        };
+        self.pos += 1;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error_at(self.pos, "eof-in-attribute-value", "Unexpected end of input in an attribute value")),
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(value);
+                }
+                Some('<') => return Err(self.error_at(self.pos, "less-than-sign-in-attribute-value", "'<' is not allowed in attribute values")),
+                Some('&') => {
+                    let reference = self.reference()?;
+                    value.push_str(&reference);
+                }
+                Some('\r') => {
+                    self.pos += 1;
+                    self.eat("\n");
+                    value.push(' ');
+                }
+                Some('\n' | '\t') => {
+                    self.pos += 1;
+                    value.push(' ');
+                }
+                Some(c) => {
+                    self.check_char(c)?;
+                    self.pos += c.len_utf8();
+                    value.push(c);
+                }
+            }
+        }
+    }
+
+    /// Parses a character or entity reference and returns its replacement
+    /// text.
+    fn reference(&mut self) -> Result<String, ParserError> {
+        let start = self.pos;
+        self.pos += 1;
+        if self.eat("#") {
+            let hex = self.eat("x");
+            let digits_start = self.pos;
+            while self.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+                self.pos += 1;
+            }
+            let digits = &self.input[digits_start..self.pos];
+            let c = if hex { u32::from_str_radix(digits, 16) } else { digits.parse() }
+                .ok()
+                .and_then(char::from_u32)
+                .filter(|&c| is_xml_char(c));
+            let Some(c) = c else {
+                return Err(self.error_at(start, "invalid-character-reference", "Invalid character reference"));
+            };
+            self.expect(";")?;
+            return Ok(c.to_string());
+        }
+
+        let name = self.name()?;
+        if !self.eat(";") {
+            return Err(self.error_at(self.pos, "missing-semicolon-after-entity-reference", "Expected ';' after the entity name"));
+        }
+        if let Some((_, text)) = PREDEFINED_ENTITIES.iter().find(|(predefined, _)| *predefined == name) {
+            return Ok(text.to_string());
+        }
+        match self.entities.iter().find(|(declared, _)| *declared == name) {
+            Some((_, Some(text))) => Ok(text.clone()),
+            // External entities are not fetched
+            Some((_, None)) => Ok(String::new()),
+            None if self.unread_declarations && !self.standalone => {
+                Ok(entities::lookup(&format!("{};", name)).unwrap_or_default().to_string())
+            }
+            None => Err(self.error_at(start, "undefined-entity", &format!("Undefined entity '&{};'", name))),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.input[self.pos..].starts_with(s)
+    }
+
+    /// Consumes `s` if the input continues with it.
+    fn eat(&mut self, s: &str) -> bool {
+        let matches = self.starts_with(s);
+        if matches {
+            self.pos += s.len();
+        }
+        matches
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), ParserError> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error_at(self.pos, "unexpected-character", &format!("Expected '{}'", s)))
+        }
+    }
+
+    /// Skips whitespace and returns whether there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while self.peek().is_some_and(is_whitespace) {
+            self.pos += 1;
+        }
+        self.pos > start
+    }
+
+    fn require_whitespace(&mut self) -> Result<(), ParserError> {
+        if self.skip_whitespace() {
+            Ok(())
+        } else {
+            Err(self.error_at(self.pos, "missing-whitespace", "Expected whitespace"))
+        }
+    }
+
+    /// Parses `=` with optional whitespace around it.
+    fn equals(&mut self) -> Result<(), ParserError> {
+        self.skip_whitespace();
+        self.expect("=")?;
+        self.skip_whitespace();
+        Ok(())
+    }
+
+    /// Parses a quoted string, without interpreting its content.
+    fn literal(&mut self) -> Result<String, ParserError> {
+        let start = self.pos;
+        let quote = match self.peek() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.error_at(start, "missing-quoted-string", "Expected a quoted string")),
+        };
+        self.pos += 1;
+        let Some(length) = self.input[self.pos..].find(quote) else {
+            return Err(self.error_at(start, "unterminated-quoted-string", "Unterminated quoted string"));
+        };
+        let literal = self.input[self.pos..self.pos + length].to_string();
+        self.pos += length + 1;
+        Ok(literal)
+    }
+
+    fn name(&mut self) -> Result<String, ParserError> {
+        let start = self.pos;
+        match self.peek() {
+            Some(c) if is_name_start_char(c) => self.pos += c.len_utf8(),
+            _ => return Err(self.error_at(start, "missing-name", "Expected a name")),
+        }
+        while let Some(c) = self.peek().filter(|&c| is_name_char(c)) {
+            self.pos += c.len_utf8();
+        }
+        Ok(self.input[start..self.pos].to_string())
+    }
+
+    /// Parses a name with at most one colon, between a prefix and a local
+    /// name.
+    fn qualified_name(&mut self) -> Result<String, ParserError> {
+        let start = self.pos;
+        let name = self.name()?;
+        let valid = match name.split_once(':') {
+            Some((prefix, local_name)) => !prefix.is_empty() && !local_name.is_empty() && !local_name.contains(':'),
+            None => true,
+        };
+        if !valid {
+            return Err(self.error_at(start, "invalid-qualified-name", &format!("Invalid qualified name '{}'", name)));
+        }
+        Ok(name)
+    }
+
+    fn check_char(&mut self, c: char) -> Result<(), ParserError> {
+        if is_xml_char(c) {
+            Ok(())
+        } else {
+            Err(self.error_at(self.pos, "invalid-character", &format!("Character U+{:04X} is not allowed", c as u32)))
+        }
+    }
+
+    /// Returns the line and column of a byte offset. A CR LF pair counts as
+    /// a single line break.
+    fn location(&mut self, offset: usize) -> (usize, usize) {
+        if offset < self.cursor.0 {
+            self.cursor = (0, 1, 1);
+        }
+        let (from, mut line, mut column) = self.cursor;
+        let mut chars = self.input[from..offset].chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\n' || (c == '\r' && chars.peek() != Some(&'\n')) {
+                line += 1;
+                column = 1;
+            } else if c != '\r' {
+                column += 1;
+            }
+        }
+        self.cursor = (offset, line, column);
+        (line, column)
+    }
+
+    /// Returns the span from `start` to the current position.
+    fn span(&mut self, start: usize) -> SourceSpan {
+        let (line, column) = self.location(start);
+        SourceSpan {
+            start,
+            end: self.pos,
+            line,
+            column,
+        }
+    }
+
+    fn error_at(&mut self, offset: usize, code: &'static str, message: &str) -> ParserError {
+        let (line, column) = self.location(offset);
+        let span = SourceSpan {
+            start: offset,
+            end: offset,
+            line,
+            column,
+        };
+        ParserError::Syntax(ParseError::new(code, Severity::Fatal, span).with_message(message))
+    }
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+/// The characters allowed in a document (§2.2). Surrogates cannot appear in a
+/// `char`.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r') || (c >= ' ' && c != '\u{FFFE}' && c != '\u{FFFF}')
+}
+
+/// The characters allowed in a public identifier (§2.3).
+fn is_pubid_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, ' ' | '\r' | '\n') || "-'()+,./:=?;!*#@$_%".contains(c)
+}
+
+fn is_name_start_char(c: char) -> bool {
+    matches!(c,
+        ':' | 'A'..='Z' | '_' | 'a'..='z'
+        | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}' | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}' | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}' | '\u{2C00}'..='\u{2FEF}' | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}' | '\u{10000}'..='\u{EFFFF}')
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c)
+        || matches!(c, '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xml::parse_xml;
+
+    /// Returns the message, line and column of a well-formedness error.
+    fn error_of(input: &str) -> (String, usize, usize) {
+        match parse_xml(input) {
+            Err(ParserError::Syntax(error)) => {
+                assert_eq!(error.severity, Severity::Fatal);
+                (error.message.unwrap(), error.span.line, error.span.column)
+            }
+            other => panic!("Expected a well-formedness error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_simple_document() {
+        let document = parse_xml(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!-- feed -->\n\
+             <rss version=\"2.0\"><channel><title>News &amp; more</title><item/></channel></rss>\n",
+        )
+        .unwrap();
+        assert_eq!(
+            document.declaration,
+            Some(XmlDeclaration {
+                version: "1.0".to_string(),
+                encoding: Some("UTF-8".to_string()),
+                standalone: None,
+            })
+        );
+        assert!(matches!(&document.root_nodes[0], XmlNode::Comment { text, .. } if text == " feed "));
+        let rss = document.document_element().unwrap();
+        assert_eq!(rss.name, "rss");
+        assert_eq!(rss.namespace, None);
+        assert_eq!(rss.get_attribute("version"), Some("2.0"));
+        let channel = rss.child_elements().next().unwrap();
+        let names: Vec<_> = channel.child_elements().map(|element| element.name.as_str()).collect();
+        assert_eq!(names, ["title", "item"]);
+        assert_eq!(channel.text_content(), "News & more");
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let document = parse_xml(
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:svg=\"http://www.w3.org/2000/svg\" \
+             xmlns:xlink=\"http://www.w3.org/1999/xlink\">\
+             <svg:svg><svg:use xlink:href=\"#a\" width=\"1\"/></svg:svg>\
+             <p xml:lang=\"fr\" xmlns=\"\">x</p></html>",
+        )
+        .unwrap();
+        let html = document.document_element().unwrap();
+        assert!(html.is_in(Namespace::Html));
+        let mut children = html.child_elements();
+        let svg = children.next().unwrap();
+        assert!(svg.is_in(Namespace::Svg));
+        assert_eq!((svg.prefix(), svg.local_name()), (Some("svg"), "svg"));
+        let use_element = svg.child_elements().next().unwrap();
+        assert_eq!(use_element.get_attribute_ns(Some(Namespace::XLink.url()), "href"), Some("#a"));
+        assert_eq!(use_element.get_attribute_ns(None, "width"), Some("1"));
+
+        let p = children.next().unwrap();
+        assert_eq!(p.namespace, None);
+        assert_eq!(p.get_attribute_ns(Some(Namespace::Xml.url()), "lang"), Some("fr"));
+        assert_eq!(p.attributes[1].namespace.as_deref(), Some(Namespace::Xmlns.url()));
+    }
+
+    #[test]
+    fn test_cdata_references_and_processing_instructions() {
+        let document = parse_xml(
+            "<?xml-stylesheet href=\"a.css\"?><a b=\"x&#x9;&lt;\ny\">1 <![CDATA[<2> & ]]>&#51;<?pi data?></a>",
+        )
+        .unwrap();
+        assert!(matches!(
+            &document.root_nodes[0],
+            XmlNode::ProcessingInstruction { target, data, .. } if target == "xml-stylesheet" && data == "href=\"a.css\""
+        ));
+        let a = document.document_element().unwrap();
+        assert_eq!(a.get_attribute("b"), Some("x\t< y"));
+        assert!(matches!(&a.children[0], XmlNode::Text { text, .. } if text == "1 <2> & 3"));
+        assert!(matches!(&a.children[1], XmlNode::ProcessingInstruction { target, .. } if target == "pi"));
+    }
+
+    #[test]
+    fn test_line_breaks_are_normalized() {
+        let document = parse_xml("<a>1\r\n2\r3</a>").unwrap();
+        assert_eq!(document.document_element().unwrap().text_content(), "1\n2\n3");
+    }
+
+    #[test]
+    fn test_doctype_internal_subset() {
+        let document = parse_xml(
+            "<!DOCTYPE note [\n  <!ELEMENT note (#PCDATA)>\n  <!ATTLIST note a CDATA \"]>\">\n\
+             <!-- ] -->\n  <!ENTITY who \"W&#246;rld\">\n]>\n<note>Hello &who;</note>",
+        )
+        .unwrap();
+        assert!(matches!(
+            &document.root_nodes[0],
+            XmlNode::Doctype { name, public_id: None, system_id: None, .. } if name == "note"
+        ));
+        assert_eq!(document.document_element().unwrap().text_content(), "Hello Wörld");
+    }
+
+    #[test]
+    fn test_xhtml_entities_with_external_subset() {
+        let document = parse_xml(
+            "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \
+             \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">\
+             <html xmlns=\"http://www.w3.org/1999/xhtml\"><p>a&nbsp;b&unknown;</p></html>",
+        )
+        .unwrap();
+        assert!(matches!(
+            &document.root_nodes[0],
+            XmlNode::Doctype { public_id: Some(id), .. } if id == "-//W3C//DTD XHTML 1.0 Strict//EN"
+        ));
+        assert_eq!(document.document_element().unwrap().text_content(), "a\u{A0}b");
+        let (message, ..) = error_of("<p>a&nbsp;b</p>");
+        assert_eq!(message, "Undefined entity '&nbsp;'");
+    }
+
+    #[test]
+    fn test_well_formedness_errors() {
+        assert_eq!(
+            error_of("<a>\n  <b></c>\n</a>"),
+            ("Expected </b> but found </c>".to_string(), 2, 6)
+        );
+        match parse_xml("<a>&nope;</a>") {
+            Err(ParserError::Syntax(error)) => assert_eq!(error.to_string(), "1:4: undefined-entity (Undefined entity '&nope;')"),
+            other => panic!("Expected a well-formedness error, got {:?}", other),
+        }
+        assert_eq!(error_of("<a><b></a>").0, "Expected </b> but found </a>");
+        assert_eq!(error_of("<a>").0, "Unexpected end of input: <a> is not closed");
+        assert_eq!(error_of("<a></a><b/>").0, "Content after the root element");
+        assert_eq!(error_of("text<a/>").0, "Content before the root element");
+        assert_eq!(error_of("").0, "The document has no root element");
+        assert_eq!(error_of("<a b=c/>").0, "Attribute values must be quoted");
+        assert_eq!(error_of("<a b='1' b='2'/>").0, "Duplicate attribute 'b'");
+        assert_eq!(error_of("<a b='<'/>").0, "'<' is not allowed in attribute values");
+        assert_eq!(error_of("<a>]]></a>").0, "']]>' is not allowed in text");
+        assert_eq!(error_of("<a><!-- a -- b --></a>").0, "'--' is not allowed in comments");
+        assert_eq!(error_of("<a>&#0;</a>").0, "Invalid character reference");
+        assert_eq!(error_of("<a>\u{1}</a>").0, "Character U+0001 is not allowed");
+        assert_eq!(error_of("<x:a/>").0, "Unbound namespace prefix 'x'");
+        assert_eq!(
+            error_of("<a xmlns:x='u' xmlns:y='u' x:b='1' y:b='2'/>").0,
+            "Duplicate attribute 'y:b'"
+        );
+        assert_eq!(error_of("<a/><?xml version='1.0'?>").0, "The XML declaration must be at the very start of the document");
+        assert_eq!(error_of("<?xml version='2.0'?><a/>").0, "Unsupported XML version '2.0'");
+    }
+}
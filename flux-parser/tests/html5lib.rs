@@ -0,0 +1,464 @@
+//! Conformance tests from the html5lib test suite
+//! (<https://github.com/html5lib/html5lib-tests>).
+//!
+//! The tokenizer tests (`tokenizer/*.test`, in JSON) are run against the
+//! [`Tokenizer`], the tree construction tests (`tree-construction/*.dat`)
+//! against [`parse_html`] and [`parse_fragment`]. They are read from two
+//! directories of `tests/html5lib`:
+//!
+//! - `upstream`, the files of the suite and its license, vendored by
+//!   `fetch-upstream.sh` at the revision recorded in `upstream/REVISION`;
+//! - `extra`, a few more cases written in the format of the suite.
+//!
+//! Both must be there: the tests fail if a directory is missing or empty.
+//!
+//! The parser does not pass the whole suite. The cases expected to fail are
+//! listed in `known-failures.txt`, as `<file>:<case>` with cases numbered
+//! from 1 in each file. Any other failure fails the test, and so does a
+//! listed case that now passes, so that the list is kept up to date. Pass
+//! and fail counts are printed for each file:
+//!
+//! ```text
+//! cargo test --test html5lib -- --nocapture
+//! ```
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use flux_parser::html::parser::HtmlNode;
+use flux_parser::html::token::{Doctype, State, Token, Tokenizer};
+use flux_parser::html::{parse_fragment, parse_html, Namespace};
+use flux_parser::json::{parse_json, JsonValue};
+
+/// The directories the test files are read from, in `tests/html5lib`.
+const SUITES: &[&str] = &["upstream", "extra"];
+
+/// The results of the cases of one test file.
+#[derive(Default)]
+struct Report {
+    passed: usize,
+    /// Cases the harness cannot run: scripting enabled, foreign fragment
+    /// contexts other than `<svg>` and `<math>`, lone surrogates...
+    skipped: usize,
+    /// The number of each failed case, with what went wrong.
+    failures: Vec<(usize, String)>,
+}
+
+impl Report {
+    /// The number of the next case, counting skipped ones.
+    fn next_case(&self) -> usize {
+        self.passed + self.skipped + self.failures.len() + 1
+    }
+
+    fn check(&mut self, description: &str, expected: &str, actual: &str) {
+        if expected == actual {
+            self.passed += 1;
+        } else {
+            self.failures.push((
+                self.next_case(),
+                format!("{}\n--- expected\n{}\n--- actual\n{}", description, expected, actual),
+            ));
+        }
+    }
+}
+
+fn html5lib_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/html5lib")
+}
+
+/// Reads `known-failures.txt`. Empty lines and lines starting with `#` are
+/// ignored.
+fn known_failures() -> BTreeSet<String> {
+    let path = html5lib_dir().join("known-failures.txt");
+    fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("Cannot read {}: {}", path.display(), err))
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Runs every file of `subdir` with the given extension, in each suite, and
+/// prints the counts. Fails on a failure that is not known, or on a known
+/// failure that no longer fails.
+fn run_suite(subdir: &str, extension: &str, run_file: fn(&str) -> Report) {
+    let known = known_failures();
+    let mut total = Report::default();
+    let mut unexpected = Vec::new();
+    let mut fixed = Vec::new();
+
+    for suite in SUITES {
+        let dir = html5lib_dir().join(suite).join(subdir);
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "Cannot read {}: {} (vendor the suite with tests/html5lib/fetch-upstream.sh)",
+                    dir.display(),
+                    err
+                )
+            })
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+            .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "No .{} file in {}", extension, dir.display());
+
+        for path in paths {
+            let name = format!("{}/{}/{}", suite, subdir, path.file_name().unwrap().to_string_lossy());
+            let contents = fs::read_to_string(&path).unwrap_or_else(|err| panic!("Cannot read {}: {}", name, err));
+            let report = run_file(&contents);
+            println!(
+                "{}: {} passed, {} failed, {} skipped",
+                name,
+                report.passed,
+                report.failures.len(),
+                report.skipped
+            );
+
+            let failed: BTreeSet<String> =
+                report.failures.iter().map(|(case, _)| format!("{}:{}", name, case)).collect();
+            for (case, failure) in &report.failures {
+                let id = format!("{}:{}", name, case);
+                if !known.contains(&id) {
+                    unexpected.push(format!("{}: {}", id, failure));
+                }
+            }
+            let prefix = format!("{}:", name);
+            fixed.extend(known.iter().filter(|id| id.starts_with(&prefix) && !failed.contains(*id)).cloned());
+
+            total.passed += report.passed;
+            total.skipped += report.skipped;
+            total.failures.extend(report.failures);
+        }
+    }
+    println!(
+        "{}: {} passed, {} failed ({} unexpected), {} skipped",
+        subdir,
+        total.passed,
+        total.failures.len(),
+        unexpected.len(),
+        total.skipped
+    );
+
+    if !unexpected.is_empty() {
+        panic!(
+            "{} unexpected failures, not in known-failures.txt:\n\n{}",
+            unexpected.len(),
+            unexpected.join("\n\n")
+        );
+    }
+    if !fixed.is_empty() {
+        panic!("Cases that now pass, to remove from known-failures.txt:\n{}", fixed.join("\n"));
+    }
+}
+
+#[test]
+fn tokenizer() {
+    run_suite("tokenizer", "test", run_tokenizer_file);
+}
+
+#[test]
+fn tree_construction() {
+    run_suite("tree-construction", "dat", run_tree_construction_file);
+}
+
+// Tokenizer tests
+
+fn run_tokenizer_file(contents: &str) -> Report {
+    let mut report = Report::default();
+    let json = parse_json(contents).unwrap_or_else(|err| panic!("Invalid test file: {}", err));
+    // `xmlViolationTests` are for XML-compatible tokenizers: not run
+    let Some(JsonValue::Array(tests)) = member(&json, "tests") else {
+        return report;
+    };
+
+    for test in tests {
+        let description = string_member(test, "description").unwrap_or_default();
+        let double_escaped = matches!(member(test, "doubleEscaped"), Some(JsonValue::Bool(true)));
+        let unescape = |text: &str| if double_escaped { unescape_code_units(text) } else { Some(text.to_string()) };
+        let Some(input) = member(test, "input").and_then(as_str).and_then(unescape) else {
+            report.skipped += 1;
+            continue;
+        };
+        let Some(expected) = member(test, "output").and_then(|output| expected_tokens(output, &unescape)) else {
+            report.skipped += 1;
+            continue;
+        };
+        let expected_errors: Vec<String> = match member(test, "errors") {
+            Some(JsonValue::Array(errors)) => errors.iter().filter_map(|error| string_member(error, "code")).collect(),
+            _ => Vec::new(),
+        };
+        let initial_states = match member(test, "initialStates") {
+            Some(JsonValue::Array(states)) => states.iter().filter_map(as_str).collect(),
+            _ => vec!["Data state"],
+        };
+
+        for initial_state in initial_states {
+            let Some(state) = tokenizer_state(initial_state) else {
+                report.skipped += 1;
+                continue;
+            };
+            let mut tokenizer = Tokenizer::new(&input);
+            tokenizer.set_state(state);
+            if let Some(last_start_tag) = string_member(test, "lastStartTag") {
+                tokenizer.set_last_start_tag(&last_start_tag);
+            }
+            let mut tokens = Vec::new();
+            loop {
+                match tokenizer.next_token() {
+                    Token::Eof => break,
+                    token => push_token(&mut tokens, token),
+                }
+            }
+            let errors: Vec<&str> = tokenizer.take_errors().iter().map(|error| error.code).collect();
+
+            report.check(
+                &format!("{} ({}), input {:?}", description, initial_state, input),
+                &format!("{:?}\nerrors: {:?}", expected, expected_errors),
+                &format!("{:?}\nerrors: {:?}", tokens, errors),
+            );
+        }
+    }
+    report
+}
+
+fn tokenizer_state(name: &str) -> Option<State> {
+    match name {
+        "Data state" => Some(State::Data),
+        "PLAINTEXT state" => Some(State::Plaintext),
+        "RCDATA state" => Some(State::Rcdata),
+        "RAWTEXT state" => Some(State::Rawtext),
+        "Script data state" => Some(State::ScriptData),
+        "CDATA section state" => Some(State::CdataSection),
+        _ => None,
+    }
+}
+
+/// Reads the expected tokens of a test, `None` if one of them cannot be
+/// represented.
+fn expected_tokens(output: &JsonValue, unescape: &dyn Fn(&str) -> Option<String>) -> Option<Vec<Token>> {
+    let JsonValue::Array(output) = output else {
+        return None;
+    };
+    let mut tokens = Vec::new();
+    for token in output {
+        let JsonValue::Array(fields) = token else {
+            return None;
+        };
+        let text = |index: usize| fields.get(index).and_then(as_str).and_then(unescape);
+        let token = match fields.first().and_then(as_str)? {
+            "DOCTYPE" => Token::Doctype(Doctype {
+                name: text(1),
+                public_id: text(2),
+                system_id: text(3),
+                force_quirks: !matches!(fields.get(4), Some(JsonValue::Bool(true))),
+            }),
+            "StartTag" => {
+                let JsonValue::Object(attributes) = fields.get(2)? else {
+                    return None;
+                };
+                let attributes = attributes
+                    .iter()
+                    .map(|(name, value)| Some((unescape(name)?, unescape(as_str(value)?)?)))
+                    .collect::<Option<_>>()?;
+                Token::StartTag {
+                    name: text(1)?,
+                    attributes,
+                    self_closing: matches!(fields.get(3), Some(JsonValue::Bool(true))),
+                }
+            }
+            "EndTag" => Token::EndTag(text(1)?),
+            "Comment" => Token::Comment(text(1)?),
+            "Character" => Token::Text(text(1)?),
+            _ => return None,
+        };
+        push_token(&mut tokens, token);
+    }
+    Some(tokens)
+}
+
+/// Appends a token, merging consecutive text tokens as the suite expects.
+fn push_token(tokens: &mut Vec<Token>, token: Token) {
+    if let (Some(Token::Text(last)), Token::Text(text)) = (tokens.last_mut(), &token) {
+        last.push_str(text);
+    } else {
+        tokens.push(token);
+    }
+}
+
+/// Decodes the `\uXXXX` escapes of a `doubleEscaped` test. Returns `None`
+/// for lone surrogates, which a Rust string cannot hold.
+fn unescape_code_units(text: &str) -> Option<String> {
+    let mut units = Vec::new();
+    let mut rest = text;
+    while let Some(index) = rest.find("\\u") {
+        units.extend(rest[..index].encode_utf16());
+        units.push(u16::from_str_radix(rest.get(index + 2..index + 6)?, 16).ok()?);
+        rest = &rest[index + 6..];
+    }
+    units.extend(rest.encode_utf16());
+    String::from_utf16(&units).ok()
+}
+
+fn member<'a>(value: &'a JsonValue, key: &str) -> Option<&'a JsonValue> {
+    match value {
+        JsonValue::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+fn string_member(value: &JsonValue, key: &str) -> Option<String> {
+    member(value, key).and_then(as_str).map(str::to_string)
+}
+
+fn as_str(value: &JsonValue) -> Option<&str> {
+    match value {
+        JsonValue::String(string) => Some(string),
+        _ => None,
+    }
+}
+
+// Tree construction tests
+
+/// A test of a `.dat` file: its sections by name (`#data`, `#document`...)
+/// with their lines.
+type DatTest = Vec<(String, Vec<String>)>;
+
+fn run_tree_construction_file(contents: &str) -> Report {
+    let mut report = Report::default();
+    for test in parse_dat(contents) {
+        let section = |name: &str| test.iter().find(|(key, _)| key == name).map(|(_, lines)| lines.join("\n"));
+        let (Some(data), Some(expected)) = (section("#data"), section("#document")) else {
+            report.skipped += 1;
+            continue;
+        };
+        // The parser runs with scripting disabled
+        if section("#script-on").is_some() {
+            report.skipped += 1;
+            continue;
+        }
+
+        let actual = match section("#document-fragment") {
+            None => dump(&parse_html(&data).unwrap().root_nodes),
+            Some(context) => {
+                let context = match context.split_once(' ') {
+                    None => context.as_str(),
+                    Some(("svg", "svg")) => "svg",
+                    Some(("math", "math")) => "math",
+                    Some(_) => {
+                        report.skipped += 1;
+                        continue;
+                    }
+                };
+                dump(&parse_fragment(&data, context).unwrap().nodes)
+            }
+        };
+        report.check(&format!("input {:?}", data), &expected, &actual);
+    }
+    report
+}
+
+/// Splits a `.dat` file into its tests. Tests start with a `#data` line
+/// and are separated by an empty line.
+fn parse_dat(contents: &str) -> Vec<DatTest> {
+    let mut tests: Vec<DatTest> = Vec::new();
+    for line in contents.lines() {
+        if line == "#data" {
+            tests.push(Vec::new());
+        }
+        let Some(test) = tests.last_mut() else {
+            continue;
+        };
+        if DAT_SECTIONS.contains(&line) {
+            test.push((line.to_string(), Vec::new()));
+        } else if let Some((_, lines)) = test.last_mut() {
+            lines.push(line.to_string());
+        }
+    }
+    for test in &mut tests {
+        if let Some((_, lines)) = test.last_mut() {
+            if lines.last().is_some_and(|line| line.is_empty()) {
+                lines.pop();
+            }
+        }
+    }
+    tests
+}
+
+const DAT_SECTIONS: &[&str] = &[
+    "#data",
+    "#errors",
+    "#new-errors",
+    "#document-fragment",
+    "#script-on",
+    "#script-off",
+    "#document",
+];
+
+/// Writes nodes in the format of the `#document` sections.
+fn dump(nodes: &[HtmlNode]) -> String {
+    let mut lines = Vec::new();
+    dump_nodes(nodes, 0, &mut lines);
+    lines.join("\n")
+}
+
+fn dump_nodes(nodes: &[HtmlNode], depth: usize, lines: &mut Vec<String>) {
+    let indent = "  ".repeat(depth);
+    for node in nodes {
+        match node {
+            HtmlNode::Element(element) => {
+                let prefix = match element.namespace {
+                    Namespace::Svg => "svg ",
+                    Namespace::MathMl => "math ",
+                    _ => "",
+                };
+                lines.push(format!("| {}<{}{}>", indent, prefix, element.tag_name));
+
+                let mut attributes: Vec<(String, &str)> = element
+                    .attributes
+                    .iter()
+                    .map(|(name, value)| {
+                        let name = match element.attribute_namespace(name) {
+                            Some(_) => name.replacen(':', " ", 1),
+                            None => name.clone(),
+                        };
+                        (name, value.as_str())
+                    })
+                    .collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    lines.push(format!("| {}  {}=\"{}\"", indent, name, value));
+                }
+
+                if let Some(contents) = &element.template_contents {
+                    lines.push(format!("| {}  content", indent));
+                    dump_nodes(contents, depth + 2, lines);
+                }
+                dump_nodes(&element.children, depth + 1, lines);
+            }
+            HtmlNode::Text { text, .. } => lines.push(format!("| {}\"{}\"", indent, text)),
+            HtmlNode::Comment { text, .. } => lines.push(format!("| {}<!-- {} -->", indent, text)),
+            HtmlNode::Doctype {
+                name,
+                public_id,
+                system_id,
+                ..
+            } => {
+                // Both identifiers are written as soon as one of them is there
+                if public_id.is_none() && system_id.is_none() {
+                    lines.push(format!("| {}<!DOCTYPE {}>", indent, name));
+                } else {
+                    lines.push(format!(
+                        "| {}<!DOCTYPE {} \"{}\" \"{}\">",
+                        indent,
+                        name,
+                        public_id.as_deref().unwrap_or_default(),
+                        system_id.as_deref().unwrap_or_default()
+                    ));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,24 @@
+#!/bin/sh
+# Vendors the html5lib test suite into `upstream`: the tokenizer and tree
+# construction tests and the license, at the given commit of
+# https://github.com/html5lib/html5lib-tests, which is recorded in
+# `upstream/REVISION`. Update `known-failures.txt` afterwards.
+#
+# Usage: tests/html5lib/fetch-upstream.sh <commit>
+set -eu
+
+revision=${1:?usage: $0 <commit>}
+dir=$(cd "$(dirname "$0")" && pwd)/upstream
+checkout=$(mktemp -d)
+trap 'rm -rf "$checkout"' EXIT
+
+git -C "$checkout" init -q
+git -C "$checkout" fetch -q --depth 1 https://github.com/html5lib/html5lib-tests "$revision"
+git -C "$checkout" checkout -q FETCH_HEAD
+
+rm -rf "$dir"
+mkdir -p "$dir/tokenizer" "$dir/tree-construction"
+cp "$checkout"/tokenizer/*.test "$dir/tokenizer/"
+cp "$checkout"/tree-construction/*.dat "$dir/tree-construction/"
+cp "$checkout/LICENSE" "$dir/"
+git -C "$checkout" rev-parse HEAD > "$dir/REVISION"
@@ -1,7 +1,16 @@
 use glib::MainContext;
 use gtk::prelude::*;
 use gtk::{Application, ApplicationWindow, Box as GtkBox, Button, Entry, Label, Orientation};
+use flux_dom::form::FormSubmission;
+use flux_dom::Navigation;
+use flux_network::Encoding;
+use flux_parser::html::StreamingParser;
+use std::rc::Rc;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// L'indicateur d'ordre des octets UTF-8, retiré par le décodage.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
 
 pub fn init() {
     let app = Application::new(
@@ -62,34 +71,7 @@ fn build_ui(app: &Application) {
     let load_url = Arc::new({
         let url_entry = url_entry.clone();
         let main_container = main_container.clone();
-        move || {
-            let url_entry = url_entry.clone();
-            let main_container = main_container.clone();
-            // Spawn the async task onto the main context
-            MainContext::default().spawn_local(async move {
-                let url = url_entry.text().to_string();
-                let response = match flux_network::fetch(&url).await {
-                    Ok(response) => response,
-                    Err(e) => {
-                        eprintln!("Error fetching URL: {:?}", e);
-                        return;
-                    }
-                };
-                // Convert body from Vec<u8> to String
-                let dom = match flux_parser::parse_document(
-                    &String::from_utf8_lossy(&response.body).to_string(),
-                    "text/html",
-                ) {
-                    Ok(flux_parser::ParsedDocument::Html(dom)) => dom,
-                    Err(e) => {
-                        eprintln!("Error parsing document: {:?}", e);
-                        return;
-                    }
-                };
-
-                flux_dom::draw_dom(main_container, dom);
-            });
-        }
+        move || load(main_container.clone(), url_entry.clone(), None)
     });
 
     // Clone the Arc for go_button
@@ -107,3 +89,99 @@ fn build_ui(app: &Application) {
     window.show();
     Arc::clone(&load_url)();
 }
+
+/// Temps minimal entre deux affichages d'une page en cours de chargement.
+const REPAINT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Charge une page et l'affiche : celle de la barre d'adresse, ou celle que
+/// demande un formulaire envoyé. Une page HTML est analysée au fil de sa
+/// réception et réaffichée de temps en temps avant d'être complète.
+fn load(main_container: GtkBox, url_entry: Entry, submission: Option<FormSubmission>) {
+    // Spawn the async task onto the main context
+    MainContext::default().spawn_local(async move {
+        let url = match &submission {
+            Some(submission) => {
+                url_entry.set_text(&submission.url);
+                submission.url.clone()
+            }
+            None => url_entry.text().to_string(),
+        };
+
+        // Feed the body to the parser as it arrives, and paint what is
+        // there so far once it looks like HTML
+        let mut parser = StreamingParser::new();
+        let mut received = Vec::new();
+        let mut last_paint = Instant::now();
+        let on_body = |piece: &[u8]| {
+            received.extend_from_slice(piece);
+            parser.feed(piece);
+            if last_paint.elapsed() >= REPAINT_INTERVAL
+                && flux_network::sniff_mime_type(&received, None, false).is_html()
+            {
+                let navigation = navigation(&main_container, &url_entry, &url);
+                flux_dom::draw_dom(main_container.clone(), parser.document(), navigation);
+                last_paint = Instant::now();
+            }
+        };
+        let result = match submission {
+            Some(submission) => {
+                flux_network::fetch_request_streaming(&url, submission.request, on_body).await
+            }
+            None => flux_network::fetch_streaming(&url, on_body).await,
+        };
+        let response = match result {
+            Ok(response) => response,
+            Err(e) => {
+                eprintln!("Error fetching URL: {:?}", e);
+                return;
+            }
+        };
+        // After redirects, the page is the one at the final URL
+        let url = match &response.url {
+            Some(final_url) if *final_url != url => {
+                url_entry.set_text(final_url);
+                final_url.clone()
+            }
+            _ => url,
+        };
+        let navigation = navigation(&main_container, &url_entry, &url);
+        // The type given by the server, unless the body says otherwise
+        let mime_type = flux_network::sniff_response(&response);
+        if mime_type.is_image() {
+            flux_dom::draw_image(main_container, &response.body, &url);
+            return;
+        }
+        // Decode the body with the encoding declared or sniffed for it
+        let (text, encoding) = flux_network::decode_response(&response);
+        // The streaming parser read the body as UTF-8: its document is only
+        // kept when that is the encoding of the page
+        if mime_type.is_html() && encoding == Encoding::Utf8 && !response.body.starts_with(UTF8_BOM) {
+            flux_dom::draw_dom(main_container, parser.finish(), navigation);
+            return;
+        }
+        match flux_parser::parse_document(&text, &mime_type.essence()) {
+            Ok(flux_parser::ParsedDocument::Html(dom)) => {
+                flux_dom::draw_dom(main_container, dom, navigation)
+            }
+            Ok(flux_parser::ParsedDocument::Xml(document)) => {
+                flux_dom::draw_xml(main_container, document)
+            }
+            Err(e) => {
+                eprintln!("Error parsing document: {:?}", e);
+            }
+        }
+    });
+}
+
+/// Les formulaires envoyés depuis la page `url` chargent leur résultat à sa
+/// place.
+fn navigation(main_container: &GtkBox, url_entry: &Entry, url: &str) -> Navigation {
+    Navigation {
+        url: url.to_string(),
+        on_submit: Rc::new({
+            let main_container = main_container.clone();
+            let url_entry = url_entry.clone();
+            move |submission| load(main_container.clone(), url_entry.clone(), Some(submission))
+        }),
+    }
+}